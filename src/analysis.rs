@@ -0,0 +1,181 @@
+use std::{fmt, ops::Range};
+
+use crate::{duration::Duration, pitch_class, project::ProjectImpl};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChordQuality {
+    Major,
+    Minor,
+    Diminished,
+    Augmented,
+    Dominant7,
+    Major7,
+    Minor7,
+    HalfDiminished7,
+    Sus4,
+    Sus2,
+}
+
+impl ChordQuality {
+    // Simpler chords first. They win ties.
+    pub const ALL: [ChordQuality; 10] = [
+        ChordQuality::Major, ChordQuality::Minor, ChordQuality::Dominant7, ChordQuality::Minor7, ChordQuality::Major7,
+        ChordQuality::Diminished, ChordQuality::HalfDiminished7, ChordQuality::Augmented, ChordQuality::Sus4, ChordQuality::Sus2,
+    ];
+
+    // Semitones from the root.
+    pub const fn intervals(self) -> &'static [u8] {
+        match self {
+            ChordQuality::Major => &[0, 4, 7],
+            ChordQuality::Minor => &[0, 3, 7],
+            ChordQuality::Diminished => &[0, 3, 6],
+            ChordQuality::Augmented => &[0, 4, 8],
+            ChordQuality::Dominant7 => &[0, 4, 7, 10],
+            ChordQuality::Major7 => &[0, 4, 7, 11],
+            ChordQuality::Minor7 => &[0, 3, 7, 10],
+            ChordQuality::HalfDiminished7 => &[0, 3, 6, 10],
+            ChordQuality::Sus4 => &[0, 5, 7],
+            ChordQuality::Sus2 => &[0, 2, 7],
+        }
+    }
+
+    pub const fn suffix(self) -> &'static str {
+        match self {
+            ChordQuality::Major => "",
+            ChordQuality::Minor => "m",
+            ChordQuality::Diminished => "dim",
+            ChordQuality::Augmented => "aug",
+            ChordQuality::Dominant7 => "7",
+            ChordQuality::Major7 => "maj7",
+            ChordQuality::Minor7 => "m7",
+            ChordQuality::HalfDiminished7 => "m7b5",
+            ChordQuality::Sus4 => "sus4",
+            ChordQuality::Sus2 => "sus2",
+        }
+    }
+}
+
+// Pitch classes are 0 for C to 11 for B.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chord {
+    pub root: u8,
+    pub quality: ChordQuality,
+    pub bass: u8,
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", pitch_class::name(self.root), self.quality.suffix())?;
+        if self.bass != self.root {
+            write!(f, "/{}", pitch_class::name(self.bass))?;
+        }
+        Ok(())
+    }
+}
+
+// Chord best explaining the sounding time of each pitch class. Tones outside of the chord and chord tones
+// missing count against it. Tones sounding a quarter of the strongest one or less are taken as passing
+// and never complete a chord. A chord on the bass wins ties.
+pub fn detect_chord(weights: &[u32; 12], bass: u8) -> Option<Chord> {
+    let total: i64 = weights.iter().map(|w| *w as i64).sum();
+    if total == 0 { return None; }
+    let max = *weights.iter().max().unwrap() as i64;
+    let present = |pc: usize| max < weights[pc] as i64 * 4;
+    let mut best: Option<(i64, Chord)> = None;
+    for quality in ChordQuality::ALL {
+        for r in 0..12u8 {
+            let root = pitch_class::transposed(bass, r as i32);
+            let tones = quality.intervals().iter().map(|i| pitch_class::transposed(root, *i as i32) as usize);
+            let (inside, missing) = tones.fold((0, 0), |(inside, missing), pc|
+                if !present(pc) { (inside, missing + 1) } else { (inside + weights[pc] as i64, missing) }
+            );
+            let score = 2 * inside - total - missing * max;
+            if best.is_none_or(|(s, _)| s < score) {
+                best = Some((score, Chord { root, quality, bass }));
+            }
+        }
+    }
+    best.map(|(_, chord)| chord)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReduceWindow {
+    Bar,
+    Beat,
+}
+
+// One chord per window of the range. Windows without sounding notes are skipped.
+pub fn reduce(proj: &ProjectImpl, range: Range<u32>, window: ReduceWindow) -> Vec<(Range<u32>, Chord)> {
+    let mut starts: Vec<u32> = std::iter::once(range.start)
+        .chain(proj.beats(range.clone()).filter(|b| window == ReduceWindow::Beat || b.beat_no == 0).map(|b| b.tick))
+        .collect();
+    starts.dedup();
+    let lookback = Duration::MAX_TICK_LENGTH as u32 * 2;
+    let notes: Vec<(u32, u32, u8)> = proj.note_repo().range(range.start.saturating_sub(lookback)..range.end)
+        .map(|(_, n)| (n.start_tick(), n.start_tick() + n.tick_len(), proj.sounding_pitch(n).value()))
+        .collect();
+
+    starts.iter().enumerate().filter_map(|(i, start)| {
+        let end = starts.get(i + 1).copied().unwrap_or(range.end);
+        let mut weights = [0; 12];
+        let mut sounding: Vec<(u8, u32)> = vec![];
+        for (from, to, value) in notes.iter() {
+            let overlap = (*to).min(end).saturating_sub((*from).max(*start));
+            if overlap == 0 { continue; }
+            weights[pitch_class::of_value(*value) as usize] += overlap;
+            sounding.push((*value, overlap));
+        }
+        // Short notes in the bass do not make an inversion.
+        let bass = sounding.iter().filter(|(_, overlap)| end - start < overlap * 4).map(|(v, _)| *v).min()
+            .or(sounding.iter().map(|(v, _)| *v).min())?;
+        detect_chord(&weights, pitch_class::of_value(bass)).map(|chord| (*start..end, chord))
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serdo::undo_store::UndoStore;
+
+    use crate::{bar::Bar, duration::{Denominator, Dots, Duration, Numerator}, note::Note, octave::Octave, pitch::Pitch, project::{memory_project_store, MemoryProjectStore, Project, DEFAULT_MEMORY_UNDO_LIMIT}, sharp_flat::SharpFlat, solfa::Solfa};
+    use super::{detect_chord, reduce, Chord, ChordQuality, ReduceWindow};
+
+    fn weights(pcs: &[u8]) -> [u32; 12] {
+        let mut w = [0; 12];
+        pcs.iter().for_each(|pc| w[*pc as usize] += 1);
+        w
+    }
+
+    #[test]
+    fn detect() {
+        assert_eq!(detect_chord(&weights(&[0, 4, 7]), 0), Some(Chord { root: 0, quality: ChordQuality::Major, bass: 0 }));
+        assert_eq!(detect_chord(&weights(&[9, 0, 4]), 9).unwrap().to_string(), "Am");
+        assert_eq!(detect_chord(&weights(&[7, 11, 2, 5]), 7).unwrap().to_string(), "G7");
+        assert_eq!(detect_chord(&weights(&[9, 0, 5]), 9).unwrap().to_string(), "F/A");
+        // A passing D does not change the C major triad.
+        assert_eq!(detect_chord(&weights(&[0, 0, 4, 4, 7, 7, 2]), 0).unwrap().to_string(), "C");
+        assert_eq!(detect_chord(&[0; 12], 0), None);
+    }
+
+    #[test]
+    fn reduce_by_bar_and_beat() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_bar(Bar::new(960, None, None, Default::default()), false);
+        let add = |store: &mut MemoryProjectStore, tick: u32, numerator: Numerator, pitches: &[(Solfa, Octave)]| for (solfa, octave) in pitches {
+            let duration = Duration::new(numerator, Denominator::default(), Dots::ZERO);
+            store.add_note(Note { base_start_tick: tick, pitch: Pitch::new(*solfa, *octave, SharpFlat::Null), duration, ..Default::default() }, false);
+        };
+        add(&mut store, 0, Numerator::Half, &[(Solfa::C, Octave::Oct4), (Solfa::E, Octave::Oct4), (Solfa::G, Octave::Oct4)]);
+        add(&mut store, 480, Numerator::Quarter, &[(Solfa::C, Octave::Oct4), (Solfa::E, Octave::Oct4), (Solfa::G, Octave::Oct4)]);
+        add(&mut store, 720, Numerator::Quarter, &[(Solfa::G, Octave::Oct3), (Solfa::B, Octave::Oct3), (Solfa::D, Octave::Oct4)]);
+        add(&mut store, 960, Numerator::Whole, &[(Solfa::D, Octave::Oct4), (Solfa::G, Octave::Oct4), (Solfa::B, Octave::Oct4), (Solfa::F, Octave::Oct4)]);
+
+        let names = |window| reduce(store.model(), 0..1920, window).iter().map(|(r, c)| (r.start, c.to_string())).collect::<Vec<_>>();
+        assert_eq!(names(ReduceWindow::Beat), vec![
+            (0, "C".to_owned()), (240, "C".to_owned()), (480, "C".to_owned()), (720, "G".to_owned()),
+            (960, "G7/D".to_owned()), (1200, "G7/D".to_owned()), (1440, "G7/D".to_owned()), (1680, "G7/D".to_owned()),
+        ]);
+        // The G chord at the last beat of the first bar is passing.
+        assert_eq!(names(ReduceWindow::Bar), vec![(0, "C".to_owned()), (960, "G7/D".to_owned())]);
+        assert!(reduce(store.model(), 1920..2880, ReduceWindow::Bar).is_empty());
+    }
+}
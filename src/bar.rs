@@ -2,7 +2,7 @@ use std::fmt;
 
 use enumset::{EnumSetType, EnumSet, enum_set};
 
-use super::{key::Key, rhythm::Rhythm, note::TickError, have_start_tick::{HaveBaseStartTick, HaveStartTick}};
+use super::{key::Key, rhythm::Rhythm, note::TickError, tick::Tick, have_start_tick::{HaveBaseStartTick, HaveStartTick}};
 
 #[derive(serde::Deserialize, serde::Serialize)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -97,7 +97,9 @@ impl fmt::Display for Repeat {
 
 #[derive(serde::Deserialize, serde::Serialize)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct RepeatSet {
+    #[cfg_attr(feature = "schema", schemars(with = "u16"))]
     value: EnumSet<Repeat>,
 }
 
@@ -197,6 +199,15 @@ impl RepeatSet {
     pub fn len(self) -> usize {
         self.value.len()
     }
+
+    pub fn iter(self) -> impl Iterator<Item = Repeat> {
+        self.value.iter()
+    }
+
+    // Repeats of other are added unless they conflict with the ones already in the set.
+    pub fn merged(self, other: RepeatSet) -> Self {
+        other.iter().fold(self, |set, r| set.try_add(r).unwrap_or(set))
+    }
 }
 
 impl Default for RepeatSet {
@@ -220,8 +231,17 @@ macro_rules! repeat_set {
     };
 }
 
+// Attributes of a bar that can be copied to other bars.
+#[derive(Debug, EnumSetType)]
+pub enum BarAttribute {
+    Key,
+    Rhythm,
+    Repeats,
+}
+
 #[derive(serde::Deserialize, serde::Serialize)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Bar {
     pub start_tick: u32,
     pub rhythm: Option<Rhythm>,
@@ -243,26 +263,60 @@ impl Bar {
 
     pub fn drag(&self, tick_delta: i32) -> Self {
         Self {
-             start_tick: ((self.start_tick as i64) + tick_delta as i64) as u32,
+             start_tick: Tick::new(self.start_tick).saturating_add_signed(tick_delta as i64).as_u32(),
              ..*self
         }
     }
 
+    // The start tick is kept.
+    pub fn with_attributes_of(self, from: &Bar, what: EnumSet<BarAttribute>) -> Self {
+        Self {
+            start_tick: self.start_tick,
+            rhythm: if what.contains(BarAttribute::Rhythm) { from.rhythm } else { self.rhythm },
+            key: if what.contains(BarAttribute::Key) { from.key } else { self.key },
+            repeats: if what.contains(BarAttribute::Repeats) { from.repeats } else { self.repeats },
+        }
+    }
+
     pub fn with_tick_added(&self, tick_delta: i32) -> Result<Self, TickError> {
-        let tick = (self.start_tick as i64) + tick_delta as i64;
-        if tick < 0 {
-            Err(TickError::Minus)
-        } else {
-            Ok(
-                Self {
-                    start_tick: tick as u32,
-                    ..*self
-                }
-            )
+        let tick = Tick::new(self.start_tick).checked_add_signed(tick_delta as i64)?;
+        Ok(Self { start_tick: tick.as_u32(), ..*self })
+    }
+
+    // The pasted bar merged into this one at the same tick. Key and rhythm of the pasted bar win if it has them.
+    // Pasted repeats are added unless they conflict with the repeats of this bar.
+    pub fn merged_with(self, pasted: &Bar) -> (Self, Vec<BarMergeWarning>) {
+        let tick = self.start_tick;
+        let mut warnings = vec![];
+        if let (Some(existing), Some(p)) = (self.key, pasted.key) {
+            if existing != p { warnings.push(BarMergeWarning::KeyReplaced { tick, existing, pasted: p }); }
+        }
+        if let (Some(existing), Some(p)) = (self.rhythm, pasted.rhythm) {
+            if existing != p { warnings.push(BarMergeWarning::RhythmReplaced { tick, existing, pasted: p }); }
         }
+        let repeats = self.repeats.merged(pasted.repeats);
+        warnings.extend(
+            pasted.repeats.iter().filter(|r| !repeats.contains(*r)).map(|repeat| BarMergeWarning::RepeatDropped { tick, repeat })
+        );
+        let bar = Self {
+            start_tick: tick,
+            rhythm: pasted.rhythm.or(self.rhythm),
+            key: pasted.key.or(self.key),
+            repeats,
+        };
+        (bar, warnings)
     }
 }
 
+// Conflicts found while merging a pasted bar into an existing one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarMergeWarning {
+    KeyReplaced { tick: u32, existing: Key, pasted: Key },
+    RhythmReplaced { tick: u32, existing: Rhythm, pasted: Rhythm },
+    // The pasted repeat conflicts with the repeats of the existing bar and is dropped.
+    RepeatDropped { tick: u32, repeat: Repeat },
+}
+
 impl HaveBaseStartTick for Bar {
     fn base_start_tick(&self) -> u32 {
         self.start_tick
@@ -0,0 +1,119 @@
+use std::cmp::Ordering;
+
+use crate::{bar::{Bar, RepeatSet}, note::Note, rhythm::Rhythm};
+
+// Onsets are gathered per 16th note.
+const BIN_TICKS: u32 = 60;
+
+// Rhythms tried when no hint is given. Earlier ones win ties.
+const CANDIDATES: [(u8, u8); 4] = [(4, 4), (3, 4), (2, 4), (6, 8)];
+
+// Bar lines for notes without time signatures, such as a recording imported from a format-0 SMF. The bar length
+// is the lag at which the onsets correlate best, and bar lines are placed where the onsets are the strongest.
+// The first bar line carries the rhythm. Notes before it make a pickup bar. The hint fixes the rhythm, leaving
+// only the position of the bar lines to guess.
+pub fn infer_bars(notes: &[Note], hint: Option<Rhythm>) -> Vec<Bar> {
+    let Some(end) = notes.iter().map(|n| n.start_tick().saturating_add(n.tick_len())).max() else { return vec![]; };
+    let strengths = onset_strengths(notes);
+
+    let rhythm = match hint {
+        Some(rhythm) => rhythm,
+        None => {
+            let score = |r: &Rhythm| {
+                let beat = if r.is_compound() { r.beat_tick_len() * 3 } else { r.beat_tick_len() };
+                (autocorrelation(&strengths, r.tick_len()), autocorrelation(&strengths, beat))
+            };
+            let mut best = Rhythm::new(CANDIDATES[0].0, CANDIDATES[0].1);
+            let mut best_score = score(&best);
+            for (numerator, denominator) in CANDIDATES.iter().skip(1) {
+                let r = Rhythm::new(*numerator, *denominator);
+                let s = score(&r);
+                if s.partial_cmp(&best_score) == Some(Ordering::Greater) {
+                    best = r;
+                    best_score = s;
+                }
+            }
+            best
+        }
+    };
+
+    let bar_len = rhythm.tick_len();
+    let downbeat_strength = |phase: u32| -> u64 {
+        (phase..end).step_by(bar_len as usize).map(|t| strengths.get((t / BIN_TICKS) as usize).copied().unwrap_or(0)).sum()
+    };
+    let phase = (0..bar_len).step_by(rhythm.beat_tick_len() as usize)
+        .fold((0, 0), |(best, best_strength), phase| {
+            let s = downbeat_strength(phase);
+            if best_strength < s { (phase, s) } else { (best, best_strength) }
+        }).0;
+
+    let first = if phase == 0 { bar_len } else { phase };
+    let mut bars = vec![Bar::new(first, Some(rhythm), None, RepeatSet::EMPTY)];
+    let mut tick = first;
+    while tick < end {
+        tick += bar_len;
+        bars.push(Bar::new(tick, None, None, RepeatSet::EMPTY));
+    }
+    bars
+}
+
+// Sum of the velocities of the notes starting in each bin.
+fn onset_strengths(notes: &[Note]) -> Vec<u64> {
+    let len = notes.iter().map(|n| n.start_tick() / BIN_TICKS + 1).max().unwrap_or(0);
+    let mut strengths = vec![0; len as usize];
+    for n in notes {
+        strengths[(n.start_tick() / BIN_TICKS) as usize] += n.velocity().as_u8() as u64;
+    }
+    strengths
+}
+
+// Normalized by the number of pairs so that shorter lags are not favored.
+fn autocorrelation(strengths: &[u64], lag_ticks: u32) -> f64 {
+    let lag = (lag_ticks / BIN_TICKS) as usize;
+    if lag == 0 || strengths.len() <= lag { return 0.0; }
+    let pairs = strengths.len() - lag;
+    let sum: u64 = (0..pairs).map(|i| strengths[i] * strengths[i + lag]).sum();
+    sum as f64 / pairs as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{duration::Duration, note::Note, rhythm::Rhythm, velocity::Velocity};
+    use super::infer_bars;
+
+    // Quarter notes with the accent every bar, starting after the pickup.
+    fn accented(beats_per_bar: u32, pickup_beats: u32, bars: u32) -> Vec<Note> {
+        (0..pickup_beats + beats_per_bar * bars).map(|beat| {
+            let accent = beat >= pickup_beats && (beat - pickup_beats).is_multiple_of(beats_per_bar);
+            Note {
+                base_start_tick: beat * 240,
+                duration: Duration::default(),
+                base_velocity: Velocity::new(if accent { 110 } else { 50 }),
+                ..Default::default()
+            }
+        }).collect()
+    }
+
+    #[test]
+    fn waltz() {
+        let bars = infer_bars(&accented(3, 1, 8), None);
+        assert_eq!(bars[0].start_tick, 240);
+        assert_eq!(bars[0].rhythm, Some(Rhythm::new(3, 4)));
+        assert_eq!(bars[1].start_tick, 960);
+        assert!(bars[1].rhythm.is_none());
+        // The last bar line closes the last bar.
+        assert_eq!(bars.last().unwrap().start_tick, 240 + 720 * 8);
+    }
+
+    #[test]
+    fn hinted() {
+        let bars = infer_bars(&accented(4, 0, 4), Some(Rhythm::new(2, 4)));
+        assert_eq!(bars[0].rhythm, Some(Rhythm::new(2, 4)));
+        assert_eq!(bars.iter().map(|b| b.start_tick).take(3).collect::<Vec<_>>(), vec![480, 960, 1440]);
+
+        let bars = infer_bars(&accented(4, 2, 4), None);
+        assert_eq!(bars[0].rhythm, Some(Rhythm::new(4, 4)));
+        assert_eq!(bars[0].start_tick, 480);
+        assert!(infer_bars(&[], None).is_empty());
+    }
+}
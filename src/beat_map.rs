@@ -0,0 +1,98 @@
+use std::fmt;
+
+use crate::duration::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BeatMapError {
+    Empty,
+    // Anchors should be strictly increasing both in tick and beat.
+    NotIncreasing { tick: u32, beat: u32 },
+}
+
+impl fmt::Display for BeatMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BeatMapError::Empty => write!(f, "Beat map has no anchor"),
+            BeatMapError::NotIncreasing { tick, beat } => write!(f, "Anchor (tick: {}, beat: {}) is not after the previous one", tick, beat),
+        }
+    }
+}
+
+// Anchors of (tick in the performance, beat on the bar grid). A beat is a quarter note.
+// Ticks between anchors are linearly interpolated. Ticks outside of anchors follow the nearest segment.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BeatMap {
+    anchors: Vec<(u32, u32)>,
+}
+
+impl BeatMap {
+    pub fn new(anchors: Vec<(u32, u32)>) -> Result<Self, BeatMapError> {
+        if anchors.is_empty() {
+            return Err(BeatMapError::Empty);
+        }
+        for w in anchors.windows(2) {
+            if w[1].0 <= w[0].0 || w[1].1 <= w[0].1 {
+                return Err(BeatMapError::NotIncreasing { tick: w[1].0, beat: w[1].1 });
+            }
+        }
+        Ok(Self { anchors })
+    }
+
+    pub fn anchors(&self) -> &[(u32, u32)] {
+        &self.anchors
+    }
+
+    // Tick on the bar grid for the tick in the performance.
+    pub fn warp(&self, tick: u32) -> u32 {
+        let to_grid = |beat: u32| beat as i64 * Duration::TICK_RESOLUTION as i64;
+        if self.anchors.len() == 1 {
+            let (t, b) = self.anchors[0];
+            return (to_grid(b) + tick as i64 - t as i64).clamp(0, u32::MAX as i64) as u32;
+        }
+
+        let idx = self.anchors.partition_point(|(t, _)| *t <= tick).clamp(1, self.anchors.len() - 1);
+        let (t0, b0) = self.anchors[idx - 1];
+        let (t1, b1) = self.anchors[idx];
+        let (g0, g1) = (to_grid(b0), to_grid(b1));
+        let warped = g0 + (tick as i64 - t0 as i64) * (g1 - g0) / (t1 as i64 - t0 as i64);
+        warped.clamp(0, u32::MAX as i64) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{models::Models, note::Note, ctrl_chg::CtrlChg, velocity::Velocity, channel::Channel};
+    use super::{BeatMap, BeatMapError};
+
+    #[test]
+    fn warp() {
+        let map = BeatMap::new(vec![(100, 0), (400, 1), (500, 2)]).unwrap();
+        assert_eq!(map.warp(100), 0);
+        assert_eq!(map.warp(250), 120);
+        assert_eq!(map.warp(400), 240);
+        assert_eq!(map.warp(450), 360);
+        assert_eq!(map.warp(600), 720);
+        assert_eq!(map.warp(0), 0);
+
+        let shift = BeatMap::new(vec![(100, 1)]).unwrap();
+        assert_eq!(shift.warp(150), 290);
+
+        assert_eq!(BeatMap::new(vec![]), Err(BeatMapError::Empty));
+        assert_eq!(BeatMap::new(vec![(100, 0), (100, 1)]), Err(BeatMapError::NotIncreasing { tick: 100, beat: 1 }));
+    }
+
+    #[test]
+    fn warp_models() {
+        let models = Models {
+            notes: vec![Note { base_start_tick: 130, ..Default::default() }, Note { base_start_tick: 390, ..Default::default() }],
+            dumpers: vec![CtrlChg::new(260, Velocity::new(127), Channel::default())],
+            ..Models::empty()
+        };
+        let map = BeatMap::new(vec![(130, 0), (260, 1), (390, 2)]).unwrap();
+        let warped = models.warp_to_beatmap(&map);
+        let ticks: Vec<u32> = warped.notes.iter().map(|n| n.base_start_tick).collect();
+        assert_eq!(ticks, vec![0, 480]);
+        assert_eq!(warped.dumpers[0].start_tick, 240);
+    }
+}
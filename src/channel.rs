@@ -1,5 +1,6 @@
 #[derive(serde::Deserialize, serde::Serialize)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Channel(u8);
 
 impl Channel {
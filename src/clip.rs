@@ -0,0 +1,185 @@
+use std::collections::BTreeMap;
+
+use std::ops::Range;
+
+use crate::{duration::{Denominator, Duration}, models::Models, note::Note};
+
+// A reusable pattern saved from a selection. Models are anchored at tick 0. Bars are not part of clip.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clip {
+    pub name: String,
+    pub models: Models,
+}
+
+impl Clip {
+    pub fn new(name: &str, models: &Models) -> Self {
+        let models = Models { bars: vec![], ..models.clone() };
+        Self {
+            name: name.to_owned(),
+            models: models.move_to_tick(0),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let m = &self.models;
+        m.notes.is_empty() && m.tempos.is_empty() && m.dumpers.is_empty() && m.softs.is_empty()
+    }
+
+    pub fn tick_len(&self) -> u32 {
+        let m = &self.models;
+        let notes = m.notes.iter().map(|n| n.base_start_tick + n.tick_len());
+        let others = m.tempos.iter().map(|t| t.start_tick)
+            .chain(m.dumpers.iter().map(|d| d.start_tick))
+            .chain(m.softs.iter().map(|s| s.start_tick));
+        notes.chain(others).max().unwrap_or(0)
+    }
+
+    // Models to be placed when instantiated at the tick.
+    pub fn models_at(&self, tick: u32) -> Models {
+        self.models.clone().move_to_tick(tick)
+    }
+
+    // Repeat the clip across the range. Notes crossing the end of range are shortened if the rest can be
+    // written as a single note, otherwise dropped. Anything starting at or after the end is dropped.
+    pub fn tile(&self, range: Range<u32>) -> Models {
+        let mut tiled = Models::empty();
+        let period = self.tick_len();
+        if period == 0 { return tiled; }
+
+        let mut tick = range.start;
+        while tick < range.end {
+            let models = self.models_at(tick);
+            tiled.notes.extend(models.notes.into_iter().filter_map(|n| Self::fit_note(n, range.end)));
+            tiled.tempos.extend(models.tempos.into_iter().filter(|t| t.start_tick < range.end));
+            tiled.dumpers.extend(models.dumpers.into_iter().filter(|d| d.start_tick < range.end));
+            tiled.softs.extend(models.softs.into_iter().filter(|s| s.start_tick < range.end));
+            tick += period;
+        }
+        tiled
+    }
+
+    fn fit_note(note: Note, end_tick: u32) -> Option<Note> {
+        if end_tick <= note.base_start_tick { return None; }
+        let rest = end_tick - note.base_start_tick;
+        if note.duration.tick_length() <= rest { return Some(note); }
+        let duration = Duration::from_tick_length(rest, note.duration.denominator)
+            .or_else(|| Duration::from_tick_length(rest, Denominator::from_value(2).unwrap()))?;
+        Some(Note { duration, tie: false, ..note })
+    }
+}
+
+// Placement of a clip instantiated by reference. It follows updates of the clip.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ClipInstance {
+    pub tick: u32,
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ClipLibrary {
+    clips: BTreeMap<String, Clip>,
+    instances: BTreeMap<String, Vec<ClipInstance>>,
+}
+
+impl ClipLibrary {
+    pub fn get(&self, name: &str) -> Option<&Clip> {
+        self.clips.get(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.clips.keys()
+    }
+
+    pub fn len(&self) -> usize {
+        self.clips.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clips.is_empty()
+    }
+
+    pub fn instances(&self, name: &str) -> &[ClipInstance] {
+        self.instances.get(name).map(|i| i.as_slice()).unwrap_or(&[])
+    }
+
+    // Returns the clip replaced.
+    pub fn insert(&mut self, clip: Clip) -> Option<Clip> {
+        self.clips.insert(clip.name.clone(), clip)
+    }
+
+    // Instances of the removed clip are detached. Their models stay as plain copies.
+    pub fn remove(&mut self, name: &str) -> Option<Clip> {
+        self.instances.remove(name);
+        self.clips.remove(name)
+    }
+
+    // Drops instances of clips no longer in the library. Returns the count dropped.
+    pub fn drop_orphan_instances(&mut self) -> usize {
+        let clips = &self.clips;
+        let orphans: Vec<String> = self.instances.keys().filter(|name| !clips.contains_key(*name)).cloned().collect();
+        orphans.iter().filter_map(|name| self.instances.remove(name)).map(|i| i.len()).sum()
+    }
+
+    pub fn add_instance(&mut self, name: &str, instance: ClipInstance) {
+        let instances = self.instances.entry(name.to_owned()).or_default();
+        if let Err(idx) = instances.binary_search(&instance) {
+            instances.insert(idx, instance);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{models::Models, note::Note, bar::{Bar, RepeatSet}, ctrl_chg::CtrlChg, velocity::Velocity, channel::Channel, duration::{Duration, Numerator, Denominator, Dots}};
+    use super::{Clip, ClipLibrary, ClipInstance};
+
+    #[test]
+    fn clip_is_anchored_at_zero() {
+        let models = Models {
+            notes: vec![Note { base_start_tick: 480, ..Default::default() }],
+            bars: vec![Bar::new(960, None, None, RepeatSet::EMPTY)],
+            dumpers: vec![CtrlChg::new(600, Velocity::new(127), Channel::default())],
+            ..Models::empty()
+        };
+        let clip = Clip::new("riff", &models);
+        assert_eq!(clip.models.notes[0].base_start_tick, 0);
+        assert_eq!(clip.models.dumpers[0].start_tick, 120);
+        assert!(clip.models.bars.is_empty());
+        assert_eq!(clip.tick_len(), 240);
+        assert_eq!(clip.models_at(100).dumpers[0].start_tick, 220);
+    }
+
+    #[test]
+    fn remove_detaches_instances() {
+        let mut lib = ClipLibrary::default();
+        lib.insert(Clip::new("riff", &Models::empty()));
+        lib.add_instance("riff", ClipInstance { tick: 960 });
+        lib.add_instance("riff", ClipInstance { tick: 0 });
+        lib.add_instance("riff", ClipInstance { tick: 960 });
+        assert_eq!(lib.instances("riff"), &[ClipInstance { tick: 0 }, ClipInstance { tick: 960 }]);
+
+        assert!(lib.remove("riff").is_some());
+        assert!(lib.instances("riff").is_empty());
+        assert!(lib.is_empty());
+    }
+
+    #[test]
+    fn tile() {
+        let eighth = Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO);
+        let models = Models {
+            notes: (0..4).map(|i| Note { base_start_tick: i * 120, duration: eighth, ..Default::default() }).collect(),
+            ..Models::empty()
+        };
+        let clip = Clip::new("alberti", &models);
+        let tiled = clip.tile(960..1980);
+        let ticks: Vec<u32> = tiled.notes.iter().map(|n| n.base_start_tick).collect();
+        assert_eq!(ticks, vec![960, 1080, 1200, 1320, 1440, 1560, 1680, 1800, 1920]);
+        assert_eq!(tiled.notes.last().unwrap().duration.tick_length(), 60);
+
+        let tiled = clip.tile(0..250);
+        assert_eq!(tiled.notes.len(), 2);
+        assert!(Clip::new("empty", &Models::empty()).tile(0..960).notes.is_empty());
+    }
+}
@@ -0,0 +1,64 @@
+use std::ops::Range;
+
+use crate::score_position::ScorePosition;
+
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommentAnchor {
+    Position(ScorePosition),
+    Range(Range<u32>),
+}
+
+// Review feedback attached to a passage. Comments have no identity and are matched by value.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    pub anchor: CommentAnchor,
+    pub author: String,
+    pub text: String,
+    // Seconds since the Unix epoch, given by the caller.
+    pub created_at: u64,
+    pub resolved: bool,
+}
+
+impl Comment {
+    pub fn new(anchor: CommentAnchor, author: &str, text: &str, created_at: u64) -> Self {
+        Self { anchor, author: author.to_owned(), text: text.to_owned(), created_at, resolved: false }
+    }
+
+    // Ticks of the passage. A position covers its tick only.
+    pub fn tick_range(&self) -> Range<u32> {
+        match &self.anchor {
+            CommentAnchor::Position(pos) => pos.tick..pos.tick.saturating_add(1),
+            CommentAnchor::Range(range) => range.clone(),
+        }
+    }
+
+    pub fn overlaps(&self, range: &Range<u32>) -> bool {
+        let r = self.tick_range();
+        r.start < range.end && range.start < r.end
+    }
+
+    pub fn with_resolved(&self, resolved: bool) -> Self {
+        Self { resolved, ..self.clone() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::score_position::ScorePosition;
+    use super::{Comment, CommentAnchor};
+
+    #[test]
+    fn overlaps() {
+        let on_position = Comment::new(CommentAnchor::Position(ScorePosition::new(480, 2)), "teacher", "Softer the second time.", 0);
+        assert_eq!(on_position.tick_range(), 480..481);
+        assert!(on_position.overlaps(&(0..481)));
+        assert!(!on_position.overlaps(&(0..480)));
+
+        let on_range = Comment::new(CommentAnchor::Range(240..960), "teacher", "Keep the tempo.", 0);
+        assert!(on_range.overlaps(&(900..1000)));
+        assert!(!on_range.overlaps(&(960..1000)));
+        assert!(on_range.with_resolved(true).resolved);
+    }
+}
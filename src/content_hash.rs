@@ -0,0 +1,149 @@
+use klavier_helper::{bag_store::BagStoreEvent, store::StoreEvent};
+use serde::Serialize;
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+// FNV-1a. Unlike DefaultHasher, the result does not change between Rust versions.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET, |h, b| (h ^ *b as u64).wrapping_mul(FNV_PRIME))
+}
+
+pub fn value_hash<T: Serialize + ?Sized>(value: &T) -> u64 {
+    match bincode::serialize(value) {
+        Ok(bytes) => fnv1a(&bytes),
+        Err(_) => panic!("Logic error."),
+    }
+}
+
+// Order independent hash of a repo. Element hashes are added to or subtracted from the sum as store events
+// arrive, so the repo is never hashed as a whole.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RepoHash {
+    sum: u64,
+    // Number of events already folded into the sum.
+    folded: usize,
+}
+
+impl RepoHash {
+    pub fn value(&self) -> u64 {
+        self.sum
+    }
+
+    fn add<T: Serialize>(&mut self, value: &T) {
+        self.sum = self.sum.wrapping_add(value_hash(value));
+    }
+
+    fn sub<T: Serialize>(&mut self, value: &T) {
+        self.sum = self.sum.wrapping_sub(value_hash(value));
+    }
+
+    pub fn fold_store_events<T: Serialize, M>(&mut self, events: &[StoreEvent<u32, T, M>]) {
+        for e in events.iter().skip(self.folded) {
+            match e {
+                StoreEvent::Added { added, .. } => self.add(added),
+                StoreEvent::Removed(removed) => self.sub(removed),
+                StoreEvent::ClearedAll => self.sum = 0,
+                StoreEvent::BulkAddedRemoved { added, removed, .. } => {
+                    added.iter().for_each(|(_, t)| self.add(t));
+                    removed.iter().for_each(|(_, t)| self.sub(t));
+                }
+                StoreEvent::Changed { from_to, removed, .. } => {
+                    for ((_, from), (_, to)) in from_to {
+                        self.sub(from);
+                        self.add(to);
+                    }
+                    removed.iter().for_each(|(_, t)| self.sub(t));
+                }
+            }
+        }
+        self.folded = events.len();
+    }
+
+    pub fn fold_bag_store_events<T: Serialize, M>(&mut self, events: &[BagStoreEvent<u32, T, M>]) {
+        for e in events.iter().skip(self.folded) {
+            match e {
+                BagStoreEvent::Added { added, .. } => self.add(added),
+                BagStoreEvent::AddedVec { added, .. } => added.iter().for_each(|t| self.add(t)),
+                BagStoreEvent::Removed(removed) => self.sub(removed),
+                BagStoreEvent::RemovedVec(removed) => removed.iter().for_each(|t| self.sub(t)),
+                BagStoreEvent::ClearedAll => self.sum = 0,
+                BagStoreEvent::Changed { from_to, removed, .. } => {
+                    for ((_, from), (_, to)) in from_to {
+                        self.sub(from);
+                        self.add(to);
+                    }
+                    removed.iter().for_each(|(_, t)| self.sub(t));
+                }
+                BagStoreEvent::BulkAddedRemoved { added, removed, .. } => {
+                    added.iter().for_each(|(_, t)| self.add(t));
+                    removed.iter().for_each(|(_, t)| self.sub(t));
+                }
+            }
+        }
+        self.folded = events.len();
+    }
+
+    // Call after the events of the store are cleared. Pending events must be folded before that.
+    pub fn events_cleared(&mut self) {
+        self.folded = 0;
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ContentHash {
+    pub notes: RepoHash,
+    pub bars: RepoHash,
+    pub tempos: RepoHash,
+    pub dumpers: RepoHash,
+    pub softs: RepoHash,
+}
+
+impl ContentHash {
+    pub fn events_cleared(&mut self) {
+        self.notes.events_cleared();
+        self.bars.events_cleared();
+        self.tempos.events_cleared();
+        self.dumpers.events_cleared();
+        self.softs.events_cleared();
+    }
+
+    // Header is the hash of the settings other than models.
+    pub fn combine(&self, header: u64) -> u64 {
+        value_hash(&[header, self.notes.value(), self.bars.value(), self.tempos.value(), self.dumpers.value(), self.softs.value()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use klavier_helper::store::Store;
+    use super::{fnv1a, value_hash, RepoHash};
+
+    #[test]
+    fn fnv1a_known_values() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+    }
+
+    #[test]
+    fn fold_store_events() {
+        let mut store: Store<u32, u32, ()> = Store::new(true);
+        let mut hash = RepoHash::default();
+        store.add(0, 10, ());
+        store.add(1, 11, ());
+        store.add(1, 12, ());
+        hash.fold_store_events(store.events());
+        assert_eq!(hash.value(), value_hash(&10u32).wrapping_add(value_hash(&12u32)));
+
+        store.clear_events();
+        hash.events_cleared();
+        store.change(&[(&0, (1, 13))], ());
+        hash.fold_store_events(store.events());
+        hash.fold_store_events(store.events());
+        assert_eq!(hash.value(), value_hash(&13u32));
+
+        store.clear();
+        hash.fold_store_events(store.events());
+        assert_eq!(hash.value(), 0);
+    }
+}
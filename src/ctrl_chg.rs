@@ -1,14 +1,61 @@
+use std::ops::{Range, RangeInclusive};
+
 use crate::channel::Channel;
-use super::{note::TickError, have_start_tick::{HaveBaseStartTick, HaveStartTick}, velocity::Velocity};
+use super::{note::TickError, repeat::AccumTick, tick::Tick, have_start_tick::{HaveBaseStartTick, HaveStartTick}, velocity::{self, Velocity}};
 
 #[derive(serde::Deserialize, serde::Serialize)]
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct CtrlChg {
     pub start_tick: u32,
     pub velocity: Velocity,
     pub channel: Channel,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CtrlChgKind {
+    Dumper,
+    Soft,
+}
+
+// Thinning of dense controller curves on export. The default keeps every event.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct CtrlThinning {
+    pub min_value_delta: u8,
+    pub min_tick_spacing: u32,
+}
+
+impl CtrlThinning {
+    // Points of a single controller sorted by tick. An event is dropped if it is closer than the limits to the
+    // previous kept one. Turning points, fully on/off values and the last event are always kept so that
+    // the value held afterwards does not change.
+    pub fn keep(&self, points: &[(AccumTick, Velocity)]) -> Vec<bool> {
+        let mut kept = vec![false; points.len()];
+        let mut last: Option<(AccumTick, Velocity)> = None;
+        for (i, (tick, value)) in points.iter().enumerate() {
+            let Some((last_tick, last_value)) = last else {
+                kept[i] = true;
+                last = Some((*tick, *value));
+                continue;
+            };
+            if *value == last_value && self.min_value_delta != 0 { continue; }
+            let direction = |from: Velocity, to: Velocity| (to.as_u8() as i16 - from.as_u8() as i16).signum();
+            let turning = match points.get(i + 1) {
+                None => true,
+                Some((_, next)) => direction(*value, *next) != direction(last_value, *value),
+            };
+            let extreme = *value == velocity::MIN || *value == velocity::MAX;
+            let far = self.min_value_delta <= value.as_u8().abs_diff(last_value.as_u8())
+                && AccumTick::from(self.min_tick_spacing) <= tick - last_tick;
+            if turning || extreme || far {
+                kept[i] = true;
+                last = Some((*tick, *value));
+            }
+        }
+        kept
+    }
+}
+
 impl CtrlChg {
     pub fn new(start_tick: u32, velocity: Velocity, channel: Channel) -> Self {
         Self { start_tick, velocity, channel }
@@ -16,23 +63,115 @@ impl CtrlChg {
     
     pub fn drag(&self, tick_delta: i32) -> Self {
         Self {
-             start_tick: (self.start_tick as i64 + tick_delta as i64) as u32,
+             start_tick: Tick::new(self.start_tick).saturating_add_signed(tick_delta as i64).as_u32(),
              ..*self
         }
     }
 
     pub fn with_tick_added(&self, tick_delta: i32) -> Result<Self, TickError> {
-        let tick = self.start_tick as i64 + tick_delta as i64;
-        if tick < 0 {
-            Err(TickError::Minus)
-        } else {
-            Ok(
-                Self {
-                    start_tick: tick as u32,
-                    ..*self
+        let tick = Tick::new(self.start_tick).checked_add_signed(tick_delta as i64)?;
+        Ok(Self { start_tick: tick.as_u32(), ..*self })
+    }
+
+    // Events every step_ticks from the start of range. The end of range always gets an event having 'to' velocity.
+    pub fn ramp(range: RangeInclusive<u32>, from: Velocity, to: Velocity, step_ticks: u32, channel: Channel) -> Vec<CtrlChg> {
+        let (start, end) = (*range.start(), *range.end());
+        if step_ticks == 0 || end < start {
+            return vec![];
+        }
+
+        let velocity_at = |tick: u32| Velocity::interpolate(from, to, tick - start, end - start);
+
+        let mut ramp: Vec<CtrlChg> = (start..end).step_by(step_ticks as usize)
+            .map(|tick| CtrlChg::new(tick, velocity_at(tick), channel))
+            .collect();
+        ramp.push(CtrlChg::new(end, to, channel));
+        ramp
+    }
+
+    // Thin each channel of the events sorted by tick.
+    pub fn thin(events: &[CtrlChg], thinning: CtrlThinning) -> Vec<CtrlChg> {
+        Self::per_channel(events, |points| {
+            let points: Vec<(AccumTick, Velocity)> = points.iter().map(|(t, v)| (AccumTick::from(*t), *v)).collect();
+            thinning.keep(&points)
+        })
+    }
+
+    // Anchors of each channel such that linear interpolation between them stays within the tolerance of
+    // the dropped events. Use ramp() to expand anchors back to a curve.
+    pub fn simplify(events: &[CtrlChg], tolerance: u8) -> Vec<CtrlChg> {
+        Self::per_channel(events, |points| {
+            let mut kept = vec![false; points.len()];
+            if let Some(last) = kept.last_mut() { *last = true; }
+            if let Some(first) = kept.first_mut() { *first = true; }
+            let mut spans = vec![(0, points.len().saturating_sub(1))];
+            while let Some((from, to)) = spans.pop() {
+                if to <= from + 1 { continue; }
+                let (from_tick, from_value) = points[from];
+                let (to_tick, to_value) = points[to];
+                let (idx, err) = (from + 1..to).map(|i| {
+                    let (tick, value) = points[i];
+                    let line = Velocity::interpolate(from_value, to_value, tick - from_tick, to_tick - from_tick);
+                    (i, line.as_u8().abs_diff(value.as_u8()))
+                }).max_by_key(|(i, err)| (*err, std::cmp::Reverse(*i))).unwrap();
+                if tolerance < err {
+                    kept[idx] = true;
+                    spans.push((from, idx));
+                    spans.push((idx, to));
+                }
+            }
+            kept
+        })
+    }
+
+    fn per_channel(events: &[CtrlChg], keep: impl Fn(&[(u32, Velocity)]) -> Vec<bool>) -> Vec<CtrlChg> {
+        let mut channels: Vec<Channel> = events.iter().map(|e| e.channel).collect();
+        channels.sort();
+        channels.dedup();
+        let mut result: Vec<CtrlChg> = channels.into_iter().flat_map(|channel| {
+            let events: Vec<CtrlChg> = events.iter().filter(|e| e.channel == channel).copied().collect();
+            let points: Vec<(u32, Velocity)> = events.iter().map(|e| (e.start_tick, e.velocity)).collect();
+            events.into_iter().zip(keep(&points)).filter(|(_, k)| *k).map(|(e, _)| e).collect::<Vec<_>>()
+        }).collect();
+        result.sort_by_key(|e| e.start_tick);
+        result
+    }
+}
+
+// Pedal values at or above this are down, as MIDI instruments without half pedaling treat them.
+pub const PEDAL_DOWN_THRESHOLD: u8 = 64;
+
+// Span of a pedal held down. The depth is the deepest value while it is down.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PedalSegment {
+    pub down_tick: u32,
+    pub up_tick: u32,
+    pub depth: Velocity,
+}
+
+impl PedalSegment {
+    // Segments in the range, clipped to it. Initial is the value at the start of the range and points are
+    // the changes in the range sorted by tick.
+    pub fn from_points(initial: Velocity, points: &[(u32, Velocity)], range: Range<u32>) -> Vec<PedalSegment> {
+        let mut segments = vec![];
+        let mut down: Option<PedalSegment> = (PEDAL_DOWN_THRESHOLD <= initial.as_u8())
+            .then_some(PedalSegment { down_tick: range.start, up_tick: range.end, depth: initial });
+        for (tick, value) in points.iter().filter(|(tick, _)| range.contains(tick)) {
+            match down.as_mut() {
+                Some(seg) if value.as_u8() < PEDAL_DOWN_THRESHOLD => {
+                    segments.push(PedalSegment { up_tick: *tick, ..*seg });
+                    down = None;
+                }
+                Some(seg) if seg.depth.as_u8() < value.as_u8() => seg.depth = *value,
+                Some(_) => {}
+                None if PEDAL_DOWN_THRESHOLD <= value.as_u8() => {
+                    down = Some(PedalSegment { down_tick: *tick, up_tick: range.end, depth: *value });
                 }
-            )
+                None => {}
+            }
         }
+        segments.extend(down);
+        segments
     }
 }
 
@@ -51,7 +190,8 @@ impl HaveStartTick for CtrlChg {
 #[cfg(test)]
 mod tests {
     use crate::channel::Channel;
-    use crate::ctrl_chg::CtrlChg;
+    use crate::ctrl_chg::{CtrlChg, CtrlThinning, PedalSegment};
+    use crate::velocity::Velocity as V;
     use crate::velocity::Velocity;
     use serde_json::Value;
     use serde_json::json;
@@ -88,4 +228,58 @@ mod tests {
             })
         );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn ramp() {
+        let ch = Channel::default();
+        assert_eq!(
+            CtrlChg::ramp(0..=100, V::new(0), V::new(100), 25, ch),
+            vec![
+                CtrlChg::new(0, V::new(0), ch), CtrlChg::new(25, V::new(25), ch), CtrlChg::new(50, V::new(50), ch),
+                CtrlChg::new(75, V::new(75), ch), CtrlChg::new(100, V::new(100), ch),
+            ]
+        );
+        assert_eq!(
+            CtrlChg::ramp(10..=40, V::new(127), V::new(0), 20, ch),
+            vec![CtrlChg::new(10, V::new(127), ch), CtrlChg::new(30, V::new(42), ch), CtrlChg::new(40, V::new(0), ch)]
+        );
+        assert_eq!(CtrlChg::ramp(10..=10, V::new(0), V::new(64), 20, ch), vec![CtrlChg::new(10, V::new(64), ch)]);
+        assert_eq!(CtrlChg::ramp(0..=100, V::new(0), V::new(64), 0, ch), vec![]);
+    }
+
+    #[test]
+    fn thin() {
+        let ch = Channel::default();
+        let curve: Vec<CtrlChg> = [(0, 0), (10, 5), (20, 10), (30, 30), (40, 35), (50, 20), (60, 19), (70, 127), (80, 126)]
+            .iter().map(|(t, v)| CtrlChg::new(*t, V::new(*v), ch)).collect();
+        assert_eq!(CtrlChg::thin(&curve, CtrlThinning::default()), curve);
+
+        let ticks = |events: Vec<CtrlChg>| events.iter().map(|e| e.start_tick).collect::<Vec<_>>();
+        // 40 and 60 are turning points, 70 is fully on and 80 is the last.
+        assert_eq!(ticks(CtrlChg::thin(&curve, CtrlThinning { min_value_delta: 10, min_tick_spacing: 0 })), vec![0, 20, 30, 40, 50, 60, 70, 80]);
+        assert_eq!(ticks(CtrlChg::thin(&curve, CtrlThinning { min_value_delta: 0, min_tick_spacing: 25 })), vec![0, 30, 40, 60, 70, 80]);
+    }
+
+    #[test]
+    fn simplify() {
+        let ch = Channel::default();
+        let mut curve = CtrlChg::ramp(0..=100, V::new(0), V::new(100), 10, ch);
+        curve.extend(CtrlChg::ramp(110..=200, V::new(90), V::new(0), 10, ch));
+        let anchors = CtrlChg::simplify(&curve, 2);
+        assert_eq!(anchors.iter().map(|e| (e.start_tick, e.velocity.as_u8())).collect::<Vec<_>>(), vec![(0, 0), (100, 100), (200, 0)]);
+        assert_eq!(CtrlChg::simplify(&curve, 0).len(), 3);
+        assert_eq!(CtrlChg::simplify(&curve[..1], 0).len(), 1);
+    }
+
+    #[test]
+    fn pedal_segments() {
+        let points = [(0, V::new(127)), (100, V::new(90)), (200, V::new(0)), (300, V::new(63)), (400, V::new(64))];
+        assert_eq!(PedalSegment::from_points(V::new(0), &points, 0..1000), vec![
+            PedalSegment { down_tick: 0, up_tick: 200, depth: V::new(127) },
+            PedalSegment { down_tick: 400, up_tick: 1000, depth: V::new(64) },
+        ]);
+        assert_eq!(PedalSegment::from_points(V::new(90), &points[1..3], 150..500), vec![
+            PedalSegment { down_tick: 150, up_tick: 200, depth: V::new(90) },
+        ]);
+    }
+}
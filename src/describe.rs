@@ -0,0 +1,98 @@
+// Verbal names for screen readers.
+use crate::{duration::Duration, key::Key, pitch::Pitch, sharp_flat::SharpFlat, solfa::Solfa, velocity::Dynamic};
+
+pub fn solfa_name(solfa: Solfa) -> &'static str {
+    match solfa {
+        Solfa::C => "C",
+        Solfa::D => "D",
+        Solfa::E => "E",
+        Solfa::F => "F",
+        Solfa::G => "G",
+        Solfa::A => "A",
+        Solfa::B => "B",
+    }
+}
+
+pub fn sharp_flat_name(sharp_flat: SharpFlat) -> Option<&'static str> {
+    match sharp_flat {
+        SharpFlat::Sharp => Some("sharp"),
+        SharpFlat::DoubleSharp => Some("double sharp"),
+        SharpFlat::Flat => Some("flat"),
+        SharpFlat::DoubleFlat => Some("double flat"),
+        SharpFlat::Natural => Some("natural"),
+        SharpFlat::Null => None,
+    }
+}
+
+// "C sharp 4", the spoken form of Pitch's Display (scientific pitch notation). Apply the key beforehand to name
+// the sounding accidental.
+pub fn pitch_name(pitch: Pitch) -> String {
+    match sharp_flat_name(pitch.sharp_flat()) {
+        Some(sf) => format!("{} {} {}", solfa_name(pitch.solfa()), sf, pitch.scientific_octave()),
+        None => format!("{} {}", solfa_name(pitch.solfa()), pitch.scientific_octave()),
+    }
+}
+
+// "dotted eighth note", "quarter note triplet".
+pub fn duration_name(duration: Duration) -> String {
+    duration.note_name()
+}
+
+pub fn dynamic_name(dynamic: Dynamic) -> &'static str {
+    match dynamic {
+        Dynamic::PP => "pianissimo",
+        Dynamic::P => "piano",
+        Dynamic::MP => "mezzo piano",
+        Dynamic::MF => "mezzo forte",
+        Dynamic::F => "forte",
+        Dynamic::FF => "fortissimo",
+    }
+}
+
+pub fn key_name(key: Key) -> String {
+    let count = key.offset().unsigned_abs();
+    let plural = if count == 1 { "" } else { "s" };
+    if key.is_sharp() {
+        format!("{} sharp{}", count, plural)
+    } else if key.is_flat() {
+        format!("{} flat{}", count, plural)
+    } else {
+        "no sharps or flats".to_owned()
+    }
+}
+
+// Beats are counted from 1. Off beat positions have up to two decimals, such as 2.5. The fraction is truncated
+// so that a position just before a beat is not named after it.
+pub fn beat_name(offset_in_bar: u32, beat_tick_len: u32) -> String {
+    let whole = offset_in_bar / beat_tick_len + 1;
+    let hundredths = (offset_in_bar % beat_tick_len) as u64 * 100 / beat_tick_len as u64;
+    if hundredths == 0 {
+        whole.to_string()
+    } else {
+        let fraction = format!("{:02}", hundredths);
+        format!("{}.{}", whole, fraction.trim_end_matches('0'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{duration::{Denominator, Dots, Duration, Numerator}, key::Key, octave::Octave, pitch::Pitch, sharp_flat::SharpFlat, solfa::Solfa};
+    use super::{beat_name, duration_name, key_name, pitch_name};
+
+    #[test]
+    fn names() {
+        assert_eq!(pitch_name(Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Sharp)), "C sharp 5");
+        assert_eq!(pitch_name(Pitch::new(Solfa::A, Octave::Oct3, SharpFlat::Null)), "A 4");
+        assert_eq!(pitch_name("Bb3".parse().unwrap()), "B flat 3");
+        assert_eq!(duration_name(Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ONE)), "dotted eighth note");
+        assert_eq!(duration_name(Duration::new(Numerator::Quarter, Denominator::from_value(3).unwrap(), Dots::ZERO)), "quarter note triplet");
+        assert_eq!(key_name(Key::FLAT_1), "1 flat");
+        assert_eq!(key_name(Key::SHARP_3), "3 sharps");
+        assert_eq!(beat_name(480, 240), "3");
+        assert_eq!(beat_name(600, 240), "3.5");
+        assert_eq!(beat_name(80, 240), "1.33");
+        assert_eq!(beat_name(252, 240), "2.05");
+        assert_eq!(beat_name(239, 240), "1.99");
+        assert_eq!(beat_name(1, 240), "1");
+    }
+}
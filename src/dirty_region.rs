@@ -0,0 +1,91 @@
+use std::ops::Range;
+
+use klavier_helper::{bag_store::BagStoreEvent, store::StoreEvent};
+
+// Tick ranges touched by model events per repo. Ranges are sorted and do not overlap or touch each other.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DirtyRegions {
+    pub notes: Vec<Range<u32>>,
+    pub bars: Vec<Range<u32>>,
+    pub tempos: Vec<Range<u32>>,
+    pub dumpers: Vec<Range<u32>>,
+    pub softs: Vec<Range<u32>>,
+}
+
+impl DirtyRegions {
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty() && self.bars.is_empty() && self.tempos.is_empty() && self.dumpers.is_empty() && self.softs.is_empty()
+    }
+
+    // All repos in one list.
+    pub fn merged(&self) -> Vec<Range<u32>> {
+        coalesce(
+            self.notes.iter().chain(self.bars.iter()).chain(self.tempos.iter()).chain(self.dumpers.iter()).chain(self.softs.iter()).cloned().collect()
+        )
+    }
+}
+
+pub fn coalesce(mut ranges: Vec<Range<u32>>) -> Vec<Range<u32>> {
+    ranges.retain(|r| !r.is_empty());
+    ranges.sort_by_key(|r| r.start);
+    let mut result: Vec<Range<u32>> = Vec::with_capacity(ranges.len());
+    for r in ranges {
+        match result.last_mut() {
+            Some(last) if r.start <= last.end => last.end = last.end.max(r.end),
+            _ => result.push(r),
+        }
+    }
+    result
+}
+
+// Span is the tick range occupied by an element.
+pub fn from_store_events<T, M>(events: &[StoreEvent<u32, T, M>], span: impl Fn(&T) -> Range<u32>) -> Vec<Range<u32>> {
+    let mut ranges = vec![];
+    for e in events {
+        match e {
+            StoreEvent::Added { added, .. } => ranges.push(span(added)),
+            StoreEvent::Removed(removed) => ranges.push(span(removed)),
+            StoreEvent::ClearedAll => return std::iter::once(0..u32::MAX).collect(),
+            StoreEvent::BulkAddedRemoved { added, removed, .. } => {
+                ranges.extend(added.iter().chain(removed.iter()).map(|(_, t)| span(t)));
+            }
+            StoreEvent::Changed { from_to, removed, .. } => {
+                ranges.extend(from_to.iter().flat_map(|((_, from), (_, to))| [span(from), span(to)]));
+                ranges.extend(removed.iter().map(|(_, t)| span(t)));
+            }
+        }
+    }
+    coalesce(ranges)
+}
+
+pub fn from_bag_store_events<T, M>(events: &[BagStoreEvent<u32, T, M>], span: impl Fn(&T) -> Range<u32>) -> Vec<Range<u32>> {
+    let mut ranges = vec![];
+    for e in events {
+        match e {
+            BagStoreEvent::Added { added, .. } => ranges.push(span(added)),
+            BagStoreEvent::AddedVec { added, .. } => ranges.extend(added.iter().map(&span)),
+            BagStoreEvent::Removed(removed) => ranges.push(span(removed)),
+            BagStoreEvent::RemovedVec(removed) => ranges.extend(removed.iter().map(&span)),
+            BagStoreEvent::ClearedAll => return std::iter::once(0..u32::MAX).collect(),
+            BagStoreEvent::Changed { from_to, removed, .. } => {
+                ranges.extend(from_to.iter().flat_map(|((_, from), (_, to))| [span(from), span(to)]));
+                ranges.extend(removed.iter().map(|(_, t)| span(t)));
+            }
+            BagStoreEvent::BulkAddedRemoved { added, removed, .. } => {
+                ranges.extend(added.iter().chain(removed.iter()).map(|(_, t)| span(t)));
+            }
+        }
+    }
+    coalesce(ranges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::coalesce;
+
+    #[test]
+    fn can_coalesce() {
+        assert_eq!(coalesce(vec![480..720, 0..240, 240..300, 500..600, 10..10, 1000..1001]), vec![0..300, 480..720, 1000..1001]);
+        assert!(coalesce(vec![]).is_empty());
+    }
+}
@@ -0,0 +1,99 @@
+use crate::models::{ModelChanges, Models};
+
+// Selection being dragged. Updates only change the preview. The project turns the session into a single
+// change on commit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DragSession {
+    origin: Models,
+    preview: Models,
+    delta_tick: i32,
+    delta_pitch: i32,
+}
+
+impl DragSession {
+    pub fn new(selection: Models) -> Self {
+        Self {
+            preview: selection.clone(),
+            origin: selection,
+            delta_tick: 0,
+            delta_pitch: 0,
+        }
+    }
+
+    pub fn origin(&self) -> &Models {
+        &self.origin
+    }
+
+    pub fn preview(&self) -> &Models {
+        &self.preview
+    }
+
+    // Score offset delta for notes. Other models only move in tick.
+    pub fn delta(&self) -> (i32, i32) {
+        (self.delta_tick, self.delta_pitch)
+    }
+
+    // Deltas are relative to the origin. False (and the preview is kept) if something would go before tick zero
+    // or out of the pitch range.
+    pub fn update(&mut self, delta_tick: i32, delta_pitch: i32) -> bool {
+        let notes = self.origin.notes.iter().map(|n| {
+            n.pitch.with_score_offset_delta(delta_pitch).ok()?;
+            n.with_tick_added(delta_tick, false).ok().map(|n| n.drag(0, delta_pitch))
+        }).collect::<Option<Vec<_>>>();
+        let bars = self.origin.bars.iter().map(|b| b.with_tick_added(delta_tick).ok()).collect::<Option<Vec<_>>>();
+        let tempos = self.origin.tempos.iter().map(|t| t.with_tick_added(delta_tick).ok()).collect::<Option<Vec<_>>>();
+        let dumpers = self.origin.dumpers.iter().map(|d| d.with_tick_added(delta_tick).ok()).collect::<Option<Vec<_>>>();
+        let softs = self.origin.softs.iter().map(|s| s.with_tick_added(delta_tick).ok()).collect::<Option<Vec<_>>>();
+
+        match (notes, bars, tempos, dumpers, softs) {
+            (Some(notes), Some(bars), Some(tempos), Some(dumpers), Some(softs)) => {
+                self.preview = Models { notes, bars, tempos, dumpers, softs };
+                self.delta_tick = delta_tick;
+                self.delta_pitch = delta_pitch;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_moved(&self) -> bool {
+        self.delta_tick != 0 || self.delta_pitch != 0
+    }
+
+    pub fn to_changes(&self) -> ModelChanges {
+        ModelChanges::empty()
+            .with_notes(self.origin.notes.iter().cloned().zip(self.preview.notes.iter().cloned()).collect())
+            .with_bars(self.origin.bars.iter().copied().zip(self.preview.bars.iter().copied()).collect())
+            .with_tempos(self.origin.tempos.iter().copied().zip(self.preview.tempos.iter().copied()).collect())
+            .with_dumpers(self.origin.dumpers.iter().copied().zip(self.preview.dumpers.iter().copied()).collect())
+            .with_softs(self.origin.softs.iter().copied().zip(self.preview.softs.iter().copied()).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{models::Models, note::Note, ctrl_chg::CtrlChg, velocity::Velocity, channel::Channel, pitch};
+    use super::DragSession;
+
+    #[test]
+    fn update() {
+        let selection = Models {
+            notes: vec![Note { base_start_tick: 240, ..Default::default() }],
+            dumpers: vec![CtrlChg::new(480, Velocity::new(127), Channel::default())],
+            ..Models::empty()
+        };
+        let mut drag = DragSession::new(selection.clone());
+        assert!(!drag.is_moved());
+
+        assert!(drag.update(-240, 2));
+        assert_eq!(drag.preview().notes[0].base_start_tick, 0);
+        assert_eq!(drag.preview().notes[0].pitch, Note::default().pitch.with_score_offset_delta(2).unwrap());
+        assert_eq!(drag.preview().dumpers[0].start_tick, 240);
+        assert_eq!(drag.origin(), &selection);
+
+        assert!(!drag.update(-241, 0));
+        assert!(!drag.update(0, pitch::MAX_SCORE_OFFSET));
+        assert_eq!(drag.delta(), (-240, 2));
+        assert_eq!(drag.to_changes().notes.len(), 1);
+    }
+}
@@ -1,7 +1,8 @@
-use std::hash::{Hash};
+use std::{hash::Hash, fmt, str::FromStr};
 
 #[derive(serde::Deserialize, serde::Serialize)]
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum Numerator {
     Whole,
     Half,
@@ -46,11 +47,81 @@ impl Numerator {
             _ => None,
         }
     }
+
+    // 1 for whole note, 2 for half note, ... 128 for 128th note.
+    pub const fn note_value(self) -> u8 {
+        1 << self.ord()
+    }
+
+    pub const fn from_note_value(value: u8) -> Option<Numerator> {
+        if value.is_power_of_two() {
+            Self::from_ord(value.trailing_zeros() as u8)
+        } else {
+            None
+        }
+    }
+
+    // "quarter", "eighth".
+    pub const fn name(self) -> &'static str {
+        match self {
+            Numerator::Whole => "whole",
+            Numerator::Half => "half",
+            Numerator::Quarter => "quarter",
+            Numerator::N8th => "eighth",
+            Numerator::N16th => "sixteenth",
+            Numerator::N32nd => "thirty-second",
+            Numerator::N64th => "sixty-fourth",
+            Numerator::N128th => "hundred twenty-eighth",
+        }
+    }
+
+    // Value of the MusicXML type element.
+    pub const fn musicxml_type(self) -> &'static str {
+        match self {
+            Numerator::Whole => "whole",
+            Numerator::Half => "half",
+            Numerator::Quarter => "quarter",
+            Numerator::N8th => "eighth",
+            Numerator::N16th => "16th",
+            Numerator::N32nd => "32nd",
+            Numerator::N64th => "64th",
+            Numerator::N128th => "128th",
+        }
+    }
+
+    // SMuFL precomposed note with the stem up (noteWhole, noteHalfUp, ...).
+    pub const fn smufl_glyph(self) -> char {
+        match self {
+            Numerator::Whole => '\u{E1D2}',
+            Numerator::Half => '\u{E1D3}',
+            Numerator::Quarter => '\u{E1D5}',
+            Numerator::N8th => '\u{E1D7}',
+            Numerator::N16th => '\u{E1D9}',
+            Numerator::N32nd => '\u{E1DB}',
+            Numerator::N64th => '\u{E1DD}',
+            Numerator::N128th => '\u{E1DF}',
+        }
+    }
+
+    // SMuFL rest (restWhole, restHalf, ...).
+    pub const fn smufl_rest_glyph(self) -> char {
+        match self {
+            Numerator::Whole => '\u{E4E3}',
+            Numerator::Half => '\u{E4E4}',
+            Numerator::Quarter => '\u{E4E5}',
+            Numerator::N8th => '\u{E4E6}',
+            Numerator::N16th => '\u{E4E7}',
+            Numerator::N32nd => '\u{E4E8}',
+            Numerator::N64th => '\u{E4E9}',
+            Numerator::N128th => '\u{E4EA}',
+        }
+    }
 }
 
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(from="SerializedDenominator")]
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Denominator(u8);
 
 impl Default for Denominator {
@@ -86,10 +157,65 @@ impl Denominator {
             Some(Denominator(value))
         }
     }
+
+    // Count of notes in a group, such as the ratio of notes computed by tuplize().
+    pub fn from_count(count: usize) -> Result<Denominator, DenominatorError> {
+        u8::try_from(count).ok().and_then(Denominator::from_value).ok_or(DenominatorError::OutOfRange(count))
+    }
+
+    // Every value from_value() accepts, smallest first.
+    pub fn supported() -> impl Iterator<Item = Denominator> {
+        (Duration::MIN_DENOMINATOR..=Duration::MAX_DENOMINATOR).map(Denominator)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DenominatorError {
+    OutOfRange(usize),
+}
+
+impl fmt::Display for DenominatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DenominatorError::OutOfRange(count) => write!(
+                f, "Denominator {} is out of range ({}-{})", count, Duration::MIN_DENOMINATOR, Duration::MAX_DENOMINATOR
+            ),
+        }
+    }
 }
 
+impl std::error::Error for DenominatorError {}
+
+// Count of plain notes the group of the denominator takes the time of. The default 2 makes a triplet 3:2.
+// 5:4 and 7:8 tuplets need a larger count.
 #[derive(serde::Deserialize, serde::Serialize)]
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct NormalNotes(u8);
+
+impl Default for NormalNotes {
+    fn default() -> Self {
+        Self(2)
+    }
+}
+
+impl NormalNotes {
+    pub const fn value(self) -> u8 {
+        self.0
+    }
+
+    pub const fn from_value(value: u8) -> Option<NormalNotes> {
+        if value == 0 {
+            None
+        } else {
+            Some(NormalNotes(value))
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Dots(u8);
 
 impl Default for Dots {
@@ -119,6 +245,9 @@ impl Dots {
             Some(Dots(value))
         }
     }
+
+    // SMuFL augmentationDot, to be repeated by the number of dots.
+    pub const SMUFL_GLYPH: char = '\u{E1E7}';
 }
 
 // numerator
@@ -129,12 +258,18 @@ impl Dots {
 //
 // dot
 // 0-7
+//
+// normal
+// 2:Normal, 4:5:4 tuplets, 8:7:8 tuplets, ...
 #[derive(serde::Deserialize, serde::Serialize)]
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Duration {
     pub numerator: Numerator,
     pub denominator: Denominator,
     pub dots: Dots,
+    #[serde(default)]
+    pub normal: NormalNotes,
 }
 
 impl Default for Duration {
@@ -142,7 +277,8 @@ impl Default for Duration {
         Self {
             numerator: Default::default(),
             denominator: Default::default(),
-            dots: Default::default()
+            dots: Default::default(),
+            normal: Default::default(),
         }
     }
 }
@@ -156,7 +292,14 @@ impl Duration {
     pub const MAX_NUMERATOR: u8 = 7;
 
     pub fn new(numerator: Numerator, denominator: Denominator, dots: Dots) -> Duration {
-        Self { numerator, denominator, dots }
+        Self { numerator, denominator, dots, normal: NormalNotes::default() }
+    }
+
+    // Note of the group of 'actual' notes in the time of 'normal' ones, such as 5:4.
+    pub fn tuplet(numerator: Numerator, dots: Dots, actual: usize, normal: usize) -> Result<Duration, DenominatorError> {
+        let denominator = Denominator::from_count(actual)?;
+        let normal = u8::try_from(normal).ok().and_then(NormalNotes::from_value).ok_or(DenominatorError::OutOfRange(normal))?;
+        Ok(Self { numerator, denominator, dots, normal })
     }
 
     pub const fn tick_length(self) -> u32 {
@@ -168,35 +311,156 @@ impl Duration {
                 (Duration::TICK_RESOLUTION >> (numerator - 2)) as u32
             };
 
-        if self.dots.value() == 0 && self.denominator.value() == 2 {
+        if self.dots.value() == 0 && self.denominator.value() == self.normal.value() {
             len
         } else {
-            ((len + (len - (len >> self.dots.value()))) as i64 * 2 / (self.denominator.value() as i64)) as u32
+            ((len + (len - (len >> self.dots.value()))) as i64 * self.normal.value() as i64 / (self.denominator.value() as i64)) as u32
         }
     }
 
     pub fn with_numerator(self, numerator: Numerator) -> Duration {
-        Self::new(numerator, self.denominator, self.dots)
+        Self { numerator, ..self }
     }
 
     pub fn with_denominator(self, denominator: Denominator) -> Duration {
-        Self::new(self.numerator, denominator, self.dots)
+        Self { denominator, ..self }
     }
 
     pub fn with_dots(self, dots: Dots) -> Duration {
-        Self::new(self.numerator, self.denominator, dots)
+        Self { dots, ..self }
+    }
+
+    pub fn with_normal(self, normal: NormalNotes) -> Duration {
+        Self { normal, ..self }
     }
 
     pub fn min(self, other: Self) -> Self {
         if self.tick_length() < other.tick_length() { self } else { other }
     }
+
+    // "dotted eighth", "quarter triplet".
+    pub fn name(self) -> String {
+        self.describe("")
+    }
+
+    // "dotted eighth note", "quarter note triplet".
+    pub fn note_name(self) -> String {
+        self.describe(" note")
+    }
+
+    // "dotted eighth rest", "quarter rest triplet".
+    pub fn rest_name(self) -> String {
+        self.describe(" rest")
+    }
+
+    fn describe(self, noun: &str) -> String {
+        let dots = match self.dots.value() {
+            0 => "",
+            1 => "dotted ",
+            2 => "double dotted ",
+            _ => "triple dotted ",
+        };
+        let tuplet = match (self.denominator.value(), self.normal.value()) {
+            (d, n) if d == n => String::new(),
+            (3, 2) => " triplet".to_owned(),
+            (d, 2) => format!(" {}-tuplet", d),
+            (d, n) => format!(" {}:{} tuplet", d, n),
+        };
+        format!("{}{}{}{}", dots, self.numerator.name(), noun, tuplet)
+    }
+
+    // Single duration that is exactly the ticks long. Fewer dots are preferred.
+    pub fn from_tick_length(ticks: u32, denominator: Denominator) -> Option<Duration> {
+        (0..=Self::MAX_DOT).flat_map(|dots|
+            (0..=Self::MAX_NUMERATOR).map(move |ord|
+                Duration::new(Numerator::from_ord(ord).unwrap(), denominator, Dots::from_value(dots).unwrap())
+            )
+        ).find(|d| d.tick_length() == ticks)
+    }
+}
+
+// Durations offered when rounding performed lengths, simplest first: plain, dotted, double dotted and,
+// if allowed, plain triplets.
+fn notatable_durations(allow_tuplets: bool) -> impl Iterator<Item = Duration> {
+    let plain = (0..=2).flat_map(|dots| (0..=Duration::MAX_NUMERATOR).map(move |ord| (ord, 2, dots)));
+    let triplets = (0..=Duration::MAX_NUMERATOR).map(|ord| (ord, 3, 0)).filter(move |_| allow_tuplets);
+    plain.chain(triplets).map(|(ord, denominator, dots)|
+        Duration::new(Numerator::from_ord(ord).unwrap(), Denominator::from_value(denominator).unwrap(), Dots::from_value(dots).unwrap())
+    )
+}
+
+// Duration closest to the tick length for import and recording. Ties go to the simpler duration.
+pub fn nearest_notatable(ticks: u32, allow_tuplets: bool) -> Duration {
+    notatable_durations(allow_tuplets).enumerate()
+        .min_by_key(|(i, d)| (d.tick_length().abs_diff(ticks), *i))
+        .map(|(_, d)| d)
+        .unwrap()
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DurationParseError {
+    CannotParse(String),
+    InvalidNumerator(String),
+    InvalidDenominator(String),
+    TooManyDots(String),
+}
+
+// "4" is a quarter note, "8." is a dotted 8th note, "8/3" is an 8th note of triplets and "16/5:4" is a 16th note
+// of 5:4 tuplets.
+impl FromStr for Duration {
+    type Err = DurationParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (body, denominator, normal) = match s.split_once('/') {
+            Some((body, d)) => {
+                let (d, n) = match d.split_once(':') {
+                    Some((d, n)) => (d, Some(n)),
+                    None => (d, None),
+                };
+                let d = u8::from_str(d.trim()).ok().and_then(Denominator::from_value)
+                    .ok_or_else(|| DurationParseError::InvalidDenominator(s.to_owned()))?;
+                let n = match n {
+                    Some(n) => u8::from_str(n.trim()).ok().and_then(NormalNotes::from_value)
+                        .ok_or_else(|| DurationParseError::InvalidDenominator(s.to_owned()))?,
+                    None => NormalNotes::default(),
+                };
+                (body.trim_end(), d, n)
+            },
+            None => (s, Denominator::default(), NormalNotes::default()),
+        };
+
+        let value = body.trim_end_matches('.');
+        if value.is_empty() {
+            return Err(DurationParseError::CannotParse(s.to_owned()));
+        }
+        let numerator = u8::from_str(value).ok().and_then(Numerator::from_note_value)
+            .ok_or_else(|| DurationParseError::InvalidNumerator(s.to_owned()))?;
+        let dots = Dots::from_value((body.len() - value.len()).min(u8::MAX as usize) as u8)
+            .ok_or_else(|| DurationParseError::TooManyDots(s.to_owned()))?;
+
+        Ok(Duration::new(numerator, denominator, dots).with_normal(normal))
+    }
+}
+
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.numerator.note_value(), ".".repeat(self.dots.value() as usize))?;
+        if self.denominator != Denominator::default() || self.normal != NormalNotes::default() {
+            write!(f, "/{}", self.denominator.value())?;
+        }
+        if self.normal != NormalNotes::default() {
+            write!(f, ":{}", self.normal.value())?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::duration::Duration;
+    use crate::duration::{nearest_notatable, Duration, DurationParseError};
 
-    use super::{Numerator, Denominator, Dots};
+    use super::{Numerator, Denominator, DenominatorError, Dots, NormalNotes};
 
     #[test]
     #[should_panic]
@@ -218,6 +482,34 @@ mod tests {
         assert_eq!(d.dots.value(), 3);
     }
 
+    #[test]
+    fn from_tick_length() {
+        let d2 = Denominator::from_value(2).unwrap();
+        assert_eq!(Duration::from_tick_length(240, d2), Some(Duration::new(Numerator::Quarter, d2, Dots::ZERO)));
+        assert_eq!(Duration::from_tick_length(420, d2), Some(Duration::new(Numerator::Quarter, d2, Dots::TWO)));
+        assert_eq!(Duration::from_tick_length(300, d2), None);
+        let d3 = Denominator::from_value(3).unwrap();
+        assert_eq!(Duration::from_tick_length(80, d3), Some(Duration::new(Numerator::N8th, d3, Dots::ZERO)));
+    }
+
+    #[test]
+    fn nearest_notatable_durations() {
+        let d2 = Denominator::from_value(2).unwrap();
+        let d3 = Denominator::from_value(3).unwrap();
+        assert_eq!(nearest_notatable(240, false), Duration::new(Numerator::Quarter, d2, Dots::ZERO));
+        assert_eq!(nearest_notatable(250, true), Duration::new(Numerator::Quarter, d2, Dots::ZERO));
+        assert_eq!(nearest_notatable(355, false), Duration::new(Numerator::Quarter, d2, Dots::ONE));
+        assert_eq!(nearest_notatable(161, false), Duration::new(Numerator::N8th, d2, Dots::ONE));
+        assert_eq!(nearest_notatable(161, true), Duration::new(Numerator::Quarter, d3, Dots::ZERO));
+        // 7/480 of a beat is 3.5 ticks.
+        assert_eq!(nearest_notatable(7 * 240 / 480, false), Duration::new(Numerator::N128th, d2, Dots::ZERO));
+        assert_eq!(nearest_notatable(7 * 240 / 480, true), Duration::new(Numerator::N128th, d3, Dots::ZERO));
+        // Same distance to a 16th (60) and a dotted 16th (90). The plain one wins.
+        assert_eq!(nearest_notatable(75, false), Duration::new(Numerator::N16th, d2, Dots::ZERO));
+        assert_eq!(nearest_notatable(0, false), Duration::new(Numerator::N128th, d2, Dots::ZERO));
+        assert_eq!(nearest_notatable(5000, true), Duration::new(Numerator::Whole, d2, Dots::TWO));
+    }
+
     #[test]
     fn tick_length() {
         assert_eq!(Duration::new(Numerator::Quarter, Denominator::from_value(2).unwrap(), Dots::ZERO).tick_length(), 240);
@@ -225,4 +517,59 @@ mod tests {
         assert_eq!(Duration::new(Numerator::Quarter, Denominator::from_value(3).unwrap(), Dots::ZERO).tick_length(), 160);
         assert_eq!(Duration::new(Numerator::Quarter, Denominator::from_value(2).unwrap(), Dots::from_value(1).unwrap()).tick_length(), 360);
     }
+
+    #[test]
+    fn tuplets() {
+        let quintuplet = Duration::tuplet(Numerator::N16th, Dots::ZERO, 5, 4).unwrap();
+        assert_eq!(quintuplet.tick_length(), 48);
+        assert_eq!(Duration::tuplet(Numerator::N32nd, Dots::ZERO, 7, 8).unwrap().tick_length(), 30 * 8 / 7);
+        assert_eq!(Duration::tuplet(Numerator::Quarter, Dots::ZERO, 3, 2).unwrap(), "4/3".parse().unwrap());
+        assert_eq!(Duration::tuplet(Numerator::Quarter, Dots::ZERO, 4, 4).unwrap().tick_length(), 240);
+        assert_eq!(Duration::tuplet(Numerator::Quarter, Dots::ZERO, 256, 4), Err(DenominatorError::OutOfRange(256)));
+        assert_eq!(Duration::tuplet(Numerator::Quarter, Dots::ZERO, 5, 0), Err(DenominatorError::OutOfRange(0)));
+        assert_eq!(quintuplet.note_name(), "sixteenth note 5:4 tuplet");
+        assert_eq!("16/5:4".parse::<Duration>().unwrap(), quintuplet);
+        assert_eq!(quintuplet.with_normal(NormalNotes::from_value(2).unwrap()).tick_length(), 24);
+
+        assert_eq!(Denominator::from_count(1), Err(DenominatorError::OutOfRange(1)));
+        assert_eq!(Denominator::from_count(300), Err(DenominatorError::OutOfRange(300)));
+        assert_eq!(Denominator::supported().count(), 254);
+        assert!(Denominator::supported().all(|d| Denominator::from_value(d.value()) == Some(d)));
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("4".parse::<Duration>().unwrap(), Duration::new(Numerator::Quarter, Denominator::from_value(2).unwrap(), Dots::ZERO));
+        assert_eq!("8.".parse::<Duration>().unwrap(), Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ONE));
+        assert_eq!("1..".parse::<Duration>().unwrap(), Duration::new(Numerator::Whole, Denominator::from_value(2).unwrap(), Dots::TWO));
+        assert_eq!("16/3".parse::<Duration>().unwrap(), Duration::new(Numerator::N16th, Denominator::from_value(3).unwrap(), Dots::ZERO));
+        assert_eq!("128".parse::<Duration>().unwrap().numerator, Numerator::N128th);
+    }
+
+    #[test]
+    fn parse_error() {
+        assert_eq!("".parse::<Duration>(), Err(DurationParseError::CannotParse("".to_owned())));
+        assert_eq!("3".parse::<Duration>(), Err(DurationParseError::InvalidNumerator("3".to_owned())));
+        assert_eq!("256".parse::<Duration>(), Err(DurationParseError::InvalidNumerator("256".to_owned())));
+        assert_eq!("4/1".parse::<Duration>(), Err(DurationParseError::InvalidDenominator("4/1".to_owned())));
+        assert_eq!("4........".parse::<Duration>(), Err(DurationParseError::TooManyDots("4........".to_owned())));
+    }
+
+    #[test]
+    fn names() {
+        let d2 = Denominator::from_value(2).unwrap();
+        assert_eq!(Duration::new(Numerator::N8th, d2, Dots::ONE).name(), "dotted eighth");
+        assert_eq!(Duration::new(Numerator::Half, d2, Dots::TWO).rest_name(), "double dotted half rest");
+        assert_eq!(Duration::new(Numerator::Quarter, Denominator::from_value(5).unwrap(), Dots::ZERO).note_name(), "quarter note 5-tuplet");
+        assert_eq!(Numerator::Quarter.smufl_glyph(), '\u{E1D5}');
+        assert_eq!(Numerator::N16th.smufl_rest_glyph(), '\u{E4E7}');
+        assert_eq!(Numerator::N16th.musicxml_type(), "16th");
+    }
+
+    #[test]
+    fn to_string() {
+        for s in ["1", "2.", "4", "8..", "16/3", "32./5", "128", "16/5:4", "8/2:3"] {
+            assert_eq!(s.parse::<Duration>().unwrap().to_string(), s);
+        }
+    }
 }
@@ -0,0 +1,161 @@
+use std::{ops::Range, rc::Rc};
+
+use crate::{bar::Bar, channel::Channel, comment::Comment, ctrl_chg::CtrlChg, grid::Grid, instrument::Instrument, key::Key, models::{ModelChanges, Models}, note::Note, percent::PercentU16, project::{BarContentPolicy, ModelChangeMetadata, PasteOptions, Project, TieRemovalPolicy}, rehearsal::RehearsalMark, rhythm::Rhythm, tempo::Tempo};
+
+// A Project call with its parameters. Notes are held by value and looked up in the project on replay.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditStep {
+    SetRhythm(Rhythm),
+    SetKey(Key),
+    SetGrid(Grid),
+    AddNote { note: Note, select: bool },
+    AddBar { bar: Bar, select: bool },
+    RemoveBar { bar: Bar, policy: BarContentPolicy },
+    AddTempo { tempo: Tempo, select: bool },
+    AddDumper { dumper: CtrlChg, select: bool },
+    AddSoft { soft: CtrlChg, select: bool },
+    BulkAdd { models: Models, select: bool },
+    BulkRemove(Models),
+    ChangeNotes(Vec<(Note, Note)>),
+    SetMuted { notes: Vec<Note>, muted: bool },
+    RemoveNotes { notes: Vec<Note>, policy: TieRemovalPolicy },
+    ScaleTempo { range: Range<u32>, factor: PercentU16 },
+    Paste { models: Models, tick: u32, options: PasteOptions },
+    AddComment(Comment),
+    AddRehearsalMark(RehearsalMark),
+    RemoveRehearsalMark(usize),
+    SetInstrument { channel: Channel, instrument: Option<Instrument> },
+}
+
+// Notes of the project equal to the given ones. Each project note is taken at most once, so that stacked duplicates
+// given twice resolve to two distinct notes. Those not found are left out.
+fn find_notes<P: Project>(proj: &P, notes: &[Note]) -> Vec<Rc<Note>> {
+    let mut found: Vec<Rc<Note>> = Vec::with_capacity(notes.len());
+    for note in notes {
+        let tick = note.start_tick();
+        let hit = proj.note_repo().range(tick..tick + 1)
+            .find(|(_, n)| ***n == *note && !found.iter().any(|f| Rc::ptr_eq(f, n)))
+            .map(|(_, n)| n.clone());
+        found.extend(hit);
+    }
+    found
+}
+
+impl EditStep {
+    pub fn apply<P: Project>(&self, proj: &mut P) {
+        let select = |select: bool| ModelChangeMetadata::new().with_need_select(select);
+        match self.clone() {
+            EditStep::SetRhythm(rhythm) => proj.set_rhythm(rhythm),
+            EditStep::SetKey(key) => proj.set_key(key),
+            EditStep::SetGrid(grid) => proj.set_grid(grid),
+            EditStep::AddNote { note, select } => proj.add_note(note, select),
+            EditStep::AddBar { bar, select } => proj.add_bar(bar, select),
+            EditStep::RemoveBar { bar, policy } => proj.remove_bar(bar, policy),
+            EditStep::AddTempo { tempo, select } => proj.add_tempo(tempo, select),
+            EditStep::AddDumper { dumper, select } => proj.add_dumper(dumper, select),
+            EditStep::AddSoft { soft, select } => proj.add_soft(soft, select),
+            EditStep::BulkAdd { models, select: s } => proj.bulk_add(models, select(s)),
+            EditStep::BulkRemove(models) => proj.bulk_remove(models, ModelChangeMetadata::new()),
+            EditStep::ChangeNotes(notes) => proj.change(ModelChanges::empty().with_notes(notes), ModelChangeMetadata::new()),
+            EditStep::SetMuted { notes, muted } => {
+                let notes = find_notes(proj, &notes);
+                proj.set_muted(&notes, muted);
+            }
+            EditStep::RemoveNotes { notes, policy } => {
+                let notes = find_notes(proj, &notes);
+                proj.remove_notes_tie_aware(&notes, policy);
+            }
+            EditStep::ScaleTempo { range, factor } => proj.scale_tempo(range, factor),
+            EditStep::Paste { models, tick, options } => { proj.paste(models, tick, options); }
+            EditStep::AddComment(comment) => proj.add_comment(comment),
+            EditStep::AddRehearsalMark(mark) => proj.add_rehearsal_mark(mark),
+            EditStep::RemoveRehearsalMark(bar_no) => proj.remove_rehearsal_mark(bar_no),
+            EditStep::SetInstrument { channel, instrument } => proj.set_instrument(channel, instrument),
+        }
+    }
+}
+
+// Recorded edits to replay on another project, for repetitive editorial tasks and bug reproductions.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EditMacro {
+    pub steps: Vec<EditStep>,
+}
+
+impl EditMacro {
+    pub fn replay<P: Project>(&self, proj: &mut P) {
+        for step in self.steps.iter() {
+            step.apply(proj);
+        }
+    }
+}
+
+// Applies edits to the project while recording them. Only the steps given to run() are recorded. The project is
+// borrowed for the whole recording so that it cannot be edited behind the recorder's back.
+pub struct MacroRecorder<'a, P: Project> {
+    project: &'a mut P,
+    recorded: EditMacro,
+}
+
+impl<'a, P: Project> MacroRecorder<'a, P> {
+    pub fn new(project: &'a mut P) -> Self {
+        Self { project, recorded: EditMacro::default() }
+    }
+
+    pub fn run(&mut self, step: EditStep) {
+        step.apply(self.project);
+        self.recorded.steps.push(step);
+    }
+
+    pub fn project(&self) -> &P {
+        self.project
+    }
+
+    pub fn finish(self) -> EditMacro {
+        self.recorded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serdo::undo_store::UndoStore;
+
+    use crate::{key::Key, note::Note, percent::PercentU16, project::{memory_project_store, Project, DEFAULT_MEMORY_UNDO_LIMIT}, tempo::Tempo};
+    use super::{EditMacro, EditStep, MacroRecorder};
+
+    #[test]
+    fn record_and_replay() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        let mut recorder = MacroRecorder::new(&mut store);
+        let note = Note { base_start_tick: 240, ..Default::default() };
+        recorder.run(EditStep::SetKey(Key::FLAT_2));
+        recorder.run(EditStep::AddNote { note: note.clone(), select: false });
+        recorder.run(EditStep::AddTempo { tempo: Tempo::new(0, 100), select: false });
+        recorder.run(EditStep::SetMuted { notes: vec![note], muted: true });
+        recorder.run(EditStep::ScaleTempo { range: 0..960, factor: PercentU16::from_value(1100) });
+        assert_eq!(recorder.project().note_repo().len(), 1);
+        let recorded = recorder.finish();
+        assert_eq!(recorded.steps.len(), 5);
+
+        let json = serde_json::to_string(&recorded).unwrap();
+        let mut other = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        serde_json::from_str::<EditMacro>(&json).unwrap().replay(&mut other);
+        assert!(other.note_repo().iter().all(|(_, n)| n.muted));
+        assert_eq!(other.model().content_hash(), store.model().content_hash());
+    }
+
+    #[test]
+    fn stacked_duplicates() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        let note = Note { base_start_tick: 240, ..Default::default() };
+        let mut recorder = MacroRecorder::new(&mut store);
+        recorder.run(EditStep::AddNote { note: note.clone(), select: false });
+        recorder.run(EditStep::AddNote { note: note.clone(), select: false });
+        recorder.run(EditStep::AddNote { note: note.clone(), select: false });
+        recorder.run(EditStep::SetMuted { notes: vec![note.clone(), note], muted: true });
+        recorder.finish();
+
+        assert_eq!(store.note_repo().iter().filter(|(_, n)| n.muted).count(), 2);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::{channel::Channel, duration::Duration, grid::SnapRounding, location::Location, project::{LocationError, ProjectImpl}};
+
+// Insertion point shared by step entry and keyboard navigation. The cursor snaps to the grid the same way as
+// quantizing does. See Grid::snap_in_bar().
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntryCursor {
+    pub tick: u32,
+    // Length of the note entered at the cursor and of each advance.
+    pub duration: Duration,
+    // Channel the entered notes go to. None for the channel of the note defaults.
+    pub voice: Option<Channel>,
+}
+
+impl EntryCursor {
+    pub fn new(tick: u32, duration: Duration) -> Self {
+        Self { tick, duration, voice: None }
+    }
+
+    pub fn with_duration(self, duration: Duration) -> Self {
+        Self { duration, ..self }
+    }
+
+    pub fn with_voice(self, voice: Channel) -> Self {
+        Self { voice: Some(voice), ..self }
+    }
+
+    // Just after the note entered at the cursor. The note may cross a bar line, so the grid is not applied.
+    pub fn advance(self) -> Self {
+        Self { tick: self.tick.saturating_add(self.duration.tick_length()), ..self }
+    }
+
+    // Back to the start of the note entered before. Stops at tick 0.
+    pub fn retreat(self) -> Self {
+        Self { tick: self.tick.saturating_sub(self.duration.tick_length()), ..self }
+    }
+
+    pub fn location(self, proj: &ProjectImpl) -> Location {
+        proj.tick_to_location(self.tick)
+    }
+
+    // Snapped to the nearest grid position. Snapping up to the end of the bar moves to the next bar line.
+    pub fn to_location(self, loc: Location, proj: &ProjectImpl) -> Result<Self, LocationError> {
+        let tick = proj.location_to_tick(loc)?;
+        Ok(Self { tick: proj.grid().snap_in_bar(tick, proj.bar_span(tick), SnapRounding::Nearest), ..self })
+    }
+
+    // Moves by grid positions, backward if negative. A bar line is always a stop even if it is off the grid.
+    pub fn step(self, proj: &ProjectImpl, grids: i32) -> Self {
+        let grid = proj.grid();
+        let mut tick = self.tick;
+        for _ in 0..grids.unsigned_abs() {
+            tick = if 0 < grids {
+                grid.snap_in_bar(tick.saturating_add(1), proj.bar_span(tick), SnapRounding::Up)
+            } else if 0 < tick {
+                grid.snap_in_bar(tick - 1, proj.bar_span(tick - 1), SnapRounding::Down)
+            } else {
+                break;
+            };
+        }
+        Self { tick, ..self }
+    }
+
+    // Start of the next bar.
+    pub fn next_bar(self, proj: &ProjectImpl) -> Self {
+        Self { tick: proj.bar_span(self.tick).end, ..self }
+    }
+
+    // Start of the bar, or of the bar before if already there.
+    pub fn prev_bar(self, proj: &ProjectImpl) -> Self {
+        let start = proj.bar_span(self.tick).start;
+        let tick = if start < self.tick || start == 0 { start } else { proj.bar_span(start - 1).start };
+        Self { tick, ..self }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serdo::undo_store::UndoStore;
+
+    use crate::{bar::Bar, duration::{Denominator, Dots, Duration, Numerator}, grid::{Grid, GridKind}, location::Location, project::{memory_project_store, Project, DEFAULT_MEMORY_UNDO_LIMIT}};
+    use super::EntryCursor;
+
+    #[test]
+    fn navigation() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        // Pickup bar of a quarter, then 4/4 bars.
+        store.add_bar(Bar::new(240, None, None, Default::default()), false);
+        store.add_bar(Bar::new(1200, None, None, Default::default()), false);
+        store.set_grid(Grid::of(Numerator::Half, GridKind::Straight));
+        let proj = store.model();
+
+        let quarter = Duration::new(Numerator::Quarter, Denominator::default(), Dots::ZERO);
+        let cursor = EntryCursor::new(0, quarter);
+        assert_eq!(cursor.advance().tick, 240);
+        assert_eq!(cursor.advance().advance().retreat().tick, 240);
+        assert_eq!(cursor.retreat().tick, 0);
+
+        // The grid of half notes counts from the bar line at 240.
+        assert_eq!(cursor.step(proj, 1).tick, 240);
+        assert_eq!(cursor.step(proj, 2).tick, 720);
+        assert_eq!(cursor.step(proj, 3).tick, 1200);
+        assert_eq!(cursor.step(proj, 4).tick, 1680);
+        assert_eq!(cursor.step(proj, 4).step(proj, -2).tick, 720);
+        assert_eq!(cursor.step(proj, -1).tick, 0);
+        assert_eq!(EntryCursor::new(300, quarter).step(proj, -1).tick, 240);
+
+        let cursor = cursor.to_location(Location::new(1, 500), proj).unwrap();
+        assert_eq!(cursor.tick, 720);
+        assert_eq!(cursor.location(proj), Location::new(1, 480));
+        assert_eq!(cursor.to_location(Location::new(1, 900), proj).unwrap().tick, 1200);
+
+        // Bars past the last bar line follow its rhythm.
+        assert_eq!(cursor.next_bar(proj).tick, 1200);
+        assert_eq!(cursor.next_bar(proj).next_bar(proj).tick, 2160);
+        assert_eq!(cursor.prev_bar(proj).tick, 240);
+        assert_eq!(cursor.prev_bar(proj).prev_bar(proj).tick, 0);
+        assert_eq!(cursor.prev_bar(proj).prev_bar(proj).prev_bar(proj).tick, 0);
+    }
+}
@@ -3,6 +3,7 @@ use serde_json::Value;
 use crate::{bar::Bar, ctrl_chg::CtrlChg, key::Key, models::{FromClipboardTextErr, Models}, note::Note, rhythm::Rhythm, tempo::Tempo};
 
 #[derive(Clone, PartialEq, Debug, serde::Deserialize, serde::Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct ExportedProject {
     pub key: Option<Key>,
     pub rhythm: Option<Rhythm>,
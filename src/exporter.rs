@@ -0,0 +1,517 @@
+use std::{collections::BTreeMap, fmt, io::{Read, Write}};
+
+use error_stack::{Report, ResultExt};
+
+use crate::{channel::Channel, ctrl_chg::CtrlThinning, duration::Duration, note::Note, playback::{self, PlayEvent}, project::ProjectImpl, repeat::AccumTick, solfa::Solfa, tempo::Tempo, tick::Tick, velocity::Velocity};
+
+#[derive(Debug)]
+pub enum ExportError {
+    UnknownFormat(String),
+    Io,
+    Render,
+    Json,
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::UnknownFormat(id) => write!(f, "Unknown format: {}", id),
+            ExportError::Io => write!(f, "I/O error"),
+            ExportError::Render => write!(f, "Cannot render the project"),
+            ExportError::Json => write!(f, "JSON error"),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+pub trait ScoreExporter {
+    // Shown in save dialogs.
+    fn name(&self) -> &str;
+    fn extension(&self) -> &str;
+    fn export(&self, proj: &ProjectImpl, w: &mut dyn Write) -> Result<(), Report<ExportError>>;
+}
+
+pub trait ScoreImporter {
+    fn name(&self) -> &str;
+    fn extension(&self) -> &str;
+    fn import(&self, r: &mut dyn Read) -> Result<ProjectImpl, Report<ExportError>>;
+}
+
+// Exporters and importers by format id.
+#[derive(Default)]
+pub struct FormatRegistry {
+    exporters: BTreeMap<String, Box<dyn ScoreExporter>>,
+    importers: BTreeMap<String, Box<dyn ScoreImporter>>,
+}
+
+impl FormatRegistry {
+    pub const JSON: &'static str = "json";
+    pub const MIDI: &'static str = "midi";
+    pub const MUSICXML: &'static str = "musicxml";
+
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::default();
+        registry.register_exporter(Self::JSON, Box::new(JsonFormat));
+        registry.register_importer(Self::JSON, Box::new(JsonFormat));
+        registry.register_exporter(Self::MIDI, Box::new(MidiExporter::default()));
+        registry.register_exporter(Self::MUSICXML, Box::new(MusicXmlExporter));
+        registry
+    }
+
+    // Returns the exporter replaced.
+    pub fn register_exporter(&mut self, id: &str, exporter: Box<dyn ScoreExporter>) -> Option<Box<dyn ScoreExporter>> {
+        self.exporters.insert(id.to_owned(), exporter)
+    }
+
+    pub fn register_importer(&mut self, id: &str, importer: Box<dyn ScoreImporter>) -> Option<Box<dyn ScoreImporter>> {
+        self.importers.insert(id.to_owned(), importer)
+    }
+
+    pub fn exporter(&self, id: &str) -> Option<&dyn ScoreExporter> {
+        self.exporters.get(id).map(|e| e.as_ref())
+    }
+
+    pub fn importer(&self, id: &str) -> Option<&dyn ScoreImporter> {
+        self.importers.get(id).map(|i| i.as_ref())
+    }
+
+    pub fn exporter_ids(&self) -> impl Iterator<Item = &str> {
+        self.exporters.keys().map(|k| k.as_str())
+    }
+
+    pub fn importer_ids(&self) -> impl Iterator<Item = &str> {
+        self.importers.keys().map(|k| k.as_str())
+    }
+
+    pub fn export(&self, id: &str, proj: &ProjectImpl, w: &mut dyn Write) -> Result<(), Report<ExportError>> {
+        self.exporter(id).ok_or_else(|| Report::new(ExportError::UnknownFormat(id.to_owned())))?.export(proj, w)
+    }
+
+    pub fn import(&self, id: &str, r: &mut dyn Read) -> Result<ProjectImpl, Report<ExportError>> {
+        self.importer(id).ok_or_else(|| Report::new(ExportError::UnknownFormat(id.to_owned())))?.import(r)
+    }
+}
+
+// Whole project including settings.
+pub struct JsonFormat;
+
+impl ScoreExporter for JsonFormat {
+    fn name(&self) -> &str { "Klavier JSON" }
+    fn extension(&self) -> &str { "json" }
+
+    fn export(&self, proj: &ProjectImpl, w: &mut dyn Write) -> Result<(), Report<ExportError>> {
+        serde_json::to_writer(w, proj).change_context(ExportError::Json)
+    }
+}
+
+impl ScoreImporter for JsonFormat {
+    fn name(&self) -> &str { "Klavier JSON" }
+    fn extension(&self) -> &str { "json" }
+
+    fn import(&self, r: &mut dyn Read) -> Result<ProjectImpl, Report<ExportError>> {
+        serde_json::from_reader(r).change_context(ExportError::Json)
+    }
+}
+
+// Standard MIDI file (format 0) of the rendered performance. Repeats are expanded.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MidiExporter {
+    pub pedal_thinning: CtrlThinning,
+}
+
+// Whether each event is written after thinning pedal curves.
+fn pedal_events_kept(events: &[PlayEvent], thinning: CtrlThinning) -> Vec<bool> {
+    // By controller number and channel.
+    let mut curves: BTreeMap<(u8, Channel), Vec<usize>> = BTreeMap::new();
+    for (i, e) in events.iter().enumerate() {
+        match e {
+            PlayEvent::Dumper { channel, .. } => curves.entry((64, *channel)).or_default().push(i),
+            PlayEvent::Soft { channel, .. } => curves.entry((67, *channel)).or_default().push(i),
+            _ => {}
+        }
+    }
+    let mut kept = vec![true; events.len()];
+    for indices in curves.values() {
+        let points: Vec<(AccumTick, Velocity)> = indices.iter().map(|i| match events[*i] {
+            PlayEvent::Dumper { tick, velocity, .. } | PlayEvent::Soft { tick, velocity, .. } => (tick, velocity),
+            _ => panic!("Logic error."),
+        }).collect();
+        for (i, k) in indices.iter().zip(thinning.keep(&points)) {
+            kept[*i] = k;
+        }
+    }
+    kept
+}
+
+fn write_var_len(buf: &mut Vec<u8>, mut value: u32) {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    value >>= 7;
+    while value != 0 {
+        bytes.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.extend(bytes.iter().rev());
+}
+
+impl ScoreExporter for MidiExporter {
+    fn name(&self) -> &str { "Standard MIDI File" }
+    fn extension(&self) -> &str { "mid" }
+
+    fn export(&self, proj: &ProjectImpl, w: &mut dyn Write) -> Result<(), Report<ExportError>> {
+        let events = playback::render(proj).change_context(ExportError::Render)?;
+        let mut track: Vec<u8> = vec![];
+        let rhythm = proj.rhythm();
+        track.extend([0x00, 0xff, 0x58, 0x04, rhythm.numerator().value(), rhythm.denominator().value().trailing_zeros() as u8, 24, 8]);
+        for (channel, instrument) in proj.instruments().iter() {
+            track.extend([0x00, 0xc0 | (channel.as_u8() & 0x0f), instrument.program & 0x7f]);
+        }
+
+        let kept = pedal_events_kept(&events, self.pedal_thinning);
+        let mut cur = 0;
+        for e in events.iter().zip(kept).filter(|(_, k)| *k).map(|(e, _)| e) {
+            // A delta past the variable length quantity of SMF cannot be written anyway.
+            write_var_len(&mut track, Tick::from_accum(e.tick() - cur).map_or(u32::MAX, Tick::as_u32));
+            cur = e.tick();
+            match *e {
+                PlayEvent::Tempo { value, marking, .. } => {
+                    if let Some(marking) = marking {
+                        let text = marking.to_string();
+                        track.extend([0xff, 0x06]);
+                        write_var_len(&mut track, text.len() as u32);
+                        track.extend(text.as_bytes());
+                        track.push(0x00);
+                    }
+                    let micros = 60_000_000 / value.value() as u32;
+                    track.extend([0xff, 0x51, 0x03, (micros >> 16) as u8, (micros >> 8) as u8, micros as u8]);
+                }
+                PlayEvent::Dumper { channel, velocity, .. } => track.extend([0xb0 | (channel.as_u8() & 0x0f), 64, velocity.as_u8()]),
+                PlayEvent::Soft { channel, velocity, .. } => track.extend([0xb0 | (channel.as_u8() & 0x0f), 67, velocity.as_u8()]),
+                PlayEvent::NoteOff { channel, pitch, .. } => track.extend([0x80 | (channel.as_u8() & 0x0f), pitch, 0]),
+                PlayEvent::NoteOn { channel, pitch, velocity, .. } => track.extend([0x90 | (channel.as_u8() & 0x0f), pitch, velocity.as_u8()]),
+            }
+        }
+        track.extend([0x00, 0xff, 0x2f, 0x00]);
+
+        let division = Duration::TICK_RESOLUTION as u16;
+        let mut smf: Vec<u8> = b"MThd".to_vec();
+        smf.extend(6u32.to_be_bytes());
+        smf.extend(0u16.to_be_bytes());
+        smf.extend(1u16.to_be_bytes());
+        smf.extend(division.to_be_bytes());
+        smf.extend(b"MTrk");
+        smf.extend((track.len() as u32).to_be_bytes());
+        smf.extend(track);
+        w.write_all(&smf).change_context(ExportError::Io)
+    }
+}
+
+// Single part MusicXML (partwise). Notes are written in one voice using forward/backup.
+pub struct MusicXmlExporter;
+
+fn step(solfa: Solfa) -> &'static str {
+    match solfa {
+        Solfa::C => "C",
+        Solfa::D => "D",
+        Solfa::E => "E",
+        Solfa::F => "F",
+        Solfa::G => "G",
+        Solfa::A => "A",
+        Solfa::B => "B",
+    }
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+impl MusicXmlExporter {
+    // The marking is written as words, otherwise as a metronome mark.
+    fn tempo_xml(tempo: &Tempo, offset: u32) -> String {
+        let direction_type = match tempo.marking {
+            Some(marking) => format!("<words>{}</words>", marking),
+            None => format!("<metronome><beat-unit>quarter</beat-unit><per-minute>{}</per-minute></metronome>", tempo.value.value()),
+        };
+        format!(
+            r#"<direction placement="above"><direction-type>{}</direction-type><offset>{}</offset><sound tempo="{}"/></direction>"#,
+            direction_type, offset, tempo.value.value()
+        )
+    }
+
+    // Note is a part of the original note if it was split at a bar line. Slurs and glissandos start at the first part
+    // and stop at the last one.
+    fn note_xml(proj: &ProjectImpl, note: &Note, original: &Note, chord: bool) -> String {
+        let pitch = proj.sounding_pitch(original);
+        let mut xml = String::from("<note>");
+        if chord { xml.push_str("<chord/>"); }
+        xml.push_str(&format!("<pitch><step>{}</step>", step(pitch.solfa())));
+        if pitch.sharp_flat().offset() != 0 {
+            xml.push_str(&format!("<alter>{}</alter>", pitch.sharp_flat().offset()));
+        }
+        xml.push_str(&format!("<octave>{}</octave></pitch>", pitch.scientific_octave()));
+        xml.push_str(&format!("<duration>{}</duration>", note.duration.tick_length()));
+        if note.tied { xml.push_str(r#"<tie type="stop"/>"#); }
+        if note.tie { xml.push_str(r#"<tie type="start"/>"#); }
+        xml.push_str(&format!("<voice>1</voice><type>{}</type>", note.duration.numerator.musicxml_type()));
+        for _ in 0..note.duration.dots.value() { xml.push_str("<dot/>"); }
+
+        let mut notations = String::new();
+        if note.tied { notations.push_str(r#"<tied type="stop"/>"#); }
+        if note.tie { notations.push_str(r#"<tied type="start"/>"#); }
+        let first = note.base_start_tick == original.base_start_tick;
+        let last = note.base_start_tick + note.duration.tick_length() == original.base_start_tick + original.duration.tick_length();
+        for slur in proj.slurs() {
+            let (start, stop) = slur.to_musicxml(1);
+            if first && slur.start_note == *original { notations.push_str(&start); }
+            if last && slur.end_note == *original { notations.push_str(&stop); }
+        }
+        for gliss in proj.glissandos() {
+            let (start, stop) = gliss.to_musicxml(1);
+            if last && gliss.from_note == *original { notations.push_str(&start); }
+            if first && gliss.to_note == *original { notations.push_str(&stop); }
+        }
+        if !notations.is_empty() {
+            xml.push_str(&format!("<notations>{}</notations>", notations));
+        }
+        xml.push_str("</note>");
+        xml
+    }
+}
+
+impl ScoreExporter for MusicXmlExporter {
+    fn name(&self) -> &str { "MusicXML" }
+    fn extension(&self) -> &str { "musicxml" }
+
+    fn export(&self, proj: &ProjectImpl, w: &mut dyn Write) -> Result<(), Report<ExportError>> {
+        let mut notes: Vec<&Note> = proj.note_repo().iter().map(|(_, n)| n.as_ref()).collect();
+        notes.sort_by_key(|n| (n.base_start_tick, n.pitch.value()));
+        let end_tick = notes.iter().map(|n| n.base_start_tick + n.duration.tick_length()).max().unwrap_or(0);
+        let mut bar_ticks: Vec<u32> = vec![0];
+        bar_ticks.extend(proj.bar_repo().iter().map(|(t, _)| *t).filter(|t| *t != 0));
+        if *bar_ticks.last().unwrap() < end_tick || bar_ticks.len() == 1 {
+            bar_ticks.push(end_tick.max(bar_ticks.last().unwrap() + proj.rhythm().tick_len()));
+        }
+
+        let rhythm = proj.rhythm();
+        let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?><score-partwise version="4.0">"#);
+        let channel = proj.default_channel();
+        match proj.instruments().get(channel) {
+            Some(instrument) => xml.push_str(&format!(
+                r#"<part-list><score-part id="P1"><part-name>{name}</part-name><score-instrument id="P1-I1"><instrument-name>{name}</instrument-name></score-instrument><midi-instrument id="P1-I1"><midi-channel>{}</midi-channel><midi-program>{}</midi-program></midi-instrument></score-part></part-list><part id="P1">"#,
+                channel.as_u8() + 1, instrument.program as u16 + 1, name = escape_xml(&instrument.name)
+            )),
+            None => xml.push_str(r#"<part-list><score-part id="P1"><part-name>Piano</part-name></score-part></part-list><part id="P1">"#),
+        }
+        let rehearsal_labels = proj.rehearsal_labels();
+        let mut idx = 0;
+        // Rest of the notes crossing the previous bar line.
+        let mut carried: Vec<(Note, &Note)> = vec![];
+        for (no, range) in bar_ticks.windows(2).enumerate() {
+            let (start, end) = (range[0], range[1]);
+            xml.push_str(&format!(r#"<measure number="{}">"#, no + 1));
+            if no == 0 {
+                xml.push_str(&format!(
+                    "<attributes><divisions>{}</divisions><key><fifths>{}</fifths></key><time><beats>{}</beats><beat-type>{}</beat-type></time><clef><sign>G</sign><line>2</line></clef></attributes>",
+                    Duration::TICK_RESOLUTION, proj.key().offset(),
+                    rhythm.grouping.map(|g| g.to_string()).unwrap_or_else(|| rhythm.numerator().value().to_string()), rhythm.denominator().value()
+                ));
+            }
+            if let Some((_, label)) = rehearsal_labels.iter().find(|(bar_no, _)| *bar_no == no) {
+                xml.push_str(&format!(
+                    r#"<direction placement="above"><direction-type><rehearsal>{}</rehearsal></direction-type></direction>"#, escape_xml(label)
+                ));
+            }
+            for (tick, tempo) in proj.tempo_repo().range(start..end).1 {
+                xml.push_str(&Self::tempo_xml(tempo, tick - start));
+            }
+            let mut bar_notes: Vec<(Note, &Note)> = std::mem::take(&mut carried);
+            while idx < notes.len() && notes[idx].base_start_tick < end {
+                bar_notes.push((notes[idx].clone(), notes[idx]));
+                idx += 1;
+            }
+            let mut cursor = start;
+            let mut prev_tick: Option<u32> = None;
+            for (note, original) in bar_notes {
+                // A note crossing the bar line is written as tied notes unless the parts cannot be notated.
+                let note = match note.split_at(end) {
+                    Ok((first, second)) => {
+                        carried.push((second, original));
+                        first
+                    }
+                    Err(_) => note,
+                };
+                let tick = note.base_start_tick;
+                let chord = prev_tick == Some(tick);
+                if !chord {
+                    if cursor < tick {
+                        xml.push_str(&format!("<forward><duration>{}</duration></forward>", tick - cursor));
+                    } else if tick < cursor {
+                        xml.push_str(&format!("<backup><duration>{}</duration></backup>", cursor - tick));
+                    }
+                    cursor = tick + note.duration.tick_length();
+                }
+                xml.push_str(&Self::note_xml(proj, &note, original, chord));
+                prev_tick = Some(tick);
+            }
+            if cursor < end {
+                xml.push_str(&format!("<forward><duration>{}</duration></forward>", end - cursor));
+            }
+            xml.push_str("</measure>");
+        }
+        xml.push_str("</part></score-partwise>");
+        w.write_all(xml.as_bytes()).change_context(ExportError::Io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serdo::undo_store::UndoStore;
+
+    use crate::{project::{Project, memory_project_store, DEFAULT_MEMORY_UNDO_LIMIT, ProjectImpl}, note::Note, pitch::Pitch, solfa::Solfa, octave::Octave, sharp_flat::SharpFlat, velocity::Velocity, tempo::{Tempo, Marking}, ctrl_chg::{CtrlChg, CtrlThinning}, channel::Channel, models::Models, project::ModelChangeMetadata, rehearsal::RehearsalMark, bar::Bar, instrument::Instrument};
+    use super::{FormatRegistry, ExportError, MidiExporter, ScoreExporter};
+
+    struct CountExporter;
+
+    impl ScoreExporter for CountExporter {
+        fn name(&self) -> &str { "Count" }
+        fn extension(&self) -> &str { "txt" }
+        fn export(&self, proj: &ProjectImpl, w: &mut dyn std::io::Write) -> Result<(), error_stack::Report<ExportError>> {
+            write!(w, "{}", proj.note_repo().len()).map_err(|_| error_stack::Report::new(ExportError::Io))
+        }
+    }
+
+    fn store() -> crate::project::MemoryProjectStore {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(Note { pitch: Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null), base_velocity: Velocity::new(64), ..Default::default() }, false);
+        store.add_note(Note { base_start_tick: 240, pitch: Pitch::new(Solfa::F, Octave::Oct4, SharpFlat::Sharp), base_velocity: Velocity::new(64), ..Default::default() }, false);
+        store
+    }
+
+    #[test]
+    fn registry() {
+        let mut registry = FormatRegistry::with_builtins();
+        assert_eq!(registry.exporter_ids().collect::<Vec<&str>>(), vec!["json", "midi", "musicxml"]);
+        assert!(registry.register_exporter("count", Box::new(CountExporter)).is_none());
+
+        let mut out: Vec<u8> = vec![];
+        registry.export("count", store().model(), &mut out).unwrap();
+        assert_eq!(out, b"2");
+        assert!(matches!(registry.export("abc", store().model(), &mut out).unwrap_err().current_context(), ExportError::UnknownFormat(_)));
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let registry = FormatRegistry::with_builtins();
+        let mut out: Vec<u8> = vec![];
+        registry.export(FormatRegistry::JSON, store().model(), &mut out).unwrap();
+        let imported = registry.import(FormatRegistry::JSON, &mut out.as_slice()).unwrap();
+        assert_eq!(imported.note_repo().len(), 2);
+    }
+
+    #[test]
+    fn midi() {
+        let mut out: Vec<u8> = vec![];
+        FormatRegistry::with_builtins().export(FormatRegistry::MIDI, store().model(), &mut out).unwrap();
+        assert_eq!(&out[0..4], b"MThd");
+        assert_eq!(&out[12..14], &240u16.to_be_bytes());
+        assert_eq!(&out[14..18], b"MTrk");
+        // Time signature, first note on and end of track.
+        assert_eq!(&out[22..30], &[0x00, 0xff, 0x58, 0x04, 4, 2, 24, 8]);
+        assert_eq!(&out[30..34], &[0x00, 0x90, 72, 64]);
+        assert_eq!(&out[out.len() - 4..], &[0x00, 0xff, 0x2f, 0x00]);
+    }
+
+    #[test]
+    fn tempo_marking() {
+        let mut store = store();
+        store.add_tempo(Tempo::new(0, 132).with_marking(Some(Marking::Allegro)), false);
+        store.add_tempo(Tempo::new(240, 90), false);
+
+        let mut out: Vec<u8> = vec![];
+        FormatRegistry::with_builtins().export(FormatRegistry::MIDI, store.model(), &mut out).unwrap();
+        let marker = [&[0xff, 0x06, 7][..], b"Allegro"].concat();
+        assert!(out.windows(marker.len()).any(|w| w == marker.as_slice()));
+
+        let mut out: Vec<u8> = vec![];
+        FormatRegistry::with_builtins().export(FormatRegistry::MUSICXML, store.model(), &mut out).unwrap();
+        let xml = String::from_utf8(out).unwrap();
+        assert!(xml.contains(r#"<direction-type><words>Allegro</words></direction-type><offset>0</offset><sound tempo="132"/>"#));
+        assert!(xml.contains("<per-minute>90</per-minute></metronome></direction-type><offset>240</offset>"));
+    }
+
+    #[test]
+    fn musicxml() {
+        let mut out: Vec<u8> = vec![];
+        FormatRegistry::with_builtins().export(FormatRegistry::MUSICXML, store().model(), &mut out).unwrap();
+        let xml = String::from_utf8(out).unwrap();
+        assert!(xml.contains("<divisions>240</divisions>"));
+        assert!(xml.contains("<pitch><step>F</step><alter>1</alter><octave>5</octave></pitch><duration>240</duration>"));
+        assert!(xml.contains("<forward><duration>480</duration></forward></measure>"));
+    }
+
+    #[test]
+    fn musicxml_octave() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(Note { pitch: Pitch::new(Solfa::C, Octave::Oct3, SharpFlat::Null), ..Default::default() }, false);
+        assert_eq!(store.note_repo().iter().next().unwrap().1.pitch.value(), 60);
+        let mut out: Vec<u8> = vec![];
+        FormatRegistry::with_builtins().export(FormatRegistry::MUSICXML, store.model(), &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("<step>C</step><octave>4</octave>"));
+    }
+
+    #[test]
+    fn musicxml_bar_line_ties() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(Note { base_start_tick: 840, base_velocity: Velocity::new(64), ..Default::default() }, false);
+        let mut out: Vec<u8> = vec![];
+        FormatRegistry::with_builtins().export(FormatRegistry::MUSICXML, store.model(), &mut out).unwrap();
+        let xml = String::from_utf8(out).unwrap();
+        assert!(xml.contains(r#"<duration>120</duration><tie type="start"/><voice>1</voice><type>eighth</type><notations><tied type="start"/></notations></note></measure><measure number="2">"#));
+        assert!(xml.contains(r#"<measure number="2"><note><pitch><step>A</step><octave>5</octave></pitch><duration>120</duration><tie type="stop"/>"#));
+    }
+
+    #[test]
+    fn musicxml_rehearsal_marks() {
+        let mut store = store();
+        store.add_bar(Bar::new(960, None, None, Default::default()), false);
+        store.add_bar(Bar::new(1920, None, None, Default::default()), false);
+        store.add_rehearsal_mark(RehearsalMark::auto(0));
+        store.add_rehearsal_mark(RehearsalMark::custom(1, "Coda & end"));
+        let mut out: Vec<u8> = vec![];
+        FormatRegistry::with_builtins().export(FormatRegistry::MUSICXML, store.model(), &mut out).unwrap();
+        let xml = String::from_utf8(out).unwrap();
+        assert!(xml.contains(r#"<measure number="1"><attributes>"#));
+        assert!(xml.contains("<rehearsal>A</rehearsal>"));
+        assert!(xml.contains("<rehearsal>Coda &amp; end</rehearsal>"));
+    }
+
+    #[test]
+    fn instruments() {
+        let mut store = store();
+        store.set_instrument(Channel::new(0), Some(Instrument::new("Cello", 42)));
+        let mut out: Vec<u8> = vec![];
+        FormatRegistry::with_builtins().export(FormatRegistry::MIDI, store.model(), &mut out).unwrap();
+        assert_eq!(&out[30..33], &[0x00, 0xc0, 42]);
+
+        let mut out: Vec<u8> = vec![];
+        FormatRegistry::with_builtins().export(FormatRegistry::MUSICXML, store.model(), &mut out).unwrap();
+        let xml = String::from_utf8(out).unwrap();
+        assert!(xml.contains("<part-name>Cello</part-name>"));
+        assert!(xml.contains("<midi-channel>1</midi-channel><midi-program>43</midi-program>"));
+    }
+
+    #[test]
+    fn midi_pedal_thinning() {
+        let mut store = store();
+        let ramp = CtrlChg::ramp(0..=240, Velocity::new(0), Velocity::new(120), 10, Channel::default());
+        store.bulk_add(Models::empty().with_dumpers(ramp), ModelChangeMetadata::new());
+        let dumper_count = |exporter: MidiExporter| {
+            let mut out: Vec<u8> = vec![];
+            exporter.export(store.model(), &mut out).unwrap();
+            out.windows(2).filter(|w| *w == [0xb0, 64]).count()
+        };
+        assert_eq!(dumper_count(MidiExporter::default()), 25);
+        assert_eq!(dumper_count(MidiExporter { pedal_thinning: CtrlThinning { min_value_delta: 20, min_tick_spacing: 0 } }), 7);
+        assert_eq!(dumper_count(MidiExporter { pedal_thinning: CtrlThinning { min_value_delta: 0, min_tick_spacing: 60 } }), 5);
+    }
+}
@@ -0,0 +1,124 @@
+// Common figures for insert tools. Notes take velocity, channel and articulation from the defaults and
+// the returned models can be pasted or combined freely.
+use std::ops::Range;
+
+use crate::{duration::{Denominator, Dots, Duration, Numerator}, key::Key, models::Models, note::Note, note_defaults::NoteDefaults, pitch::Pitch, rhythm::Rhythm, sharp_flat::SharpFlat};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArpeggioDirection {
+    Up,
+    Down,
+    // The top and bottom tones are not repeated at turns.
+    UpDown,
+}
+
+fn notes_of(pitches: impl Iterator<Item = Pitch>, start_tick: u32, duration: Duration, defaults: &NoteDefaults) -> Models {
+    let defaults = defaults.with_duration(duration);
+    let notes = pitches.enumerate()
+        .map(|(i, pitch)| Note::from_defaults(start_tick + i as u32 * duration.tick_length(), pitch, &defaults))
+        .collect();
+    Models { notes, ..Models::empty() }
+}
+
+fn sorted(chord: &[Pitch]) -> Vec<Pitch> {
+    let mut chord = chord.to_vec();
+    chord.sort_by_key(|p| p.value());
+    chord.dedup_by_key(|p| p.value());
+    chord
+}
+
+// Chord tones one after another filling the range. Notes start in the range.
+pub fn arpeggio(chord: &[Pitch], range: Range<u32>, duration: Duration, direction: ArpeggioDirection, defaults: &NoteDefaults) -> Models {
+    let up = sorted(chord);
+    if up.is_empty() || range.is_empty() { return Models::empty(); }
+    let cycle: Vec<Pitch> = match direction {
+        ArpeggioDirection::Up => up,
+        ArpeggioDirection::Down => up.into_iter().rev().collect(),
+        ArpeggioDirection::UpDown => {
+            let down: Vec<Pitch> = up.iter().rev().skip(1).take(up.len().saturating_sub(2)).copied().collect();
+            up.into_iter().chain(down).collect()
+        }
+    };
+    let count = (range.end - range.start).div_ceil(duration.tick_length());
+    notes_of(cycle.into_iter().cycle().take(count as usize), range.start, duration, defaults)
+}
+
+// Diatonic run from one pitch to the other, both included. Accidentals of the key are written on each note
+// so that the run sounds the same under any key signature.
+pub fn scale_run(key: Key, from: Pitch, to: Pitch, start_tick: u32, duration: Duration, defaults: &NoteDefaults) -> Models {
+    let steps = from.steps_to(to);
+    let pitches = (0..=steps.abs()).filter_map(|i|
+        from.with_score_offset_delta(i * steps.signum()).ok()
+            .and_then(|p| Pitch::value_of(p.solfa(), p.octave(), SharpFlat::Null).ok())
+            .and_then(|p| p.apply_key(key).ok())
+    );
+    notes_of(pitches, start_tick, duration, defaults)
+}
+
+// Eighth notes of the lowest, highest, middle and highest tones of the chord for the bars.
+pub fn alberti(chord: &[Pitch], start_tick: u32, bars: u32, rhythm: Rhythm, defaults: &NoteDefaults) -> Models {
+    let tones = sorted(chord);
+    let (Some(low), Some(high)) = (tones.first(), tones.last()) else { return Models::empty(); };
+    let middle = tones[tones.len() / 2];
+    let duration = Duration::new(Numerator::N8th, Denominator::default(), Dots::ZERO);
+    let count = bars * rhythm.tick_len() / duration.tick_length();
+    notes_of([*low, *high, middle, *high].into_iter().cycle().take(count as usize), start_tick, duration, defaults)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{duration::{Denominator, Dots, Duration, Numerator}, key::Key, models::Models, note_defaults::NoteDefaults, octave::Octave, pitch::Pitch, rhythm::Rhythm, sharp_flat::SharpFlat, solfa::Solfa};
+    use super::{alberti, arpeggio, scale_run, ArpeggioDirection};
+
+    fn pitch(solfa: Solfa) -> Pitch {
+        Pitch::new(solfa, Octave::Oct4, SharpFlat::Null)
+    }
+
+    fn ticks_and_solfas(models: &Models) -> Vec<(u32, Solfa)> {
+        models.notes.iter().map(|n| (n.base_start_tick, n.pitch.solfa())).collect()
+    }
+
+    #[test]
+    fn arpeggios() {
+        let chord = [pitch(Solfa::G), pitch(Solfa::C), pitch(Solfa::E)];
+        let n16th = Duration::new(Numerator::N16th, Denominator::default(), Dots::ZERO);
+        let defaults = NoteDefaults::default();
+        assert_eq!(
+            ticks_and_solfas(&arpeggio(&chord, 0..240, n16th, ArpeggioDirection::Up, &defaults)),
+            vec![(0, Solfa::C), (60, Solfa::E), (120, Solfa::G), (180, Solfa::C)]
+        );
+        assert_eq!(
+            ticks_and_solfas(&arpeggio(&chord, 100..250, n16th, ArpeggioDirection::Down, &defaults)),
+            vec![(100, Solfa::G), (160, Solfa::E), (220, Solfa::C)]
+        );
+        assert_eq!(
+            arpeggio(&chord, 0..360, n16th, ArpeggioDirection::UpDown, &defaults).notes.iter().map(|n| n.pitch.solfa()).collect::<Vec<_>>(),
+            vec![Solfa::C, Solfa::E, Solfa::G, Solfa::E, Solfa::C, Solfa::E]
+        );
+        assert!(arpeggio(&[], 0..240, n16th, ArpeggioDirection::Up, &defaults).is_empty());
+    }
+
+    #[test]
+    fn scale_runs() {
+        let n8th = Duration::new(Numerator::N8th, Denominator::default(), Dots::ZERO);
+        let run = scale_run(Key::SHARP_1, pitch(Solfa::E), pitch(Solfa::A), 480, n8th, &NoteDefaults::default());
+        assert_eq!(run.notes.iter().map(|n| (n.base_start_tick, n.pitch)).collect::<Vec<_>>(), vec![
+            (480, pitch(Solfa::E)),
+            (600, Pitch::new(Solfa::F, Octave::Oct4, SharpFlat::Sharp)),
+            (720, pitch(Solfa::G)),
+            (840, pitch(Solfa::A)),
+        ]);
+        let down = scale_run(Key::NONE, pitch(Solfa::D), Pitch::new(Solfa::B, Octave::Oct3, SharpFlat::Null), 0, n8th, &NoteDefaults::default());
+        assert_eq!(down.notes.len(), 3);
+        assert_eq!(down.notes[2].pitch, Pitch::new(Solfa::B, Octave::Oct3, SharpFlat::Null));
+    }
+
+    #[test]
+    fn alberti_bass() {
+        let chord = [pitch(Solfa::C), pitch(Solfa::E), pitch(Solfa::G)];
+        let models = alberti(&chord, 0, 2, Rhythm::new(2, 4), &NoteDefaults::default());
+        assert_eq!(models.notes.len(), 8);
+        assert_eq!(ticks_and_solfas(&models)[..4], [(0, Solfa::C), (120, Solfa::G), (240, Solfa::E), (360, Solfa::G)]);
+        assert_eq!(models.notes[7].base_start_tick, 840);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::note::Note;
+
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GlissandoStyle {
+    // Every semitone between the notes (portamento-like slide).
+    #[default]
+    Chromatic,
+    // Natural notes only, like sliding over white keys of piano.
+    WhiteKey,
+}
+
+impl GlissandoStyle {
+    // Element name used by MusicXML notations.
+    pub fn musicxml_element(self) -> &'static str {
+        match self {
+            GlissandoStyle::Chromatic => "slide",
+            GlissandoStyle::WhiteKey => "glissando",
+        }
+    }
+
+    pub fn musicxml_line_type(self) -> &'static str {
+        match self {
+            GlissandoStyle::Chromatic => "solid",
+            GlissandoStyle::WhiteKey => "wavy",
+        }
+    }
+}
+
+const WHITE_KEYS: [u8; 7] = [0, 2, 4, 5, 7, 9, 11];
+
+// Glissando from a note to another. Notes are held by value. If one of them is changed or removed,
+// the glissando no longer matches and is ignored by playback.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Glissando {
+    pub from_note: Note,
+    pub to_note: Note,
+    pub style: GlissandoStyle,
+}
+
+impl Glissando {
+    pub fn new(from_note: Note, to_note: Note, style: GlissandoStyle) -> Self {
+        Self { from_note, to_note, style }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.from_note.start_tick() < self.to_note.start_tick()
+    }
+
+    // Pitches played between the notes. Both ends are excluded.
+    pub fn run(&self, from: u8, to: u8) -> Vec<u8> {
+        let pitches: Vec<u8> = if from < to {
+            (from + 1..to).collect()
+        } else {
+            (to + 1..from).rev().collect()
+        };
+        match self.style {
+            GlissandoStyle::Chromatic => pitches,
+            GlissandoStyle::WhiteKey => pitches.into_iter().filter(|p| WHITE_KEYS.contains(&(p % 12))).collect(),
+        }
+    }
+
+    // Start (on the from note) and stop (on the to note) notation elements of MusicXML.
+    pub fn to_musicxml(&self, number: u8) -> (String, String) {
+        let (name, line_type) = (self.style.musicxml_element(), self.style.musicxml_line_type());
+        (
+            format!(r#"<{} line-type="{}" number="{}" type="start"/>"#, name, line_type, number),
+            format!(r#"<{} line-type="{}" number="{}" type="stop"/>"#, name, line_type, number),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::note::Note;
+    use super::{Glissando, GlissandoStyle};
+
+    #[test]
+    fn run() {
+        let chromatic = Glissando::new(Note::default(), Note::default(), GlissandoStyle::Chromatic);
+        assert_eq!(chromatic.run(72, 76), vec![73, 74, 75]);
+        assert_eq!(chromatic.run(76, 72), vec![75, 74, 73]);
+        assert!(chromatic.run(72, 73).is_empty());
+
+        let white_key = Glissando { style: GlissandoStyle::WhiteKey, ..chromatic };
+        assert_eq!(white_key.run(72, 79), vec![74, 76, 77]);
+        assert_eq!(white_key.run(79, 71), vec![77, 76, 74, 72]);
+    }
+
+    #[test]
+    fn musicxml() {
+        let gliss = Glissando::new(Note::default(), Note::default(), GlissandoStyle::WhiteKey);
+        let (start, stop) = gliss.to_musicxml(1);
+        assert_eq!(start, r#"<glissando line-type="wavy" number="1" type="start"/>"#);
+        assert_eq!(stop, r#"<glissando line-type="wavy" number="1" type="stop"/>"#);
+        assert!(gliss.style.musicxml_element() != GlissandoStyle::Chromatic.musicxml_element());
+    }
+}
@@ -1,7 +1,7 @@
 use error_stack::{Report, report};
 use interval::{IntervalSet, interval_set::ToIntervalSet};
 use error_stack::{Result, ResultExt};
-use crate::{rhythm::Rhythm, repeat::RenderRegionError, bar::{Bar, Repeat}, have_start_tick::HaveBaseStartTick};
+use crate::{rhythm::Rhythm, repeat::RenderRegionError, bar::{Bar, Repeat, VarIndex}, have_start_tick::HaveBaseStartTick};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Coda {
@@ -30,6 +30,15 @@ pub enum RenderRegionWarning {
   OrphanCodaFound { coda_tick: u32 },
 }
 
+impl RenderRegionWarning {
+  pub fn tick(&self) -> u32 {
+    match self {
+      RenderRegionWarning::SegnoAndDcFound { segno_tick, .. } => *segno_tick,
+      RenderRegionWarning::OrphanCodaFound { coda_tick } => *coda_tick,
+    }
+  }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct GlobalRepeatBuilder {
   pub ds_dc: Option<DsDc>,
@@ -62,6 +71,113 @@ impl GlobalRepeat {
   pub fn iter1_interval_set(&self) -> &IntervalSet<u32> {
     &self.iter1_interval_set
   }
+
+  pub fn fine(&self) -> Option<u32> {
+    self.fine
+  }
+
+  pub fn coda(&self) -> Option<[u32; 2]> {
+    self.coda
+  }
+
+  pub fn jump_target(&self) -> JumpTarget {
+    if self.coda.is_some() {
+      JumpTarget::AlCoda
+    } else if self.fine.is_some() {
+      JumpTarget::AlFine
+    } else {
+      JumpTarget::End
+    }
+  }
+
+  // Such as "D.S. al Coda".
+  pub fn describe(&self) -> String {
+    match self.ds_dc {
+      DsDc::Dc { .. } => RepeatLabel::DaCapo(self.jump_target()),
+      DsDc::Ds { .. } => RepeatLabel::DalSegno(self.jump_target()),
+    }.to_string()
+  }
+}
+
+// Where D.C./D.S. leads after jumping back.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum JumpTarget {
+  End,
+  AlFine,
+  AlCoda,
+}
+
+// Text or symbol to show on a bar.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RepeatLabel {
+  RepeatStart,
+  RepeatEnd,
+  Volta(VarIndex),
+  Segno,
+  // Jump point to the coda.
+  ToCoda,
+  // Start of the coda.
+  Coda,
+  Fine,
+  DaCapo(JumpTarget),
+  DalSegno(JumpTarget),
+}
+
+impl std::fmt::Display for RepeatLabel {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn suffix(target: &JumpTarget) -> &'static str {
+      match target {
+        JumpTarget::End => "",
+        JumpTarget::AlFine => " al Fine",
+        JumpTarget::AlCoda => " al Coda",
+      }
+    }
+
+    match self {
+      RepeatLabel::RepeatStart => write!(f, "|:"),
+      RepeatLabel::RepeatEnd => write!(f, ":|"),
+      RepeatLabel::Volta(idx) => write!(f, "{}.", idx.value()),
+      RepeatLabel::Segno => write!(f, "Segno"),
+      RepeatLabel::ToCoda => write!(f, "To Coda"),
+      RepeatLabel::Coda => write!(f, "Coda"),
+      RepeatLabel::Fine => write!(f, "Fine"),
+      RepeatLabel::DaCapo(target) => write!(f, "D.C.{}", suffix(target)),
+      RepeatLabel::DalSegno(target) => write!(f, "D.S.{}", suffix(target)),
+    }
+  }
+}
+
+// Labels of the bar in display order. The global repeat resolves the jump target of D.C./D.S. and
+// which coda mark is the jump point. Without it, codas are shown as Coda and jumps have no target.
+pub fn repeat_labels(bar: &Bar, global: Option<&GlobalRepeat>) -> Vec<RepeatLabel> {
+  let repeats = bar.repeats;
+  let tick = bar.base_start_tick();
+  let mut labels = vec![];
+
+  if repeats.contains(Repeat::Start) { labels.push(RepeatLabel::RepeatStart); }
+  if let Some(idx) = repeats.region_index() { labels.push(RepeatLabel::Volta(idx)); }
+  if repeats.contains(Repeat::Segno) { labels.push(RepeatLabel::Segno); }
+  if repeats.contains(Repeat::Coda) {
+    let is_jump = global.and_then(|g| g.coda).is_some_and(|[from, _]| from == tick);
+    labels.push(if is_jump { RepeatLabel::ToCoda } else { RepeatLabel::Coda });
+  }
+  if repeats.contains(Repeat::Fine) { labels.push(RepeatLabel::Fine); }
+
+  let target = global.map(|g| g.jump_target()).unwrap_or(JumpTarget::End);
+  if repeats.contains(Repeat::Dc) { labels.push(RepeatLabel::DaCapo(target)); }
+  if repeats.contains(Repeat::Ds) { labels.push(RepeatLabel::DalSegno(target)); }
+  if repeats.contains(Repeat::End) { labels.push(RepeatLabel::RepeatEnd); }
+
+  labels
+}
+
+// Resolve the global repeat of bars ordered by tick.
+pub fn global_repeat<'a>(tune_rhythm: Rhythm, bars: impl Iterator<Item = &'a Bar>) -> std::result::Result<Option<GlobalRepeat>, Report<RenderRegionError>> {
+  let mut builder = GlobalRepeatBuilder::new(tune_rhythm);
+  for bar in bars {
+    builder = builder.on_bar(bar)?;
+  }
+  Ok(builder.build()?.0)
 }
 
 impl GlobalRepeatBuilder {
@@ -298,8 +414,8 @@ impl GlobalRepeatBuilder {
 mod tests {
     use error_stack::Result;
     use interval::interval_set::*;
-    use crate::{rhythm::Rhythm, repeat::RenderRegionError};
-    use super::GlobalRepeatBuilder;
+    use crate::{rhythm::Rhythm, repeat::RenderRegionError, bar::{Bar, Repeat, RepeatSet, VarIndex}, repeat_set};
+    use super::{GlobalRepeatBuilder, global_repeat, repeat_labels, JumpTarget, RepeatLabel};
 
   #[test]
   fn dc_without_fine() -> Result<(), RenderRegionError> {
@@ -401,4 +517,37 @@ mod tests {
     Ok(())
   }
 
-}
\ No newline at end of file
+
+  #[test]
+  fn labels() {
+    let bars = [
+      Bar::new(960, None, None, repeat_set!(Repeat::Segno)),
+      Bar::new(1920, None, None, repeat_set!(Repeat::Coda)),
+      Bar::new(2880, None, None, repeat_set!(Repeat::Ds)),
+      Bar::new(3840, None, None, repeat_set!(Repeat::Coda)),
+    ];
+    let global = global_repeat(Rhythm::new(4, 4), bars.iter()).unwrap();
+    let labels: Vec<Vec<RepeatLabel>> = bars.iter().map(|b| repeat_labels(b, global.as_ref())).collect();
+    assert_eq!(labels, vec![
+      vec![RepeatLabel::Segno],
+      vec![RepeatLabel::ToCoda],
+      vec![RepeatLabel::DalSegno(JumpTarget::AlCoda)],
+      vec![RepeatLabel::Coda],
+    ]);
+    assert_eq!(labels[2][0].to_string(), "D.S. al Coda");
+
+    let bars = [
+      Bar::new(960, None, None, repeat_set!(Repeat::Fine)),
+      Bar::new(1920, None, None, repeat_set!(Repeat::Dc)),
+    ];
+    let global = global_repeat(Rhythm::new(4, 4), bars.iter()).unwrap();
+    assert_eq!(repeat_labels(&bars[1], global.as_ref())[0].to_string(), "D.C. al Fine");
+    assert_eq!(repeat_labels(&bars[1], None)[0].to_string(), "D.C.");
+
+    let bar = Bar::new(960, None, None, repeat_set!(Repeat::Var1));
+    assert_eq!(repeat_labels(&bar, None), vec![RepeatLabel::Volta(VarIndex::VI1)]);
+    let bar = Bar::new(960, None, None, repeat_set!(Repeat::Start, Repeat::End));
+    assert_eq!(repeat_labels(&bar, None), vec![RepeatLabel::RepeatStart, RepeatLabel::RepeatEnd]);
+    assert!(repeat_labels(&Bar::new(960, None, None, RepeatSet::EMPTY), None).is_empty());
+  }
+}
@@ -1,9 +1,28 @@
+use std::ops::Range;
+
+use crate::duration::{Duration, Numerator, Denominator, Dots};
+
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum GridError {
     ParseError(String),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GridKind {
+    Straight,
+    Dotted,
+    Triplet,
+    Quintuplet,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapRounding {
+    Nearest,
+    Down,
+    Up,
+}
+
 #[derive(serde::Deserialize, serde::Serialize)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Grid {
@@ -30,20 +49,45 @@ impl Grid {
         }
     }
 
+    pub fn of(numerator: Numerator, kind: GridKind) -> Self {
+        let len = Duration::new(numerator, Denominator::default(), Dots::ZERO).tick_length();
+        let value = match kind {
+            GridKind::Straight => len,
+            GridKind::Dotted => len * 3 / 2,
+            GridKind::Triplet => len * 2 / 3,
+            GridKind::Quintuplet => len * 4 / 5,
+        };
+        Self { value }
+    }
+
     #[inline]
     pub fn as_u32(self) -> u32 {
         self.value
     }
 
     #[inline]
-    pub fn snap(self, tick: i64) -> i64 {
+    pub fn snap(self, tick: i64, rounding: SnapRounding) -> i64 {
         let i = self.value as i64;
-        if tick < 0 {
-            i * ((tick - i / 2) / i)
-        } else {
-            i * ((tick + i / 2) / i)
+        match rounding {
+            SnapRounding::Nearest =>
+                if tick < 0 {
+                    i * ((tick - i / 2) / i)
+                } else {
+                    i * ((tick + i / 2) / i)
+                },
+            SnapRounding::Down => i * tick.div_euclid(i),
+            SnapRounding::Up => i * (tick + i - 1).div_euclid(i),
         }
     }
+
+    // Grid positions are counted from the bar line at the start of the bar, so a pickup bar of odd length does
+    // not shift the grid of the bars after it. The bar line at the end is always a stop. Every snapping to
+    // the grid of a score goes through here.
+    pub fn snap_in_bar(self, tick: u32, bar: Range<u32>, rounding: SnapRounding) -> u32 {
+        let offset = self.snap((tick - bar.start) as i64, rounding);
+        let snapped = bar.start.saturating_add(u32::try_from(offset).unwrap_or(u32::MAX)).min(bar.end);
+        if rounding == SnapRounding::Nearest && bar.end - tick < snapped.abs_diff(tick) { bar.end } else { snapped }
+    }
 }
 
 impl Default for Grid {
@@ -60,7 +104,18 @@ impl ToString for Grid {
 
 #[cfg(test)]
 mod tests {
-    use crate::grid::{Grid, GridError};
+    use crate::{grid::{Grid, GridError, GridKind, SnapRounding}, duration::Numerator};
+
+    #[test]
+    fn snap_in_bar() {
+        let grid = Grid::of(Numerator::Half, GridKind::Straight);
+        assert_eq!(grid.snap_in_bar(500, 240..1200, SnapRounding::Nearest), 720);
+        assert_eq!(grid.snap_in_bar(479, 240..1200, SnapRounding::Nearest), 240);
+        assert_eq!(grid.snap_in_bar(1000, 240..1200, SnapRounding::Nearest), 1200);
+        assert_eq!(grid.snap_in_bar(1000, 240..1100, SnapRounding::Up), 1100);
+        assert_eq!(grid.snap_in_bar(1000, 240..1100, SnapRounding::Nearest), 1100);
+        assert_eq!(grid.snap_in_bar(1000, 240..1200, SnapRounding::Down), 720);
+    }
 
     #[test]
     fn empty_str() {
@@ -85,15 +140,35 @@ mod tests {
 
     #[test]
     fn snap() {
-        assert_eq!(Grid::from_u32(100).unwrap().snap(49), 0);
-        assert_eq!(Grid::from_u32(100).unwrap().snap(50), 100);
-        assert_eq!(Grid::from_u32(100).unwrap().snap(99), 100);
-        assert_eq!(Grid::from_u32(100).unwrap().snap(149), 100);
-        assert_eq!(Grid::from_u32(100).unwrap().snap(150), 200);
-        assert_eq!(Grid::from_u32(100).unwrap().snap(199), 200);
-        assert_eq!(Grid::from_u32(100).unwrap().snap(249), 200);
+        assert_eq!(Grid::from_u32(100).unwrap().snap(49, SnapRounding::Nearest), 0);
+        assert_eq!(Grid::from_u32(100).unwrap().snap(50, SnapRounding::Nearest), 100);
+        assert_eq!(Grid::from_u32(100).unwrap().snap(99, SnapRounding::Nearest), 100);
+        assert_eq!(Grid::from_u32(100).unwrap().snap(149, SnapRounding::Nearest), 100);
+        assert_eq!(Grid::from_u32(100).unwrap().snap(150, SnapRounding::Nearest), 200);
+        assert_eq!(Grid::from_u32(100).unwrap().snap(199, SnapRounding::Nearest), 200);
+        assert_eq!(Grid::from_u32(100).unwrap().snap(249, SnapRounding::Nearest), 200);
  
-        assert_eq!(Grid::from_u32(100).unwrap().snap(-49), 0);
-        assert_eq!(Grid::from_u32(100).unwrap().snap(-50), -100);
+        assert_eq!(Grid::from_u32(100).unwrap().snap(-49, SnapRounding::Nearest), 0);
+        assert_eq!(Grid::from_u32(100).unwrap().snap(-50, SnapRounding::Nearest), -100);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn snap_down_up() {
+        let grid = Grid::from_u32(100).unwrap();
+        assert_eq!(grid.snap(199, SnapRounding::Down), 100);
+        assert_eq!(grid.snap(200, SnapRounding::Down), 200);
+        assert_eq!(grid.snap(-1, SnapRounding::Down), -100);
+        assert_eq!(grid.snap(101, SnapRounding::Up), 200);
+        assert_eq!(grid.snap(100, SnapRounding::Up), 100);
+        assert_eq!(grid.snap(-101, SnapRounding::Up), -100);
+    }
+
+    #[test]
+    fn kinds() {
+        assert_eq!(Grid::of(Numerator::N8th, GridKind::Straight).as_u32(), 120);
+        assert_eq!(Grid::of(Numerator::N8th, GridKind::Dotted).as_u32(), 180);
+        assert_eq!(Grid::of(Numerator::N8th, GridKind::Triplet).as_u32(), 80);
+        assert_eq!(Grid::of(Numerator::N16th, GridKind::Quintuplet).as_u32(), 48);
+        assert!(Grid::of(Numerator::N128th, GridKind::Triplet).as_u32() > 0);
+    }
+}
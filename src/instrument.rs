@@ -0,0 +1,87 @@
+use std::collections::BTreeMap;
+
+use crate::{channel::Channel, transposition::Transposition, velocity::DynamicsTable};
+
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instrument {
+    pub name: String,
+    // General MIDI program, 0 to 127.
+    pub program: u8,
+    pub transposition: Transposition,
+    // None uses the dynamics table of the project.
+    pub dynamics: Option<DynamicsTable>,
+}
+
+impl Instrument {
+    pub fn new(name: &str, program: u8) -> Self {
+        Self { name: name.to_owned(), program: program.min(127), transposition: Transposition::NONE, dynamics: None }
+    }
+
+    pub fn with_transposition(self, transposition: Transposition) -> Self {
+        Self { transposition, ..self }
+    }
+
+    pub fn with_dynamics(self, dynamics: Option<DynamicsTable>) -> Self {
+        Self { dynamics, ..self }
+    }
+}
+
+// Instruments by channel. Channels without an instrument play the default program.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct InstrumentTable {
+    instruments: BTreeMap<Channel, Instrument>,
+}
+
+impl InstrumentTable {
+    pub fn is_empty(&self) -> bool {
+        self.instruments.is_empty()
+    }
+
+    pub fn get(&self, channel: Channel) -> Option<&Instrument> {
+        self.instruments.get(&channel)
+    }
+
+    // None removes the instrument of the channel.
+    pub fn set(&mut self, channel: Channel, instrument: Option<Instrument>) {
+        match instrument {
+            Some(instrument) => { self.instruments.insert(channel, instrument); }
+            None => { self.instruments.remove(&channel); }
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Channel, &Instrument)> {
+        self.instruments.iter()
+    }
+
+    // "Cello", or "Channel 4" (numbered from 1) if the channel has no instrument.
+    pub fn channel_name(&self, channel: Channel) -> String {
+        match self.get(channel) {
+            Some(instrument) => instrument.name.clone(),
+            None => format!("Channel {}", channel.as_u8() + 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{channel::Channel, transposition::Transposition};
+    use super::{Instrument, InstrumentTable};
+
+    #[test]
+    fn table() {
+        let mut table = InstrumentTable::default();
+        table.set(Channel::new(2), Some(Instrument::new("Cello", 42)));
+        table.set(Channel::new(1), Some(Instrument::new("Clarinet", 71).with_transposition(Transposition::B_FLAT)));
+        assert_eq!(table.channel_name(Channel::new(2)), "Cello");
+        assert_eq!(table.channel_name(Channel::new(3)), "Channel 4");
+        assert_eq!(table.iter().map(|(c, _)| c.as_u8()).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(Instrument::new("Synth", 200).program, 127);
+
+        table.set(Channel::new(2), None);
+        assert!(table.get(Channel::new(2)).is_none());
+        let json = serde_json::to_string(&table).unwrap();
+        assert_eq!(serde_json::from_str::<InstrumentTable>(&json).unwrap(), table);
+    }
+}
@@ -6,6 +6,7 @@ use crate::solfa::Solfa;
 
 #[derive(serde::Deserialize, serde::Serialize)]
 #[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Key(i8);
 
 impl Key {
@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+
+use crate::{duration::{Dots, Numerator}, entry_cursor::EntryCursor, note::Note, octave::Octave, pitch::Pitch, project::Project, sharp_flat::SharpFlat, solfa::Solfa};
+
+// What a key does in note entry.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryAction {
+    // Enters the note of the letter in the current octave. Accidentals come from the key.
+    Pitch(Solfa),
+    OctaveUp,
+    OctaveDown,
+    // Selects the duration of the notes to enter. Dots are cleared.
+    Duration(Numerator),
+    // Toggles a dot of the selected duration.
+    Dot,
+    // Ties the last entered note to a new one of the same pitch.
+    Tie,
+}
+
+// Key codes of a frontend bound to entry actions. Key codes are whatever the frontend reports, such as "KeyC"
+// of KeyboardEvent.code. Serialized as JSON, this is the config format shared by the frontends.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: BTreeMap<String, EntryAction>,
+}
+
+impl Default for Keymap {
+    // Letters for pitches, arrows for octaves, digits 1 to 7 for whole to 64th notes, period for a dot and
+    // T for a tie, by KeyboardEvent.code.
+    fn default() -> Self {
+        let mut keymap = Self::empty();
+        for solfa in [Solfa::C, Solfa::D, Solfa::E, Solfa::F, Solfa::G, Solfa::A, Solfa::B] {
+            keymap.bind(&format!("Key{}", solfa), Some(EntryAction::Pitch(solfa)));
+        }
+        keymap.bind("ArrowUp", Some(EntryAction::OctaveUp));
+        keymap.bind("ArrowDown", Some(EntryAction::OctaveDown));
+        for ord in 0..Numerator::N128th.ord() {
+            keymap.bind(&format!("Digit{}", ord + 1), Numerator::from_ord(ord).map(EntryAction::Duration));
+        }
+        keymap.bind("Period", Some(EntryAction::Dot));
+        keymap.bind("KeyT", Some(EntryAction::Tie));
+        keymap
+    }
+}
+
+impl Keymap {
+    pub fn empty() -> Self {
+        Self { bindings: BTreeMap::new() }
+    }
+
+    // None unbinds the key.
+    pub fn bind(&mut self, key: &str, action: Option<EntryAction>) {
+        match action {
+            Some(action) => { self.bindings.insert(key.to_owned(), action); }
+            None => { self.bindings.remove(key); }
+        }
+    }
+
+    pub fn action(&self, key: &str) -> Option<EntryAction> {
+        self.bindings.get(key).copied()
+    }
+
+    // Keys bound to the action, to show in menus and tooltips.
+    pub fn keys_of(&self, action: EntryAction) -> impl Iterator<Item = &str> + '_ {
+        self.bindings.iter().filter(move |(_, a)| **a == action).map(|(k, _)| k.as_str())
+    }
+}
+
+// Step entry driven by entry actions. Notes are entered at the cursor with the note defaults of the project
+// and the duration of the cursor. The voice of the cursor, if any, overrides the default channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepEntry {
+    pub cursor: EntryCursor,
+    pub octave: Octave,
+    last: Option<Note>,
+}
+
+impl StepEntry {
+    pub fn new(cursor: EntryCursor, octave: Octave) -> Self {
+        Self { cursor, octave, last: None }
+    }
+
+    // The key is looked up in the keymap. Unbound keys do nothing and return false.
+    pub fn key<P: Project>(&mut self, keymap: &Keymap, key: &str, proj: &mut P) -> bool {
+        match keymap.action(key) {
+            Some(action) => { self.apply(action, proj); true }
+            None => false,
+        }
+    }
+
+    pub fn apply<P: Project>(&mut self, action: EntryAction, proj: &mut P) {
+        match action {
+            EntryAction::Pitch(solfa) => {
+                let note = self.note_at_cursor(Pitch::new(solfa, self.octave, SharpFlat::Null), proj);
+                self.enter(note, proj);
+            }
+            EntryAction::OctaveUp => self.octave = self.octave.checked_add(1).unwrap_or(self.octave),
+            EntryAction::OctaveDown => self.octave = self.octave.checked_add(-1).unwrap_or(self.octave),
+            EntryAction::Duration(numerator) => {
+                self.cursor = self.cursor.with_duration(self.cursor.duration.with_numerator(numerator).with_dots(Dots::ZERO));
+            }
+            EntryAction::Dot => {
+                let dots = if self.cursor.duration.dots == Dots::ZERO { Dots::ONE } else { Dots::ZERO };
+                self.cursor = self.cursor.with_duration(self.cursor.duration.with_dots(dots));
+            }
+            EntryAction::Tie => {
+                let Some(last) = self.last.take() else { return; };
+                let note = Note { tied: true, ..self.note_at_cursor(last.pitch, proj) };
+                proj.add_tied_note(&last, note.clone(), false);
+                self.last = Some(note);
+                self.cursor = self.cursor.advance();
+            }
+        }
+    }
+
+    fn note_at_cursor<P: Project>(&self, pitch: Pitch, proj: &P) -> Note {
+        let note = Note::from_defaults(self.cursor.tick, pitch, proj.note_defaults()).with_duration(self.cursor.duration);
+        match self.cursor.voice {
+            Some(channel) => Note { channel, ..note },
+            None => note,
+        }
+    }
+
+    fn enter<P: Project>(&mut self, note: Note, proj: &mut P) {
+        proj.add_note(note.clone(), false);
+        self.last = Some(note);
+        self.cursor = self.cursor.advance();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serdo::undo_store::UndoStore;
+
+    use crate::{channel::Channel, duration::{Dots, Duration, Numerator}, entry_cursor::EntryCursor, note_defaults::NoteDefaults, octave::Octave, project::{memory_project_store, Project, DEFAULT_MEMORY_UNDO_LIMIT}, solfa::Solfa};
+    use super::{EntryAction, Keymap, StepEntry};
+
+    #[test]
+    fn keymap() {
+        let mut keymap = Keymap::default();
+        assert_eq!(keymap.action("KeyG"), Some(EntryAction::Pitch(Solfa::G)));
+        assert_eq!(keymap.action("Digit3"), Some(EntryAction::Duration(Numerator::Quarter)));
+        assert_eq!(keymap.action("Digit8"), None);
+        keymap.bind("KeyH", Some(EntryAction::Pitch(Solfa::B)));
+        assert_eq!(keymap.keys_of(EntryAction::Pitch(Solfa::B)).collect::<Vec<_>>(), vec!["KeyB", "KeyH"]);
+        keymap.bind("KeyB", None);
+        assert_eq!(keymap.action("KeyB"), None);
+
+        let json = serde_json::to_string(&keymap).unwrap();
+        assert_eq!(serde_json::from_str::<Keymap>(&json).unwrap(), keymap);
+    }
+
+    #[test]
+    fn step_entry() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        let keymap = Keymap::default();
+        let mut entry = StepEntry::new(EntryCursor::new(0, Duration::default()), Octave::Oct4);
+        for key in ["KeyC", "Digit4", "Period", "ArrowUp", "KeyE", "Digit4", "KeyT"] {
+            entry.key(&keymap, key, &mut store);
+        }
+        assert!(!entry.key(&keymap, "Escape", &mut store));
+
+        let notes: Vec<_> = store.model().note_repo().iter().map(|(t, n)| (*t, n.pitch.solfa(), n.pitch.octave(), n.duration.dots, n.tie, n.tied)).collect();
+        assert_eq!(notes, vec![
+            (0, Solfa::C, Octave::Oct4, Dots::ZERO, false, false),
+            (240, Solfa::E, Octave::Oct5, Dots::ONE, true, false),
+            (420, Solfa::E, Octave::Oct5, Dots::ZERO, false, true),
+        ]);
+        assert_eq!(entry.cursor.tick, 540);
+
+        // The tie is a single undo step.
+        store.undo();
+        let notes: Vec<_> = store.model().note_repo().iter().map(|(t, n)| (*t, n.tie)).collect();
+        assert_eq!(notes, vec![(0, false), (240, false)]);
+    }
+
+    #[test]
+    fn voice() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.set_note_defaults(NoteDefaults::default().with_channel(Channel::new(3)));
+        let keymap = Keymap::default();
+        let mut entry = StepEntry::new(EntryCursor::new(0, Duration::default()), Octave::Oct4);
+        entry.key(&keymap, "KeyC", &mut store);
+        entry.cursor = entry.cursor.with_voice(Channel::new(5));
+        entry.key(&keymap, "KeyD", &mut store);
+
+        let channels: Vec<_> = store.model().note_repo().iter().map(|(_, n)| n.channel).collect();
+        assert_eq!(channels, vec![Channel::new(3), Channel::new(5)]);
+    }
+}
@@ -2,30 +2,73 @@ pub mod octave;
 pub mod sharp_flat;
 pub mod solfa;
 pub mod pitch;
+pub mod pitch_class;
 pub mod fly_weight;
 pub mod trimmer;
 pub mod duration;
+pub mod small_string;
 pub mod note;
+pub mod note_defaults;
 pub mod percent;
 pub mod clipper;
 pub mod rhythm;
 pub mod key;
+pub mod scale;
 pub mod can_apply;
 pub mod bar;
 pub mod tempo;
 pub mod ctrl_chg;
 pub mod have_start_tick;
+pub mod tick;
 pub mod location;
+pub mod entry_cursor;
+pub mod keymap;
 pub mod tuple;
+pub mod transform;
+pub mod sanitize;
+pub mod transposition;
+pub mod glissando;
+pub mod slur;
+pub mod comment;
+pub mod rehearsal;
+pub mod instrument;
 pub mod velocity;
 pub mod project;
+pub mod store_format;
+pub mod settings_file;
+pub mod template;
 pub mod grid;
 pub mod undo;
 pub mod models;
+pub mod dirty_region;
+pub mod content_hash;
+pub mod drag;
+pub mod beat_map;
 pub mod repeat;
 pub mod global_repeat;
 pub mod channel;
+pub mod clip;
+pub mod generate;
 pub mod play_iter;
 pub mod play_start_tick;
+pub mod score_position;
 pub mod text_input;
-pub mod exported_project;
\ No newline at end of file
+pub mod exported_project;
+pub mod playback;
+pub mod skip_region;
+pub mod render_cache;
+pub mod transport;
+pub mod exporter;
+pub mod describe;
+pub mod analysis;
+pub mod bar_inference;
+pub mod validation;
+pub mod edit_macro;
+#[cfg(feature = "sqlite")]
+pub mod save_status;
+#[cfg(feature = "practice")]
+pub mod practice;
+#[cfg(feature = "schema")]
+pub mod schema;
+#[cfg(feature = "wasm")]
+pub mod wasm;
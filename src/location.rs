@@ -2,7 +2,12 @@ use std::fmt::Display;
 
 use regex::Regex;
 
-#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+use crate::{project::{LocationError, ProjectImpl}, rhythm::Rhythm};
+
+pub type TickDelta = i64;
+
+// Ordered by bar_no, then offset.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
 pub struct Location {
     bar_no: usize,
     offset: usize,
@@ -16,6 +21,22 @@ impl Location {
     pub fn bar_no(&self) -> usize { self.bar_no }
     pub fn offset(&self) -> usize { self.offset }
     pub fn parse(s: &str) -> Option<Location> { parse_location(s) }
+
+    // Move by beats assuming every bar has the given rhythm. Stops at 0:0.
+    pub fn add_beats(self, rhythm: Rhythm, beats: i64) -> Location {
+        let bar_len = rhythm.tick_len() as i64;
+        let total = self.bar_no as i64 * bar_len + self.offset as i64 + beats * rhythm.beat_tick_len() as i64;
+        if total <= 0 {
+            Location::new(0, 0)
+        } else {
+            Location::new((total / bar_len) as usize, (total % bar_len) as usize)
+        }
+    }
+
+    // Ticks from other to self.
+    pub fn diff(self, other: Location, project: &ProjectImpl) -> Result<TickDelta, LocationError> {
+        Ok(project.location_to_tick(self)? as i64 - project.location_to_tick(other)? as i64)
+    }
 }
 
 impl Display for Location {
@@ -37,7 +58,8 @@ pub fn parse_location(s: &str) -> Option<Location> {
 
 #[cfg(test)]
 mod tests {
-    use crate::location::{parse_location, Location};
+    use crate::{location::{parse_location, Location}, rhythm::Rhythm, project::{Project, memory_project_store, DEFAULT_MEMORY_UNDO_LIMIT}, bar::{Bar, RepeatSet}};
+    use serdo::undo_store::UndoStore;
 
     #[test]
     fn parse_fail() {
@@ -51,4 +73,30 @@ mod tests {
     fn parse_ok() {
         assert_eq!(parse_location("123:456"), Some(Location::new(123, 456)));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn ordering() {
+        assert!(Location::new(1, 500) < Location::new(2, 0));
+        assert!(Location::new(2, 0) < Location::new(2, 1));
+        assert_eq!(Location::new(3, 0).max(Location::new(2, 900)), Location::new(3, 0));
+    }
+
+    #[test]
+    fn add_beats() {
+        let r = Rhythm::new(3, 4);
+        assert_eq!(Location::new(1, 0).add_beats(r, 1), Location::new(1, 240));
+        assert_eq!(Location::new(1, 480).add_beats(r, 1), Location::new(2, 0));
+        assert_eq!(Location::new(2, 120).add_beats(r, -4), Location::new(0, 600));
+        assert_eq!(Location::new(1, 0).add_beats(r, -10), Location::new(0, 0));
+        assert_eq!(Location::new(0, 0).add_beats(Rhythm::new(6, 8), 7), Location::new(1, 120));
+    }
+
+    #[test]
+    fn diff() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_bar(Bar::new(960, None, None, RepeatSet::EMPTY), false);
+        store.add_bar(Bar::new(1680, Some(Rhythm::new(3, 4)), None, RepeatSet::EMPTY), false);
+        assert_eq!(Location::new(2, 10).diff(Location::new(1, 0), store.model()).unwrap(), 730);
+        assert_eq!(Location::new(0, 0).diff(Location::new(2, 0), store.model()).unwrap(), -1680);
+    }
+}
@@ -1,10 +1,11 @@
-use std::{rc::Rc, io::Cursor};
+use std::{collections::BTreeMap, rc::Rc, io::Cursor};
 
 use serde_json::Value;
 
-use crate::{note::Note, bar::Bar, tempo::Tempo, ctrl_chg::CtrlChg};
+use crate::{channel::Channel, note::Note, bar::Bar, tempo::Tempo, ctrl_chg::CtrlChg, beat_map::BeatMap, rhythm::Rhythm, duration::Duration};
 
 #[derive(Clone, PartialEq, Debug, serde::Deserialize, serde::Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Models {
     pub notes: Vec<Note>,
     pub bars: Vec<Bar>,
@@ -13,6 +14,32 @@ pub struct Models {
     pub softs: Vec<CtrlChg>,
 }
 
+// Borrowed models visible in a viewport.
+#[derive(Debug, Default)]
+pub struct ModelsView<'a> {
+    pub notes: Vec<&'a Rc<Note>>,
+    pub bars: Vec<&'a Bar>,
+    pub tempos: Vec<&'a Tempo>,
+    pub dumpers: Vec<&'a CtrlChg>,
+    pub softs: Vec<&'a CtrlChg>,
+}
+
+impl ModelsView<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty() && self.bars.is_empty() && self.tempos.is_empty() && self.dumpers.is_empty() && self.softs.is_empty()
+    }
+
+    pub fn to_models(&self) -> Models {
+        Models {
+            notes: self.notes.iter().map(|n| (***n).clone()).collect(),
+            bars: self.bars.iter().map(|b| **b).collect(),
+            tempos: self.tempos.iter().map(|t| **t).collect(),
+            dumpers: self.dumpers.iter().map(|d| **d).collect(),
+            softs: self.softs.iter().map(|s| **s).collect(),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum FromClipboardTextErr {
     VersionErr { detected_ver: u64 },
@@ -21,8 +48,22 @@ pub enum FromClipboardTextErr {
     VersionNotU64 { err_json: String },
 }
 
+#[derive(Debug, PartialEq)]
+pub enum FromClipboardBytesErr {
+    // Neither the binary form nor UTF-8 text.
+    UnknownFormat,
+    VersionErr { detected_ver: u64 },
+    CannotParse { detail: String },
+    Text(FromClipboardTextErr),
+}
+
 impl Models {
     pub const VERSION: u64 = 1;
+    // Clipboard formats. The text form is the version followed by JSON.
+    pub const CLIPBOARD_TEXT_MIME: &'static str = "application/x-klavier-models+json";
+    pub const CLIPBOARD_BINARY_MIME: &'static str = "application/x-klavier-models";
+    // The binary form is the magic, the version in little endian u64 and bincode.
+    const CLIPBOARD_MAGIC: &'static [u8; 4] = b"KLVM";
 
     #[inline]
     pub fn unwrap_rc(notes: &[Rc<Note>]) -> Vec<Note> {
@@ -51,6 +92,10 @@ impl Models {
         }
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty() && self.bars.is_empty() && self.tempos.is_empty() && self.dumpers.is_empty() && self.softs.is_empty()
+    }
+
     pub fn move_to_tick(mut self, tick: u32) -> Self {
         let mut smallest_tick: u32 = u32::MAX;
         for n in self.notes.iter() {
@@ -91,6 +136,76 @@ impl Models {
         self
     }
 
+    // Move start ticks of a free-time performance onto the bar grid. Durations are kept as is.
+    pub fn warp_to_beatmap(mut self, map: &BeatMap) -> Self {
+        for n in self.notes.iter_mut() {
+            n.base_start_tick = map.warp(n.base_start_tick);
+        }
+        for b in self.bars.iter_mut() {
+            b.start_tick = map.warp(b.start_tick);
+        }
+        for t in self.tempos.iter_mut() {
+            t.start_tick = map.warp(t.start_tick);
+        }
+        for d in self.dumpers.iter_mut() {
+            d.start_tick = map.warp(d.start_tick);
+        }
+        for s in self.softs.iter_mut() {
+            s.start_tick = map.warp(s.start_tick);
+        }
+
+        self
+    }
+
+    // Channels of notes and pedals not in the map are kept.
+    pub fn remap_channels(mut self, map: &BTreeMap<Channel, Channel>) -> Self {
+        let remap = |c: Channel| map.get(&c).copied().unwrap_or(c);
+        for n in self.notes.iter_mut() {
+            n.channel = remap(n.channel);
+        }
+        for d in self.dumpers.iter_mut() {
+            d.channel = remap(d.channel);
+        }
+        for s in self.softs.iter_mut() {
+            s.channel = remap(s.channel);
+        }
+
+        self
+    }
+
+    // Re-bar models written in the `from` rhythm into the `to` rhythm. Models are anchored at tick 0, which is
+    // assumed to be the start of a bar. Positions in a bar are kept, or scaled to the bar length if
+    // scale_durations is set (note durations are scaled as well when the result can be written as a note).
+    // Without scaling, elements that do not fit in the destination bar are dropped. Bars are dropped.
+    pub fn fit_to_rhythm(self, from: Rhythm, to: Rhythm, scale_durations: bool) -> Self {
+        let (from_len, to_len) = (from.tick_len() as u64, to.tick_len() as u64);
+        let map = |tick: u32| -> Option<u32> {
+            let (bar, offset) = (tick as u64 / from_len, tick as u64 % from_len);
+            let offset = if scale_durations { offset * to_len / from_len } else { offset };
+            if to_len <= offset { None } else { u32::try_from(bar * to_len + offset).ok() }
+        };
+
+        let models = Models { bars: vec![], ..self }.move_to_tick(0);
+        let notes = models.notes.into_iter().filter_map(|n| {
+            let base_start_tick = map(n.base_start_tick)?;
+            let duration = if scale_durations {
+                let len = n.duration.tick_length() as u64 * to_len / from_len;
+                Duration::from_tick_length(len as u32, n.duration.denominator).unwrap_or(n.duration)
+            } else {
+                n.duration
+            };
+            Some(Note { base_start_tick, duration, ..n })
+        }).collect();
+
+        Models {
+            notes,
+            bars: vec![],
+            tempos: models.tempos.into_iter().filter_map(|t| Some(Tempo { start_tick: map(t.start_tick)?, ..t })).collect(),
+            dumpers: models.dumpers.into_iter().filter_map(|d| Some(CtrlChg { start_tick: map(d.start_tick)?, ..d })).collect(),
+            softs: models.softs.into_iter().filter_map(|s| Some(CtrlChg { start_tick: map(s.start_tick)?, ..s })).collect(),
+        }
+    }
+
     pub fn with_notes(mut self, notes: &[Rc<Note>]) -> Self {
         self.notes = Self::unwrap_rc(notes);
         self
@@ -147,6 +262,127 @@ impl Models {
             Some(Err(e)) => Err(FromClipboardTextErr::CannotParse { err_json: json, detail: e.to_string() })
         }
     }
+
+    // Compact form for large selections. The bincode layout does not depend on the enabled features of the
+    // crate, so any build reading the same VERSION can paste it. Bump VERSION when the layout changes.
+    pub fn to_clipboard_bytes(&self) -> Vec<u8> {
+        let mut bytes = Self::CLIPBOARD_MAGIC.to_vec();
+        bytes.extend(Self::VERSION.to_le_bytes());
+        bytes.extend(bincode::serialize(self).unwrap());
+        bytes
+    }
+
+    pub fn from_clipboard_bytes(bytes: &[u8]) -> Result<Self, FromClipboardBytesErr> {
+        let body = bytes.strip_prefix(Self::CLIPBOARD_MAGIC).ok_or(FromClipboardBytesErr::UnknownFormat)?;
+        let (ver, body) = body.split_first_chunk::<8>().ok_or(FromClipboardBytesErr::UnknownFormat)?;
+        let ver = u64::from_le_bytes(*ver);
+        if ver != Self::VERSION {
+            return Err(FromClipboardBytesErr::VersionErr { detected_ver: ver });
+        }
+        bincode::deserialize(body).map_err(|e| FromClipboardBytesErr::CannotParse { detail: e.to_string() })
+    }
+
+    // Either clipboard form, told apart by the magic.
+    pub fn parse_any(bytes: &[u8]) -> Result<Self, FromClipboardBytesErr> {
+        if bytes.starts_with(Self::CLIPBOARD_MAGIC) {
+            return Self::from_clipboard_bytes(bytes);
+        }
+        let text = std::str::from_utf8(bytes).map_err(|_| FromClipboardBytesErr::UnknownFormat)?;
+        Self::from_clipboard_text(text.to_owned()).map_err(FromClipboardBytesErr::Text)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelKind {
+    Note,
+    Bar,
+    Tempo,
+    Dumper,
+    Soft,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Tolerance {
+    pub tick: u32,
+    pub velocity: u8,
+}
+
+// The first difference found by Models::semantically_eq(). Index is the one after sorted by tick.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModelDiff {
+    Count { kind: ModelKind, left: usize, right: usize },
+    Note { index: usize, left: Box<Note>, right: Box<Note> },
+    Bar { index: usize, left: Bar, right: Bar },
+    Tempo { index: usize, left: Tempo, right: Tempo },
+    Dumper { index: usize, left: CtrlChg, right: CtrlChg },
+    Soft { index: usize, left: CtrlChg, right: CtrlChg },
+}
+
+fn within(l: u32, r: u32, tolerance: u32) -> bool {
+    l.abs_diff(r) <= tolerance
+}
+
+fn note_eq(l: &Note, r: &Note, tolerance: Tolerance) -> bool {
+    within(l.start_tick(), r.start_tick(), tolerance.tick)
+        && within(l.tick_len(), r.tick_len(), tolerance.tick)
+        && within(l.velocity().as_u8() as u32, r.velocity().as_u8() as u32, tolerance.velocity as u32)
+        && l.pitch == r.pitch && l.tie == r.tie && l.tied == r.tied && l.channel == r.channel
+}
+
+fn ctrl_chg_eq(l: &CtrlChg, r: &CtrlChg, tolerance: Tolerance) -> bool {
+    within(l.start_tick, r.start_tick, tolerance.tick)
+        && within(l.velocity.as_u8() as u32, r.velocity.as_u8() as u32, tolerance.velocity as u32)
+        && l.channel == r.channel
+}
+
+fn first_diff<T: Clone>(
+    kind: ModelKind, left: &[T], right: &[T], key: impl Fn(&T) -> (u32, u32),
+    eq: impl Fn(&T, &T) -> bool, diff: impl Fn(usize, T, T) -> ModelDiff,
+) -> Result<(), ModelDiff> {
+    if left.len() != right.len() {
+        return Err(ModelDiff::Count { kind, left: left.len(), right: right.len() });
+    }
+    let mut left: Vec<&T> = left.iter().collect();
+    let mut right: Vec<&T> = right.iter().collect();
+    left.sort_by_key(|t| key(t));
+    right.sort_by_key(|t| key(t));
+
+    match left.iter().zip(right.iter()).position(|(l, r)| !eq(l, r)) {
+        Some(idx) => Err(diff(idx, left[idx].clone(), right[idx].clone())),
+        None => Ok(()),
+    }
+}
+
+impl Models {
+    // Compare ignoring the order of models, how trimmers are split into slots and playback only muted flag.
+    pub fn semantically_eq(&self, other: &Models, tolerance: Tolerance) -> Result<(), ModelDiff> {
+        first_diff(
+            ModelKind::Note, &self.notes, &other.notes,
+            |n| (n.start_tick(), n.pitch.value() as u32 * 16 + n.channel.as_u8() as u32),
+            |l, r| note_eq(l, r, tolerance),
+            |index, left, right| ModelDiff::Note { index, left: Box::new(left), right: Box::new(right) },
+        )?;
+        first_diff(
+            ModelKind::Bar, &self.bars, &other.bars, |b| (b.start_tick, 0),
+            |l, r| within(l.start_tick, r.start_tick, tolerance.tick) && Bar { start_tick: r.start_tick, ..*l } == *r,
+            |index, left, right| ModelDiff::Bar { index, left, right },
+        )?;
+        first_diff(
+            ModelKind::Tempo, &self.tempos, &other.tempos, |t| (t.start_tick, 0),
+            |l, r| within(l.start_tick, r.start_tick, tolerance.tick) && l.value == r.value,
+            |index, left, right| ModelDiff::Tempo { index, left, right },
+        )?;
+        first_diff(
+            ModelKind::Dumper, &self.dumpers, &other.dumpers, |c| (c.start_tick, c.channel.as_u8() as u32),
+            |l, r| ctrl_chg_eq(l, r, tolerance),
+            |index, left, right| ModelDiff::Dumper { index, left, right },
+        )?;
+        first_diff(
+            ModelKind::Soft, &self.softs, &other.softs, |c| (c.start_tick, c.channel.as_u8() as u32),
+            |l, r| ctrl_chg_eq(l, r, tolerance),
+            |index, left, right| ModelDiff::Soft { index, left, right },
+        )
+    }
 }
 
 pub struct ModelChanges {
@@ -208,7 +444,7 @@ impl ModelChanges {
 
 #[cfg(test)]
 mod clipboard_tests {
-    use crate::{models::{Models, FromClipboardTextErr}, note::Note, pitch::Pitch, solfa::Solfa, octave::Octave, sharp_flat::SharpFlat, duration::{self, Duration, Dots}, velocity::Velocity, trimmer::{Trimmer, RateTrimmer}, bar::{Bar, RepeatSet}, tempo::Tempo, ctrl_chg::CtrlChg, channel::Channel};
+    use crate::{models::{Models, FromClipboardBytesErr, FromClipboardTextErr}, note::Note, pitch::Pitch, solfa::Solfa, octave::Octave, sharp_flat::SharpFlat, duration::{self, Duration, Dots}, velocity::Velocity, trimmer::{Trimmer, RateTrimmer}, bar::{Bar, RepeatSet}, tempo::Tempo, ctrl_chg::CtrlChg, channel::Channel};
 
     #[test]
     fn parse_empty() {
@@ -265,6 +501,33 @@ mod clipboard_tests {
         assert_eq!(restored, models);
     }
 
+    #[test]
+    fn binary_and_detection() {
+        let note = Note { base_start_tick: 100, ..Default::default() };
+        let models = Models { notes: vec![note], tempos: vec![Tempo::new(0, 90)], ..Models::empty() };
+
+        let bytes = models.to_clipboard_bytes();
+        assert_eq!(Models::from_clipboard_bytes(&bytes).unwrap(), models);
+        assert_eq!(Models::parse_any(&bytes).unwrap(), models);
+        assert_eq!(Models::parse_any(models.to_clipboard_text().as_bytes()).unwrap(), models);
+
+        let mut newer = bytes.clone();
+        newer[4] += 1;
+        assert_eq!(Models::parse_any(&newer), Err(FromClipboardBytesErr::VersionErr { detected_ver: Models::VERSION + 1 }));
+        assert_eq!(Models::parse_any(&[0xff, 0xfe]), Err(FromClipboardBytesErr::UnknownFormat));
+        assert_eq!(Models::parse_any(b""), Err(FromClipboardBytesErr::Text(FromClipboardTextErr::EmptyString)));
+        assert!(matches!(Models::parse_any(&bytes[..bytes.len() - 1]), Err(FromClipboardBytesErr::CannotParse { .. })));
+    }
+
+    #[test]
+    fn binary_layout_is_fixed() {
+        let models = Models { notes: vec![Note::default()], ..Models::empty() };
+        let bytes = models.to_clipboard_bytes();
+        assert_eq!(Models::from_clipboard_bytes(&bytes).unwrap(), models);
+        // Same with and without the practice feature. Changes whenever the layout does. Bump Models::VERSION then.
+        assert_eq!(bytes.len(), 106);
+    }
+
     #[test]
     fn move_to_tick() {
         let pitch = Pitch::new(Solfa::C, Octave::Oct0, SharpFlat::Null);
@@ -304,4 +567,71 @@ mod clipboard_tests {
         assert_eq!(models.dumpers[0].start_tick, 80);
         assert_eq!(models.softs[0].start_tick, 50);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod semantic_tests {
+    use crate::{models::{Models, ModelDiff, ModelKind, Tolerance}, note::Note, pitch::Pitch, solfa::Solfa, octave::Octave, sharp_flat::SharpFlat, trimmer::{Trimmer, RateTrimmer}, velocity::Velocity, tempo::Tempo};
+
+    fn note(tick: u32, solfa: Solfa) -> Note {
+        Note {
+            base_start_tick: tick,
+            pitch: Pitch::new(solfa, Octave::Oct4, SharpFlat::Null),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn ignores_order_and_trimmer_slots() {
+        let left = Models { notes: vec![note(0, Solfa::C), note(0, Solfa::E)], ..Models::empty() };
+        let right = Models {
+            notes: vec![
+                note(0, Solfa::E),
+                Note { base_start_tick: 10, start_tick_trimmer: Trimmer::new(-20, 10, 0, 0), ..note(0, Solfa::C) },
+            ],
+            ..Models::empty()
+        };
+        assert_eq!(left.semantically_eq(&right, Tolerance::default()), Ok(()));
+
+        let muted = Models { notes: vec![note(0, Solfa::C).with_muted(true), note(0, Solfa::E)], ..Models::empty() };
+        assert_eq!(left.semantically_eq(&muted, Tolerance::default()), Ok(()));
+
+        let rate = Models {
+            notes: vec![Note { duration_trimmer: RateTrimmer::new(1.0, 1.0, 1.0, 1.0), ..note(0, Solfa::C) }, note(0, Solfa::E)],
+            ..Models::empty()
+        };
+        assert_eq!(left.semantically_eq(&rate, Tolerance::default()), Ok(()));
+    }
+
+    #[test]
+    fn tolerance() {
+        let left = Models { notes: vec![note(0, Solfa::C)], ..Models::empty() };
+        let right = Models { notes: vec![Note { base_velocity: Velocity::new(66), ..note(2, Solfa::C) }], ..Models::empty() };
+        assert!(left.semantically_eq(&right, Tolerance::default()).is_err());
+        assert_eq!(left.semantically_eq(&right, Tolerance { tick: 2, velocity: 2 }), Ok(()));
+    }
+
+    #[test]
+    fn reports_first_difference() {
+        let left = Models {
+            notes: vec![note(0, Solfa::C), note(240, Solfa::D)],
+            tempos: vec![Tempo::new(0, 100)],
+            ..Models::empty()
+        };
+        let right = Models { notes: vec![note(0, Solfa::C), note(240, Solfa::E)], ..Models::empty() };
+        assert_eq!(
+            left.semantically_eq(&right, Tolerance::default()),
+            Err(ModelDiff::Note { index: 1, left: Box::new(note(240, Solfa::D)), right: Box::new(note(240, Solfa::E)) })
+        );
+
+        let right = Models { notes: vec![note(0, Solfa::C), note(240, Solfa::D)], tempos: vec![Tempo::new(0, 101)], ..Models::empty() };
+        assert_eq!(
+            left.semantically_eq(&right, Tolerance::default()),
+            Err(ModelDiff::Tempo { index: 0, left: Tempo::new(0, 100), right: Tempo::new(0, 101) })
+        );
+        assert_eq!(
+            left.semantically_eq(&Models::empty(), Tolerance::default()),
+            Err(ModelDiff::Count { kind: ModelKind::Note, left: 2, right: 0 })
+        );
+    }
+}
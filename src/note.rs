@@ -8,25 +8,57 @@ use crate::clipper::Clipper;
 use crate::clipper;
 use crate::can_apply::CanApply;
 use crate::duration::Duration;
+use crate::note_defaults::NoteDefaults;
 use crate::trimmer::RateTrimmer;
 use crate::pitch::Pitch;
+use crate::small_string::SmallString;
+#[cfg(not(feature = "practice"))]
+use crate::store_format::NoPlayProbability;
 use super::duration::{Numerator, Dots, Denominator};
 use super::have_start_tick::{HaveBaseStartTick, HaveStartTick};
 use super::percent::PercentU16;
 use super::pitch::PitchError;
+use super::tick::Tick;
 use super::trimmer::Trimmer;
 use super::velocity::{Velocity, self};
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum TickError {
     Minus,
+    Overflow,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct InvalidDot(i32);
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SplitError {
+    // The tick is not inside of the note.
+    OutOfRange(u32),
+    // The part of the given tick length cannot be written as a single note.
+    NotNotatable(u32),
+}
+
+// User label on a note for grouping such as motifs or voices. Renderers may paint notes in the color.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct NoteTag {
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub label: SmallString,
+    // 0xRRGGBB.
+    pub color: Option<u32>,
+}
+
+impl NoteTag {
+    pub fn new(label: SmallString, color: Option<u32>) -> Self {
+        Self { label, color }
+    }
+}
+
 #[derive(serde::Deserialize, serde::Serialize)]
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Note {
     pub base_start_tick: u32,
     pub pitch: Pitch,
@@ -38,6 +70,20 @@ pub struct Note {
     pub duration_trimmer: RateTrimmer,
     pub velocity_trimmer: Trimmer,
     pub channel: Channel,
+    // Silenced in playback only. Notation and exporters still see the note.
+    #[serde(default)]
+    pub muted: bool,
+    #[serde(default)]
+    pub tag: Option<NoteTag>,
+    // Chance to sound in practice playback. None always sounds. Exporters ignore it.
+    #[cfg(feature = "practice")]
+    #[serde(default)]
+    pub play_probability: Option<PercentU16>,
+    // Zero sized. Keeps the layout of the practice build.
+    #[cfg(not(feature = "practice"))]
+    #[serde(default)]
+    #[cfg_attr(feature = "schema", schemars(skip))]
+    pub play_probability: NoPlayProbability,
 }
 
 impl Note {
@@ -63,10 +109,28 @@ impl Note {
             start_tick_trimmer,
             duration_trimmer,
             velocity_trimmer,
-            channel
+            channel,
+            muted: false,
+            tag: None,
+            play_probability: Default::default(),
         }
     }
     
+    pub fn from_defaults(base_start_tick: u32, pitch: Pitch, defaults: &NoteDefaults) -> Self {
+        Self::new(
+            base_start_tick,
+            pitch,
+            defaults.duration,
+            false,
+            false,
+            defaults.velocity,
+            Trimmer::ZERO,
+            defaults.articulation.duration_trimmer(),
+            defaults.articulation.velocity_trimmer(),
+            defaults.channel,
+        )
+    }
+
     #[inline]
     pub fn start_tick(&self) -> u32 {
         let tick = self.base_start_tick as i64 + self.start_tick_trimmer.sum() as i64;
@@ -116,30 +180,26 @@ impl Note {
     }
     
     pub fn with_tick_added(&self, tick_delta: i32, is_trim: bool) -> Result<Self, TickError> {
-        let tick = self.base_start_tick as i64 + tick_delta as i64;
-        if tick < 0 {
-            Err(TickError::Minus)
+        let tick = Tick::new(self.base_start_tick).checked_add_signed(tick_delta as i64)?;
+        if is_trim {
+            let mut copied = self.clone();
+            copied.start_tick_trimmer = self.start_tick_trimmer.added(tick_delta);
+            Ok(copied)
         } else {
-            if is_trim {
-                let mut copied = self.clone();
-                copied.start_tick_trimmer = self.start_tick_trimmer.added(tick_delta);
-                Ok(copied)
-            } else {
-                Ok(
-                    Self {
-                        base_start_tick: tick as u32,
-                        ..*self
-                    }
-                )
-            }
+            Ok(
+                Self {
+                    base_start_tick: tick.as_u32(),
+                    ..*self
+                }
+            )
         }
     }
     
     pub fn drag(&self, tick_delta: i32, score_offset_delta: i32) -> Self {
-        let tick = self.base_start_tick as i64 + tick_delta as i64;
+        let tick = Tick::new(self.base_start_tick).saturating_add_signed(tick_delta as i64);
         let pitch = self.pitch.with_score_offset_delta(score_offset_delta).unwrap();
         Self {
-            base_start_tick: tick as u32,
+            base_start_tick: tick.as_u32(),
             pitch: pitch,
             ..*self
         }
@@ -210,6 +270,83 @@ impl Note {
         }
     }
 
+    // Split into two tied notes at the tick. The tick length of the duration (without trimmers) is split.
+    // Both parts should be notatable as a single (dotted) note in the same tuplet, or as a plain one.
+    pub fn split_at(&self, tick: u32) -> Result<(Note, Note), SplitError> {
+        let len = self.duration.tick_length();
+        if tick <= self.base_start_tick || self.base_start_tick + len <= tick {
+            return Err(SplitError::OutOfRange(tick));
+        }
+        let first_len = tick - self.base_start_tick;
+        let second_len = len - first_len;
+        let duration_of = |ticks: u32| Duration::from_tick_length(ticks, self.duration.denominator)
+            .or_else(|| Duration::from_tick_length(ticks, Denominator::from_value(2).unwrap()))
+            .ok_or(SplitError::NotNotatable(ticks));
+
+        let first = Self {
+            duration: duration_of(first_len)?,
+            tie: true,
+            ..*self
+        };
+        let second = Self {
+            base_start_tick: tick,
+            duration: duration_of(second_len)?,
+            tied: true,
+            start_tick_trimmer: Trimmer::ZERO,
+            ..*self
+        };
+        Ok((first, second))
+    }
+
+    pub fn with_muted(&self, muted: bool) -> Note {
+        Self {
+            muted,
+            ..*self
+        }
+    }
+
+    pub fn with_tag(&self, tag: Option<NoteTag>) -> Note {
+        Self {
+            tag,
+            ..*self
+        }
+    }
+
+    #[cfg(feature = "practice")]
+    pub fn with_play_probability(&self, play_probability: Option<PercentU16>) -> Note {
+        Self {
+            play_probability,
+            ..*self
+        }
+    }
+
+    // Bake trimmers into base values so that the note sounds the same without trimmers.
+    // The duration trimmer is kept if the trimmed length cannot be written as a single note.
+    pub fn with_trimmers_folded(&self) -> Note {
+        let (duration, duration_trimmer) = match Duration::from_tick_length(self.tick_len(), self.duration.denominator) {
+            Some(d) => (d, RateTrimmer::ONE),
+            None => (self.duration, self.duration_trimmer),
+        };
+        Self {
+            base_start_tick: self.start_tick(),
+            base_velocity: self.velocity(),
+            duration,
+            start_tick_trimmer: Trimmer::ZERO,
+            duration_trimmer,
+            velocity_trimmer: Trimmer::ZERO,
+            ..*self
+        }
+    }
+
+    pub fn with_trimmers_cleared(&self) -> Note {
+        Self {
+            start_tick_trimmer: Trimmer::ZERO,
+            duration_trimmer: RateTrimmer::ONE,
+            velocity_trimmer: Trimmer::ZERO,
+            ..*self
+        }
+    }
+
     #[inline]
     pub fn base_velocity(&self) -> Velocity {
         self.base_velocity
@@ -237,6 +374,9 @@ impl Default for Note {
             duration_trimmer: Default::default(),
             velocity_trimmer: Default::default(),
             channel: Default::default(),
+            muted: false,
+            tag: None,
+            play_probability: Default::default(),
         }
     }
 }
@@ -279,7 +419,7 @@ impl HaveStartTick for Rc<Note> {
 
 #[cfg(test)]
 mod tests {
-    use crate::{note::Note, pitch::{Pitch, self}, solfa::Solfa, octave::Octave, sharp_flat::SharpFlat, duration::{Duration, Numerator, Denominator, Dots}, trimmer::{Trimmer, RateTrimmer}, velocity::Velocity, channel::Channel};
+    use crate::{note::{Note, SplitError}, pitch::{Pitch, self}, solfa::Solfa, octave::Octave, sharp_flat::SharpFlat, duration::{Duration, Numerator, Denominator, Dots}, trimmer::{Trimmer, RateTrimmer}, velocity::Velocity, channel::Channel};
     
     #[test]
     fn tick_len() {
@@ -348,4 +488,52 @@ mod tests {
         assert_eq!(note.with_tick_added(-123, true).unwrap().start_tick(), 0);
         assert!(note.with_tick_added(-124, true).is_err());
     }
+
+    #[test]
+    fn split_at() {
+        let d2 = Denominator::from_value(2).unwrap();
+        let note = Note {
+            base_start_tick: 100,
+            duration: Duration::new(Numerator::Half, d2, Dots::ONE),
+            tied: true,
+            start_tick_trimmer: Trimmer::new(5, 0, 0, 0),
+            ..Default::default()
+        };
+        let (first, second) = note.split_at(580).unwrap();
+        assert_eq!(first.duration, Duration::new(Numerator::Half, d2, Dots::ZERO));
+        assert_eq!((first.base_start_tick, first.tie, first.tied), (100, true, true));
+        assert_eq!(first.start_tick_trimmer, note.start_tick_trimmer);
+        assert_eq!(second.duration, Duration::new(Numerator::Quarter, d2, Dots::ZERO));
+        assert_eq!((second.base_start_tick, second.tie, second.tied), (580, false, true));
+        assert_eq!(second.start_tick_trimmer, Trimmer::ZERO);
+
+        assert_eq!(note.split_at(100), Err(SplitError::OutOfRange(100)));
+        assert_eq!(note.split_at(820), Err(SplitError::OutOfRange(820)));
+        assert_eq!(note.split_at(400), Err(SplitError::NotNotatable(300)));
+    }
+
+    #[test]
+    fn fold_trimmers() {
+        let d2 = Denominator::from_value(2).unwrap();
+        let note = Note {
+            base_start_tick: 100,
+            duration: Duration::new(Numerator::Quarter, d2, Dots::ZERO),
+            base_velocity: Velocity::new(100),
+            start_tick_trimmer: Trimmer::new(-10, 0, 0, 0),
+            duration_trimmer: RateTrimmer::new(0.5, 1.0, 1.0, 1.0),
+            velocity_trimmer: Trimmer::new(50, 0, 0, 0),
+            ..Default::default()
+        };
+        let folded = note.with_trimmers_folded();
+        assert_eq!((folded.base_start_tick, folded.base_velocity), (90, Velocity::new(127)));
+        assert_eq!(folded.duration, Duration::new(Numerator::N8th, d2, Dots::ZERO));
+        assert_eq!((folded.start_tick_trimmer, folded.duration_trimmer, folded.velocity_trimmer), (Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO));
+
+        let odd = Note { duration_trimmer: RateTrimmer::new(0.9, 1.0, 1.0, 1.0), ..note.clone() }.with_trimmers_folded();
+        assert_eq!(odd.duration, note.duration);
+        assert_eq!(odd.duration_trimmer, RateTrimmer::new(0.9, 1.0, 1.0, 1.0));
+
+        let cleared = note.with_trimmers_cleared();
+        assert_eq!((cleared.base_start_tick, cleared.start_tick(), cleared.tick_len(), cleared.velocity()), (100, 100, 240, Velocity::new(100)));
+    }
 }
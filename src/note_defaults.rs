@@ -0,0 +1,89 @@
+use crate::{channel::Channel, duration::Duration, trimmer::{RateTrimmer, Trimmer}, velocity::Velocity};
+
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Articulation {
+    #[default]
+    Normal,
+    Staccato,
+    Staccatissimo,
+    Tenuto,
+    Accent,
+}
+
+pub const ACCENT_VELOCITY: i16 = 16;
+
+impl Articulation {
+    pub fn duration_trimmer(self) -> RateTrimmer {
+        match self {
+            Articulation::Staccato => RateTrimmer::new(0.5, 1.0, 1.0, 1.0),
+            Articulation::Staccatissimo => RateTrimmer::new(0.25, 1.0, 1.0, 1.0),
+            _ => RateTrimmer::ONE,
+        }
+    }
+
+    pub fn velocity_trimmer(self) -> Trimmer {
+        match self {
+            Articulation::Accent => Trimmer::new(ACCENT_VELOCITY, 0, 0, 0),
+            _ => Trimmer::ZERO,
+        }
+    }
+}
+
+// Properties used for notes entered by step entry or click-to-insert.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NoteDefaults {
+    pub duration: Duration,
+    pub velocity: Velocity,
+    pub channel: Channel,
+    pub articulation: Articulation,
+}
+
+impl NoteDefaults {
+    pub fn with_duration(self, duration: Duration) -> Self {
+        Self { duration, ..self }
+    }
+
+    pub fn with_velocity(self, velocity: Velocity) -> Self {
+        Self { velocity, ..self }
+    }
+
+    pub fn with_channel(self, channel: Channel) -> Self {
+        Self { channel, ..self }
+    }
+
+    pub fn with_articulation(self, articulation: Articulation) -> Self {
+        Self { articulation, ..self }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{note_defaults::{Articulation, NoteDefaults}, note::Note, pitch::Pitch, solfa::Solfa, octave::Octave, sharp_flat::SharpFlat, duration::{Duration, Numerator, Denominator, Dots}, velocity::Velocity, channel::Channel};
+
+    #[test]
+    fn from_defaults() {
+        let defaults = NoteDefaults::default()
+            .with_duration(Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO))
+            .with_velocity(Velocity::new(80))
+            .with_channel(Channel::new(2))
+            .with_articulation(Articulation::Staccato);
+        let pitch = Pitch::new(Solfa::E, Octave::Oct4, SharpFlat::Null);
+        let note = Note::from_defaults(480, pitch, &defaults);
+        assert_eq!(note.base_start_tick, 480);
+        assert_eq!(note.pitch, pitch);
+        assert_eq!(note.duration.numerator, Numerator::N8th);
+        assert_eq!(note.tick_len(), 60);
+        assert_eq!(note.velocity(), Velocity::new(80));
+        assert_eq!(note.channel, Channel::new(2));
+    }
+
+    #[test]
+    fn accent() {
+        let defaults = NoteDefaults::default().with_articulation(Articulation::Accent);
+        let note = Note::from_defaults(0, Pitch::default(), &defaults);
+        assert_eq!(note.velocity(), Velocity::new(80));
+        assert_eq!(note.tick_len(), 240);
+    }
+}
@@ -1,4 +1,4 @@
-use std::ops::{Add, AddAssign, SubAssign};
+use std::ops::{Add, AddAssign, RangeInclusive, SubAssign};
 use std::fmt;
 
 #[derive(Debug)]
@@ -18,6 +18,7 @@ impl fmt::Display for OctaveError {
 
 #[derive(serde::Deserialize, serde::Serialize)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum Octave {
     OctM2, OctM1, Oct0, Oct1, Oct2, Oct3, Oct4, Oct5, Oct6, Oct7, Oct8,
 }
@@ -39,13 +40,33 @@ impl Octave {
     }
 
     pub const fn from_score_offset(idx: i32) -> Result<Octave, OctaveError> {
-        if idx < 0 || Self::ALL.len() < (idx as usize) {
+        if idx < 0 || Self::ALL.len() <= (idx as usize) {
             Err(OctaveError::InvalidValue(idx))
         } else {
             Ok(Self::ALL[idx as usize])
         }
     }
 
+    pub fn iter() -> impl Iterator<Item = Octave> {
+        Self::ALL.iter().copied()
+    }
+
+    pub fn range(range: RangeInclusive<Octave>) -> impl Iterator<Item = Octave> {
+        let (start, end) = (range.start().offset() as usize, range.end().offset() as usize);
+        Self::ALL.iter().copied().skip(start).take((end + 1).saturating_sub(start))
+    }
+
+    pub const fn octaves_to(self, other: Octave) -> i32 {
+        other.value() - self.value()
+    }
+
+    pub const fn checked_add(self, octaves: i32) -> Option<Octave> {
+        match Self::value_of(self.value() + octaves) {
+            Ok(o) => Some(o),
+            Err(_) => None,
+        }
+    }
+
     pub const fn offset(self) -> i32 {
         self.value() + Self::BIAS_VALUE
     }
@@ -88,7 +109,7 @@ impl Add for Octave {
 
 #[cfg(test)]
 mod tests {
-    use crate::octave::Octave;
+    use crate::octave::{Octave, self};
 
     #[test]
     #[should_panic]
@@ -102,6 +123,11 @@ mod tests {
         Octave::value_of(10).unwrap();
     }
 
+    #[test]
+    fn value_just_above_max() {
+        assert!(Octave::value_of(9).is_err());
+    }
+
     #[test]
     fn can_read_value() {
         assert_eq!(Octave::value_of(1).unwrap().value(), 1);
@@ -155,4 +181,25 @@ mod tests {
         let mut oct = Octave::Oct0;
         oct -= 3;
     }
+
+    #[test]
+    fn range() {
+        assert_eq!(Octave::range(octave::MIN..=octave::MAX).collect::<Vec<_>>(), Octave::ALL.to_vec());
+        assert_eq!(Octave::range(Octave::Oct3..=Octave::Oct5).collect::<Vec<_>>(), vec![Octave::Oct3, Octave::Oct4, Octave::Oct5]);
+        assert_eq!(Octave::range(Octave::Oct5..=Octave::Oct3).count(), 0);
+        assert_eq!(Octave::iter().count(), 11);
+    }
+
+    #[test]
+    fn octaves_to() {
+        assert_eq!(Octave::Oct2.octaves_to(Octave::Oct5), 3);
+        assert_eq!(Octave::Oct2.octaves_to(Octave::OctM1), -3);
+    }
+
+    #[test]
+    fn checked_add() {
+        assert_eq!(Octave::Oct7.checked_add(1), Some(Octave::Oct8));
+        assert_eq!(Octave::Oct8.checked_add(1), None);
+        assert_eq!(Octave::OctM1.checked_add(-2), None);
+    }
 }
@@ -3,6 +3,7 @@ use crate::can_apply::CanApply;
 // 0.0% - 200.0%
 #[derive(serde::Deserialize, serde::Serialize)]
 #[derive(Debug, Clone, Copy, Eq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct PercentU16 {
     value: u16,
 }
@@ -31,6 +32,36 @@ impl PercentU16 {
     pub fn to_f32(self) -> f32 {
         (self.value as f32) / 1000.0
     }
+
+    pub fn value(self) -> u16 {
+        self.value
+    }
+
+    // None if the value exceeds 200%.
+    pub const fn checked_from_value(value: u16) -> Option<PercentU16> {
+        if value <= MAX_VALUE as u16 { Some(PercentU16 { value }) } else { None }
+    }
+
+    // None if the product exceeds 200%.
+    pub fn checked_mul(self, other: PercentU16) -> Option<PercentU16> {
+        PercentU32::from(self).saturating_mul(other.into()).checked_to_u16()
+    }
+
+    pub fn saturating_mul(self, other: PercentU16) -> PercentU16 {
+        PercentU32::from(self).saturating_mul(other.into()).saturating_to_u16()
+    }
+
+    pub fn checked_add(self, other: PercentU16) -> Option<PercentU16> {
+        Self::checked_from_value(self.value + other.value)
+    }
+
+    pub fn saturating_add(self, other: PercentU16) -> PercentU16 {
+        Self::from_value((self.value + other.value).min(MAX_VALUE as u16))
+    }
+
+    pub fn saturating_sub(self, other: PercentU16) -> PercentU16 {
+        Self::from_value(self.value.saturating_sub(other.value))
+    }
 }
 
 impl CanApply<u32> for PercentU16 {
@@ -69,9 +100,110 @@ impl PartialEq for PercentU16 {
     }
 }
 
+// Same resolution (0.1%) as PercentU16 without the 200% cap. Used to accumulate chains of rates.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct PercentU32 {
+    value: u32,
+}
+
+impl PercentU32 {
+    pub const MAX: PercentU32 = PercentU32 { value: u32::MAX };
+    pub const MIN: PercentU32 = PercentU32 { value: 0 };
+    pub const ZERO: PercentU32 = PercentU32::MIN;
+    pub const HUNDRED: PercentU32 = PercentU32 { value: HUNDRED_VALUE as u32 };
+
+    pub const fn from_value(value: u32) -> PercentU32 {
+        PercentU32 { value }
+    }
+
+    pub fn value(self) -> u32 {
+        self.value
+    }
+
+    pub fn to_f32(self) -> f32 {
+        (self.value as f32) / 1000.0
+    }
+
+    pub fn checked_mul(self, other: PercentU32) -> Option<PercentU32> {
+        let v = self.value as u64 * other.value as u64 / HUNDRED_VALUE as u64;
+        u32::try_from(v).ok().map(Self::from_value)
+    }
+
+    pub fn saturating_mul(self, other: PercentU32) -> PercentU32 {
+        self.checked_mul(other).unwrap_or(Self::MAX)
+    }
+
+    pub fn checked_add(self, other: PercentU32) -> Option<PercentU32> {
+        self.value.checked_add(other.value).map(Self::from_value)
+    }
+
+    pub fn saturating_add(self, other: PercentU32) -> PercentU32 {
+        Self::from_value(self.value.saturating_add(other.value))
+    }
+
+    pub fn saturating_sub(self, other: PercentU32) -> PercentU32 {
+        Self::from_value(self.value.saturating_sub(other.value))
+    }
+
+    // None if the value exceeds 200%.
+    pub fn checked_to_u16(self) -> Option<PercentU16> {
+        u16::try_from(self.value).ok().and_then(PercentU16::checked_from_value)
+    }
+
+    // Clamped to 200%.
+    pub fn saturating_to_u16(self) -> PercentU16 {
+        self.checked_to_u16().unwrap_or(PercentU16::MAX)
+    }
+
+    // Product of rates without intermediate capping.
+    pub fn product<I: IntoIterator<Item = PercentU32>>(rates: I) -> PercentU32 {
+        rates.into_iter().fold(Self::HUNDRED, |acc, r| acc.saturating_mul(r))
+    }
+}
+
+impl Default for PercentU32 {
+    fn default() -> Self {
+        Self::HUNDRED
+    }
+}
+
+impl From<PercentU16> for PercentU32 {
+    fn from(p: PercentU16) -> Self {
+        Self::from_value(p.value as u32)
+    }
+}
+
+impl From<f32> for PercentU32 {
+    fn from(rate: f32) -> Self {
+        if rate.is_nan() || rate <= 0. {
+            Self::MIN
+        } else {
+            // Float to int casts saturate.
+            Self::from_value((rate * 1000.0) as u32)
+        }
+    }
+}
+
+impl CanApply<u32> for PercentU32 {
+    // Saturates at u32::MAX.
+    fn apply(self, value: u32) -> u32 {
+        let v = value as u64 * self.value as u64 / HUNDRED_VALUE as u64;
+        v.min(u32::MAX as u64) as u32
+    }
+}
+
+impl std::fmt::Display for PercentU32 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_f32())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::percent::PercentU16;
+    use crate::percent::PercentU32;
     use crate::percent::CanApply;
 
     #[test]
@@ -111,4 +243,37 @@ mod tests {
         let u: PercentU16 = From::from(-100.);
         assert_eq!(u, PercentU16::MIN);
     }
+
+    #[test]
+    fn checked_arithmetic() {
+        let p15 = PercentU16::from(1.5);
+        assert_eq!(p15.checked_mul(PercentU16::from(1.2)), Some(PercentU16::from_value(1800)));
+        assert_eq!(p15.checked_mul(p15), None);
+        assert_eq!(p15.saturating_mul(p15), PercentU16::MAX);
+        assert_eq!(p15.checked_add(PercentU16::from(0.5)), Some(PercentU16::MAX));
+        assert_eq!(p15.checked_add(PercentU16::from(0.6)), None);
+        assert_eq!(p15.saturating_add(p15), PercentU16::MAX);
+        assert_eq!(PercentU16::from(0.5).saturating_sub(p15), PercentU16::ZERO);
+        assert_eq!(PercentU16::checked_from_value(2001), None);
+    }
+
+    #[test]
+    fn percent_u32() {
+        let chain = PercentU32::product([PercentU16::from(1.5).into(), PercentU16::from(2.0).into(), PercentU32::from(0.5)]);
+        assert_eq!(chain, PercentU32::from_value(1500));
+        assert_eq!(chain.saturating_to_u16(), PercentU16::from(1.5));
+
+        let wide = PercentU32::product([PercentU32::from(3.0), PercentU32::from(2.0)]);
+        assert_eq!(wide.to_f32(), 6.0);
+        assert_eq!(wide.apply(100), 600);
+        assert_eq!(wide.checked_to_u16(), None);
+        assert_eq!(wide.saturating_to_u16(), PercentU16::MAX);
+
+        assert_eq!(PercentU32::MAX.checked_mul(PercentU32::from(2.0)), None);
+        assert_eq!(PercentU32::MAX.saturating_mul(PercentU32::from(2.0)), PercentU32::MAX);
+        assert_eq!(PercentU32::MAX.apply(u32::MAX), u32::MAX);
+        assert_eq!(PercentU32::from(f32::NAN), PercentU32::ZERO);
+        assert_eq!(PercentU32::from(-1.0), PercentU32::ZERO);
+        assert_eq!(PercentU32::from(1e12), PercentU32::MAX);
+    }
 }
@@ -2,6 +2,7 @@ use crate::{solfa::Solfa, key::Key};
 use crate::octave::Octave;
 use crate::sharp_flat::SharpFlat;
 use std::fmt::{self};
+use std::str::FromStr;
 
 use super::octave;
 
@@ -10,6 +11,7 @@ pub enum PitchError {
     TooLow(Solfa, Octave, SharpFlat, i32),
     TooHigh(Solfa, Octave, SharpFlat, i32),
     InvalidScoreOffset(i32),
+    CannotSpell(Solfa, Octave, i32),
 }
 
 impl fmt::Display for PitchError {
@@ -23,7 +25,10 @@ impl fmt::Display for PitchError {
             ),
             PitchError::InvalidScoreOffset(score_offset) => f.write_fmt(
                 format_args!("Score offset error({})", score_offset)
-            )
+            ),
+            PitchError::CannotSpell(solfa, octave, value) => f.write_fmt(
+                format_args!("Cannot spell {} with {:?}{:?}", value, solfa, octave)
+            ),
         }
     }
 }
@@ -31,6 +36,7 @@ impl fmt::Display for PitchError {
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(from = "PitchSerializedForm")]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Pitch {
     solfa: Solfa,
     octave: Octave,
@@ -230,10 +236,142 @@ impl Pitch {
         self.octave
     }
 
+    // Octave number of scientific pitch notation where the middle C (60) is C4. It is Oct3 here.
+    #[inline]
+    pub fn scientific_octave(self) -> i32 {
+        self.octave.value() + 1
+    }
+
     #[inline]
     pub fn value(self) -> u8 {
         self.value
     }
+
+    // Diatonic steps from self up to other, ignoring accidentals.
+    #[inline]
+    pub fn steps_to(self, other: Pitch) -> i32 {
+        other.score_offset as i32 - self.score_offset as i32
+    }
+
+    #[inline]
+    pub fn semitones_to(self, other: Pitch) -> i32 {
+        other.value as i32 - self.value as i32
+    }
+
+    // Move by diatonic steps and spell the result with an accidental so that it is the given semitones apart.
+    pub fn transposed(self, steps: i32, semitones: i32) -> Result<Self, PitchError> {
+        let moved = self.with_score_offset_delta(steps)?;
+        let (solfa, octave) = (moved.solfa, moved.octave);
+        let value = self.value as i32 + semitones;
+        let sharp_flat = match value - Self::to_value(solfa, octave, SharpFlat::Null) {
+            -2 => SharpFlat::DoubleFlat,
+            -1 => SharpFlat::Flat,
+            0 => if self.sharp_flat == SharpFlat::Null { SharpFlat::Null } else { SharpFlat::Natural },
+            1 => SharpFlat::Sharp,
+            2 => SharpFlat::DoubleSharp,
+            _ => return Err(PitchError::CannotSpell(solfa, octave, value)),
+        };
+        Self::value_of(solfa, octave, sharp_flat)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PitchParseError {
+    CannotParse(String),
+    InvalidOctave(String),
+    OutOfRange(String),
+}
+
+impl fmt::Display for PitchParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PitchParseError::CannotParse(s) => write!(f, "Cannot parse pitch '{}'", s),
+            PitchParseError::InvalidOctave(s) => write!(f, "Invalid octave in '{}'", s),
+            PitchParseError::OutOfRange(s) => write!(f, "Pitch '{}' is out of range", s),
+        }
+    }
+}
+
+// Longer names first so that "Sol" is not taken as "So" + "l".
+const SOLFEGE_NAMES: &[(&str, Solfa)] = &[
+    ("sol", Solfa::G), ("do", Solfa::C), ("re", Solfa::D), ("mi", Solfa::E), ("fa", Solfa::F),
+    ("so", Solfa::G), ("la", Solfa::A), ("si", Solfa::B), ("ti", Solfa::B),
+];
+
+const ACCIDENTALS: &[(&str, SharpFlat)] = &[
+    ("##", SharpFlat::DoubleSharp), ("x", SharpFlat::DoubleSharp), ("\u{1D12A}", SharpFlat::DoubleSharp),
+    ("#", SharpFlat::Sharp), ("\u{266F}", SharpFlat::Sharp),
+    ("bb", SharpFlat::DoubleFlat), ("\u{1D12B}", SharpFlat::DoubleFlat),
+    ("b", SharpFlat::Flat), ("\u{266D}", SharpFlat::Flat),
+    ("n", SharpFlat::Natural), ("\u{266E}", SharpFlat::Natural),
+];
+
+fn parse_solfa(s: &str) -> Option<(Solfa, &str)> {
+    for (name, solfa) in SOLFEGE_NAMES {
+        if let Some(head) = s.get(..name.len()) {
+            if head.eq_ignore_ascii_case(name) {
+                return Some((*solfa, &s[name.len()..]));
+            }
+        }
+    }
+
+    let solfa = match s.chars().next()?.to_ascii_uppercase() {
+        'C' => Solfa::C,
+        'D' => Solfa::D,
+        'E' => Solfa::E,
+        'F' => Solfa::F,
+        'G' => Solfa::G,
+        'A' => Solfa::A,
+        'B' => Solfa::B,
+        _ => return None,
+    };
+    Some((solfa, &s[1..]))
+}
+
+fn parse_sharp_flat(s: &str) -> (SharpFlat, &str) {
+    for (symbol, sharp_flat) in ACCIDENTALS {
+        if let Some(rest) = s.strip_prefix(symbol) {
+            return (*sharp_flat, rest);
+        }
+    }
+    (SharpFlat::Null, s)
+}
+
+const fn sharp_flat_symbol(sharp_flat: SharpFlat) -> &'static str {
+    match sharp_flat {
+        SharpFlat::Sharp => "#",
+        SharpFlat::DoubleSharp => "##",
+        SharpFlat::Flat => "b",
+        SharpFlat::DoubleFlat => "bb",
+        SharpFlat::Natural => "n",
+        SharpFlat::Null => "",
+    }
+}
+
+// Scientific pitch notation ("C#4", "Bb-1") or solfege ("Do#4", "Sol4").
+impl FromStr for Pitch {
+    type Err = PitchParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (solfa, rest) = parse_solfa(s).ok_or_else(|| PitchParseError::CannotParse(s.to_owned()))?;
+        let (sharp_flat, rest) = parse_sharp_flat(rest);
+        let octave = i32::from_str(rest).ok().and_then(|o| Octave::value_of(o - 1).ok())
+            .ok_or_else(|| PitchParseError::InvalidOctave(s.to_owned()))?;
+        Pitch::value_of(solfa, octave, sharp_flat).map_err(|_| PitchParseError::OutOfRange(s.to_owned()))
+    }
+}
+
+impl fmt::Display for Pitch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}{}", self.solfa, sharp_flat_symbol(self.sharp_flat), self.scientific_octave())
+    }
+}
+
+impl Pitch {
+    pub fn to_solfege_string(self) -> String {
+        format!("{}{}{}", self.solfa.solfege(), sharp_flat_symbol(self.sharp_flat), self.scientific_octave())
+    }
 }
 
 #[cfg(test)]
@@ -242,6 +380,7 @@ mod tests {
     use crate::pitch::MAX_SCORE_OFFSET;
     use crate::pitch::MIN_SCORE_OFFSET;
     use crate::pitch::Pitch;
+    use crate::pitch::PitchParseError;
     use crate::pitch::MIN;
     use crate::pitch::MAX;
     use crate::solfa::Solfa;
@@ -412,4 +551,61 @@ mod tests {
         let pitch = Pitch::new(Solfa::F, Octave::Oct1, SharpFlat::Null);
         assert_eq!(pitch.apply_key(Key::FLAT_2).unwrap(), Pitch::new(Solfa::F, Octave::Oct1, SharpFlat::Null));
     }
+
+    #[test]
+    fn parse() {
+        assert_eq!("C#4".parse::<Pitch>().unwrap(), Pitch::new(Solfa::C, Octave::Oct3, SharpFlat::Sharp));
+        assert_eq!("bb3".parse::<Pitch>().unwrap(), Pitch::new(Solfa::B, Octave::Oct2, SharpFlat::Flat));
+        assert_eq!("Ebb-1".parse::<Pitch>().unwrap(), Pitch::new(Solfa::E, Octave::OctM2, SharpFlat::DoubleFlat));
+        assert_eq!("Fx2".parse::<Pitch>().unwrap(), Pitch::new(Solfa::F, Octave::Oct1, SharpFlat::DoubleSharp));
+        assert_eq!("An4".parse::<Pitch>().unwrap(), Pitch::new(Solfa::A, Octave::Oct3, SharpFlat::Natural));
+        assert_eq!("G\u{266D}5".parse::<Pitch>().unwrap(), Pitch::new(Solfa::G, Octave::Oct4, SharpFlat::Flat));
+    }
+
+    #[test]
+    fn parse_solfege() {
+        assert_eq!("Do#4".parse::<Pitch>().unwrap(), Pitch::new(Solfa::C, Octave::Oct3, SharpFlat::Sharp));
+        assert_eq!("sol4".parse::<Pitch>().unwrap(), Pitch::new(Solfa::G, Octave::Oct3, SharpFlat::Null));
+        assert_eq!("Ti3".parse::<Pitch>().unwrap(), Pitch::new(Solfa::B, Octave::Oct2, SharpFlat::Null));
+        assert_eq!("Fab4".parse::<Pitch>().unwrap(), Pitch::new(Solfa::F, Octave::Oct3, SharpFlat::Flat));
+    }
+
+    #[test]
+    fn parse_error() {
+        assert_eq!("H4".parse::<Pitch>(), Err(PitchParseError::CannotParse("H4".to_owned())));
+        assert_eq!("C".parse::<Pitch>(), Err(PitchParseError::InvalidOctave("C".to_owned())));
+        assert_eq!("C10".parse::<Pitch>(), Err(PitchParseError::InvalidOctave("C10".to_owned())));
+        assert_eq!("G#9".parse::<Pitch>(), Err(PitchParseError::OutOfRange("G#9".to_owned())));
+    }
+
+    #[test]
+    fn to_string() {
+        assert_eq!("C4".parse::<Pitch>().unwrap().value(), 60);
+        for s in ["C4", "C#4", "Dbb-1", "An0", "G9"] {
+            assert_eq!(s.parse::<Pitch>().unwrap().to_string(), s);
+        }
+        assert_eq!(Pitch::new(Solfa::G, Octave::Oct3, SharpFlat::Sharp).to_solfege_string(), "Sol#4");
+    }
+
+    #[test]
+    fn distance() {
+        let c4 = Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null);
+        let e5 = Pitch::new(Solfa::E, Octave::Oct5, SharpFlat::Flat);
+        assert_eq!(c4.steps_to(e5), 9);
+        assert_eq!(c4.semitones_to(e5), 15);
+        assert_eq!(e5.semitones_to(c4), -15);
+    }
+
+    #[test]
+    fn transposed() {
+        let c4 = Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null);
+        assert_eq!(c4.transposed(1, 2).unwrap(), Pitch::new(Solfa::D, Octave::Oct4, SharpFlat::Null));
+        assert_eq!(c4.transposed(-1, -2).unwrap(), Pitch::new(Solfa::B, Octave::Oct3, SharpFlat::Flat));
+
+        let f_sharp = Pitch::new(Solfa::F, Octave::Oct4, SharpFlat::Sharp);
+        assert_eq!(f_sharp.transposed(1, 2).unwrap(), Pitch::new(Solfa::G, Octave::Oct4, SharpFlat::Sharp));
+        let b_flat = Pitch::new(Solfa::B, Octave::Oct4, SharpFlat::Flat);
+        assert_eq!(b_flat.transposed(1, 2).unwrap(), Pitch::new(Solfa::C, Octave::Oct5, SharpFlat::Natural));
+        assert!(c4.transposed(1, 5).is_err());
+    }
 }
@@ -0,0 +1,163 @@
+use crate::pitch::Pitch;
+
+// Pitch classes are 0 for C to 11 for B. Spelling is ignored, so C# and Db are the same class.
+pub const NAMES: [&str; 12] = ["C", "C#", "D", "Eb", "E", "F", "F#", "G", "Ab", "A", "Bb", "B"];
+
+#[inline]
+pub const fn of_value(value: u8) -> u8 {
+    value % 12
+}
+
+#[inline]
+pub fn of(pitch: Pitch) -> u8 {
+    of_value(pitch.value())
+}
+
+pub const fn transposed(pc: u8, semitones: i32) -> u8 {
+    (pc as i32 + semitones).rem_euclid(12) as u8
+}
+
+pub const fn name(pc: u8) -> &'static str {
+    NAMES[of_value(pc) as usize]
+}
+
+// Distance between two pitches. Both are negative if the second pitch is lower.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Interval {
+    // Diatonic steps, ignoring accidentals. A third is 2 steps.
+    pub steps: i32,
+    pub semitones: i32,
+}
+
+impl Interval {
+    pub fn between(from: Pitch, to: Pitch) -> Self {
+        Self { steps: from.steps_to(to), semitones: from.semitones_to(to) }
+    }
+
+    // Upward interval within an octave. A major tenth down becomes a minor sixth.
+    pub const fn simple(self) -> Self {
+        let steps = self.steps.rem_euclid(7);
+        let octaves = (steps - self.steps) / 7;
+        // Semitones are not wrapped, so an augmented seventh keeps its 12 semitones.
+        Self { steps, semitones: self.semitones + octaves * 12 }
+    }
+
+    // 0 to 6, the shorter way around the pitch class circle.
+    pub const fn class(self) -> u8 {
+        let pc = self.semitones.rem_euclid(12) as u8;
+        if pc <= 6 { pc } else { 12 - pc }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct PitchClassSet {
+    // Bit n is set if pitch class n is in the set.
+    bits: u16,
+}
+
+impl PitchClassSet {
+    pub const EMPTY: Self = Self { bits: 0 };
+
+    // Values of 12 and above are taken modulo 12.
+    pub fn from_classes(pcs: &[u8]) -> Self {
+        Self { bits: pcs.iter().fold(0, |acc, pc| acc | 1 << of_value(*pc)) }
+    }
+
+    pub fn from_pitches<I: IntoIterator<Item = Pitch>>(pitches: I) -> Self {
+        Self { bits: pitches.into_iter().fold(0, |acc, p| acc | 1 << of(p)) }
+    }
+
+    pub fn insert(&mut self, pc: u8) {
+        self.bits |= 1 << of_value(pc);
+    }
+
+    pub const fn contains(self, pc: u8) -> bool {
+        self.bits & (1 << of_value(pc)) != 0
+    }
+
+    pub const fn len(self) -> usize {
+        self.bits.count_ones() as usize
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.bits == 0
+    }
+
+    // In ascending order from C.
+    pub fn iter(self) -> impl Iterator<Item = u8> {
+        (0..12).filter(move |pc| self.contains(*pc))
+    }
+
+    pub const fn transposed(self, semitones: i32) -> Self {
+        let n = semitones.rem_euclid(12) as u32;
+        Self { bits: ((self.bits << n) | (self.bits >> (12 - n))) & 0xfff }
+    }
+
+    // Inverted around C.
+    pub fn inverted(self) -> Self {
+        Self { bits: self.iter().fold(0, |acc, pc| acc | 1 << transposed(0, -(pc as i32))) }
+    }
+
+    // Rotation packed the most tightly: the smallest span from the first to the last class, then from the
+    // first to the one before the last and so on (Rahn). Symmetric sets start at the lowest class.
+    pub fn normal_form(self) -> Vec<u8> {
+        let pcs: Vec<u8> = self.iter().collect();
+        let rotations = (0..pcs.len()).map(|i| pcs[i..].iter().chain(pcs[..i].iter()).copied().collect::<Vec<u8>>());
+        rotations.min_by_key(|r| r.iter().rev().map(|pc| transposed(*pc, -(r[0] as i32))).collect::<Vec<u8>>())
+            .unwrap_or_default()
+    }
+
+    // Normal form of the set or its inversion, transposed to start at 0, whichever is packed more tightly.
+    pub fn prime_form(self) -> Vec<u8> {
+        let zeroed = |form: Vec<u8>| -> Vec<u8> {
+            form.iter().map(|pc| transposed(*pc, -(form[0] as i32))).collect()
+        };
+        if self.is_empty() { return vec![]; }
+        let a = zeroed(self.normal_form());
+        let b = zeroed(self.inverted().normal_form());
+        let packing = |form: &Vec<u8>| form.iter().rev().copied().collect::<Vec<u8>>();
+        if packing(&b) < packing(&a) { b } else { a }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{octave::Octave, pitch::Pitch, sharp_flat::SharpFlat, solfa::Solfa};
+    use super::{name, of, Interval, PitchClassSet};
+
+    #[test]
+    fn classes_and_intervals() {
+        let c4 = Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null);
+        let e5 = Pitch::new(Solfa::E, Octave::Oct5, SharpFlat::Null);
+        let b3 = Pitch::new(Solfa::B, Octave::Oct3, SharpFlat::Sharp);
+        assert_eq!(of(c4), 0);
+        assert_eq!(of(b3), 0);
+        assert_eq!(name(of(e5)), "E");
+
+        let tenth = Interval::between(c4, e5);
+        assert_eq!(tenth, Interval { steps: 9, semitones: 16 });
+        assert_eq!(tenth.simple(), Interval { steps: 2, semitones: 4 });
+        assert_eq!(Interval::between(e5, c4).simple(), Interval { steps: 5, semitones: 8 });
+        assert_eq!(Interval::between(c4, b3), Interval { steps: -1, semitones: 0 });
+        assert_eq!(Interval::between(c4, b3).simple(), Interval { steps: 6, semitones: 12 });
+        assert_eq!(Interval::between(b3, c4).simple(), Interval { steps: 1, semitones: 0 });
+        assert_eq!(tenth.class(), 4);
+        assert_eq!(Interval { steps: 4, semitones: 7 }.class(), 5);
+    }
+
+    #[test]
+    fn sets() {
+        let g7 = PitchClassSet::from_classes(&[7, 11, 2, 5]);
+        assert_eq!(g7.len(), 4);
+        assert_eq!(g7.iter().collect::<Vec<_>>(), vec![2, 5, 7, 11]);
+        assert_eq!(g7.normal_form(), vec![11, 2, 5, 7]);
+        assert_eq!(g7.transposed(-7).normal_form(), vec![4, 7, 10, 0]);
+        assert_eq!(g7.prime_form(), vec![0, 2, 5, 8]);
+
+        let cmaj7 = PitchClassSet::from_classes(&[0, 4, 7, 11]);
+        assert_eq!(cmaj7.normal_form(), vec![11, 0, 4, 7]);
+        assert_eq!(PitchClassSet::from_classes(&[0, 4, 7]).prime_form(), vec![0, 3, 7]);
+        assert_eq!(PitchClassSet::from_classes(&[0, 4, 8]).normal_form(), vec![0, 4, 8]);
+        assert!(PitchClassSet::EMPTY.normal_form().is_empty());
+    }
+}
@@ -0,0 +1,840 @@
+use std::{collections::BTreeMap, ops::Range};
+
+use error_stack::Report;
+
+use crate::{can_apply::CanApply, channel::Channel, duration::Duration, note::Note, percent::PercentU16, pitch, project::{ctrl_chg_channels, ProjectImpl, DEFAULT_TEMPO}, repeat::{AccumTick, Chunk, RenderRegionError}, rhythm::Accent, tempo::{Marking, TempoValue}, velocity::{self, Velocity}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayEvent {
+    Tempo { tick: AccumTick, value: TempoValue, marking: Option<Marking> },
+    Dumper { tick: AccumTick, channel: Channel, velocity: Velocity },
+    Soft { tick: AccumTick, channel: Channel, velocity: Velocity },
+    NoteOff { tick: AccumTick, channel: Channel, pitch: u8 },
+    NoteOn { tick: AccumTick, channel: Channel, pitch: u8, velocity: Velocity },
+}
+
+impl PlayEvent {
+    pub fn class(&self) -> EventClass {
+        match self {
+            PlayEvent::Tempo { .. } => EventClass::Tempo,
+            PlayEvent::Dumper { .. } | PlayEvent::Soft { .. } => EventClass::CtrlChg,
+            PlayEvent::NoteOff { .. } => EventClass::NoteOff,
+            PlayEvent::NoteOn { .. } => EventClass::NoteOn,
+        }
+    }
+
+    pub fn tick(&self) -> AccumTick {
+        match self {
+            PlayEvent::Tempo { tick, .. } => *tick,
+            PlayEvent::Dumper { tick, .. } => *tick,
+            PlayEvent::Soft { tick, .. } => *tick,
+            PlayEvent::NoteOff { tick, .. } => *tick,
+            PlayEvent::NoteOn { tick, .. } => *tick,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventClass {
+    Tempo,
+    CtrlChg,
+    NoteOff,
+    NoteOn,
+}
+
+// Order of events at the same tick. Classes not listed are placed last.
+// Notes in the same class are ordered by channel and pitch. Dumper comes before soft.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventOrderPolicy {
+    pub order: [EventClass; 4],
+}
+
+impl EventOrderPolicy {
+    pub fn rank(&self, class: EventClass) -> usize {
+        self.order.iter().position(|c| *c == class).unwrap_or(self.order.len())
+    }
+}
+
+impl Default for EventOrderPolicy {
+    fn default() -> Self {
+        Self {
+            order: [EventClass::Tempo, EventClass::CtrlChg, EventClass::NoteOff, EventClass::NoteOn],
+        }
+    }
+}
+
+// Exporters should call this to get the same order as playback.
+pub fn sort_events(events: &mut [PlayEvent], policy: &EventOrderPolicy) {
+    events.sort_by_key(|e| sort_key(e, policy));
+}
+
+pub(crate) fn sort_key(e: &PlayEvent, policy: &EventOrderPolicy) -> (AccumTick, usize, (u8, u8)) {
+    let note_key = match e {
+        PlayEvent::NoteOff { channel, pitch, .. } | PlayEvent::NoteOn { channel, pitch, .. } => (channel.as_u8(), *pitch),
+        _ => (0, 0),
+    };
+    (e.tick(), policy.rank(e.class()), note_key)
+}
+
+// How long the renderer holds notes. Stored notes are never changed.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NoteLengthMode {
+    // Note::tick_len() as is.
+    #[default]
+    Exact,
+    // Percentage of Note::tick_len(). At least one tick is held.
+    Gate(PercentU16),
+}
+
+impl NoteLengthMode {
+    pub fn apply(self, tick_len: u32) -> u32 {
+        match self {
+            NoteLengthMode::Exact => tick_len,
+            NoteLengthMode::Gate(gate) => gate.apply(tick_len).max(1),
+        }
+    }
+}
+
+// Velocity scale by beat position. Notes off the beat are treated as weak.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BeatEmphasis {
+    pub strong: PercentU16,
+    pub medium: PercentU16,
+    pub weak: PercentU16,
+}
+
+impl BeatEmphasis {
+    pub fn apply(&self, accent: Accent, velocity: Velocity) -> Velocity {
+        let rate = match accent {
+            Accent::Strong => self.strong,
+            Accent::Medium => self.medium,
+            Accent::Weak => self.weak,
+        };
+        Velocity::new(rate.apply(velocity.as_u8() as u32).clamp(1, velocity::MAX_VALUE as u32) as u8)
+    }
+}
+
+impl Default for BeatEmphasis {
+    fn default() -> Self {
+        Self {
+            strong: PercentU16::from(1.1),
+            medium: PercentU16::HUNDRED,
+            weak: PercentU16::from(0.9),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OffsetCurveError {
+    Empty,
+    // Anchor ticks should be strictly increasing.
+    NotIncreasing(u32),
+}
+
+impl std::fmt::Display for OffsetCurveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OffsetCurveError::Empty => write!(f, "Offset curve without anchors"),
+            OffsetCurveError::NotIncreasing(tick) => write!(f, "Offset curve anchor at {} is not after the previous one", tick),
+        }
+    }
+}
+
+// Lead (negative) or lag (positive) in ticks against the conductor, anchored at ticks. Ticks between anchors
+// are linearly interpolated. Ticks outside of anchors take the nearest one.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "RawOffsetCurve")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OffsetCurve {
+    anchors: Vec<(u32, i32)>,
+}
+
+// Same shape as OffsetCurve. Validated by OffsetCurve::new() when deserialized.
+#[derive(serde::Deserialize)]
+struct RawOffsetCurve {
+    anchors: Vec<(u32, i32)>,
+}
+
+impl TryFrom<RawOffsetCurve> for OffsetCurve {
+    type Error = OffsetCurveError;
+
+    fn try_from(raw: RawOffsetCurve) -> Result<Self, Self::Error> {
+        Self::new(raw.anchors)
+    }
+}
+
+impl OffsetCurve {
+    pub fn new(anchors: Vec<(u32, i32)>) -> Result<Self, OffsetCurveError> {
+        if anchors.is_empty() {
+            return Err(OffsetCurveError::Empty);
+        }
+        if let Some(w) = anchors.windows(2).find(|w| w[1].0 <= w[0].0) {
+            return Err(OffsetCurveError::NotIncreasing(w[1].0));
+        }
+        Ok(Self { anchors })
+    }
+
+    pub fn constant(offset: i32) -> Self {
+        Self { anchors: vec![(0, offset)] }
+    }
+
+    pub fn anchors(&self) -> &[(u32, i32)] {
+        &self.anchors
+    }
+
+    pub fn offset_at(&self, tick: u32) -> i32 {
+        let idx = self.anchors.partition_point(|(t, _)| *t <= tick);
+        if idx == 0 { return self.anchors[0].1; }
+        if idx == self.anchors.len() { return self.anchors[idx - 1].1; }
+        let (t0, o0) = self.anchors[idx - 1];
+        let (t1, o1) = self.anchors[idx];
+        (o0 as i64 + (o1 as i64 - o0 as i64) * (tick - t0) as i64 / (t1 - t0) as i64) as i32
+    }
+}
+
+// Semitones added to the notes of each channel at render time, such as 12 for a layer sounding an octave up.
+// Notation export ignores them.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PitchShifts {
+    shifts: BTreeMap<Channel, i8>,
+}
+
+impl PitchShifts {
+    pub fn is_empty(&self) -> bool {
+        self.shifts.is_empty()
+    }
+
+    pub fn get(&self, channel: Channel) -> i8 {
+        self.shifts.get(&channel).copied().unwrap_or(0)
+    }
+
+    // Zero removes the shift of the channel.
+    pub fn set(&mut self, channel: Channel, semitones: i8) {
+        if semitones == 0 {
+            self.shifts.remove(&channel);
+        } else {
+            self.shifts.insert(channel, semitones);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Channel, &i8)> {
+        self.shifts.iter()
+    }
+
+    // Stays in the MIDI range.
+    pub fn apply(&self, channel: Channel, pitch: u8) -> u8 {
+        (pitch as i32 + self.get(channel) as i32).clamp(pitch::MIN_VALUE as i32, pitch::MAX_VALUE as i32) as u8
+    }
+}
+
+// Offset curves by channel, applied to notes and pedals at render time.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TrackOffsets {
+    // Sorted by channel.
+    curves: Vec<(Channel, OffsetCurve)>,
+}
+
+impl TrackOffsets {
+    pub fn is_empty(&self) -> bool {
+        self.curves.is_empty()
+    }
+
+    pub fn get(&self, channel: Channel) -> Option<&OffsetCurve> {
+        self.curves.iter().find(|(c, _)| *c == channel).map(|(_, curve)| curve)
+    }
+
+    // None removes the curve of the channel.
+    pub fn set(&mut self, channel: Channel, curve: Option<OffsetCurve>) {
+        let idx = self.curves.partition_point(|(c, _)| *c < channel);
+        let exists = self.curves.get(idx).is_some_and(|(c, _)| *c == channel);
+        match (curve, exists) {
+            (Some(curve), true) => self.curves[idx].1 = curve,
+            (Some(curve), false) => self.curves.insert(idx, (channel, curve)),
+            (None, true) => { self.curves.remove(idx); }
+            (None, false) => {}
+        }
+    }
+
+    pub fn offset_at(&self, channel: Channel, tick: u32) -> i32 {
+        self.get(channel).map_or(0, |curve| curve.offset_at(tick))
+    }
+
+    // Accumulated tick shifted by the offset of the channel at the (score) tick. Stops at zero.
+    pub fn apply(&self, channel: Channel, tick: u32, accum: AccumTick) -> AccumTick {
+        (accum as i64 + self.offset_at(channel, tick) as i64).max(0) as AccumTick
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlaybackSettings {
+    pub tempo_scale: PercentU16,
+    #[serde(default)]
+    pub event_order: EventOrderPolicy,
+    #[serde(default)]
+    pub note_length: NoteLengthMode,
+    // Ticks to extend each note-off so that successive notes overlap.
+    #[serde(default)]
+    pub legato_overlap: u32,
+    // None to play velocities as entered.
+    #[serde(default)]
+    pub beat_emphasis: Option<BeatEmphasis>,
+    // Hold slurred notes until the next note under the same slur starts.
+    #[serde(default)]
+    pub slur_legato: bool,
+}
+
+impl Default for PlaybackSettings {
+    fn default() -> Self {
+        Self {
+            tempo_scale: PercentU16::HUNDRED,
+            event_order: EventOrderPolicy::default(),
+            note_length: NoteLengthMode::default(),
+            legato_overlap: 0,
+            beat_emphasis: None,
+            slur_legato: false,
+        }
+    }
+}
+
+// Render the whole project into events ordered by accumulated tick, expanding repeats.
+pub fn render(proj: &ProjectImpl) -> Result<Vec<PlayEvent>, Report<RenderRegionError>> {
+    render_chunks(proj, &proj.play_chunks()?)
+}
+
+// Set-up events placed at the accumulated tick to start playing from the tick: the tempo and the pedal
+// values of each channel. Without any pedal, the default channel gets the default value. Projects have
+// neither program changes nor pitch bends, so none is emitted.
+pub fn state_events_at(proj: &ProjectImpl, tick: u32, at: AccumTick) -> Vec<PlayEvent> {
+    let mut events = vec![
+        PlayEvent::Tempo { tick: at, value: proj.tempo_at(tick).scaled(proj.playback_settings().tempo_scale), marking: None },
+    ];
+    let dumper_channels = ctrl_chg_channels(proj.dumper_repo());
+    if dumper_channels.is_empty() {
+        events.push(PlayEvent::Dumper { tick: at, channel: Channel::default(), velocity: proj.dumper_at(tick) });
+    }
+    for channel in dumper_channels {
+        events.push(PlayEvent::Dumper { tick: at, channel, velocity: proj.dumper_at_chan(tick, channel) });
+    }
+    let soft_channels = ctrl_chg_channels(proj.soft_repo());
+    if soft_channels.is_empty() {
+        events.push(PlayEvent::Soft { tick: at, channel: Channel::default(), velocity: proj.soft_at(tick) });
+    }
+    for channel in soft_channels {
+        events.push(PlayEvent::Soft { tick: at, channel, velocity: proj.soft_at_chan(tick, channel) });
+    }
+    events
+}
+
+pub fn render_chunks(proj: &ProjectImpl, chunks: &[Chunk]) -> Result<Vec<PlayEvent>, Report<RenderRegionError>> {
+    render_chunks_filtered(proj, chunks, &|_, _| true)
+}
+
+// Notes for which plays() returns false with the accumulated tick of the note are not rendered. Fails if an
+// event would be placed past the range of AccumTick.
+pub(crate) fn render_chunks_filtered(
+    proj: &ProjectImpl, chunks: &[Chunk], plays: &dyn Fn(&Note, AccumTick) -> bool,
+) -> Result<Vec<PlayEvent>, Report<RenderRegionError>> {
+    let mut events: Vec<PlayEvent> = vec![];
+    let mut accum: AccumTick = 0;
+    let mut prev_end: Option<u32> = None;
+
+    for chunk in chunks {
+        let end_accum = chunk_end_accum(proj, *chunk, accum)?;
+        if prev_end.map_or(0 < chunk.start_tick(), |e| e != chunk.start_tick()) {
+            events.extend(restoration_events(proj, *chunk, accum));
+        }
+        let mut tagged = vec![];
+        render_span_filtered(proj, *chunk, chunk.start_tick()..chunk.end_tick(), accum, &mut tagged, plays);
+        events.extend(tagged.into_iter().map(|(_, e)| e));
+
+        accum = end_accum;
+        prev_end = Some(chunk.end_tick());
+    }
+
+    sort_events(&mut events, &proj.playback_settings().event_order);
+    Ok(events)
+}
+
+// Accumulated tick at the end of the chunk starting at the accumulated tick. The open ended last chunk ends at
+// the last event in it.
+pub(crate) fn chunk_end_accum(proj: &ProjectImpl, chunk: Chunk, accum: AccumTick) -> Result<AccumTick, Report<RenderRegionError>> {
+    let end = if chunk.end_tick() == u32::MAX {
+        [
+            proj.note_repo().peek_last().map(|(t, _)| *t), proj.tempo_repo().peek_last().map(|(t, _)| *t),
+            proj.dumper_repo().peek_last().map(|(t, _)| *t), proj.soft_repo().peek_last().map(|(t, _)| *t),
+        ].into_iter().flatten().fold(chunk.start_tick(), u32::max)
+    } else {
+        chunk.end_tick()
+    };
+    accum.checked_add(AccumTick::from(end - chunk.start_tick()))
+        .ok_or_else(|| Report::new(RenderRegionError::AccumTickOverflow { tick: chunk.start_tick() }))
+}
+
+// Jumped (or started in the middle). Restore the state at the start of chunk unless the chunk sets it by itself.
+pub(crate) fn restoration_events(proj: &ProjectImpl, chunk: Chunk, accum: AccumTick) -> Vec<PlayEvent> {
+    let start = chunk.start_tick();
+    state_events_at(proj, start, accum).into_iter().filter(|e| match e {
+        PlayEvent::Tempo { .. } => proj.tempo_repo().find(&start).is_err(),
+        PlayEvent::Dumper { .. } => proj.dumper_repo().find(&start).is_err(),
+        PlayEvent::Soft { .. } => proj.soft_repo().find(&start).is_err(),
+        _ => true,
+    }).collect()
+}
+
+// Events of the elements starting in the span of the chunk, which starts at the accumulated tick. Each event
+// is tagged with the tick of the element rendering it. Events are in the order of tempos, dumpers, softs and
+// notes, and are not sorted.
+pub(crate) fn render_span(proj: &ProjectImpl, chunk: Chunk, span: Range<u32>, accum: AccumTick, events: &mut Vec<(u32, PlayEvent)>) {
+    render_span_filtered(proj, chunk, span, accum, events, &|_, _| true)
+}
+
+fn render_span_filtered(
+    proj: &ProjectImpl, chunk: Chunk, span: Range<u32>, accum: AccumTick, events: &mut Vec<(u32, PlayEvent)>,
+    plays: &dyn Fn(&Note, AccumTick) -> bool,
+) {
+    let settings = proj.playback_settings();
+    let offsets = proj.track_offsets();
+    let shifts = proj.pitch_shifts();
+    let to_accum = |tick: u32| accum + AccumTick::from(tick - chunk.start_tick());
+
+    for (tick, tempo) in proj.tempo_repo().range(span.clone()).1 {
+        events.push((*tick, PlayEvent::Tempo { tick: to_accum(*tick), value: tempo.value.scaled(settings.tempo_scale), marking: tempo.marking }));
+    }
+    for (tick, d) in proj.dumper_repo().range(span.clone()).1 {
+        events.push((*tick, PlayEvent::Dumper { tick: offsets.apply(d.channel, *tick, to_accum(*tick)), channel: d.channel, velocity: d.velocity }));
+    }
+    for (tick, s) in proj.soft_repo().range(span.clone()).1 {
+        events.push((*tick, PlayEvent::Soft { tick: offsets.apply(s.channel, *tick, to_accum(*tick)), channel: s.channel, velocity: s.velocity }));
+    }
+
+    for (tick, note) in proj.note_repo().range(span) {
+        if note.muted || !plays(note, to_accum(*tick)) { continue; }
+        let pitch = shifts.apply(note.channel, note.pitch.apply_key(proj.key_at(*tick)).unwrap_or(note.pitch).value());
+        let on_tick = offsets.apply(note.channel, *tick, to_accum(*tick));
+        if !note.tied {
+            let velocity = match &settings.beat_emphasis {
+                Some(emphasis) => {
+                    let offset = proj.tick_to_location(*tick).offset() as u32;
+                    emphasis.apply(proj.rhythm_at(*tick).accent_at(offset), note.velocity())
+                }
+                None => note.velocity(),
+            };
+            events.push((*tick, PlayEvent::NoteOn { tick: on_tick, channel: note.channel, pitch, velocity }));
+        }
+        if !note.tie {
+            let base_len = settings.note_length.apply(note.tick_len());
+            let mut len = base_len.saturating_add(settings.legato_overlap);
+            if base_len < len {
+                // The overlap stops at the next note of the same pitch, whose note-on would be cut off otherwise.
+                let same_pitch = proj.note_repo().range(*tick + 1..tick.saturating_add(len)).find(|(t, n)|
+                    n.channel == note.channel && n.pitch.apply_key(proj.key_at(**t)).unwrap_or(n.pitch).value() == note.pitch.apply_key(proj.key_at(*tick)).unwrap_or(note.pitch).value()
+                );
+                if let Some((next_tick, _)) = same_pitch {
+                    len = len.min(next_tick - tick).max(base_len);
+                }
+            }
+            if settings.slur_legato {
+                if let Some(slur) = proj.slur_over(note) {
+                    let end_tick = slur.end_note.start_tick();
+                    let next = proj.note_repo().range(*tick + 1..end_tick + 1).find(|(_, n)| n.channel == note.channel);
+                    if let Some((next_tick, _)) = next {
+                        len = len.max(next_tick - tick);
+                    }
+                }
+            }
+            let run = match proj.glissando_from(note) {
+                Some(g) if !note.tied => g.run(pitch, shifts.apply(note.channel, proj.sounding_pitch(&g.to_note).value())),
+                _ => vec![],
+            };
+            // The note is shortened to the first slot and the rest is filled with the run.
+            let slot = len / (run.len() as u32 + 1);
+            if run.is_empty() || slot == 0 {
+                events.push((*tick, PlayEvent::NoteOff { tick: on_tick.saturating_add(AccumTick::from(len)), channel: note.channel, pitch }));
+            } else {
+                events.push((*tick, PlayEvent::NoteOff { tick: on_tick.saturating_add(AccumTick::from(slot)), channel: note.channel, pitch }));
+                for (i, p) in run.iter().enumerate() {
+                    let start = on_tick.saturating_add(AccumTick::from(slot * (i as u32 + 1)));
+                    let end = if i + 1 == run.len() { on_tick.saturating_add(AccumTick::from(len)) } else { start.saturating_add(AccumTick::from(slot)) };
+                    events.push((*tick, PlayEvent::NoteOn { tick: start, channel: note.channel, pitch: *p, velocity: note.velocity() }));
+                    events.push((*tick, PlayEvent::NoteOff { tick: end, channel: note.channel, pitch: *p }));
+                }
+            }
+        }
+    }
+}
+
+// Elapsed microseconds at the tick. Events should be rendered ones.
+pub fn to_micros(events: &[PlayEvent], tick: AccumTick, settings: &PlaybackSettings) -> u64 {
+    #[cfg_attr(feature = "tick64", allow(clippy::unnecessary_cast))]
+    fn micros(ticks: AccumTick, tempo: TempoValue) -> u64 {
+        ticks as u64 * 60_000_000 / (tempo.value() as u64 * Duration::TICK_RESOLUTION as u64)
+    }
+
+    let mut elapsed: u64 = 0;
+    let mut cur_tick: AccumTick = 0;
+    let mut cur_tempo = DEFAULT_TEMPO.scaled(settings.tempo_scale);
+    for e in events {
+        if let PlayEvent::Tempo { tick: t, value, .. } = e {
+            if tick < *t { break; }
+            elapsed += micros(*t - cur_tick, cur_tempo);
+            cur_tick = *t;
+            cur_tempo = *value;
+        }
+    }
+    elapsed + micros(tick - cur_tick, cur_tempo)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use serdo::undo_store::UndoStore;
+
+    use crate::{project::{Project, memory_project_store, DEFAULT_MEMORY_UNDO_LIMIT, ProjectImpl, SettingEvent}, note::Note, pitch::Pitch, solfa::Solfa, octave::Octave, sharp_flat::SharpFlat, duration::Duration, velocity::Velocity, channel::Channel, bar::{Bar, Repeat, RepeatSet}, repeat_set, tempo::{Tempo, TempoValue}, ctrl_chg::CtrlChg};
+    use super::{render, state_events_at, to_micros, PlayEvent, EventClass, EventOrderPolicy, NoteLengthMode, BeatEmphasis, OffsetCurve, OffsetCurveError};
+    use crate::glissando::{Glissando, GlissandoStyle};
+    use crate::slur::Slur;
+    use crate::trimmer::RateTrimmer;
+    use crate::percent::PercentU16;
+    use crate::repeat::AccumTick;
+    use crate::render_cache::RenderCache;
+    use crate::skip_region::SkipRegion;
+
+    fn note(tick: u32, solfa: Solfa) -> Note {
+        Note {
+            base_start_tick: tick,
+            pitch: Pitch::new(solfa, Octave::Oct4, SharpFlat::Null),
+            duration: Duration::default(),
+            base_velocity: Velocity::new(64),
+            ..Default::default()
+        }
+    }
+
+    fn notes_on(proj: &ProjectImpl) -> Vec<(AccumTick, u8)> {
+        let mut on: Vec<(AccumTick, u8)> = render(proj).unwrap().iter().filter_map(|e| match e {
+            PlayEvent::NoteOn { tick, pitch, .. } => Some((*tick, *pitch)),
+            _ => None,
+        }).collect();
+        on.sort();
+        on
+    }
+
+    #[test]
+    fn simple() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(note(0, Solfa::C), false);
+        store.add_note(note(240, Solfa::D), false);
+
+        let events = render(store.model()).unwrap();
+        assert_eq!(events[0], PlayEvent::NoteOn { tick: 0, channel: Channel::default(), pitch: 72, velocity: Velocity::new(64) });
+        assert_eq!(events[1], PlayEvent::NoteOff { tick: 240, channel: Channel::default(), pitch: 72 });
+        assert_eq!(events[2], PlayEvent::NoteOn { tick: 240, channel: Channel::default(), pitch: 74, velocity: Velocity::new(64) });
+        assert_eq!(events[3], PlayEvent::NoteOff { tick: 480, channel: Channel::default(), pitch: 74 });
+    }
+
+    #[test]
+    fn repeat() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_tempo(Tempo { start_tick: 0, value: TempoValue::new(100), marking: None }, false);
+        store.add_note(note(0, Solfa::C), false);
+        store.add_note(note(960, Solfa::E), false);
+        store.add_bar(Bar::new(960, None, None, repeat_set!(Repeat::End)), false);
+
+        assert_eq!(notes_on(store.model()), vec![(0, 72), (960, 72), (1920, 76)]);
+        let tempos = render(store.model()).unwrap().iter().filter(|e| matches!(e, PlayEvent::Tempo { .. })).count();
+        assert_eq!(tempos, 2);
+    }
+
+    #[test]
+    fn skip_regions() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(note(0, Solfa::C), false);
+        store.add_note(note(480, Solfa::D), false);
+        store.add_note(note(960, Solfa::E), false);
+        store.add_bar(Bar::new(960, None, None, repeat_set!(Repeat::End)), false);
+        let skip = SkipRegion::new(480..960);
+        store.add_skip_region(skip.clone());
+
+        // Cut from both passes of the repeat.
+        assert_eq!(notes_on(store.model()), vec![(0, 72), (480, 72), (960, 76)]);
+        let cached: Vec<PlayEvent> = RenderCache::new(store.model()).unwrap().events().copied().collect();
+        assert_eq!(cached, render(store.model()).unwrap());
+
+        store.remove_skip_region(&skip);
+        assert_eq!(notes_on(store.model()).len(), 5);
+        assert_eq!(store.note_repo().len(), 3);
+
+        store.undo();
+        assert_eq!(store.skip_regions(), &[skip]);
+        store.redo();
+        assert!(store.skip_regions().is_empty());
+    }
+
+    #[test]
+    fn muted() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(note(0, Solfa::C), false);
+        store.add_note(note(0, Solfa::G), false);
+        let c = Rc::new(note(0, Solfa::C));
+        store.set_muted(&[c], true);
+
+        assert_eq!(notes_on(store.model()), vec![(0, 79)]);
+        assert_eq!(store.note_repo().len(), 2);
+
+        store.undo();
+        assert_eq!(notes_on(store.model()), vec![(0, 72), (0, 79)]);
+    }
+
+    #[test]
+    fn tie() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(Note { tie: true, ..note(0, Solfa::C) }, false);
+        store.add_note(Note { tied: true, ..note(240, Solfa::C) }, false);
+
+        let events = render(store.model()).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1], PlayEvent::NoteOff { tick: 480, channel: Channel::default(), pitch: 72 });
+    }
+
+    #[test]
+    fn pedal_restored_per_channel() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_dumper(CtrlChg::new(0, Velocity::new(127), Channel::default()), false);
+        store.add_dumper(CtrlChg::new(240, Velocity::new(100), Channel::new(1)), false);
+        store.add_bar(Bar::new(960, None, None, repeat_set!(Repeat::End, Repeat::Start)), false);
+        store.add_bar(Bar::new(1920, None, None, repeat_set!(Repeat::End)), false);
+        store.add_dumper(CtrlChg::new(1200, Velocity::new(0), Channel::new(1)), false);
+
+        // The second pass of 960..1920 starts at 2880 without a dumper at 960.
+        let restored: Vec<(u8, u8)> = render(store.model()).unwrap().iter().filter_map(|e| match e {
+            PlayEvent::Dumper { tick: 2880, channel, velocity } => Some((channel.as_u8(), velocity.as_u8())),
+            _ => None,
+        }).collect();
+        assert_eq!(restored, vec![(0, 127), (1, 100)]);
+    }
+
+    #[test]
+    fn state_events() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_tempo(Tempo::new(240, 90), false);
+        store.add_dumper(CtrlChg::new(0, Velocity::new(127), Channel::new(1)), false);
+        store.add_dumper(CtrlChg::new(480, Velocity::new(0), Channel::new(1)), false);
+        assert_eq!(state_events_at(store.model(), 300, 1000), vec![
+            PlayEvent::Tempo { tick: 1000, value: TempoValue::new(90), marking: None },
+            PlayEvent::Dumper { tick: 1000, channel: Channel::new(1), velocity: Velocity::new(127) },
+            PlayEvent::Soft { tick: 1000, channel: Channel::default(), velocity: store.model().soft_at(300) },
+        ]);
+    }
+
+    #[test]
+    fn tempo_scale() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_tempo(Tempo { start_tick: 240, value: TempoValue::new(60), marking: None }, false);
+        store.add_note(note(0, Solfa::C), false);
+        let events = render(store.model()).unwrap();
+        assert_eq!(to_micros(&events, 240, store.playback_settings()), 500_000);
+        assert_eq!(to_micros(&events, 480, store.playback_settings()), 1_500_000);
+
+        store.set_tempo_scale(PercentU16::from(0.5));
+        assert!(!store.can_redo());
+        let events = render(store.model()).unwrap();
+        assert!(events.contains(&PlayEvent::Tempo { tick: 240, value: TempoValue::new(30), marking: None }));
+        assert_eq!(to_micros(&events, 240, store.playback_settings()), 1_000_000);
+        assert_eq!(to_micros(&events, 480, store.playback_settings()), 3_000_000);
+
+        store.undo();
+        assert_eq!(store.note_repo().len(), 0);
+        assert_eq!(store.playback_settings().tempo_scale, PercentU16::from(0.5));
+    }
+
+    #[test]
+    fn event_order() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(note(0, Solfa::C), false);
+        store.add_note(note(240, Solfa::D), false);
+        store.add_tempo(Tempo { start_tick: 240, value: TempoValue::new(100), marking: None }, false);
+        store.add_dumper(crate::ctrl_chg::CtrlChg::new(240, Velocity::new(127), Channel::default()), false);
+
+        let classes: Vec<EventClass> = render(store.model()).unwrap().iter().filter(|e| e.tick() == 240).map(|e| e.class()).collect();
+        assert_eq!(classes, vec![EventClass::Tempo, EventClass::CtrlChg, EventClass::NoteOff, EventClass::NoteOn]);
+
+        store.set_event_order(EventOrderPolicy { order: [EventClass::NoteOn, EventClass::NoteOff, EventClass::CtrlChg, EventClass::Tempo] });
+        let classes: Vec<EventClass> = render(store.model()).unwrap().iter().filter(|e| e.tick() == 240).map(|e| e.class()).collect();
+        assert_eq!(classes, vec![EventClass::NoteOn, EventClass::NoteOff, EventClass::CtrlChg, EventClass::Tempo]);
+
+        store.undo();
+        assert_eq!(store.playback_settings().event_order, EventOrderPolicy::default());
+    }
+
+    #[test]
+    fn note_length() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(note(0, Solfa::C), false);
+        let off_tick = |store: &crate::project::MemoryProjectStore| render(store.model()).unwrap().iter().find_map(|e| match e {
+            PlayEvent::NoteOff { tick, .. } => Some(*tick),
+            _ => None,
+        });
+        assert_eq!(off_tick(&store), Some(240));
+
+        store.set_note_length(NoteLengthMode::Gate(PercentU16::from(0.5)), 0);
+        assert_eq!(off_tick(&store), Some(120));
+        store.set_note_length(NoteLengthMode::Gate(PercentU16::ZERO), 0);
+        assert_eq!(off_tick(&store), Some(1));
+        store.set_note_length(NoteLengthMode::Exact, 10);
+        assert_eq!(off_tick(&store), Some(250));
+        assert_eq!(store.note_repo().iter().next().unwrap().1.tick_len(), 240);
+
+        // The overlap does not reach the repeated note.
+        store.add_note(note(245, Solfa::C), false);
+        assert_eq!(off_tick(&store), Some(245));
+        store.undo();
+        store.undo();
+        assert_eq!(store.playback_settings().legato_overlap, 0);
+        assert_eq!(off_tick(&store), Some(1));
+    }
+
+    #[test]
+    fn beat_emphasis() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        for tick in [0, 240, 480, 600, 960] {
+            store.add_note(note(tick, Solfa::C), false);
+        }
+        let velocities = |store: &crate::project::MemoryProjectStore| render(store.model()).unwrap().iter().filter_map(|e| match e {
+            PlayEvent::NoteOn { velocity, .. } => Some(velocity.as_u8()),
+            _ => None,
+        }).collect::<Vec<u8>>();
+        assert_eq!(velocities(&store), vec![64, 64, 64, 64, 64]);
+
+        store.set_beat_emphasis(Some(BeatEmphasis::default()));
+        assert_eq!(velocities(&store), vec![70, 57, 64, 57, 70]);
+
+        store.undo();
+        assert_eq!(velocities(&store), vec![64, 64, 64, 64, 64]);
+    }
+
+    #[test]
+    fn glissando() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(note(0, Solfa::C), false);
+        store.add_note(note(240, Solfa::G), false);
+        store.add_glissando(Glissando::new(note(240, Solfa::G), note(0, Solfa::C), GlissandoStyle::Chromatic));
+        assert!(store.glissandos().is_empty());
+
+        store.add_glissando(Glissando::new(note(0, Solfa::C), note(240, Solfa::G), GlissandoStyle::WhiteKey));
+        assert_eq!(notes_on(store.model()), vec![(0, 72), (60, 74), (120, 76), (180, 77), (240, 79)]);
+        let last_off = render(store.model()).unwrap().iter().filter_map(|e| match e {
+            PlayEvent::NoteOff { tick, pitch: 77, .. } => Some(*tick),
+            _ => None,
+        }).next();
+        assert_eq!(last_off, Some(240));
+
+        store.undo();
+        assert_eq!(notes_on(store.model()), vec![(0, 72), (240, 79)]);
+    }
+
+    #[test]
+    fn slur_legato() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        let short = |tick: u32, solfa: Solfa| Note { duration_trimmer: RateTrimmer::new(0.5, 1.0, 1.0, 1.0), ..note(tick, solfa) };
+        store.add_note(short(0, Solfa::C), false);
+        store.add_note(short(240, Solfa::D), false);
+        store.add_note(short(480, Solfa::E), false);
+        store.add_slur(Slur::new(short(0, Solfa::C), short(240, Solfa::D)));
+        assert_eq!(store.slurs().len(), 1);
+
+        let offs = |store: &crate::project::MemoryProjectStore| render(store.model()).unwrap().iter().filter_map(|e| match e {
+            PlayEvent::NoteOff { tick, .. } => Some(*tick),
+            _ => None,
+        }).collect::<Vec<AccumTick>>();
+        assert_eq!(offs(&store), vec![120, 360, 600]);
+
+        store.set_slur_legato(true);
+        assert_eq!(offs(&store), vec![240, 360, 600]);
+
+        store.undo();
+        assert_eq!(store.slurs().len(), 1);
+        assert_eq!(offs(&store), vec![120, 360, 600]);
+    }
+
+    #[test]
+    fn offset_curve() {
+        let curve = OffsetCurve::new(vec![(240, -10), (480, 10)]).unwrap();
+        assert_eq!(curve.offset_at(0), -10);
+        assert_eq!(curve.offset_at(360), 0);
+        assert_eq!(curve.offset_at(1000), 10);
+        assert_eq!(OffsetCurve::new(vec![]), Err(OffsetCurveError::Empty));
+        assert_eq!(OffsetCurve::new(vec![(240, 0), (240, 1)]), Err(OffsetCurveError::NotIncreasing(240)));
+
+        let json = serde_json::to_string(&curve).unwrap();
+        assert_eq!(serde_json::from_str::<OffsetCurve>(&json).unwrap(), curve);
+        assert!(serde_json::from_str::<OffsetCurve>(r#"{"anchors":[]}"#).is_err());
+        assert!(serde_json::from_str::<OffsetCurve>(r#"{"anchors":[[480,0],[240,0]]}"#).is_err());
+    }
+
+    #[test]
+    fn track_offsets() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(note(0, Solfa::C), false);
+        store.add_note(Note { channel: Channel::new(1), ..note(240, Solfa::E) }, false);
+        store.add_note(Note { channel: Channel::new(1), ..note(480, Solfa::G) }, false);
+
+        store.set_track_offset(Channel::new(1), Some(OffsetCurve::new(vec![(240, -20), (480, 10)]).unwrap()));
+        assert!(!store.can_redo());
+        assert_eq!(notes_on(store.model()), vec![(0, 72), (220, 76), (490, 79)]);
+
+        store.set_track_offset(Channel::new(1), None);
+        assert!(store.track_offsets().is_empty());
+        assert_eq!(notes_on(store.model()), vec![(0, 72), (240, 76), (480, 79)]);
+    }
+
+    #[test]
+    fn pitch_shifts() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(note(0, Solfa::C), false);
+        store.add_note(Note { channel: Channel::new(1), ..note(240, Solfa::E) }, false);
+
+        store.set_pitch_shift(Channel::new(1), 12);
+        assert!(!store.can_redo());
+        assert_eq!(notes_on(store.model()), vec![(0, 72), (240, 88)]);
+        assert_eq!(store.pitch_shifts().apply(Channel::new(1), 120), 127);
+
+        let json = serde_json::to_string(store.model()).unwrap();
+        let loaded: ProjectImpl = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.pitch_shifts().get(Channel::new(1)), 12);
+
+        store.set_pitch_shift(Channel::new(1), 0);
+        assert!(store.pitch_shifts().is_empty());
+        assert_eq!(notes_on(store.model()), vec![(0, 72), (240, 76)]);
+
+        store.undo();
+        assert_eq!(store.pitch_shifts().get(Channel::new(1)), 12);
+        store.undo();
+        assert!(store.pitch_shifts().is_empty());
+        assert_eq!(store.setting_events(), &vec![SettingEvent::PitchShifts; 4]);
+        store.clear_model_events();
+        assert!(store.setting_events().is_empty());
+    }
+
+    #[test]
+    fn accum_tick_overflow() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_bar(Bar::new(3_000_000_000, None, None, repeat_set!(Repeat::End)), false);
+        store.add_note(note(2_999_000_000, Solfa::C), false);
+
+        let rendered = render(store.model());
+        #[cfg(not(feature = "tick64"))]
+        {
+            assert_eq!(rendered.unwrap_err().current_context(), &crate::repeat::RenderRegionError::AccumTickOverflow { tick: 0 });
+            assert!(RenderCache::new(store.model()).is_err());
+        }
+        #[cfg(feature = "tick64")]
+        assert_eq!(rendered.unwrap().iter().filter(|e| matches!(e, PlayEvent::NoteOn { .. })).count(), 2);
+    }
+}
@@ -0,0 +1,85 @@
+use error_stack::Report;
+
+use crate::{note::Note, playback::{render_chunks_filtered, PlayEvent}, project::ProjectImpl, repeat::{AccumTick, RenderRegionError}};
+
+fn mix(mut x: u64) -> u64 {
+    // splitmix64 finalizer.
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+#[cfg_attr(feature = "tick64", allow(clippy::unnecessary_cast))]
+fn draw(note: &Note, at: AccumTick, seed: u64) -> bool {
+    let Some(probability) = note.play_probability else { return true; };
+    let key = (at as u64) << 16 | (note.pitch.value() as u64) << 8 | note.channel.as_u8() as u64;
+    mix(seed ^ mix(key)) % 1000 < probability.value() as u64
+}
+
+// Whether the note sounds at the accumulated tick. The draw depends only on the seed, the tick, the pitch and
+// the channel, so the same seed plays the same variation, and each pass of a repeat is drawn again.
+// Tied notes take the draw of the head of their chain, so that a chain either sounds and is released as a
+// whole, or is dropped as a whole.
+pub fn plays(proj: &ProjectImpl, note: &Note, at: AccumTick, seed: u64) -> bool {
+    let mut head = note;
+    let mut head_at = at;
+    while let Some(prev) = proj.prev_tie_partner(head) {
+        head_at = head_at.saturating_sub(AccumTick::from(head.start_tick() - prev.start_tick()));
+        head = prev;
+    }
+    draw(head, head_at, seed)
+}
+
+// Same as playback::render() except that notes are dropped by their play probability.
+pub fn render_practice(proj: &ProjectImpl, seed: u64) -> Result<Vec<PlayEvent>, Report<RenderRegionError>> {
+    render_chunks_filtered(proj, &proj.play_chunks()?, &|note, at| plays(proj, note, at, seed))
+}
+
+#[cfg(test)]
+mod tests {
+    use serdo::undo_store::UndoStore;
+
+    use crate::{note::Note, percent::PercentU16, playback::{render, PlayEvent}, project::{memory_project_store, Project, DEFAULT_MEMORY_UNDO_LIMIT}};
+    use super::render_practice;
+
+    fn note_ons(events: &[PlayEvent]) -> usize {
+        events.iter().filter(|e| matches!(e, PlayEvent::NoteOn { .. })).count()
+    }
+
+    #[test]
+    fn probability() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        for i in 0..100 {
+            store.add_note(Note { base_start_tick: i * 240, ..Default::default() }, false);
+        }
+        let notes: Vec<_> = store.model().note_repo().iter().map(|(_, n)| n.clone()).collect();
+        store.set_play_probability(&notes, Some(PercentU16::from_value(500)));
+
+        let played = note_ons(&render_practice(store.model(), 1).unwrap());
+        assert!(20 < played && played < 80);
+        assert_eq!(note_ons(&render_practice(store.model(), 1).unwrap()), played);
+        assert_eq!(note_ons(&render(store.model()).unwrap()), 100);
+
+        let notes: Vec<_> = store.model().note_repo().iter().map(|(_, n)| n.clone()).collect();
+        store.set_play_probability(&notes, Some(PercentU16::ZERO));
+        assert_eq!(note_ons(&render_practice(store.model(), 1).unwrap()), 0);
+    }
+
+    #[test]
+    fn tie_chain_follows_head() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        for i in 0..50 {
+            store.add_note(Note { base_start_tick: i * 960, tie: true, ..Default::default() }, false);
+            store.add_note(Note { base_start_tick: i * 960 + 240, tied: true, tie: true, ..Default::default() }, false);
+            store.add_note(Note { base_start_tick: i * 960 + 480, tied: true, ..Default::default() }, false);
+        }
+        let notes: Vec<_> = store.model().note_repo().iter().map(|(_, n)| n.clone()).collect();
+        store.set_play_probability(&notes, Some(PercentU16::from_value(500)));
+
+        let events = render_practice(store.model(), 7).unwrap();
+        let ons = note_ons(&events);
+        assert!(10 < ons && ons < 40);
+        // Every chain that starts also ends, and no chain ends without starting.
+        assert_eq!(events.iter().filter(|e| matches!(e, PlayEvent::NoteOff { .. })).count(), ons);
+    }
+}
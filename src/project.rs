@@ -1,24 +1,61 @@
+use std::cell::{Cell, RefCell};
+use std::ops::{Range, RangeInclusive};
+use std::collections::{BTreeMap, BTreeSet};
 use std::rc::Rc;
 
+use enumset::EnumSet;
 use klavier_helper::bag_store::{BagStore, BagStoreEvent};
 use klavier_helper::store::{Store, StoreEvent};
 use serde::{Serialize, Deserialize};
-use serdo::undo_store::{SqliteUndoStore, UndoStore};
-use serdo::cmd::{SerializableCmd, Cmd};
+#[cfg(feature = "sqlite")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "sqlite")]
+use serdo::undo_store::{self, SqliteUndoStore};
+use serdo::undo_store::{InMemoryUndoStore, UndoStore};
+#[cfg(feature = "sqlite")]
+use serdo::cmd::SerializableCmd;
+use serdo::cmd::Cmd;
 
-use crate::bar::{Bar, RepeatSet};
-use crate::ctrl_chg::CtrlChg;
-use crate::grid::Grid;
+use crate::bar::{Bar, BarAttribute, BarMergeWarning, RepeatSet};
+use crate::channel::Channel;
+use crate::clip::{Clip, ClipInstance, ClipLibrary};
+use crate::comment::Comment;
+use crate::rehearsal::{self, RehearsalMark};
+use crate::instrument::{Instrument, InstrumentTable};
+use crate::ctrl_chg::{CtrlChg, CtrlChgKind, PedalSegment};
+use crate::dirty_region::{self, DirtyRegions};
+use crate::content_hash::{self, ContentHash};
+use crate::describe;
+use crate::drag::DragSession;
+use crate::glissando::Glissando;
+use crate::global_repeat::{global_repeat, repeat_labels, RepeatLabel};
+use crate::grid::{Grid, SnapRounding};
 use crate::key::Key;
+use crate::tick::Tick;
+use crate::pitch_class;
 use crate::location::Location;
-use crate::models::{Models, ModelChanges};
-use crate::note::Note;
-use crate::rhythm::Rhythm;
-use crate::tempo::{TempoValue, Tempo};
+use crate::models::{Models, ModelChanges, ModelsView};
+use crate::duration::Duration;
+use crate::note::{Note, NoteTag, SplitError};
+use crate::trimmer::{RateTrimmer, Trimmer, TrimmerLane};
+use crate::note_defaults::NoteDefaults;
+use crate::pitch::Pitch;
+use crate::percent::PercentU16;
+use crate::playback::{BeatEmphasis, EventOrderPolicy, NoteLengthMode, OffsetCurve, PitchShifts, PlaybackSettings, TrackOffsets};
+use crate::settings_file::SettingsFile;
+use crate::repeat::{render_region, repeat_diagnostics, Chunk, RenderRegionError, RepeatDiagnostic};
+use crate::skip_region::{self, SkipRegion};
+use crate::rhythm::{Accent, Rhythm};
+use crate::scale::{Scale, ScaleSnap};
+use crate::slur::Slur;
+use crate::template::Template;
+use crate::tempo::{TempoValue, Tempo, MIN_TEMPO_VALUE, MAX_TEMPO_VALUE};
+use crate::transposition::{DisplaySettings, Transposition};
 use crate::tuple;
-use crate::velocity::{Velocity, self};
+use crate::validation::{self, UnisonFix};
+use crate::velocity::{DynamicsTable, Velocity, self};
 
-const DEFAULT_TEMPO: TempoValue = TempoValue::new(120);
+pub const DEFAULT_TEMPO: TempoValue = TempoValue::new(120);
 const DEFAULT_CTRL_CHG: Velocity = velocity::MIN;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -34,6 +71,38 @@ impl std::fmt::Display for LocationError {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FitDurationError {
+    EmptyRange,
+    InvalidTarget(f64),
+}
+
+impl std::fmt::Display for FitDurationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::EmptyRange => write!(f, "Range is empty"),
+            Self::InvalidTarget(secs) => write!(f, "Invalid target duration {}", secs),
+        }
+    }
+}
+
+// Project setting changed by a command, its undo or its redo. Cleared by clear_model_events() along with the
+// store events of the repos.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingEvent {
+    Rhythm,
+    Key,
+    Grid,
+    DefaultChannel,
+    Playback,
+    NoteDefaults,
+    Display,
+    Dynamics,
+    TrackOffsets,
+    AddPolicy,
+    PitchShifts,
+}
+
 #[derive(PartialEq, Debug)]
 pub enum ChangeRepoType {
     MoveSelected,
@@ -63,11 +132,18 @@ impl ModelChangeMetadata {
             dragged: self.dragged,
         }
     }
+
+    pub fn with_dragged(self, dragged: bool) -> Self {
+        Self {
+            need_select: self.need_select,
+            dragged,
+        }
+    }
 }
 
 
-#[derive(Serialize, Deserialize, Clone)]
-#[serde(from = "ExportedProject", into = "ExportedProject")]
+// Serialized as ExportedProject. See store_format for the layout in the store.
+#[derive(Clone)]
 pub struct ProjectImpl {
     rhythm: Rhythm,
     key: Key,
@@ -77,6 +153,36 @@ pub struct ProjectImpl {
     tempo_repo: Store<u32, Tempo, ModelChangeMetadata>,
     dumper_repo: Store<u32, CtrlChg, ModelChangeMetadata>,
     soft_repo: Store<u32, CtrlChg, ModelChangeMetadata>,
+    playback: PlaybackSettings, // The tempo scale is out of undo history. See SettingsFile.
+    note_defaults: NoteDefaults, // Out of undo history.
+    clips: ClipLibrary,
+    display: DisplaySettings,
+    glissandos: Vec<Glissando>,
+    slurs: Vec<Slur>,
+    comments: Vec<Comment>,
+    rehearsal_marks: Vec<RehearsalMark>, // Sorted by bar.
+    instruments: InstrumentTable,
+    default_channel: Channel,
+    dynamics: DynamicsTable,
+    track_offsets: TrackOffsets, // Out of undo history.
+    pitch_shifts: PitchShifts,
+    skip_regions: Vec<SkipRegion>, // Sorted by start tick.
+    add_policy: AddPolicy,
+    repeat_diagnostics: RefCell<Option<DiagnosticsCache>>, // Not persisted.
+    content_hash: RefCell<ContentHash>, // Not persisted.
+    drag: Option<DragSession>, // Not persisted. Out of undo history.
+    preview_models: Models, // Not persisted. Out of undo history.
+    setting_events: Vec<SettingEvent>, // Not persisted.
+    sent_cmds: Cell<usize>, // Not persisted. Commands sent to the store since it was last seen saved.
+    settings_path: Option<std::path::PathBuf>, // Not persisted. Where the settings out of undo history are saved.
+}
+
+// Diagnostics are recomputed only when bars or the rhythm have changed since the last call.
+#[derive(Clone)]
+struct DiagnosticsCache {
+    rhythm: Rhythm,
+    bars: Vec<Bar>,
+    diagnostics: Vec<(u32, RepeatDiagnostic)>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -85,6 +191,44 @@ pub struct ExportedProject {
     key: Key,
     grid: Grid,
     models: Models,
+    #[serde(default)]
+    playback: PlaybackSettings,
+    #[serde(default)]
+    note_defaults: NoteDefaults,
+    #[serde(default)]
+    clips: ClipLibrary,
+    #[serde(default)]
+    display: DisplaySettings,
+    #[serde(default)]
+    glissandos: Vec<Glissando>,
+    #[serde(default)]
+    slurs: Vec<Slur>,
+    #[serde(default)]
+    default_channel: Channel,
+    #[serde(default)]
+    dynamics: DynamicsTable,
+    #[serde(default)]
+    track_offsets: TrackOffsets,
+    #[serde(default)]
+    pitch_shifts: PitchShifts,
+    #[serde(default)]
+    skip_regions: Vec<SkipRegion>,
+    #[serde(default)]
+    add_policy: AddPolicy,
+    #[serde(default)]
+    comments: Vec<Comment>,
+    #[serde(default)]
+    rehearsal_marks: Vec<RehearsalMark>,
+    #[serde(default)]
+    instruments: InstrumentTable,
+}
+
+impl ExportedProject {
+    // The models with the default settings, for data written before the settings existed.
+    pub(crate) fn with_models(rhythm: Rhythm, key: Key, grid: Grid, models: Models) -> Self {
+        let defaults: ExportedProject = ProjectImpl::default().into();
+        ExportedProject { rhythm, key, grid, models, ..defaults }
+    }
 }
 
 impl From<ExportedProject> for ProjectImpl {
@@ -108,7 +252,29 @@ impl From<ExportedProject> for ProjectImpl {
             rhythm: exported.rhythm,
             key: exported.key,
             grid: exported.grid,
-            note_repo, bar_repo, tempo_repo, dumper_repo, soft_repo
+            note_repo, bar_repo, tempo_repo, dumper_repo, soft_repo,
+            playback: exported.playback,
+            note_defaults: exported.note_defaults,
+            clips: exported.clips,
+            display: exported.display,
+            glissandos: exported.glissandos,
+            slurs: exported.slurs,
+            default_channel: exported.default_channel,
+            dynamics: exported.dynamics,
+            track_offsets: exported.track_offsets,
+            pitch_shifts: exported.pitch_shifts,
+            skip_regions: exported.skip_regions,
+            add_policy: exported.add_policy,
+            comments: exported.comments,
+            rehearsal_marks: exported.rehearsal_marks,
+            instruments: exported.instruments,
+            repeat_diagnostics: RefCell::new(None),
+            content_hash: RefCell::new(ContentHash::default()),
+            drag: None,
+            preview_models: Models::empty(),
+            setting_events: vec![],
+            sent_cmds: Cell::new(0),
+            settings_path: None,
         }
     }
 }
@@ -144,11 +310,224 @@ impl Into<ExportedProject> for ProjectImpl {
             rhythm: self.rhythm,
             key: self.key,
             grid: self.grid,
-            models: Models { notes, bars, tempos, dumpers, softs }
+            models: Models { notes, bars, tempos, dumpers, softs },
+            playback: self.playback,
+            note_defaults: self.note_defaults,
+            clips: self.clips,
+            display: self.display,
+            glissandos: self.glissandos,
+            slurs: self.slurs,
+            default_channel: self.default_channel,
+            dynamics: self.dynamics,
+            track_offsets: self.track_offsets,
+            pitch_shifts: self.pitch_shifts,
+            skip_regions: self.skip_regions,
+            add_policy: self.add_policy,
+            comments: self.comments,
+            rehearsal_marks: self.rehearsal_marks,
+            instruments: self.instruments,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PasteFitOptions {
+    // Rhythm the copied models were written in.
+    pub source_rhythm: Rhythm,
+    // Scale positions and durations to the destination bar length instead of keeping them.
+    pub scale_durations: bool,
+}
+
+// What paste() does to a pasted note when a note of the same pitch and channel starts at the same tick.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PasteConflict {
+    // The pasted note is dropped.
+    Skip,
+    // The existing note is removed.
+    Replace,
+    // Both notes are kept.
+    Merge,
+}
+
+impl PasteConflict {
+    fn duplicate_policy(self) -> DuplicatePolicy {
+        match self {
+            PasteConflict::Skip => DuplicatePolicy::Reject,
+            PasteConflict::Replace => DuplicatePolicy::Replace,
+            PasteConflict::Merge => DuplicatePolicy::Stack,
+        }
+    }
+}
+
+// What paste() does to a pasted bar when a bar already starts at the same tick.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum BarMerge {
+    // The existing bar is replaced.
+    #[default]
+    Replace,
+    // Attributes of both bars are combined by Bar::merged_with().
+    Merge,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct PasteOptions {
+    // Channels of the source mapped to the channels of this project. Unmapped channels are kept.
+    pub channel_map: BTreeMap<Channel, Channel>,
+    pub on_conflict: PasteConflict,
+    pub select_result: bool,
+    #[serde(default)]
+    pub bar_merge: BarMerge,
+}
+
+impl Default for PasteOptions {
+    fn default() -> Self {
+        Self { channel_map: BTreeMap::new(), on_conflict: PasteConflict::Replace, select_result: true, bar_merge: BarMerge::Replace }
+    }
+}
+
+// What changing the rhythm of a bar would do, for the UI to confirm before applying.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct ImpactReport {
+    // Bars before the change and their start tick after it.
+    pub shifted_bars: Vec<(Bar, u32)>,
+    // Notes that would newly cross a barline.
+    pub crossing_notes: Vec<Rc<Note>>,
+    // Repeat errors of the new layout not found in the current one.
+    pub invalid_repeats: Vec<(u32, RenderRegionError)>,
+}
+
+impl ImpactReport {
+    pub fn is_empty(&self) -> bool {
+        self.shifted_bars.is_empty() && self.crossing_notes.is_empty() && self.invalid_repeats.is_empty()
+    }
+}
+
+// Beat boundary yielded by beats(). Bar no is the same as Location. Beat no starts at 0 in each bar.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Beat {
+    pub tick: u32,
+    pub bar_no: usize,
+    pub beat_no: usize,
+    pub accent: Accent,
+}
+
+// Bucket counted by activity_map().
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ActivityBucket {
+    Bar,
+    Beat,
+}
+
+// What remove_bar() does to the measure starting at the bar.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BarContentPolicy {
+    // Delete the measure with its contents. Everything after it moves back by the measure length.
+    ShiftUp,
+    // Remove the bar line only. Contents stay and belong to the previous measure.
+    LeaveGap,
+    // Remove the bar line and the contents of the measure. Nothing moves.
+    DeleteContents,
+}
+
+// What remove_notes_tie_aware() does to the tie partners of removed notes.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TieRemovalPolicy {
+    // Clear the tie flags pointing at removed notes. A following partner becomes a struck note.
+    Unlink,
+    // Remove whole tie chains.
+    RemoveChain,
+}
+
+// What repair() has fixed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct RepairReport {
+    // Elements stored under a tick other than their own and moved to it.
+    pub misplaced: usize,
+    // Misplaced elements dropped because another one is at their tick.
+    pub duplicates_removed: usize,
+    // Notes removed because their end does not fit in u32.
+    pub notes_out_of_range: usize,
+    pub dangling_glissandos: usize,
+    pub dangling_slurs: usize,
+    pub orphan_clip_instances: usize,
+}
+
+impl RepairReport {
+    pub fn is_clean(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+// Moves elements stored under a wrong tick to their own tick.
+fn rekey<T: Clone>(repo: &mut Store<u32, T, ModelChangeMetadata>, tick_of: impl Fn(&T) -> u32, report: &mut RepairReport) {
+    let misplaced: Vec<(u32, T)> = repo.iter().filter(|(tick, e)| *tick != tick_of(e)).cloned().collect();
+    for (tick, _) in misplaced.iter() {
+        repo.remove(tick);
+    }
+    for (_, e) in misplaced {
+        let tick = tick_of(&e);
+        if repo.index(tick).is_ok() {
+            report.duplicates_removed += 1;
+        } else {
+            repo.add(tick, e, ModelChangeMetadata::new());
+            report.misplaced += 1;
         }
     }
 }
 
+// Settings of a new score given by setup_score().
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ScoreSetup {
+    pub rhythm: Rhythm,
+    pub key: Key,
+    pub tempo: TempoValue,
+    pub grid: Grid,
+    pub bar_count: u32,
+}
+
+// Start tick proposed by quantize_preview(). Notes are matched by value when applied.
+#[derive(Clone, PartialEq, Debug)]
+pub struct QuantizeMove {
+    pub note: Rc<Note>,
+    pub new_tick: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TrimmerNormalizeMode {
+    // Bake trimmers into base tick, velocity and duration.
+    FoldIntoBase,
+    // Drop trimmers, leaving base values as they are.
+    Clear,
+}
+
+// What adding a note does when a note of the same pitch and channel starts at the same tick.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum DuplicatePolicy {
+    #[default]
+    Stack,
+    // The existing note is removed.
+    Replace,
+    // The new note is not added.
+    Reject,
+}
+
+// Used by add_note() and bulk_add(), which pastes go through.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct AddPolicy {
+    pub on_duplicate: DuplicatePolicy,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AutoPedalPolicy {
+    PerBar,
+    PerHarmonyChange,
+}
+
+pub const VELOCITY_BAND_COUNT: usize = 8;
+
+// Ticks to wait before pressing the dumper again after releasing it.
+pub const AUTO_PEDAL_GAP: u32 = Duration::TICK_RESOLUTION as u32 / 16;
+
 impl ProjectImpl {
     pub fn note_repo(&self) -> &BagStore<u32, Rc<Note>, ModelChangeMetadata> {
         &self.note_repo
@@ -182,791 +561,4299 @@ impl ProjectImpl {
         self.grid
     }
 
-    // Add bars without posting undo info.
-    fn add_bar_internal(&mut self, bar: Bar, select: bool) -> Vec<Bar> {
-        let mut metadata = ModelChangeMetadata::new();
-        if select { metadata.need_select = Some(true); }
-        self.bar_repo.add(bar.start_tick, bar, metadata).map(|o| vec![o]).unwrap_or(vec![])
+    pub fn playback_settings(&self) -> &PlaybackSettings {
+        &self.playback
     }
 
-    pub fn bar_no(&self, bar: &Bar) -> Option<usize> {
-        match self.bar_repo.index(bar.start_tick) {
-            Ok(i) => Some(i),
-            Err(_) => None,
-        }
+    #[cfg(feature = "sqlite")]
+    fn restore_settings(&mut self, settings: &SettingsFile) {
+        self.playback.tempo_scale = settings.tempo_scale;
+        self.note_defaults = settings.note_defaults;
+        self.track_offsets = settings.track_offsets.clone();
     }
-    
-    pub fn tempo_at(&self, tick: u32) -> TempoValue {
-        tempo_at(tick, &self.tempo_repo)
+
+    // Called after changing a setting out of undo history. Nothing is written unless the project was opened by
+    // open_project(). A failed write keeps the previous file, which only loses the change on reopen.
+    fn save_settings(&self) {
+        if let Some(path) = &self.settings_path {
+            let _ = SettingsFile::of(self).write(path);
+        }
     }
-    
-    pub fn dumper_at(&self, tick: u32) -> Velocity {
-        ctrl_chg_at(tick, &self.dumper_repo)
+
+    pub fn display_settings(&self) -> &DisplaySettings {
+        &self.display
     }
-    
-    pub fn soft_at(&self, tick: u32) -> Velocity {
-        ctrl_chg_at(tick, &self.soft_repo)
+
+    // Pitch played back. The key signature is applied.
+    pub fn sounding_pitch(&self, note: &Note) -> Pitch {
+        note.pitch.apply_key(self.key_at(note.start_tick())).unwrap_or(note.pitch)
     }
-    
-    pub fn location_to_tick(&self, loc: Location) -> Result<u32, LocationError> {
-        if loc.bar_no() == 0 {
-            if (u32::MAX as usize) < loc.offset() {
-                Err(LocationError::Overflow)
-            } else {
-                Ok(loc.offset() as u32)
-            }
-        } else if (u32::MAX as usize) < loc.bar_no() {
-            Err(LocationError::Overflow)
-        } else {
-            if loc.bar_no() <= self.bar_repo.len() {
-                let (tick, _) = self.bar_repo[loc.bar_no() - 1];
-                let sum = (tick as usize) + loc.offset();
-                if (u32::MAX as usize) < sum {
-                    Err(LocationError::Overflow)
-                } else {
-                    Ok(sum as u32)
-                }
+
+    // Notes starting in the range brought into the target velocities through the user lane of the velocity
+    // trimmer. Relative velocities map the softest note to the minimum and the loudest to the maximum linearly.
+    // Otherwise only notes outside of the target are moved to its nearest end.
+    fn velocity_normalization(&self, range: Range<u32>, target_min: Velocity, target_max: Velocity, preserve_relative: bool) -> Vec<(Note, Note)> {
+        let (t_min, t_max) = (target_min.as_u8().min(target_max.as_u8()) as i32, target_min.as_u8().max(target_max.as_u8()) as i32);
+        let raw = |n: &Note| n.base_velocity().as_u8() as i32 + n.velocity_trimmer.sum();
+        let notes: Vec<&Rc<Note>> = self.note_repo.range(range).map(|(_, n)| n).collect();
+        let (Some(min), Some(max)) = (notes.iter().map(|n| raw(n)).min(), notes.iter().map(|n| raw(n)).max()) else { return vec![]; };
+        notes.into_iter().filter_map(|n| {
+            let v = raw(n);
+            let target = if !preserve_relative {
+                v.clamp(t_min, t_max)
+            } else if min == max {
+                (t_min + t_max) / 2
             } else {
-                let tick = match self.last_bar() {
-                    None => {
-                        loc.bar_no() * (self.rhythm.tick_len() as usize) + loc.offset()
-                    },
-                    Some((last_bar_no, last_bar)) => {
-                        let last_tick = last_bar.start_tick;
-                        let tick_len = self.rhythm_at(last_tick).tick_len() as usize;
-                        (loc.bar_no() - last_bar_no - 1) * tick_len + loc.offset() + last_bar.start_tick as usize
-                    },
-                };
+                t_min + ((v - min) * (t_max - t_min) + (max - min) / 2) / (max - min)
+            };
+            if target == v { return None; }
+            let user = n.velocity_trimmer.get_lane(TrimmerLane::User) as i32 + target - v;
+            let trimmer = n.velocity_trimmer.set_lane(TrimmerLane::User, user.clamp(i16::MIN as i32, i16::MAX as i32) as i16);
+            Some(((**n).clone(), Note { velocity_trimmer: trimmer, ..(**n).clone() }))
+        }).collect()
+    }
 
-                if (u32::MAX as usize) < tick {
-                    Err(LocationError::Overflow)
-                } else {
-                    Ok(tick as u32)
-                }
-            }
+    // Notes in the range sounding out of the scale moved to the nearest scale tone.
+    fn scale_conformation(&self, range: Range<u32>, scale: Scale) -> Vec<(Note, Note)> {
+        self.note_repo.range(range).filter_map(|(tick, note)| {
+            let pitch = scale.conform(note.pitch, self.key_at(*tick))?;
+            Some(((**note).clone(), Note { pitch, ..**note }))
+        }).collect()
+    }
+
+    // Transposition of the instrument of the channel. Falls back to the display settings if the channel has
+    // no instrument.
+    pub fn transposition(&self, channel: Channel) -> Transposition {
+        match self.instruments.get(channel) {
+            Some(instrument) => instrument.transposition,
+            None => self.display.transposition(channel),
         }
     }
-    
-    pub fn tick_to_location(&self, tick: u32) -> Location {
-        if self.bar_repo.len() == 0 {
-            Location::new(0, tick as usize)
+
+    // Pitch shown in notation. Transposing channels show the written pitch unless concert pitch is on.
+    pub fn display_pitch(&self, note: &Note) -> Pitch {
+        let transposition = self.transposition(note.channel);
+        if self.display.concert_pitch || transposition.is_none() {
+            note.pitch
         } else {
-            match self.bar_repo.find(&tick) {
-                Ok(idx) => Location::new(idx + 1, 0),
-                Err(idx) =>
-                if idx == 0 {
-                    Location::new(0, tick as usize)
-                } else {
-                    let (_, bar) = self.bar_repo[idx - 1];
-                    Location::new(idx, (tick - bar.start_tick) as usize)
-                },
-            }
+            transposition.to_written(self.sounding_pitch(note)).unwrap_or(note.pitch)
         }
     }
-    
-    pub fn rhythm_at(&self, tick: u32) -> Rhythm {
-        let idx = match self.bar_repo.index(tick) {
-            Ok(t) => t,
-            Err(t) => if t == 0 { return self.rhythm } else { t - 1 },
+
+    pub fn clips(&self) -> &ClipLibrary {
+        &self.clips
+    }
+
+    pub fn glissandos(&self) -> &[Glissando] {
+        &self.glissandos
+    }
+
+    pub fn glissando_from(&self, note: &Note) -> Option<&Glissando> {
+        self.glissandos.iter().find(|g| g.from_note == *note)
+    }
+
+    pub fn from_template(template: Template) -> Self {
+        let mut proj = ProjectImpl {
+            rhythm: template.rhythm,
+            key: template.key,
+            grid: template.grid,
+            playback: template.playback,
+            note_defaults: template.note_defaults,
+            display: template.display,
+            default_channel: template.default_channel,
+            dynamics: template.dynamics,
+            track_offsets: template.track_offsets,
+            pitch_shifts: template.pitch_shifts,
+            skip_regions: vec![],
+            add_policy: template.add_policy,
+            instruments: template.instruments,
+            ..ProjectImpl::default()
         };
-        
-        for i in (0..=idx).rev() {
-            let bar = self.bar_repo[i].1;
-            if let Some(rhythm) = bar.rhythm {
-                return rhythm;
-            }
+        proj.bar_repo.bulk_add(template.bars.into_iter().map(|b| (b.start_tick, b)).collect(), ModelChangeMetadata::new());
+        proj.tempo_repo.bulk_add(template.tempos.into_iter().map(|t| (t.start_tick, t)).collect(), ModelChangeMetadata::new());
+        proj
+    }
+
+    // Notes and everything attached to them such as pedals, slurs and comments are left out.
+    pub fn to_template(&self, name: &str) -> Template {
+        Template {
+            name: name.to_owned(),
+            rhythm: self.rhythm,
+            key: self.key,
+            grid: self.grid,
+            bars: self.bar_repo.iter().map(|(_, b)| *b).collect(),
+            tempos: self.tempo_repo.iter().map(|(_, t)| *t).collect(),
+            playback: self.playback,
+            note_defaults: self.note_defaults,
+            display: self.display.clone(),
+            default_channel: self.default_channel,
+            dynamics: self.dynamics,
+            track_offsets: self.track_offsets.clone(),
+            pitch_shifts: self.pitch_shifts.clone(),
+            add_policy: self.add_policy,
+            instruments: self.instruments.clone(),
         }
-        
-        self.rhythm
     }
-    
-    pub fn key_at(&self, tick: u32) -> Key {
-        let idx = match self.bar_repo.index(tick) {
-            Ok(t) => t,
-            Err(t) => if t == 0 { return self.key } else { t - 1 },
+
+    // One project per channel having notes, in channel order, for printing parts. Conductor data such as bars,
+    // tempos, the key, the rhythm, rehearsal marks and comments are copied to every part. Pedals stay with their
+    // channel. Notes entered into a part go to its channel.
+    pub fn extract_parts(&self) -> Vec<ExportedProject> {
+        let whole: ExportedProject = self.clone().into();
+        let channels: BTreeSet<Channel> = whole.models.notes.iter().map(|n| n.channel).collect();
+        let of_channel = |ctrls: &[CtrlChg], channel: Channel| -> Vec<CtrlChg> {
+            ctrls.iter().filter(|c| c.channel == channel).copied().collect()
         };
-        
-        for i in (0..=idx).rev() {
-            let bar = self.bar_repo[i].1;
-            if let Some(key) = bar.key {
-                return key;
-            }
-        }
-        
-        self.key
+
+        channels.into_iter().map(|channel| {
+            let mut part = whole.clone();
+            part.models.notes.retain(|n| n.channel == channel);
+            part.models.dumpers = of_channel(&whole.models.dumpers, channel);
+            part.models.softs = of_channel(&whole.models.softs, channel);
+            part.glissandos.retain(|g| g.from_note.channel == channel);
+            part.slurs.retain(|s| s.start_note.channel == channel);
+            part.default_channel = channel;
+            part.note_defaults = part.note_defaults.with_channel(channel);
+            part
+        }).collect()
     }
 
-    /// Returns bar no(0 offset) and bar.
-    #[inline]
-    fn last_bar(&self) -> Option<(usize, Bar)> {
-        self.bar_repo.peek_last().map(|(_, bar)| (self.bar_repo.len() - 1, bar.clone()))
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
     }
-    
-    fn note_max_end_tick(&self) -> Option<u32> {
-        if self.note_repo.is_empty() { return None; }
-        let mut start_tick =
-        self.note_repo.peek_last().map(|t| { t.0.clone() }).unwrap_or(0) as i64 - *Note::LONGEST_TICK_LEN as i64;
-        if start_tick < 0 { start_tick = 0; }
-        let mut max_tick_loc = 0;
-        for (tick, note) in self.note_repo.range(start_tick as u32..) {
-            let end_tick = tick + note.tick_len();
-            max_tick_loc = max_tick_loc.max(end_tick);
-        }
-        
-        Some(max_tick_loc)
+
+    pub fn rehearsal_marks(&self) -> &[RehearsalMark] {
+        &self.rehearsal_marks
     }
-    
-    #[inline]
-    fn tempo_max_tick(&self) -> Option<u32> {
-        self.tempo_repo.peek_last().map(|t| t.0)
+
+    // Text of each mark by bar, with auto marks lettered in order.
+    pub fn rehearsal_labels(&self) -> Vec<(usize, String)> {
+        rehearsal::labels(&self.rehearsal_marks)
     }
-    
-    #[inline]
-    fn dumper_max_tick(&self) -> Option<u32> {
-        self.dumper_repo.peek_last().map(|t| t.0)
+
+    pub fn comments_in(&self, range: Range<u32>) -> impl Iterator<Item = &Comment> + '_ {
+        self.comments.iter().filter(move |c| c.overlaps(&range))
     }
-    
-    #[inline]
-    fn soft_max_tick(&self) -> Option<u32> {
-        self.soft_repo.peek_last().map(|t| t.0)
+
+    pub fn slurs(&self) -> &[Slur] {
+        &self.slurs
     }
-    
-    /// Returns replenished bars.
-    fn replenish_bars(&mut self) -> Vec<Bar> {
-        let mut bar_tick = self.last_bar().map(|(_, b)| b.start_tick).unwrap_or(0);
-        let max_end_tick = 
-        self.note_max_end_tick().unwrap_or(0)
-        .max(self.tempo_max_tick().unwrap_or(0))
-        .max(self.dumper_max_tick().unwrap_or(0))
-        .max(self.soft_max_tick().unwrap_or(0));
-        
-        let mut replenished_bars: Vec<Bar> = vec![];
-        if max_end_tick <= bar_tick { return replenished_bars; }
-        let bar_tick_len = self.rhythm_at(bar_tick).tick_len();
-        while bar_tick < max_end_tick {
-            bar_tick += bar_tick_len;
-            let bar = Bar::new(bar_tick, None, None, RepeatSet::EMPTY);
-            self.add_bar_internal(bar, false);
-            replenished_bars.push(bar);
+
+    pub fn repeat_diagnostics(&self) -> Vec<(u32, RepeatDiagnostic)> {
+        let mut cache = self.repeat_diagnostics.borrow_mut();
+        let is_fresh = cache.as_ref().is_some_and(|c|
+            c.rhythm == self.rhythm && c.bars.len() == self.bar_repo.len() && c.bars.iter().zip(self.bar_repo.iter()).all(|(b, (_, cur))| b == cur)
+        );
+        if !is_fresh {
+            let bars: Vec<Bar> = self.bar_repo.iter().map(|(_, b)| *b).collect();
+            let diagnostics = repeat_diagnostics(self.rhythm, bars.iter());
+            *cache = Some(DiagnosticsCache { rhythm: self.rhythm, bars, diagnostics });
         }
-        replenished_bars
+        cache.as_ref().unwrap().diagnostics.clone()
     }
-}
 
-pub fn tempo_at(tick: u32, store: &Store<u32, Tempo, ModelChangeMetadata>) -> TempoValue {
-    if store.is_empty() {
-        DEFAULT_TEMPO
-    } else {
-        match store.find(&tick) {
-            Ok(idx) => {
-                store[idx].1.value
-            },
-            Err(idx) => {
-                if idx == 0 {
-                    DEFAULT_TEMPO
-                } else {
-                    store[idx - 1].1.value
-                }
-            },
-        }
+    // Commands sent to the store since save_status() last saw it saved. See SaveProgress.
+    pub(crate) fn sent_cmds(&self) -> &Cell<usize> {
+        &self.sent_cmds
     }
-}
 
-pub fn ctrl_chg_at(tick: u32, store: &Store<u32, CtrlChg, ModelChangeMetadata>) -> Velocity {
-    if store.is_empty() {
-        DEFAULT_CTRL_CHG
-    } else {
-        match store.find(&tick) {
-            Ok(idx) => {
-                store[idx].1.velocity
-            },
-            Err(idx) => {
-                if idx == 0 {
-                    DEFAULT_CTRL_CHG
-                } else {
-                    store[idx - 1].1.velocity
-                }
-            },
-        }
+    // Pending store events are folded into the per repo sums, so the cost is proportional to the changes
+    // since the last call rather than the size of the project.
+    pub fn content_hash(&self) -> u64 {
+        self.fold_content_hash();
+        let header = content_hash::value_hash(&(
+            &self.rhythm, &self.key, &self.grid, &self.playback, &self.note_defaults, &self.clips,
+            &self.display, &self.glissandos, &self.slurs, &self.default_channel, &self.dynamics,
+            (&self.track_offsets, &self.pitch_shifts, &self.skip_regions), &self.add_policy, &self.comments,
+            &self.rehearsal_marks, &self.instruments,
+        ));
+        self.content_hash.borrow().combine(header)
     }
-}
 
-impl Default for ProjectImpl {
-    fn default() -> Self {
-        ProjectImpl {
-            rhythm: Rhythm::default(),
-            key: Key::NONE,
-            grid: Grid::default(),
-            note_repo: BagStore::new(true),
-            bar_repo: Store::new(true),
-            tempo_repo: Store::new(true),
-            dumper_repo: Store::new(true),
-            soft_repo: Store::new(true),
+    // Stable dump of the whole project for golden-file tests, one element per line in a fixed order. Every
+    // collection is sorted by its start tick and then by the line itself, so neither the insertion order nor
+    // the order of stacked duplicates shows. Default note attributes are left out.
+    pub fn to_canonical_string(&self) -> Result<String, serde_json::Error> {
+        fn json<T: Serialize>(value: &T) -> Result<String, serde_json::Error> {
+            serde_json::to_string(value)
+        }
+        fn sorted(mut lines: Vec<(u32, String)>) -> impl Iterator<Item = String> {
+            lines.sort();
+            lines.into_iter().map(|(_, line)| line)
         }
+
+        let mut lines = vec![
+            format!("rhythm {}", json(&self.rhythm)?),
+            format!("key {}", json(&self.key)?),
+            format!("grid {}", json(&self.grid)?),
+            format!("default_channel {}", self.default_channel.as_u8()),
+            format!("playback {}", json(&self.playback)?),
+            format!("note_defaults {}", json(&self.note_defaults)?),
+            format!("display {}", json(&self.display)?),
+            format!("dynamics {}", json(&self.dynamics)?),
+            format!("track_offsets {}", json(&self.track_offsets)?),
+            format!("pitch_shifts {}", json(&self.pitch_shifts)?),
+            format!("skip_regions {}", json(&self.skip_regions)?),
+            format!("add_policy {}", json(&self.add_policy)?),
+            format!("instruments {}", json(&self.instruments)?),
+            format!("clips {}", json(&self.clips)?),
+        ];
+        for (_, bar) in self.bar_repo.iter() {
+            lines.push(format!("bar {}", json(bar)?));
+        }
+        lines.extend(self.tempo_repo.iter().map(|(tick, tempo)| match tempo.marking {
+            Some(marking) => format!("tempo {} {} {}", tick, tempo.value.as_u16(), marking),
+            None => format!("tempo {} {}", tick, tempo.value.as_u16()),
+        }));
+        lines.extend(self.dumper_repo.iter().map(|(tick, d)| format!("dumper {} ch{} {}", tick, d.channel.as_u8(), d.velocity)));
+        lines.extend(self.soft_repo.iter().map(|(tick, s)| format!("soft {} ch{} {}", tick, s.channel.as_u8(), s.velocity)));
+
+        let mut notes = Vec::with_capacity(self.note_repo.len());
+        for (_, n) in self.note_repo.iter() {
+            let mut line = format!("note {} ch{} {} {} {}", n.base_start_tick, n.channel.as_u8(), n.pitch, n.duration, n.base_velocity);
+            if n.start_tick_trimmer != Trimmer::ZERO { line.push_str(&format!(" start_trim {}", json(&n.start_tick_trimmer)?)); }
+            if n.duration_trimmer != RateTrimmer::default() { line.push_str(&format!(" duration_trim {}", json(&n.duration_trimmer)?)); }
+            if n.velocity_trimmer != Trimmer::ZERO { line.push_str(&format!(" velocity_trim {}", json(&n.velocity_trimmer)?)); }
+            if n.tie { line.push_str(" tie"); }
+            if n.tied { line.push_str(" tied"); }
+            if n.muted { line.push_str(" muted"); }
+            if let Some(tag) = &n.tag { line.push_str(&format!(" tag {}", json(tag)?)); }
+            #[cfg(feature = "practice")]
+            if let Some(p) = n.play_probability { line.push_str(&format!(" play_probability {}", p.value())); }
+            notes.push(((n.base_start_tick, n.channel, n.pitch.value(), n.pitch.score_offset()), line));
+        }
+        notes.sort();
+        lines.extend(notes.into_iter().map(|(_, line)| line));
+
+        let mut glissandos = Vec::with_capacity(self.glissandos.len());
+        for g in self.glissandos.iter() {
+            glissandos.push((g.from_note.base_start_tick, format!("glissando {}", json(g)?)));
+        }
+        lines.extend(sorted(glissandos));
+        let mut slurs = Vec::with_capacity(self.slurs.len());
+        for s in self.slurs.iter() {
+            slurs.push((s.start_note.base_start_tick, format!("slur {}", json(s)?)));
+        }
+        lines.extend(sorted(slurs));
+        let mut comments = Vec::with_capacity(self.comments.len());
+        for c in self.comments.iter() {
+            comments.push((c.tick_range().start, format!("comment {}", json(c)?)));
+        }
+        lines.extend(sorted(comments));
+        let mut marks = Vec::with_capacity(self.rehearsal_marks.len());
+        for m in self.rehearsal_marks.iter() {
+            marks.push((m.bar_no as u32, format!("rehearsal {}", json(m)?)));
+        }
+        lines.extend(sorted(marks));
+        lines.push(String::new());
+        Ok(lines.join("\n"))
     }
-}
 
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
-pub enum ProjectCmd {
-    SetRhythm(Rhythm, Rhythm),
-    SetKey(Key, Key),
-    SetGrid(Grid, Grid),
-    ModelChanged { added: Models, removed: Models, metadata: ModelChangeMetadata },
-}
+    fn fold_content_hash(&self) {
+        let mut hash = self.content_hash.borrow_mut();
+        hash.notes.fold_bag_store_events(self.note_repo.events());
+        hash.bars.fold_store_events(self.bar_repo.events());
+        hash.tempos.fold_store_events(self.tempo_repo.events());
+        hash.dumpers.fold_store_events(self.dumper_repo.events());
+        hash.softs.fold_store_events(self.soft_repo.events());
+    }
 
-impl Cmd for ProjectCmd {
-    type Model = ProjectImpl;
-    
-    fn undo(&self, proj: &mut Self::Model) {
-        match self {
-            ProjectCmd::SetRhythm(old_rhythm, _) => {
-                proj.rhythm = *old_rhythm;
-            },
-            ProjectCmd::SetKey(old_key, _) => {
-                proj.key = *old_key;
-            },
-            ProjectCmd::SetGrid(old_grid, _) => {
-                proj.grid = *old_grid;
-            },
-            ProjectCmd::ModelChanged { added, removed, metadata } => {
-                for n in added.notes.iter() {
-                    proj.note_repo.remove(&n.start_tick(), &Rc::new((*n).clone()));
-                }
-                for b in added.bars.iter() {
-                    proj.bar_repo.remove(&b.start_tick);
-                }
-                for t in added.tempos.iter() {
-                    proj.tempo_repo.remove(&t.start_tick);
-                }
-                for d in added.dumpers.iter() {
-                    proj.dumper_repo.remove(&d.start_tick);
-                }
-                for s in added.softs.iter() {
-                    proj.soft_repo.remove(&&s.start_tick);
-                }
-                
-                for n in removed.notes.iter() {
-                    proj.note_repo.add(n.start_tick(), Rc::new((*n).clone()), *metadata);
-                }
-                for b in removed.bars.iter() {
-                    proj.bar_repo.add(b.start_tick, *b, *metadata);
-                }
-                for t in removed.tempos.iter() {
-                    proj.tempo_repo.add(t.start_tick, *t, *metadata);
-                }
-                for d in removed.dumpers.iter() {
-                    proj.dumper_repo.add(d.start_tick, *d, *metadata);
-                }
-                for s in removed.softs.iter() {
-                    proj.soft_repo.add(s.start_tick, *s, *metadata);
-                }
-            },
+    // Rebuilds the repos without spare capacity, which is left behind by big deletions. Pending store events are
+    // folded into the content hash and cleared, so dirty regions should be taken before.
+    pub fn shrink_to_fit(&mut self) {
+        fn compacted<T: Clone>(store: &Store<u32, T, ModelChangeMetadata>) -> Store<u32, T, ModelChangeMetadata> {
+            let mut compacted = Store::with_capacity(store.len(), true);
+            compacted.bulk_add(store.iter().cloned().collect(), ModelChangeMetadata::new());
+            compacted.clear_events();
+            compacted
         }
+
+        self.fold_content_hash();
+        let notes: Vec<(u32, Rc<Note>)> = self.note_repo.iter().map(|(tick, note)| (*tick, note.clone())).collect();
+        self.note_repo = BagStore::new(true);
+        self.note_repo.bulk_add(notes, ModelChangeMetadata::new());
+        self.note_repo.clear_events();
+        self.bar_repo = compacted(&self.bar_repo);
+        self.tempo_repo = compacted(&self.tempo_repo);
+        self.dumper_repo = compacted(&self.dumper_repo);
+        self.soft_repo = compacted(&self.soft_repo);
+        self.content_hash.get_mut().events_cleared();
+
+        self.glissandos.shrink_to_fit();
+        self.slurs.shrink_to_fit();
+        self.comments.shrink_to_fit();
+        self.rehearsal_marks.shrink_to_fit();
     }
-    
-    fn redo(&self, proj: &mut Self::Model) {
-        match self {
-            ProjectCmd::SetRhythm(_, new_rhythm) => {
-                proj.rhythm = *new_rhythm;
-            },
-            ProjectCmd::SetKey(_, new_key) => {
-                proj.key = *new_key;
-            },
-            ProjectCmd::SetGrid(_, new_grid) => {
-                proj.grid = *new_grid;
+
+    // Full measures governed by the bar (up to the next bar having its own rhythm) change their length to
+    // the new rhythm and the following bars shift accordingly. Notes stay at their ticks.
+    // None if there is no bar at the index.
+    pub fn preview_rhythm_change(&self, at_bar: usize, new_rhythm: Rhythm) -> Option<ImpactReport> {
+        if self.bar_repo.len() <= at_bar { return None; }
+        let bars: Vec<Bar> = self.bar_repo.iter().map(|(_, b)| *b).collect();
+        let old_len = self.rhythm_at(bars[at_bar].start_tick).tick_len() as i64;
+        let new_len = new_rhythm.tick_len() as i64;
+
+        let mut new_bars = bars.clone();
+        new_bars[at_bar].rhythm = Some(new_rhythm);
+        let mut delta: i64 = 0;
+        let mut governed = true;
+        for i in at_bar + 1..bars.len() {
+            let prev = &bars[i - 1];
+            if at_bar < i - 1 && prev.rhythm.is_some() { governed = false; }
+            if governed && (bars[i].start_tick - prev.start_tick) as i64 == old_len {
+                delta += new_len - old_len;
             }
-            ProjectCmd::ModelChanged { added, removed , metadata } => {
-                for n in removed.notes.iter() {
-                    proj.note_repo.remove(&n.start_tick(), &Rc::new(n.clone()));
-                }
-                for b in removed.bars.iter() {
-                    proj.bar_repo.remove(&b.start_tick);
-                }
-                for t in removed.tempos.iter() {
-                    proj.tempo_repo.remove(&t.start_tick);
-                }
-                for d in removed.dumpers.iter() {
-                    proj.dumper_repo.remove(&d.start_tick);
-                }
-                for s in removed.softs.iter() {
-                    proj.soft_repo.remove(&&s.start_tick);
-                }
-                
-                for n in added.notes.iter() {
-                    proj.note_repo.add(n.start_tick(), Rc::new(n.clone()), *metadata);
-                }
-                for b in added.bars.iter() {
-                    proj.bar_repo.add(b.start_tick, *b, *metadata);
-                }
-                for t in added.tempos.iter() {
-                    proj.tempo_repo.add(t.start_tick, *t, *metadata);
-                }
-                for d in added.dumpers.iter() {
-                    proj.dumper_repo.add(d.start_tick, *d, *metadata);
-                }
-                for s in added.softs.iter() {
-                    proj.soft_repo.add(s.start_tick, *s, *metadata);
+            new_bars[i].start_tick = (bars[i].start_tick as i64 + delta).max(0) as u32;
+        }
+
+        let shifted_bars = bars.iter().zip(new_bars.iter())
+            .filter(|(old, new)| old.start_tick != new.start_tick)
+            .map(|(old, new)| (*old, new.start_tick))
+            .collect();
+
+        let crosses = |bars: &[Bar], note: &Note| {
+            let (start, end) = (note.start_tick(), note.start_tick() + note.tick_len());
+            let idx = bars.partition_point(|b| b.start_tick <= start);
+            idx < bars.len() && bars[idx].start_tick < end
+        };
+        let crossing_notes = self.note_repo.iter()
+            .filter(|(_, n)| crosses(&new_bars, n) && !crosses(&bars, n))
+            .map(|(_, n)| n.clone())
+            .collect();
+
+        let current = self.repeat_diagnostics();
+        let invalid_repeats = repeat_diagnostics(self.rhythm, new_bars.iter()).into_iter()
+            .filter(|d| !current.contains(d))
+            .filter_map(|(tick, d)| match d {
+                RepeatDiagnostic::Error(e) => Some((tick, e)),
+                RepeatDiagnostic::Warning(_) => None,
+            })
+            .collect();
+
+        Some(ImpactReport { shifted_bars, crossing_notes, invalid_repeats })
+    }
+
+    // Repeat texts/symbols to show on the bar. If repeats are inconsistent, jump targets are not resolved.
+    pub fn repeat_labels(&self, bar: &Bar) -> Vec<RepeatLabel> {
+        let global = global_repeat(self.rhythm, self.bar_repo.iter().map(|(_, b)| b)).ok().flatten();
+        repeat_labels(bar, global.as_ref())
+    }
+
+    // Slur over the note. Slurs whose start note no longer exists are ignored.
+    pub fn slur_over(&self, note: &Note) -> Option<&Slur> {
+        self.slurs.iter().find(|s| s.covers(note) && self.note_repo.iter().any(|(_, n)| **n == s.start_note))
+    }
+
+    pub fn note_defaults(&self) -> &NoteDefaults {
+        &self.note_defaults
+    }
+
+    pub fn drag_session(&self) -> Option<&DragSession> {
+        self.drag.as_ref()
+    }
+
+    // Overlay such as the note under the mouse before click. Renderers draw it, but it is not a part of the tune.
+    pub fn preview_models(&self) -> &Models {
+        &self.preview_models
+    }
+
+    pub fn default_channel(&self) -> Channel {
+        self.default_channel
+    }
+
+    pub fn dynamics_table(&self) -> &DynamicsTable {
+        &self.dynamics
+    }
+
+    // Dynamics table of the instrument of the channel, or that of the project.
+    pub fn dynamics_table_of(&self, channel: Channel) -> &DynamicsTable {
+        self.instruments.get(channel).and_then(|i| i.dynamics.as_ref()).unwrap_or(&self.dynamics)
+    }
+
+    pub fn instruments(&self) -> &InstrumentTable {
+        &self.instruments
+    }
+
+    pub fn track_offsets(&self) -> &TrackOffsets {
+        &self.track_offsets
+    }
+
+    pub fn pitch_shifts(&self) -> &PitchShifts {
+        &self.pitch_shifts
+    }
+
+    pub fn skip_regions(&self) -> &[SkipRegion] {
+        &self.skip_regions
+    }
+
+    // Chunks to play the whole project: repeats expanded and skip regions cut out.
+    pub fn play_chunks(&self) -> Result<Vec<Chunk>, error_stack::Report<RenderRegionError>> {
+        let (region, _warnings) = render_region(self.rhythm, self.bar_repo.iter().map(|(_, bar)| bar))?;
+        Ok(skip_region::cut(&region.to_chunks(), &self.skip_regions))
+    }
+
+    pub fn add_policy(&self) -> AddPolicy {
+        self.add_policy
+    }
+
+    fn is_duplicate(l: &Note, r: &Note) -> bool {
+        l.start_tick() == r.start_tick() && l.pitch.value() == r.pitch.value() && l.channel == r.channel
+    }
+
+    // Notes to add and existing notes to remove under the add policy. Duplicates among the new notes are
+    // treated the same way as those in the repo.
+    fn resolve_duplicates(&self, notes: Vec<Note>, policy: DuplicatePolicy) -> (Vec<Note>, Vec<Rc<Note>>) {
+        if policy == DuplicatePolicy::Stack { return (notes, vec![]); }
+        let mut added: Vec<Note> = Vec::with_capacity(notes.len());
+        let mut removed: Vec<Rc<Note>> = vec![];
+        for note in notes {
+            let existing: Vec<Rc<Note>> = self.note_repo.range(note.start_tick()..note.start_tick() + 1)
+                .filter(|(_, n)| Self::is_duplicate(n, &note) && !removed.contains(n))
+                .map(|(_, n)| n.clone())
+                .collect();
+            let pending = added.iter().position(|n| Self::is_duplicate(n, &note));
+            match policy {
+                DuplicatePolicy::Stack => {}
+                DuplicatePolicy::Replace => {
+                    removed.extend(existing);
+                    if let Some(idx) = pending { added.remove(idx); }
                 }
-            },
+                DuplicatePolicy::Reject => if !existing.is_empty() || pending.is_some() { continue; }
+            }
+            added.push(note);
         }
+        (added, removed)
     }
-}
 
-impl SerializableCmd for ProjectCmd {
-}
+    // Bulk addition of models. Notes are added under the duplicate policy and elements at the same tick as
+    // added bars, tempos and pedals are replaced.
+    fn add_models(&mut self, mut to_add: Models, metadata: ModelChangeMetadata, on_duplicate: DuplicatePolicy) -> ProjectCmd {
+        let mut removed = Models::empty();
 
-#[derive(Debug)]
-pub enum ProjectCmdErr {
-    NoOp,
-}
+        let (notes, duplicates) = self.resolve_duplicates(std::mem::take(&mut to_add.notes), on_duplicate);
+        to_add.notes = notes;
+        for n in duplicates.iter() {
+            self.note_repo.remove(&n.start_tick(), n);
+        }
+        removed.notes = Models::unwrap_rc(&duplicates);
 
-impl error_stack::Context for ProjectCmdErr {}
+        let mut buf: Vec<(u32, Rc<Note>)> = Vec::with_capacity(to_add.notes.len());
+        for n in to_add.notes.iter() {
+            buf.push((n.start_tick(), Rc::new(n.clone())));
+        }
+        self.note_repo.bulk_add(buf, metadata);
 
-impl std::fmt::Display for ProjectCmdErr {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            ProjectCmdErr::NoOp => write!(f, "No Operation"),
+        let mut buf = Vec::with_capacity(to_add.bars.len());
+        for b in to_add.bars.iter() {
+            buf.push((b.start_tick, *b));
         }
+        removed.bars = self.bar_repo.bulk_add(buf, metadata).iter().map(|(_, bar)| *bar).collect();
+
+        let mut buf = Vec::with_capacity(to_add.tempos.len());
+        for t in to_add.tempos.iter() {
+            buf.push((t.start_tick, *t));
+        }
+        removed.tempos = self.tempo_repo.bulk_add(buf, metadata).iter().map(|(_, t)| *t).collect();
+
+        let mut buf = Vec::with_capacity(to_add.dumpers.len());
+        for d in to_add.dumpers.iter() {
+            buf.push((d.start_tick, *d));
+        }
+        removed.dumpers = self.dumper_repo.bulk_add(buf, metadata).iter().map(|(_, d)| *d).collect();
+
+        let mut buf = Vec::with_capacity(to_add.softs.len());
+        for s in to_add.softs.iter() {
+            buf.push((s.start_tick, *s));
+        }
+        removed.softs = self.soft_repo.bulk_add(buf, metadata).iter().map(|(_, s)| *s).collect();
+
+        let replenished_bars = self.replenish_bars();
+        to_add.bars.extend(replenished_bars);
+
+        ProjectCmd::ModelChanged { added: to_add, removed, metadata }
     }
-}
 
-pub trait Project {
-    fn set_rhythm(&mut self, rhythm: Rhythm);
-    fn rhythm(&self) -> Rhythm;
-    fn set_key(&mut self, key: Key);
-    fn key(&self) -> Key;
-    fn set_grid(&mut self, key: Grid);
-    fn grid(&self) -> Grid;
-    fn add_note(&mut self, note: Note, select: bool);
-    fn add_bar(&mut self, bar: Bar, select: bool);
-    fn add_tempo(&mut self, bar: Tempo, select: bool);
-    fn add_dumper(&mut self, dumper: CtrlChg, select: bool);
-    fn add_soft(&mut self, soft: CtrlChg, select: bool);
-    fn tuplize(&mut self, notes: Vec<Rc<Note>>);
-    fn bulk_remove(&mut self, to_remove: Models, metadata: ModelChangeMetadata);
-    fn bulk_add(&mut self, to_add: Models, metadata: ModelChangeMetadata);
-    fn change(&mut self, from_to: ModelChanges, metadata: ModelChangeMetadata);
-    fn bar_no(&self, bar: &Bar) -> Option<usize>;
-    fn tempo_at(&self, tick: u32) -> TempoValue;
-    fn dumper_at(&self, tick: u32) -> Velocity;
-    fn soft_at(&self, tick: u32) -> Velocity;
-    fn clear_model_events(&mut self);
-    fn bar_events(&self) -> &Vec<StoreEvent<u32, Bar, ModelChangeMetadata>>;
-    fn tempo_events(&self) -> &Vec<StoreEvent<u32, Tempo, ModelChangeMetadata>>;
-    fn dumper_events(&self) -> &Vec<StoreEvent<u32, CtrlChg, ModelChangeMetadata>>;
-    fn soft_events(&self) -> &Vec<StoreEvent<u32, CtrlChg, ModelChangeMetadata>>;
-    fn note_events(&self) -> &Vec<BagStoreEvent<u32, Rc<Note>, ModelChangeMetadata>>;
-    fn location_to_tick(&self, loc: Location) -> Result<u32, LocationError>;
-    fn tick_to_location(&self, tick: u32) -> Location;
-    fn rhythm_at(&self, tick: u32) -> Rhythm;
-    fn key_at(&self, tick: u32) -> Key;
-    fn note_repo(&self) -> &BagStore<u32, Rc<Note>, ModelChangeMetadata>;
-    fn bar_repo(&self) -> &Store<u32, Bar, ModelChangeMetadata>;
-    fn tempo_repo(&self) -> &Store<u32, Tempo, ModelChangeMetadata>;
-    fn soft_repo(&self) -> &Store<u32, CtrlChg, ModelChangeMetadata>;
-    fn dumper_repo(&self) -> &Store<u32, CtrlChg, ModelChangeMetadata>;
-}
+    // Bars to paste with the existing bars at the same ticks merged in.
+    pub fn bars_merged(&self, bars: &[Bar]) -> (Vec<Bar>, Vec<BarMergeWarning>) {
+        let mut warnings = vec![];
+        let merged = bars.iter().map(|bar| match self.bar_repo.find(&bar.start_tick) {
+            Ok(idx) => {
+                let (merged, w) = self.bar_repo[idx].1.merged_with(bar);
+                warnings.extend(w);
+                merged
+            },
+            Err(_) => *bar,
+        }).collect();
+        (merged, warnings)
+    }
 
-impl Project for SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr> {
-    fn set_rhythm(&mut self, rhythm: Rhythm) {
-        self.add_cmd(ProjectCmd::SetRhythm(self.model().rhythm, rhythm));
+    // Notes that do not follow the default channel, to find stray notes after changing instruments.
+    pub fn notes_with_nondefault_channel(&self) -> Vec<Rc<Note>> {
+        self.note_repo.iter().filter(|(_, n)| n.channel != self.default_channel).map(|(_, n)| n.clone()).collect()
     }
-    
-    fn rhythm(&self) -> Rhythm {
-        self.model().rhythm
+
+    // Step entry takes the channel from note defaults, so it follows the default channel.
+    fn apply_default_channel(&mut self, channel: Channel) {
+        self.default_channel = channel;
+        self.note_defaults.channel = channel;
     }
 
-    fn set_key(&mut self, key: Key) {
-        self.add_cmd(ProjectCmd::SetKey(self.model().key, key));
+    // Everything visible in the viewport. Notes sounding in the tick range are included even if they start before it.
+    // Pitch range is by pitch value and applies to notes only.
+    pub fn models_in(&self, tick_range: Range<u32>, pitch_range: RangeInclusive<u8>) -> ModelsView<'_> {
+        let lookback = tick_range.start.saturating_sub(Duration::MAX_TICK_LENGTH as u32);
+        let notes = self.note_repo.range(lookback..tick_range.end)
+            .filter(|(_, n)| tick_range.start < n.start_tick() + n.tick_len() && pitch_range.contains(&n.pitch.value()))
+            .map(|(_, n)| n)
+            .collect();
+        ModelsView {
+            notes,
+            bars: self.bar_repo.range(tick_range.clone()).1.iter().map(|(_, b)| b).collect(),
+            tempos: self.tempo_repo.range(tick_range.clone()).1.iter().map(|(_, t)| t).collect(),
+            dumpers: self.dumper_repo.range(tick_range.clone()).1.iter().map(|(_, d)| d).collect(),
+            softs: self.soft_repo.range(tick_range).1.iter().map(|(_, s)| s).collect(),
+        }
     }
 
-    fn key(&self) -> Key {
-        self.model().key
+    // Number of notes starting in the range by velocity. Each bucket covers `bucket` velocity values from 0.
+    pub fn velocity_histogram(&self, range: Range<u32>, bucket: u8) -> Vec<usize> {
+        let bucket = bucket.max(1) as usize;
+        let mut histogram = vec![0; (velocity::MAX_VALUE as usize + 1).div_ceil(bucket)];
+        for (_, note) in self.note_repo.range(range) {
+            histogram[note.velocity().as_u8() as usize / bucket] += 1;
+        }
+        histogram
     }
-    
-    fn set_grid(&mut self, grid: Grid) {
-        self.add_cmd(ProjectCmd::SetGrid(self.model().grid, grid));
+
+    // Notes starting in the range grouped into VELOCITY_BAND_COUNT bands of the same width. Band 0 is the softest.
+    pub fn notes_by_velocity_band(&self, range: Range<u32>) -> Vec<Vec<Rc<Note>>> {
+        let width = (velocity::MAX_VALUE as usize + 1) / VELOCITY_BAND_COUNT;
+        let mut bands = vec![vec![]; VELOCITY_BAND_COUNT];
+        for (_, note) in self.note_repo.range(range) {
+            bands[note.velocity().as_u8() as usize / width].push(note.clone());
+        }
+        bands
     }
 
-    fn grid(&self) -> Grid {
-        self.model().grid
+    // Ticks where the dumper should be released and pressed again. Always starts with the start of range.
+    pub fn pedal_change_ticks(&self, range: RangeInclusive<u32>, policy: AutoPedalPolicy) -> Vec<u32> {
+        let (start, end) = (*range.start(), *range.end());
+        let mut ticks = vec![start];
+        match policy {
+            AutoPedalPolicy::PerBar => {
+                ticks.extend(self.bar_repo.range(start + 1..end).1.iter().map(|(tick, _)| *tick));
+            },
+            AutoPedalPolicy::PerHarmonyChange => {
+                let mut harmony: BTreeSet<u8> = BTreeSet::new();
+                for (tick, notes) in self.note_repo.range_vec(start..end) {
+                    let onset: BTreeSet<u8> = notes.iter().map(|n| pitch_class::of(n.pitch)).collect();
+                    if onset.is_subset(&harmony) { continue; }
+                    if !harmony.is_empty() && start < *tick {
+                        ticks.push(*tick);
+                    }
+                    harmony = onset;
+                }
+            },
+        }
+        ticks
     }
-    
-    fn add_note(&mut self, note: Note, select: bool) {
-        let note = Rc::new(note);
+
+    // Add bars without posting undo info.
+    fn add_bar_internal(&mut self, bar: Bar, select: bool) -> Vec<Bar> {
         let mut metadata = ModelChangeMetadata::new();
         if select { metadata.need_select = Some(true); }
+        self.bar_repo.add(bar.start_tick, bar, metadata).map(|o| vec![o]).unwrap_or(vec![])
+    }
 
-        let _ = self.mutate(Box::new(move |proj| {
-            proj.note_repo.add(note.start_tick(), note.clone(), metadata);
-            let replenishid_bars = proj.replenish_bars();
-            Ok(
-                ProjectCmd::ModelChanged {
-                    added: Models::empty().with_notes(&[note.clone()]).with_bars(replenishid_bars),
-                    removed: Models::empty(),
-                    metadata,
-                }
-            )
-        }));
+    pub fn bar_no(&self, bar: &Bar) -> Option<usize> {
+        match self.bar_repo.index(bar.start_tick) {
+            Ok(i) => Some(i),
+            Err(_) => None,
+        }
     }
     
-    fn add_bar(&mut self, bar: Bar, select: bool) {
-        let mut metadata = ModelChangeMetadata::new();
-        if select { metadata.need_select = Some(true); }
-        let _ = self.mutate(Box::new(move |proj| {
-            let origin = proj.bar_repo.add(bar.start_tick, bar, metadata).map(|o| vec![o]).unwrap_or(vec![]);
-            
-            Ok(
-                ProjectCmd::ModelChanged {
-                    added: Models::empty().with_bars(vec![bar]),
-                    removed: Models::empty().with_bars(origin),
-                    metadata,
-                }
-            )
-        }));
+    pub fn tempo_at(&self, tick: u32) -> TempoValue {
+        tempo_at(tick, &self.tempo_repo)
     }
     
-    fn add_tempo(&mut self, tempo: Tempo, select: bool) {
-        let mut metadata = ModelChangeMetadata::new();
-        if select { metadata.need_select = Some(true); }
-        let _ = self.mutate(Box::new(move |proj| {
-            let origin = proj.tempo_repo.add(tempo.start_tick, tempo, metadata).map(|o| vec![o]).unwrap_or(vec![]);
-            let replenishid_bars = proj.replenish_bars();
-            Ok(
-                ProjectCmd::ModelChanged {
-                    added: Models::empty().with_bars(replenishid_bars).with_tempos(vec![tempo]),
-                    removed: Models::empty().with_tempos(origin),
-                    metadata,
-                }
-                
-            )
-        }));
-    }
-    
-    fn add_dumper(&mut self, dumper: CtrlChg, select: bool) {
-        let mut metadata = ModelChangeMetadata::new();
-        if select { metadata.need_select = Some(true); }
-        let _ = self.mutate(Box::new(move |proj| {
-            let origin = proj.dumper_repo.add(dumper.start_tick, dumper, metadata).map(|o| vec![o]).unwrap_or(vec![]);
-            let replenishid_bars = proj.replenish_bars();
-            Ok(
-                ProjectCmd::ModelChanged {
-                    added: Models::empty().with_bars(replenishid_bars).with_dumpers(vec![dumper]),
-                    removed: Models::empty().with_dumpers(origin),
-                    metadata,
-                }
-            )
-        }));
-    }
-    
-    fn add_soft(&mut self, soft: CtrlChg, select: bool) {
-        let mut metadata = ModelChangeMetadata::new();
-        if select { metadata.need_select = Some(true); }
-        let _ = self.mutate(Box::new(move |proj| {
-            let origin = proj.soft_repo.add(soft.start_tick, soft, metadata).map(|o| vec![o]).unwrap_or(vec![]);
-            let replenishid_bars = proj.replenish_bars();
-            Ok(
-                ProjectCmd::ModelChanged {
-                    added: Models::empty().with_bars(replenishid_bars).with_softs(vec![soft]),
-                    removed: Models::empty().with_softs(origin),
-                    metadata
-                }
-            )
-        }));
-    }
-    
-    fn tuplize(&mut self, notes: Vec<Rc<Note>>) {
-        let metadata = ModelChangeMetadata::new().with_need_select(true);
-        let _ = self.mutate(Box::new(move |proj| {
-            if 1 < notes.len() {
-                let mut to_remove = Vec::with_capacity(notes.len());
-                for n in notes.iter() {
-                    to_remove.push((n.start_tick(), n.clone()));
-                }
-                let tupled = tuple::tuplize(notes.clone());
-                proj.note_repo.bulk_remove(&to_remove, ModelChangeMetadata::new());
-                proj.note_repo.bulk_add(
-                    tupled.iter().map(|n| (n.start_tick(), n.clone())).collect(),
-                    metadata
-                );
-                let replenishid_bars = proj.replenish_bars();
-                
-                Ok(
-                    ProjectCmd::ModelChanged {
-                        added: Models::empty().with_notes(&tupled).with_bars(replenishid_bars),
-                        removed: Models::empty().with_notes(&notes),
-                        metadata,
-                    }
-                )
-            } else {
-                Err(error_stack::report!(ProjectCmdErr::NoOp))
-            }
-        }));
-        
+    pub fn dumper_at(&self, tick: u32) -> Velocity {
+        ctrl_chg_at(tick, &self.dumper_repo)
     }
 
-    fn bulk_remove(&mut self, to_remove: Models, metadata: ModelChangeMetadata) {
-        self.add_cmd(ProjectCmd::ModelChanged { added: Models::empty(), removed: to_remove, metadata });
+    // Wall clock seconds to play the range. The playback tempo scale is not applied.
+    pub fn seconds_of(&self, range: Range<u32>) -> f64 {
+        if range.is_empty() { return 0.0; }
+        tempo_secs(range.clone(), self.tempo_at(range.start), self.tempo_repo.range(range).1.iter().map(|(t, tempo)| (*t, tempo.value)))
     }
 
-    fn bulk_add(&mut self, mut to_add: Models, metadata: ModelChangeMetadata) {
-        let _ = self.mutate(Box::new(move |proj| {
-            let mut removed = Models::empty();
+    // Added and removed tempos to play the range in the target seconds, with the achieved error in seconds.
+    // Tempos in the range are scaled by the same factor so that existing ramps keep their shape. The tempo
+    // at the end of range is restored.
+    fn duration_fit(&self, range: Range<u32>, target_secs: f64) -> Result<(Models, Models, f64), FitDurationError> {
+        if range.is_empty() { return Err(FitDurationError::EmptyRange); }
+        if !target_secs.is_finite() || target_secs <= 0.0 { return Err(FitDurationError::InvalidTarget(target_secs)); }
 
-            let mut buf: Vec<(u32, Rc<Note>)> = Vec::with_capacity(to_add.notes.len());
-            for n in to_add.notes.iter() {
-                buf.push((n.start_tick(), Rc::new(n.clone())));
-            }   
-            proj.note_repo.bulk_add(buf, metadata);
-    
-            let mut buf = Vec::with_capacity(to_add.bars.len());
-            for b in to_add.bars.iter() {
-                buf.push((b.start_tick, *b));
-            }
-            removed.bars = proj.bar_repo.bulk_add(buf, metadata).iter().map(|(_, bar)| *bar).collect();
-    
-            let mut buf = Vec::with_capacity(to_add.tempos.len());
-            for t in to_add.tempos.iter() {
-                buf.push((t.start_tick, *t));
-            }
-            removed.tempos = proj.tempo_repo.bulk_add(buf, metadata).iter().map(|(_, t)| *t).collect();
-    
-    
-            let mut buf = Vec::with_capacity(to_add.dumpers.len());
-            for d in to_add.dumpers.iter() {
-                buf.push((d.start_tick, *d));
-            }
-            removed.dumpers = proj.dumper_repo.bulk_add(buf, metadata).iter().map(|(_, d)| *d).collect();
+        let factor = self.seconds_of(range.clone()) / target_secs;
+        let scale = |v: TempoValue| TempoValue::new(
+            (v.value() as f64 * factor).round().clamp(MIN_TEMPO_VALUE as f64, MAX_TEMPO_VALUE as f64) as u16
+        );
+        let (added, removed) = self.tempo_scaling(range.clone(), scale);
+        let achieved = tempo_secs(range, added.tempos[0].value, added.tempos.iter().map(|t| (t.start_tick, t.value)));
+        Ok((added, removed, achieved - target_secs))
+    }
     
-            let mut buf = Vec::with_capacity(to_add.softs.len());
-            for s in to_add.softs.iter() {
-                buf.push((s.start_tick, *s));
+    // Added and removed tempos to scale the tempos in the range. Tempos are added at both ends of the range
+    // so that the tempo outside stays the same.
+    fn tempo_scaling(&self, range: Range<u32>, scale: impl Fn(TempoValue) -> TempoValue) -> (Models, Models) {
+        let mut added = Models::empty();
+        let mut removed = Models::empty();
+        match self.tempo_repo.find(&range.start) {
+            Ok(idx) => {
+                let tempo = self.tempo_repo[idx].1;
+                removed.tempos.push(tempo);
+                added.tempos.push(Tempo { value: scale(tempo.value), ..tempo });
             }
-            removed.softs = proj.soft_repo.bulk_add(buf, metadata).iter().map(|(_, s)| *s).collect();
-            
-            let replenished_bars = proj.replenish_bars();
-            to_add.bars.extend(replenished_bars);
-    
-            Ok(ProjectCmd::ModelChanged { added: to_add, removed, metadata })
-        }));
+            Err(_) => added.tempos.push(Tempo { start_tick: range.start, value: scale(self.tempo_at(range.start)), marking: None }),
+        }
+        for (_, tempo) in self.tempo_repo.range(range.start + 1..range.end).1 {
+            removed.tempos.push(*tempo);
+            added.tempos.push(Tempo { value: scale(tempo.value), ..*tempo });
+        }
+        if range.end != u32::MAX && self.tempo_repo.find(&range.end).is_err() {
+            added.tempos.push(Tempo { start_tick: range.end, value: self.tempo_at(range.end), marking: None });
+        }
+        (added, removed)
     }
 
-    fn change(&mut self, from_to: ModelChanges, metadata: ModelChangeMetadata) {
-        let _ = self.mutate(Box::new(move |proj| {
-            let mut added: Models = Models::with_capacity(
-                from_to.notes.len(),
-                from_to.bars.len(),
-                from_to.tempos.len(),
-                from_to.dumpers.len(),
-                from_to.softs.len(),
-            );
+    pub fn soft_at(&self, tick: u32) -> Velocity {
+        ctrl_chg_at(tick, &self.soft_repo)
+    }
 
-            let mut removed: Models = Models::with_capacity(
-                from_to.notes.len(),
-                from_to.bars.len(),
-                from_to.tempos.len(),
-                from_to.dumpers.len(),
-                from_to.softs.len(),
-            );
+    // Spans the pedal is held down in the range, for drawing pedal bars. Channels are not told apart.
+    pub fn pedal_segments(&self, kind: CtrlChgKind, range: Range<u32>) -> Vec<PedalSegment> {
+        let store = match kind {
+            CtrlChgKind::Dumper => &self.dumper_repo,
+            CtrlChgKind::Soft => &self.soft_repo,
+        };
+        let points: Vec<(u32, Velocity)> = store.range(range.clone()).1.iter().map(|(t, c)| (*t, c.velocity)).collect();
+        PedalSegment::from_points(ctrl_chg_at(range.start, store), &points, range)
+    }
 
-            let mut note_change: Vec<((u32, Rc<Note>), (u32, Rc<Note>))> = Vec::with_capacity(from_to.notes.len());
-            for (from, to) in from_to.notes.iter() {
-                note_change.push((
-                    (from.start_tick(), Rc::new(from.clone())), (to.start_tick(), Rc::new(to.clone()))
-                ));
-                added.notes.push(to.clone());
-                removed.notes.push(from.clone());
-            }
-            proj.note_repo.change(&note_change, metadata);
+    // Falls back to dumper_at() if the channel has no dumper at all.
+    pub fn dumper_at_chan(&self, tick: u32, channel: Channel) -> Velocity {
+        ctrl_chg_at_chan(tick, channel, &self.dumper_repo)
+    }
 
-            let mut bar_change: Vec<(&u32, (u32, Bar))> = Vec::with_capacity(from_to.bars.len());
-            for (from, to) in from_to.bars.iter() {
-                bar_change.push((
-                    &from.start_tick, (to.start_tick, *to)
-                ));
-                added.bars.push(*to);
-                removed.bars.push(*from);
-            }
-            removed.bars.extend(proj.bar_repo.change(&bar_change, metadata).iter().map(|(_, b)| *b).collect::<Vec<Bar>>());
+    // Falls back to soft_at() if the channel has no soft at all.
+    pub fn soft_at_chan(&self, tick: u32, channel: Channel) -> Velocity {
+        ctrl_chg_at_chan(tick, channel, &self.soft_repo)
+    }
 
-            let mut tempo_change: Vec<(&u32, (u32, Tempo))> = Vec::with_capacity(from_to.tempos.len());
-            for (from, to) in from_to.tempos.iter() {
-                tempo_change.push((
-                    &from.start_tick, (to.start_tick, *to)
-                ));
-                added.tempos.push(*to);
-                removed.tempos.push(*from);
+    // Vertical slice at the tick to audition while scrubbing. Notes are the struck (head) notes of tie chains
+    // sounding at the tick. Tempo and pedals are the effective values placed at the tick. Muted notes are excluded.
+    pub fn events_at(&self, tick: u32) -> Models {
+        let mut notes = vec![];
+        for (_, head) in self.note_repo.range(0..tick.saturating_add(1)) {
+            if head.tied || head.muted { continue; }
+            let mut last = head;
+            while last.tie {
+                let next = self.note_repo.range(last.start_tick().saturating_add(1)..u32::MAX)
+                    .find(|(_, n)| n.tied && n.pitch == last.pitch && n.channel == last.channel);
+                match next {
+                    Some((_, n)) => last = n,
+                    None => break,
+                }
             }
-            removed.tempos.extend(proj.tempo_repo.change(&tempo_change,metadata).iter().map(|(_, t)| *t).collect::<Vec<Tempo>>());
-
-            let mut dumper_change: Vec<(&u32, (u32, CtrlChg))> = Vec::with_capacity(from_to.dumpers.len());
-            for (from, to) in from_to.dumpers.iter() {
-                dumper_change.push((
-                    &from.start_tick, (to.start_tick, *to)
-                ));
-                added.dumpers.push(*to);
-                removed.dumpers.push(*from);
+            if tick < last.start_tick().saturating_add(last.tick_len()) {
+                notes.push((**head).clone());
             }
-            removed.dumpers.extend(proj.dumper_repo.change(&dumper_change,metadata).iter().map(|(_, t)| *t).collect::<Vec<CtrlChg>>());
+        }
 
-            let mut soft_change: Vec<(&u32, (u32, CtrlChg))> = Vec::with_capacity(from_to.softs.len());
-            for (from, to) in from_to.softs.iter() {
-                soft_change.push((
-                    &from.start_tick, (to.start_tick, *to)
-                ));
-                added.softs.push(*to);
-                removed.softs.push(*from);
+        Models {
+            notes,
+            bars: vec![],
+            tempos: vec![Tempo { start_tick: tick, value: self.tempo_at(tick), marking: None }],
+            dumpers: vec![CtrlChg::new(tick, self.dumper_at(tick), Channel::default())],
+            softs: vec![CtrlChg::new(tick, self.soft_at(tick), Channel::default())],
+        }
+    }
+    
+    pub fn location_to_tick(&self, loc: Location) -> Result<u32, LocationError> {
+        let bar_start = if loc.bar_no() == 0 {
+            Ok(Tick::ZERO)
+        } else if loc.bar_no() <= self.bar_repo.len() {
+            Ok(Tick::new(self.bar_repo[loc.bar_no() - 1].0))
+        } else {
+            match self.last_bar() {
+                None => Tick::of_bars(loc.bar_no(), self.rhythm.tick_len()),
+                Some((last_bar_no, last_bar)) => {
+                    let tick_len = self.rhythm_at(last_bar.start_tick).tick_len();
+                    Tick::of_bars(loc.bar_no() - last_bar_no - 1, tick_len)
+                        .and_then(|tick| tick.checked_add(last_bar.start_tick as u64))
+                },
             }
-            removed.softs.extend(proj.soft_repo.change(&soft_change,metadata).iter().map(|(_, t)| *t).collect::<Vec<CtrlChg>>());
-
-            added.bars.extend(proj.replenish_bars());
-            Ok(ProjectCmd::ModelChanged {
-                added, removed, metadata
-            })
-        }));
+        };
+        bar_start.and_then(|tick| tick.checked_add(loc.offset() as u64))
+            .map(Tick::as_u32)
+            .map_err(|_| LocationError::Overflow)
     }
+    
+    // Chunks to play bars in the range (bar no is the same as Location). Repeats are honored only if
+    // the jump lands inside the range, so the passages before the range are never replayed.
+    pub fn render_range(&self, bars: Range<usize>, honor_repeats: bool) -> Result<Vec<Chunk>, error_stack::Report<RenderRegionError>> {
+        let tick_of = |bar_no: usize| self.location_to_tick(Location::new(bar_no, 0)).unwrap_or(u32::MAX);
+        let (start, end) = (tick_of(bars.start), tick_of(bars.end));
+        if end <= start { return Ok(vec![]); }
+        if !honor_repeats { return Ok(skip_region::cut(&[Chunk::new(start, end)], &self.skip_regions)); }
 
-    #[inline]
-    fn bar_no(&self, bar: &Bar) -> Option<usize> {
-        self.model().bar_no(bar)
+        let (region, _warnings) = render_region(self.rhythm, self.bar_repo.iter().map(|(_, bar)| bar))?;
+        let mut chunks: Vec<Chunk> = vec![];
+        let mut played: Vec<Chunk> = vec![];
+        for c in region.to_chunks() {
+            let clipped = match c.intersect(start..end) {
+                Some(clipped) => clipped,
+                None => continue,
+            };
+            let replay = played.iter().any(|p| p.start_tick() < clipped.end_tick() && clipped.start_tick() < p.end_tick());
+            if c.start_tick() < start && replay { continue; }
+            played.push(clipped);
+            chunks.push(clipped);
+        }
+        Ok(skip_region::cut(&Chunk::optimize(&chunks), &self.skip_regions))
     }
 
-    #[inline]
-    fn tempo_at(&self, tick: u32) -> TempoValue {
-        self.model().tempo_at(tick)
+    // Note the tie of the note continues to. The partner starts within MAX_TICK_LENGTH after the end of the note.
+    pub fn next_tie_partner(&self, note: &Note) -> Option<&Rc<Note>> {
+        if !note.tie { return None; }
+        let limit = note.start_tick().saturating_add(note.tick_len()).saturating_add(Duration::MAX_TICK_LENGTH as u32);
+        self.note_repo.range(note.start_tick().saturating_add(1)..limit.saturating_add(1))
+            .find(|(_, n)| n.tied && n.pitch == note.pitch && n.channel == note.channel)
+            .map(|(_, n)| n)
     }
 
-    #[inline]
-    fn dumper_at(&self, tick: u32) -> Velocity {
-        self.model().dumper_at(tick)
+    // Note tied to the note. The note starts within MAX_TICK_LENGTH after the end of the partner.
+    pub fn prev_tie_partner(&self, note: &Note) -> Option<&Rc<Note>> {
+        if !note.tied { return None; }
+        let from = note.start_tick().saturating_sub(Duration::MAX_TICK_LENGTH as u32 * 2);
+        self.note_repo.range(from..note.start_tick())
+            .filter(|(_, n)| n.tie && n.pitch == note.pitch && n.channel == note.channel)
+            .filter(|(_, n)| note.start_tick() <= n.start_tick().saturating_add(n.tick_len()).saturating_add(Duration::MAX_TICK_LENGTH as u32))
+            .last()
+            .map(|(_, n)| n)
     }
 
-    #[inline]
-    fn soft_at(&self, tick: u32) -> Velocity {
-        self.model().soft_at(tick)
-    }
+    // Fixes inconsistencies left by corrupted files or crashed sessions. Changes are not recorded as undo
+    // history.
+    pub fn repair(&mut self) -> RepairReport {
+        let mut report = RepairReport::default();
+        rekey(&mut self.bar_repo, |b| b.start_tick, &mut report);
+        rekey(&mut self.tempo_repo, |t| t.start_tick, &mut report);
+        rekey(&mut self.dumper_repo, |d| d.start_tick, &mut report);
+        rekey(&mut self.soft_repo, |s| s.start_tick, &mut report);
 
-    fn clear_model_events(&mut self) {
-        let _ = self.irreversible_mutate(Box::new(|proj| {
-            proj.note_repo.clear_events();
-            proj.bar_repo.clear_events();
-            proj.tempo_repo.clear_events();
-            proj.dumper_repo.clear_events();
-            proj.soft_repo.clear_events();
-        }));
-    }
+        let out_of_range = |n: &Note| n.start_tick().checked_add(n.tick_len()).is_none_or(|end| end == u32::MAX);
+        let bad_notes: Vec<(u32, Rc<Note>)> = self.note_repo.iter()
+            .filter(|(tick, n)| **tick != n.start_tick() || out_of_range(n))
+            .map(|(tick, n)| (*tick, n.clone()))
+            .collect();
+        for (tick, note) in bad_notes {
+            self.note_repo.remove(&tick, &note);
+            if out_of_range(&note) {
+                report.notes_out_of_range += 1;
+            } else {
+                self.note_repo.add(note.start_tick(), note, ModelChangeMetadata::new());
+                report.misplaced += 1;
+            }
+        }
 
-    #[inline]
-    fn bar_events(&self) -> &Vec<StoreEvent<u32, Bar, ModelChangeMetadata>> {
-        self.model().bar_repo.events()
+        let note_repo = &self.note_repo;
+        let exists = |n: &Note| note_repo.range(n.start_tick()..n.start_tick().saturating_add(1)).any(|(_, e)| **e == *n);
+        let count = self.glissandos.len();
+        self.glissandos.retain(|g| exists(&g.from_note) && exists(&g.to_note));
+        report.dangling_glissandos = count - self.glissandos.len();
+        let count = self.slurs.len();
+        self.slurs.retain(|s| exists(&s.start_note) && exists(&s.end_note));
+        report.dangling_slurs = count - self.slurs.len();
+        report.orphan_clip_instances = self.clips.drop_orphan_instances();
+        report
     }
 
-    #[inline]
-    fn tempo_events(&self) -> &Vec<StoreEvent<u32, Tempo, ModelChangeMetadata>> {
-        self.model().tempo_repo.events()
+    // Moves of the notes toward the nearest grid line by the strength. Strengths over 100% are taken as 100%.
+    // Notes in tie chains and notes not moving are left out.
+    pub fn quantize_preview(&self, notes: &[Rc<Note>], grid: Grid, strength: PercentU16) -> Vec<QuantizeMove> {
+        let strength = strength.value().min(PercentU16::HUNDRED.value()) as i64;
+        notes.iter().filter(|n| !n.tie && !n.tied).filter_map(|n| {
+            let tick = n.start_tick() as i64;
+            let target = grid.snap_in_bar(n.start_tick(), self.bar_span(n.start_tick()), SnapRounding::Nearest) as i64;
+            let new_tick = tick + (target - tick) * strength / PercentU16::HUNDRED.value() as i64;
+            (new_tick != tick).then(|| QuantizeMove { note: n.clone(), new_tick: new_tick as u32 })
+        }).collect()
     }
 
-    #[inline]
-    fn dumper_events(&self) -> &Vec<StoreEvent<u32, CtrlChg, ModelChangeMetadata>> {
-        self.model().dumper_repo.events()
+    // Added and removed models to remove the notes without leaving dangling tie flags.
+    fn tie_aware_removal(&self, notes: &[Rc<Note>], policy: TieRemovalPolicy) -> (Models, Models) {
+        let mut to_remove: Vec<Rc<Note>> = notes.iter()
+            .filter_map(|n| self.note_repo.range(n.start_tick()..n.start_tick() + 1).find(|(_, m)| *m == n).map(|(_, m)| m.clone()))
+            .collect();
+        if policy == TieRemovalPolicy::RemoveChain {
+            let mut i = 0;
+            while i < to_remove.len() {
+                let note = to_remove[i].clone();
+                for partner in [self.prev_tie_partner(&note), self.next_tie_partner(&note)].into_iter().flatten() {
+                    if !to_remove.contains(partner) { to_remove.push(partner.clone()); }
+                }
+                i += 1;
+            }
+        }
 
+        let mut changed: Vec<(Rc<Note>, Note)> = vec![];
+        for note in to_remove.iter() {
+            let partners = [(self.prev_tie_partner(note), true), (self.next_tie_partner(note), false)];
+            for (partner, is_prev) in partners {
+                let Some(partner) = partner else { continue; };
+                if to_remove.contains(partner) { continue; }
+                let idx = match changed.iter().position(|(from, _)| from == partner) {
+                    Some(idx) => idx,
+                    None => { changed.push((partner.clone(), (**partner).clone())); changed.len() - 1 }
+                };
+                if is_prev { changed[idx].1.tie = false; } else { changed[idx].1.tied = false; }
+            }
+        }
+
+        let added = Models { notes: changed.iter().map(|(_, to)| to.clone()).collect(), ..Models::empty() };
+        let removed_notes: Vec<Rc<Note>> = to_remove.into_iter().chain(changed.into_iter().map(|(from, _)| from)).collect();
+        (added, Models::empty().with_notes(&removed_notes))
     }
 
-    #[inline]
-    fn soft_events(&self) -> &Vec<StoreEvent<u32, CtrlChg, ModelChangeMetadata>> {
-        self.model().soft_repo.events()
+    // Added and removed models to remove the bar. The key, rhythm and repeats of the bar are inherited by the
+    // next bar unless it has its own. None if the bar is not in the project.
+    fn bar_removal(&self, bar: &Bar, policy: BarContentPolicy) -> Option<(Models, Models)> {
+        let idx = self.bar_repo.find(&bar.start_tick).ok()?;
+        if self.bar_repo[idx].1 != *bar { return None; }
+        let start = bar.start_tick;
+        let next = self.bar_repo.get(idx + 1).map(|(_, b)| *b);
+        let end = next.map(|b| b.start_tick).unwrap_or(start + self.rhythm_at(start).tick_len());
+
+        let mut added = Models::empty();
+        let mut removed = Models::empty().with_bars(vec![*bar]);
+        if policy != BarContentPolicy::LeaveGap {
+            removed.notes.extend(self.note_repo.range(start..end).map(|(_, n)| (**n).clone()));
+            removed.tempos.extend(self.tempo_repo.range(start..end).1.iter().map(|(_, t)| *t));
+            removed.dumpers.extend(self.dumper_repo.range(start..end).1.iter().map(|(_, d)| *d));
+            removed.softs.extend(self.soft_repo.range(start..end).1.iter().map(|(_, s)| *s));
+        }
+        if policy == BarContentPolicy::ShiftUp {
+            let delta = -((end - start) as i32);
+            for (_, n) in self.note_repo.range(end..) {
+                removed.notes.push((**n).clone());
+                added.notes.push(n.with_tick_added(delta, false).ok()?);
+            }
+            for (_, b) in self.bar_repo.range(end + 1..).1 {
+                removed.bars.push(*b);
+                added.bars.push(b.with_tick_added(delta).ok()?);
+            }
+            for (_, t) in self.tempo_repo.range(end..).1 {
+                removed.tempos.push(*t);
+                added.tempos.push(t.with_tick_added(delta).ok()?);
+            }
+            for (_, d) in self.dumper_repo.range(end..).1 {
+                removed.dumpers.push(*d);
+                added.dumpers.push(d.with_tick_added(delta).ok()?);
+            }
+            for (_, s) in self.soft_repo.range(end..).1 {
+                removed.softs.push(*s);
+                added.softs.push(s.with_tick_added(delta).ok()?);
+            }
+        }
+        if let Some(next) = next {
+            let mut inheriting = Bar::new(next.start_tick, next.rhythm.or(bar.rhythm), next.key.or(bar.key), next.repeats.merged(bar.repeats));
+            if policy == BarContentPolicy::ShiftUp {
+                inheriting.start_tick = start;
+            }
+            if inheriting != next {
+                removed.bars.push(next);
+                added.bars.push(inheriting);
+            }
+        }
+        Some((added, removed))
     }
 
-    #[inline]
-    fn note_events(&self) -> &Vec<BagStoreEvent<u32, Rc<Note>, ModelChangeMetadata>> {
-        self.model().note_repo.events()
+    // One line per note, tempo and bar attribute in tick order, such as "Bar 12, beat 3: C sharp 5 eighth note, forte".
+    // Bars are counted from 1. Tied continuations are not listed.
+    pub fn describe_range(&self, range: Range<u32>) -> Vec<String> {
+        let position = |tick: u32| {
+            let loc = self.tick_to_location(tick);
+            format!("Bar {}, beat {}", loc.bar_no() + 1, describe::beat_name(loc.offset() as u32, self.rhythm_at(tick).beat_tick_len()))
+        };
+        let mut lines: Vec<((u32, u8, u8), String)> = vec![];
+        for (tick, bar) in self.bar_repo.range(range.clone()).1 {
+            let bar_no = self.tick_to_location(*tick).bar_no() + 1;
+            if let Some(rhythm) = bar.rhythm {
+                lines.push(((*tick, 0, 0), format!("Bar {}: time signature {}/{}", bar_no, rhythm.numerator(), rhythm.denominator().value())));
+            }
+            if let Some(key) = bar.key {
+                lines.push(((*tick, 0, 1), format!("Bar {}: key {}", bar_no, describe::key_name(key))));
+            }
+        }
+        for (tick, tempo) in self.tempo_repo.range(range.clone()).1 {
+            let marking = tempo.marking.map(|m| format!(", {}", m)).unwrap_or_default();
+            lines.push(((*tick, 1, 0), format!("{}: tempo {}{}", position(*tick), tempo.value.value(), marking)));
+        }
+        for (tick, note) in self.note_repo.range(range) {
+            if note.tied { continue; }
+            let pitch = note.pitch.apply_key(self.key_at(*tick)).unwrap_or(note.pitch);
+            let mut line = format!(
+                "{}: {} {}, {}", position(*tick), describe::pitch_name(pitch), describe::duration_name(note.duration),
+                describe::dynamic_name(self.dynamics_table_of(note.channel).dynamic(note.velocity()))
+            );
+            if note.tie { line.push_str(", tied"); }
+            if note.muted { line.push_str(", muted"); }
+            lines.push(((*tick, 2, pitch.value()), line));
+        }
+        lines.sort_by_key(|(k, _)| *k);
+        lines.into_iter().map(|(_, line)| line).collect()
     }
 
-    #[inline]
-    fn location_to_tick(&self, loc: Location) -> Result<u32, LocationError> {
-        self.model().location_to_tick(loc)
+    // Notes in tick order grouped by the bar they start in. Bars without notes are skipped.
+    pub fn notes_by_bar(&self) -> impl Iterator<Item = (usize, Vec<Rc<Note>>)> + '_ {
+        let mut notes = self.note_repo.iter().map(|(_, n)| n.clone()).peekable();
+        std::iter::from_fn(move || {
+            let first = notes.next()?;
+            let bar_no = self.tick_to_location(first.start_tick()).bar_no();
+            let mut group = vec![first];
+            while let Some(n) = notes.next_if(|n| self.tick_to_location(n.start_tick()).bar_no() == bar_no) {
+                group.push(n);
+            }
+            Some((bar_no, group))
+        })
     }
 
-    #[inline]
-    fn tick_to_location(&self, tick: u32) -> Location {
-        self.model().tick_to_location(tick)
+    // Notes starting at the same tick, in tick order.
+    pub fn notes_chordwise(&self) -> impl Iterator<Item = (u32, Vec<Rc<Note>>)> + '_ {
+        self.note_repo.iter_vec().map(|(tick, notes)| (*tick, notes.clone()))
     }
 
-    #[inline]
-    fn rhythm_at(&self, tick: u32) -> Rhythm {
-        self.model().rhythm_at(tick)
+    // Beat boundaries in the range. Beats restart at each bar line, so a bar shorter than its rhythm ends
+    // with a partial beat.
+    pub fn beats(&self, range: Range<u32>) -> impl Iterator<Item = Beat> + '_ {
+        let loc = self.tick_to_location(range.start);
+        let mut bar_no = loc.bar_no();
+        let mut bar_start = range.start - loc.offset() as u32;
+        let mut rhythm = self.rhythm_at(bar_start);
+        let mut beat_no = loc.offset().div_ceil(rhythm.beat_tick_len() as usize);
+        std::iter::from_fn(move || {
+            loop {
+                let next_bar = self.bar_repo.get(bar_no).map(|(t, _)| *t as u64).unwrap_or(u64::MAX);
+                let tick = bar_start as u64 + beat_no as u64 * rhythm.beat_tick_len() as u64;
+                if next_bar <= tick {
+                    bar_no += 1;
+                    bar_start = next_bar as u32;
+                    rhythm = self.rhythm_at(bar_start);
+                    beat_no = 0;
+                    continue;
+                }
+                if range.end as u64 <= tick { return None; }
+
+                let beat = Beat { tick: tick as u32, bar_no, beat_no, accent: rhythm.accent_at(tick as u32 - bar_start) };
+                beat_no += 1;
+                return Some(beat);
+            }
+        })
     }
 
-    #[inline]
-    fn key_at(&self, tick: u32) -> Key {
-        self.model().key_at(tick)
+    // Number of notes starting in each bucket up to the end of the last bar or note, for an overview strip.
+    // Bar buckets are indexed by the bar no of Location.
+    pub fn activity_map(&self, bucket: ActivityBucket) -> Vec<u32> {
+        let end = self.bar_repo.peek_last().map(|(t, _)| *t).unwrap_or(0).max(self.note_max_end_tick().unwrap_or(0));
+        let starts: Vec<u32> = match bucket {
+            ActivityBucket::Bar => std::iter::once(0).chain(self.bar_repo.iter().map(|(t, _)| *t))
+                .enumerate().filter(|(i, t)| *i == 0 || *t < end).map(|(_, t)| t).collect(),
+            ActivityBucket::Beat => self.beats(0..end).map(|b| b.tick).collect(),
+        };
+        starts.iter().enumerate().map(|(i, start)| {
+            let end = starts.get(i + 1).copied().unwrap_or(end);
+            self.note_repo.range(*start..end).count() as u32
+        }).collect()
     }
 
-    #[inline]
-    fn note_repo(&self) -> &BagStore<u32, Rc<Note>, ModelChangeMetadata> {
-        &self.model().note_repo()
+    pub fn tick_to_location(&self, tick: u32) -> Location {
+        if self.bar_repo.len() == 0 {
+            Location::new(0, tick as usize)
+        } else {
+            match self.bar_repo.find(&tick) {
+                Ok(idx) => Location::new(idx + 1, 0),
+                Err(idx) =>
+                if idx == 0 {
+                    Location::new(0, tick as usize)
+                } else {
+                    let (_, bar) = self.bar_repo[idx - 1];
+                    Location::new(idx, (tick - bar.start_tick) as usize)
+                },
+            }
+        }
     }
 
-    #[inline]
-    fn bar_repo(&self) -> &Store<u32, Bar, ModelChangeMetadata> {
-        self.model().bar_repo()
+    // Ticks of the bar containing the tick. Bars past the last bar line follow the rhythm of the last bar.
+    pub fn bar_span(&self, tick: u32) -> Range<u32> {
+        let loc = self.tick_to_location(tick);
+        let mut bar_no = loc.bar_no();
+        let mut start = tick - loc.offset() as u32;
+        loop {
+            match self.location_to_tick(Location::new(bar_no + 1, 0)) {
+                Ok(next) if next <= tick => {
+                    bar_no += 1;
+                    start = next;
+                }
+                Ok(next) => return start..next,
+                Err(_) => return start..u32::MAX,
+            }
+        }
+    }
+    
+    pub fn rhythm_at(&self, tick: u32) -> Rhythm {
+        let idx = match self.bar_repo.index(tick) {
+            Ok(t) => t,
+            Err(t) => if t == 0 { return self.rhythm } else { t - 1 },
+        };
+        
+        for i in (0..=idx).rev() {
+            let bar = self.bar_repo[i].1;
+            if let Some(rhythm) = bar.rhythm {
+                return rhythm;
+            }
+        }
+        
+        self.rhythm
+    }
+    
+    pub fn key_at(&self, tick: u32) -> Key {
+        let idx = match self.bar_repo.index(tick) {
+            Ok(t) => t,
+            Err(t) => if t == 0 { return self.key } else { t - 1 },
+        };
+        
+        for i in (0..=idx).rev() {
+            let bar = self.bar_repo[i].1;
+            if let Some(key) = bar.key {
+                return key;
+            }
+        }
+        
+        self.key
     }
 
+    /// Returns bar no(0 offset) and bar.
     #[inline]
-    fn tempo_repo(&self) -> &Store<u32, Tempo, ModelChangeMetadata> {
-        self.model().tempo_repo()
+    fn last_bar(&self) -> Option<(usize, Bar)> {
+        self.bar_repo.peek_last().map(|(_, bar)| (self.bar_repo.len() - 1, bar.clone()))
     }
-
+    
+    fn note_max_end_tick(&self) -> Option<u32> {
+        if self.note_repo.is_empty() { return None; }
+        let mut start_tick =
+        self.note_repo.peek_last().map(|t| { t.0.clone() }).unwrap_or(0) as i64 - *Note::LONGEST_TICK_LEN as i64;
+        if start_tick < 0 { start_tick = 0; }
+        let mut max_tick_loc = 0;
+        for (tick, note) in self.note_repo.range(start_tick as u32..) {
+            let end_tick = tick + note.tick_len();
+            max_tick_loc = max_tick_loc.max(end_tick);
+        }
+        
+        Some(max_tick_loc)
+    }
+    
     #[inline]
-    fn soft_repo(&self) -> &Store<u32, CtrlChg, ModelChangeMetadata> {
-        self.model().soft_repo()
+    fn tempo_max_tick(&self) -> Option<u32> {
+        self.tempo_repo.peek_last().map(|t| t.0)
     }
-
+    
     #[inline]
-    fn dumper_repo(&self) -> &Store<u32, CtrlChg, ModelChangeMetadata> {
-        self.model().dumper_repo()
+    fn dumper_max_tick(&self) -> Option<u32> {
+        self.dumper_repo.peek_last().map(|t| t.0)
+    }
+    
+    #[inline]
+    fn soft_max_tick(&self) -> Option<u32> {
+        self.soft_repo.peek_last().map(|t| t.0)
+    }
+    
+    /// Returns replenished bars.
+    fn undo_model_change(&mut self, added: &Models, removed: &Models, metadata: ModelChangeMetadata) {
+        for n in added.notes.iter() {
+            self.note_repo.remove(&n.start_tick(), &Rc::new((*n).clone()));
+        }
+        for b in added.bars.iter() {
+            self.bar_repo.remove(&b.start_tick);
+        }
+        for t in added.tempos.iter() {
+            self.tempo_repo.remove(&t.start_tick);
+        }
+        for d in added.dumpers.iter() {
+            self.dumper_repo.remove(&d.start_tick);
+        }
+        for s in added.softs.iter() {
+            self.soft_repo.remove(&s.start_tick);
+        }
+        
+        for n in removed.notes.iter() {
+            self.note_repo.add(n.start_tick(), Rc::new((*n).clone()), metadata);
+        }
+        for b in removed.bars.iter() {
+            self.bar_repo.add(b.start_tick, *b, metadata);
+        }
+        for t in removed.tempos.iter() {
+            self.tempo_repo.add(t.start_tick, *t, metadata);
+        }
+        for d in removed.dumpers.iter() {
+            self.dumper_repo.add(d.start_tick, *d, metadata);
+        }
+        for s in removed.softs.iter() {
+            self.soft_repo.add(s.start_tick, *s, metadata);
+        }
+        self.retarget_slurs(&added.notes, &removed.notes);
     }
-}   
 
-pub type ProjectStore = SqliteUndoStore<ProjectCmd, ProjectImpl, ProjectCmdErr>;
+    fn redo_model_change(&mut self, added: &Models, removed: &Models, metadata: ModelChangeMetadata) {
+        for n in removed.notes.iter() {
+            self.note_repo.remove(&n.start_tick(), &Rc::new(n.clone()));
+        }
+        for b in removed.bars.iter() {
+            self.bar_repo.remove(&b.start_tick);
+        }
+        for t in removed.tempos.iter() {
+            self.tempo_repo.remove(&t.start_tick);
+        }
+        for d in removed.dumpers.iter() {
+            self.dumper_repo.remove(&d.start_tick);
+        }
+        for s in removed.softs.iter() {
+            self.soft_repo.remove(&s.start_tick);
+        }
+        
+        for n in added.notes.iter() {
+            self.note_repo.add(n.start_tick(), Rc::new(n.clone()), metadata);
+        }
+        for b in added.bars.iter() {
+            self.bar_repo.add(b.start_tick, *b, metadata);
+        }
+        for t in added.tempos.iter() {
+            self.tempo_repo.add(t.start_tick, *t, metadata);
+        }
+        for d in added.dumpers.iter() {
+            self.dumper_repo.add(d.start_tick, *d, metadata);
+        }
+        for s in added.softs.iter() {
+            self.soft_repo.add(s.start_tick, *s, metadata);
+        }
+        self.retarget_slurs(&removed.notes, &added.notes);
+    }
 
-#[cfg(test)]
-mod tests {
-    use std::rc::Rc;
-    use klavier_helper::store::Store;
-    use serdo::undo_store::{SqliteUndoStore, UndoStore, self};
-    use crate::{tempo::{Tempo, TempoValue}, project::{tempo_at, ProjectCmd, ProjectCmdErr, ModelChangeMetadata, ProjectStore, LocationError}, note::Note, solfa::Solfa, octave::Octave, sharp_flat::SharpFlat, pitch::Pitch, duration::{Duration, Numerator, Denominator, Dots}, velocity::Velocity, trimmer::{Trimmer, RateTrimmer}, bar::{Bar, RepeatSet}, location::Location, rhythm::Rhythm, ctrl_chg::CtrlChg, key::Key, grid::Grid, models::{Models, ModelChanges}, channel::Channel};
-    use super::{DEFAULT_TEMPO, ProjectImpl};
+    // Slurs follow their notes through a change. A replaced note is paired with the added note at the same index
+    // if as many notes are added as removed, otherwise with the added note starting at the same tick on the same channel.
+    fn retarget_slurs(&mut self, from: &[Note], to: &[Note]) {
+        if self.slurs.is_empty() || from.is_empty() || to.is_empty() { return; }
+        let replacement = |note: &Note| -> Option<Note> {
+            let idx = from.iter().position(|n| n == note)?;
+            if from.len() == to.len() {
+                Some(to[idx].clone())
+            } else {
+                to.iter().find(|n| n.channel == note.channel && n.start_tick() == note.start_tick()).cloned()
+            }
+        };
+        for slur in self.slurs.iter_mut() {
+            let retargeted = Slur::new(
+                replacement(&slur.start_note).unwrap_or_else(|| slur.start_note.clone()),
+                replacement(&slur.end_note).unwrap_or_else(|| slur.end_note.clone()),
+            );
+            if retargeted.is_valid() {
+                *slur = retargeted;
+            }
+        }
+    }
 
-    #[test]
-    fn tempo() {
-        let mut store: Store<u32, Tempo, ModelChangeMetadata> = Store::new(false);
-        assert_eq!(tempo_at(0, &store), DEFAULT_TEMPO);
-        let metadata = ModelChangeMetadata::new();
+    // Models removed by placing the clip changes. Notes edited by hand after instantiation are left as is.
+    fn clip_change_removed(&self, to_remove: Models, to_add: &Models) -> Models {
+        let mut removed = Models::empty();
+        removed.notes = to_remove.notes.into_iter()
+            .filter(|n| self.note_repo.range(n.start_tick()..n.start_tick() + 1).any(|(_, e)| **e == *n))
+            .collect();
+        removed.tempos = existing_at(&self.tempo_repo, to_add.tempos.iter().chain(to_remove.tempos.iter()).map(|t| t.start_tick));
+        removed.dumpers = existing_at(&self.dumper_repo, to_add.dumpers.iter().chain(to_remove.dumpers.iter()).map(|d| d.start_tick));
+        removed.softs = existing_at(&self.soft_repo, to_add.softs.iter().chain(to_remove.softs.iter()).map(|s| s.start_tick));
+        removed
+    }
 
-        store.add(10, Tempo { start_tick: 10, value: TempoValue::new(100) }, metadata);
-        assert_eq!(tempo_at(0, &store), DEFAULT_TEMPO);
-        assert_eq!(tempo_at(10, &store), TempoValue::new(100));
-        assert_eq!(tempo_at(11, &store), TempoValue::new(100));
+    fn replenish_bars(&mut self) -> Vec<Bar> {
+        let mut bar_tick = self.last_bar().map(|(_, b)| b.start_tick).unwrap_or(0);
+        let max_end_tick = 
+        self.note_max_end_tick().unwrap_or(0)
+        .max(self.tempo_max_tick().unwrap_or(0))
+        .max(self.dumper_max_tick().unwrap_or(0))
+        .max(self.soft_max_tick().unwrap_or(0));
         
-        store.add(20, Tempo { start_tick: 20, value: TempoValue::new(200) }, metadata);
-        assert_eq!(tempo_at(0, &store), DEFAULT_TEMPO);
-        assert_eq!(tempo_at(10, &store), TempoValue::new(100));
-        assert_eq!(tempo_at(11, &store), TempoValue::new(100));
-        assert_eq!(tempo_at(19, &store), TempoValue::new(100));
-        assert_eq!(tempo_at(20, &store), TempoValue::new(200));
-        assert_eq!(tempo_at(100, &store), TempoValue::new(200));
+        let mut replenished_bars: Vec<Bar> = vec![];
+        if max_end_tick <= bar_tick { return replenished_bars; }
+        let bar_tick_len = self.rhythm_at(bar_tick).tick_len();
+        while bar_tick < max_end_tick {
+            bar_tick += bar_tick_len;
+            let bar = Bar::new(bar_tick, None, None, RepeatSet::EMPTY);
+            self.add_bar_internal(bar, false);
+            replenished_bars.push(bar);
+        }
+        replenished_bars
+    }
+}
+
+fn existing_at<T: Copy>(store: &Store<u32, T, ModelChangeMetadata>, ticks: impl Iterator<Item = u32>) -> Vec<T> {
+    ticks.collect::<BTreeSet<u32>>().iter()
+        .filter_map(|t| store.find(t).ok().map(|idx| store[idx].1))
+        .collect()
+}
+
+// Seconds to play the range starting with the tempo. Changes should be sorted by tick.
+fn tempo_secs(range: Range<u32>, tempo: TempoValue, changes: impl Iterator<Item = (u32, TempoValue)>) -> f64 {
+    let secs = |ticks: u32, tempo: TempoValue| ticks as f64 * 60.0 / (tempo.value() as f64 * Duration::TICK_RESOLUTION as f64);
+    let (mut cur_tick, mut cur_tempo) = (range.start, tempo);
+    let mut total = 0.0;
+    for (tick, tempo) in changes.filter(|(t, _)| range.start < *t && *t < range.end) {
+        total += secs(tick - cur_tick, cur_tempo);
+        (cur_tick, cur_tempo) = (tick, tempo);
+    }
+    total + secs(range.end.saturating_sub(cur_tick), cur_tempo)
+}
+
+pub fn tempo_at(tick: u32, store: &Store<u32, Tempo, ModelChangeMetadata>) -> TempoValue {
+    if store.is_empty() {
+        DEFAULT_TEMPO
+    } else {
+        match store.find(&tick) {
+            Ok(idx) => {
+                store[idx].1.value
+            },
+            Err(idx) => {
+                if idx == 0 {
+                    DEFAULT_TEMPO
+                } else {
+                    store[idx - 1].1.value
+                }
+            },
+        }
+    }
+}
+
+pub fn ctrl_chg_at(tick: u32, store: &Store<u32, CtrlChg, ModelChangeMetadata>) -> Velocity {
+    if store.is_empty() {
+        DEFAULT_CTRL_CHG
+    } else {
+        match store.find(&tick) {
+            Ok(idx) => {
+                store[idx].1.velocity
+            },
+            Err(idx) => {
+                if idx == 0 {
+                    DEFAULT_CTRL_CHG
+                } else {
+                    store[idx - 1].1.velocity
+                }
+            },
+        }
+    }
+}
+
+pub fn ctrl_chg_at_chan(tick: u32, channel: Channel, store: &Store<u32, CtrlChg, ModelChangeMetadata>) -> Velocity {
+    if !store.iter().any(|(_, c)| c.channel == channel) {
+        return ctrl_chg_at(tick, store);
+    }
+    store.iter()
+        .take_while(|(t, _)| *t <= tick)
+        .filter(|(_, c)| c.channel == channel)
+        .last()
+        .map(|(_, c)| c.velocity)
+        .unwrap_or(DEFAULT_CTRL_CHG)
+}
+
+// Channels having at least one control change.
+pub fn ctrl_chg_channels(store: &Store<u32, CtrlChg, ModelChangeMetadata>) -> BTreeSet<Channel> {
+    store.iter().map(|(_, c)| c.channel).collect()
+}
+
+impl Default for ProjectImpl {
+    fn default() -> Self {
+        ProjectImpl {
+            rhythm: Rhythm::default(),
+            key: Key::NONE,
+            grid: Grid::default(),
+            note_repo: BagStore::new(true),
+            bar_repo: Store::new(true),
+            tempo_repo: Store::new(true),
+            dumper_repo: Store::new(true),
+            soft_repo: Store::new(true),
+            playback: PlaybackSettings::default(),
+            note_defaults: NoteDefaults::default(),
+            clips: ClipLibrary::default(),
+            display: DisplaySettings::default(),
+            glissandos: vec![],
+            slurs: vec![],
+            default_channel: Channel::default(),
+            dynamics: DynamicsTable::default(),
+            track_offsets: TrackOffsets::default(),
+            pitch_shifts: PitchShifts::default(),
+            skip_regions: vec![],
+            add_policy: AddPolicy::default(),
+            comments: vec![],
+            rehearsal_marks: vec![],
+            instruments: InstrumentTable::default(),
+            repeat_diagnostics: RefCell::new(None),
+            content_hash: RefCell::new(ContentHash::default()),
+            drag: None,
+            preview_models: Models::empty(),
+            setting_events: vec![],
+            sent_cmds: Cell::new(0),
+            settings_path: None,
+        }
+    }
+}
+
+// Serialization is in store_format, which wraps this derived form with the store version.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(remote = "Self")]
+pub enum ProjectCmd {
+    SetRhythm(Rhythm, Rhythm),
+    SetKey(Key, Key),
+    SetGrid(Grid, Grid),
+    ModelChanged { added: Models, removed: Models, metadata: ModelChangeMetadata },
+    ClipChanged { old_clips: ClipLibrary, new_clips: ClipLibrary, added: Models, removed: Models, metadata: ModelChangeMetadata },
+    GlissandoChanged(Vec<Glissando>, Vec<Glissando>),
+    SlurChanged(Vec<Slur>, Vec<Slur>),
+    CommentChanged(Vec<Comment>, Vec<Comment>),
+    RehearsalMarkChanged(Vec<RehearsalMark>, Vec<RehearsalMark>),
+    InstrumentsChanged(InstrumentTable, InstrumentTable),
+    SetDefaultChannel(Channel, Channel),
+    SetupScore { setup: ScoreSetup, old_rhythm: Rhythm, old_key: Key, old_grid: Grid, added: Models, removed: Models, metadata: ModelChangeMetadata },
+    // The tempo scale is out of undo history and left as is.
+    SetPlayback(PlaybackSettings, PlaybackSettings),
+    SetDisplay(DisplaySettings, DisplaySettings),
+    SetDynamics(DynamicsTable, DynamicsTable),
+    SetAddPolicy(AddPolicy, AddPolicy),
+    SetPitchShifts(PitchShifts, PitchShifts),
+    SkipRegionChanged(Vec<SkipRegion>, Vec<SkipRegion>),
+}
+
+impl ProjectCmd {
+    fn setting_event(&self) -> Option<SettingEvent> {
+        match self {
+            ProjectCmd::SetRhythm(..) => Some(SettingEvent::Rhythm),
+            ProjectCmd::SetKey(..) => Some(SettingEvent::Key),
+            ProjectCmd::SetGrid(..) => Some(SettingEvent::Grid),
+            ProjectCmd::SetDefaultChannel(..) => Some(SettingEvent::DefaultChannel),
+            ProjectCmd::SetPlayback(..) => Some(SettingEvent::Playback),
+            ProjectCmd::SetDisplay(..) => Some(SettingEvent::Display),
+            ProjectCmd::SetDynamics(..) => Some(SettingEvent::Dynamics),
+            ProjectCmd::SetAddPolicy(..) => Some(SettingEvent::AddPolicy),
+            ProjectCmd::SetPitchShifts(..) => Some(SettingEvent::PitchShifts),
+            _ => None,
+        }
+    }
+}
+
+impl Cmd for ProjectCmd {
+    type Model = ProjectImpl;
+    
+    fn undo(&self, proj: &mut Self::Model) {
+        // The store answers undo and redo only after writing every earlier command.
+        proj.sent_cmds.set(0);
+        proj.setting_events.extend(self.setting_event());
+        match self {
+            ProjectCmd::SetRhythm(old_rhythm, _) => {
+                proj.rhythm = *old_rhythm;
+            },
+            ProjectCmd::SetKey(old_key, _) => {
+                proj.key = *old_key;
+            },
+            ProjectCmd::SetGrid(old_grid, _) => {
+                proj.grid = *old_grid;
+            },
+            ProjectCmd::ModelChanged { added, removed, metadata } => {
+                proj.undo_model_change(added, removed, *metadata);
+            },
+            ProjectCmd::ClipChanged { old_clips, added, removed, metadata, .. } => {
+                proj.undo_model_change(added, removed, *metadata);
+                proj.clips = old_clips.clone();
+            },
+            ProjectCmd::GlissandoChanged(old_glissandos, _) => {
+                proj.glissandos = old_glissandos.clone();
+            },
+            ProjectCmd::SlurChanged(old_slurs, _) => {
+                proj.slurs = old_slurs.clone();
+            },
+            ProjectCmd::CommentChanged(old_comments, _) => {
+                proj.comments = old_comments.clone();
+            },
+            ProjectCmd::RehearsalMarkChanged(old_marks, _) => {
+                proj.rehearsal_marks = old_marks.clone();
+            },
+            ProjectCmd::InstrumentsChanged(old_instruments, _) => {
+                proj.instruments = old_instruments.clone();
+            },
+            ProjectCmd::SetDefaultChannel(old_channel, _) => {
+                proj.apply_default_channel(*old_channel);
+            },
+            ProjectCmd::SetupScore { old_rhythm, old_key, old_grid, added, removed, metadata, .. } => {
+                proj.undo_model_change(added, removed, *metadata);
+                proj.rhythm = *old_rhythm;
+                proj.key = *old_key;
+                proj.grid = *old_grid;
+            },
+            ProjectCmd::SetPlayback(old_playback, _) => {
+                proj.playback = PlaybackSettings { tempo_scale: proj.playback.tempo_scale, ..*old_playback };
+            },
+            ProjectCmd::SetDisplay(old_display, _) => {
+                proj.display = old_display.clone();
+            },
+            ProjectCmd::SetDynamics(old_table, _) => {
+                proj.dynamics = *old_table;
+            },
+            ProjectCmd::SetAddPolicy(old_policy, _) => {
+                proj.add_policy = *old_policy;
+            },
+            ProjectCmd::SetPitchShifts(old_shifts, _) => {
+                proj.pitch_shifts = old_shifts.clone();
+            },
+            ProjectCmd::SkipRegionChanged(old_regions, _) => {
+                proj.skip_regions = old_regions.clone();
+            },
+        }
+    }
+    
+    fn redo(&self, proj: &mut Self::Model) {
+        // The store answers undo and redo only after writing every earlier command.
+        proj.sent_cmds.set(0);
+        proj.setting_events.extend(self.setting_event());
+        match self {
+            ProjectCmd::SetRhythm(_, new_rhythm) => {
+                proj.rhythm = *new_rhythm;
+            },
+            ProjectCmd::SetKey(_, new_key) => {
+                proj.key = *new_key;
+            },
+            ProjectCmd::SetGrid(_, new_grid) => {
+                proj.grid = *new_grid;
+            }
+            ProjectCmd::ModelChanged { added, removed , metadata } => {
+                proj.redo_model_change(added, removed, *metadata);
+            },
+            ProjectCmd::ClipChanged { new_clips, added, removed, metadata, .. } => {
+                proj.redo_model_change(added, removed, *metadata);
+                proj.clips = new_clips.clone();
+            },
+            ProjectCmd::GlissandoChanged(_, new_glissandos) => {
+                proj.glissandos = new_glissandos.clone();
+            },
+            ProjectCmd::SlurChanged(_, new_slurs) => {
+                proj.slurs = new_slurs.clone();
+            },
+            ProjectCmd::CommentChanged(_, new_comments) => {
+                proj.comments = new_comments.clone();
+            },
+            ProjectCmd::RehearsalMarkChanged(_, new_marks) => {
+                proj.rehearsal_marks = new_marks.clone();
+            },
+            ProjectCmd::InstrumentsChanged(_, new_instruments) => {
+                proj.instruments = new_instruments.clone();
+            },
+            ProjectCmd::SetDefaultChannel(_, new_channel) => {
+                proj.apply_default_channel(*new_channel);
+            },
+            ProjectCmd::SetupScore { setup, added, removed, metadata, .. } => {
+                proj.rhythm = setup.rhythm;
+                proj.key = setup.key;
+                proj.grid = setup.grid;
+                proj.redo_model_change(added, removed, *metadata);
+            },
+            ProjectCmd::SetPlayback(_, new_playback) => {
+                proj.playback = PlaybackSettings { tempo_scale: proj.playback.tempo_scale, ..*new_playback };
+            },
+            ProjectCmd::SetDisplay(_, new_display) => {
+                proj.display = new_display.clone();
+            },
+            ProjectCmd::SetDynamics(_, new_table) => {
+                proj.dynamics = *new_table;
+            },
+            ProjectCmd::SetAddPolicy(_, new_policy) => {
+                proj.add_policy = *new_policy;
+            },
+            ProjectCmd::SetPitchShifts(_, new_shifts) => {
+                proj.pitch_shifts = new_shifts.clone();
+            },
+            ProjectCmd::SkipRegionChanged(_, new_regions) => {
+                proj.skip_regions = new_regions.clone();
+            },
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl SerializableCmd for ProjectCmd {
+}
+
+#[derive(Debug)]
+pub enum ProjectCmdErr {
+    NoOp,
+}
+
+impl error_stack::Context for ProjectCmdErr {}
+
+// serdo reports errors through error_stack only when its persistence feature is on.
+#[cfg(feature = "sqlite")]
+type ProjectCmdResult<T> = Result<T, error_stack::Report<ProjectCmdErr>>;
+
+#[cfg(not(feature = "sqlite"))]
+type ProjectCmdResult<T> = Result<T, ProjectCmdErr>;
+
+impl ProjectCmdErr {
+    #[cfg(feature = "sqlite")]
+    fn raise<T>(self) -> ProjectCmdResult<T> {
+        Err(error_stack::report!(self))
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    fn raise<T>(self) -> ProjectCmdResult<T> {
+        Err(self)
+    }
+}
+
+impl std::fmt::Display for ProjectCmdErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProjectCmdErr::NoOp => write!(f, "No Operation"),
+        }
+    }
+}
+
+pub trait Project {
+    fn set_rhythm(&mut self, rhythm: Rhythm);
+    fn rhythm(&self) -> Rhythm;
+    fn set_key(&mut self, key: Key);
+    fn key(&self) -> Key;
+    fn set_grid(&mut self, key: Grid);
+    fn setup_score(&mut self, setup: ScoreSetup);
+    fn grid(&self) -> Grid;
+    fn add_note(&mut self, note: Note, select: bool);
+    // Adds the note tied from prev as one command, marking prev as tying if it is not yet. Nothing is done if
+    // prev is not in the project.
+    fn add_tied_note(&mut self, prev: &Note, note: Note, select: bool);
+    fn add_bar(&mut self, bar: Bar, select: bool);
+    fn remove_bar(&mut self, bar: Bar, policy: BarContentPolicy);
+    // Bars are indexed as bar_no(). Bars out of range are skipped.
+    fn copy_bar_attributes(&mut self, from_bar: usize, to_bars: &[usize], what: EnumSet<BarAttribute>);
+    fn add_tempo(&mut self, bar: Tempo, select: bool);
+    fn add_dumper(&mut self, dumper: CtrlChg, select: bool);
+    fn add_soft(&mut self, soft: CtrlChg, select: bool);
+    fn ramp_ctrl(&mut self, kind: CtrlChgKind, range: RangeInclusive<u32>, from: Velocity, to: Velocity, step_ticks: u32, replace: bool);
+    fn auto_pedal(&mut self, range: RangeInclusive<u32>, policy: AutoPedalPolicy);
+    fn tuplize(&mut self, notes: Vec<Rc<Note>>);
+    fn split_note(&mut self, note: Rc<Note>, tick: u32) -> Result<(), SplitError>;
+    fn bulk_remove(&mut self, to_remove: Models, metadata: ModelChangeMetadata);
+    fn remove_notes_tie_aware(&mut self, notes: &[Rc<Note>], policy: TieRemovalPolicy);
+    fn fix_unison_conflicts(&mut self, range: Range<u32>, fix: UnisonFix);
+    fn apply_quantize(&mut self, preview: &[QuantizeMove]);
+    fn scale_tempo(&mut self, range: Range<u32>, factor: PercentU16);
+    fn fit_duration(&mut self, range: Range<u32>, target_secs: f64) -> Result<f64, FitDurationError>;
+    fn bulk_add(&mut self, to_add: Models, metadata: ModelChangeMetadata);
+    fn change(&mut self, from_to: ModelChanges, metadata: ModelChangeMetadata);
+    fn set_muted(&mut self, notes: &[Rc<Note>], muted: bool);
+    fn set_note_tag(&mut self, notes: &[Rc<Note>], tag: Option<NoteTag>);
+    #[cfg(feature = "practice")]
+    fn set_play_probability(&mut self, notes: &[Rc<Note>], play_probability: Option<PercentU16>);
+    fn conform_to_scale(&mut self, range: Range<u32>, scale: Scale) -> Vec<ScaleSnap>;
+    // One command for every note starting in the range. See ProjectImpl::velocity_normalization().
+    fn normalize_velocities(&mut self, range: Range<u32>, target_min: Velocity, target_max: Velocity, preserve_relative: bool);
+    fn normalize_trimmers(&mut self, notes: &[Rc<Note>], mode: TrimmerNormalizeMode);
+    fn begin_drag(&mut self, selection: Models);
+    fn update_drag(&mut self, delta_tick: i32, delta_pitch: i32) -> bool;
+    fn drag_preview(&self) -> Option<&Models>;
+    fn commit_drag(&mut self);
+    fn cancel_drag(&mut self);
+    fn preview_models(&self) -> &Models;
+    fn set_preview_models(&mut self, models: Models);
+    fn clear_preview_models(&mut self);
+    fn promote_preview(&mut self, select: bool);
+    fn playback_settings(&self) -> &PlaybackSettings;
+    fn set_tempo_scale(&mut self, scale: PercentU16);
+    fn set_event_order(&mut self, policy: EventOrderPolicy);
+    fn set_note_length(&mut self, mode: NoteLengthMode, legato_overlap: u32);
+    fn set_beat_emphasis(&mut self, emphasis: Option<BeatEmphasis>);
+    fn set_slur_legato(&mut self, slur_legato: bool);
+    fn note_defaults(&self) -> &NoteDefaults;
+    fn display_settings(&self) -> &DisplaySettings;
+    fn set_transposition(&mut self, channel: Channel, transposition: Transposition);
+    fn set_concert_pitch(&mut self, concert_pitch: bool);
+    fn display_pitch(&self, note: &Note) -> Pitch;
+    fn sounding_pitch(&self, note: &Note) -> Pitch;
+    fn set_note_defaults(&mut self, defaults: NoteDefaults);
+    fn default_channel(&self) -> Channel;
+    fn dynamics_table(&self) -> &DynamicsTable;
+    fn set_dynamics_table(&mut self, table: DynamicsTable);
+    fn track_offsets(&self) -> &TrackOffsets;
+    fn set_track_offset(&mut self, channel: Channel, curve: Option<OffsetCurve>);
+    fn pitch_shifts(&self) -> &PitchShifts;
+    // Playback only. Zero removes the shift of the channel.
+    fn set_pitch_shift(&mut self, channel: Channel, semitones: i8);
+    fn skip_regions(&self) -> &[SkipRegion];
+    // Playback only. Nothing is deleted from the score.
+    fn add_skip_region(&mut self, region: SkipRegion);
+    fn remove_skip_region(&mut self, region: &SkipRegion);
+    fn add_policy(&self) -> AddPolicy;
+    fn set_add_policy(&mut self, policy: AddPolicy);
+    fn set_default_channel(&mut self, channel: Channel);
+    fn instruments(&self) -> &InstrumentTable;
+    fn set_instrument(&mut self, channel: Channel, instrument: Option<Instrument>);
+    fn notes_with_nondefault_channel(&self) -> Vec<Rc<Note>>;
+    fn clips(&self) -> &ClipLibrary;
+    fn save_clip(&mut self, name: &str, models: &Models);
+    fn remove_clip(&mut self, name: &str);
+    fn instantiate_clip(&mut self, name: &str, tick: u32, by_ref: bool);
+    fn repeat_fill(&mut self, pattern: Models, range: Range<u32>);
+    // Warnings are the conflicts of merged bars. Always empty unless the bars are merged.
+    fn paste(&mut self, models: Models, tick: u32, options: PasteOptions) -> Vec<BarMergeWarning>;
+    fn paste_fit(&mut self, models: Models, location: Location, options: PasteFitOptions) -> Result<(), LocationError>;
+    fn glissandos(&self) -> &[Glissando];
+    fn add_glissando(&mut self, glissando: Glissando);
+    fn remove_glissando(&mut self, glissando: &Glissando);
+    fn slurs(&self) -> &[Slur];
+    fn add_slur(&mut self, slur: Slur);
+    fn remove_slur(&mut self, slur: &Slur);
+    fn comments(&self) -> &[Comment];
+    fn comments_in(&self, range: Range<u32>) -> impl Iterator<Item = &Comment> + '_;
+    fn add_comment(&mut self, comment: Comment);
+    fn remove_comment(&mut self, comment: &Comment);
+    fn set_comment_resolved(&mut self, comment: &Comment, resolved: bool);
+    fn rehearsal_marks(&self) -> &[RehearsalMark];
+    fn add_rehearsal_mark(&mut self, mark: RehearsalMark);
+    fn remove_rehearsal_mark(&mut self, bar_no: usize);
+    fn bar_no(&self, bar: &Bar) -> Option<usize>;
+    fn repeat_labels(&self, bar: &Bar) -> Vec<RepeatLabel>;
+    fn models_in(&self, tick_range: Range<u32>, pitch_range: RangeInclusive<u8>) -> ModelsView<'_>;
+    fn repeat_diagnostics(&self) -> Vec<(u32, RepeatDiagnostic)>;
+    fn preview_rhythm_change(&self, at_bar: usize, new_rhythm: Rhythm) -> Option<ImpactReport>;
+    fn content_hash(&self) -> u64;
+    fn describe_range(&self, range: Range<u32>) -> Vec<String>;
+    fn tempo_at(&self, tick: u32) -> TempoValue;
+    fn dumper_at(&self, tick: u32) -> Velocity;
+    fn soft_at(&self, tick: u32) -> Velocity;
+    fn dumper_at_chan(&self, tick: u32, channel: Channel) -> Velocity;
+    fn soft_at_chan(&self, tick: u32, channel: Channel) -> Velocity;
+    fn events_at(&self, tick: u32) -> Models;
+    fn clear_model_events(&mut self);
+    // Maintenance after heavy editing. See ProjectImpl::shrink_to_fit().
+    fn compact(&mut self);
+    fn take_dirty_regions(&mut self) -> DirtyRegions;
+    fn bar_events(&self) -> &Vec<StoreEvent<u32, Bar, ModelChangeMetadata>>;
+    fn tempo_events(&self) -> &Vec<StoreEvent<u32, Tempo, ModelChangeMetadata>>;
+    fn dumper_events(&self) -> &Vec<StoreEvent<u32, CtrlChg, ModelChangeMetadata>>;
+    fn soft_events(&self) -> &Vec<StoreEvent<u32, CtrlChg, ModelChangeMetadata>>;
+    fn note_events(&self) -> &Vec<BagStoreEvent<u32, Rc<Note>, ModelChangeMetadata>>;
+    fn setting_events(&self) -> &Vec<SettingEvent>;
+    fn location_to_tick(&self, loc: Location) -> Result<u32, LocationError>;
+    fn tick_to_location(&self, tick: u32) -> Location;
+    fn notes_by_bar(&self) -> impl Iterator<Item = (usize, Vec<Rc<Note>>)> + '_;
+    fn notes_chordwise(&self) -> impl Iterator<Item = (u32, Vec<Rc<Note>>)> + '_;
+    fn beats(&self, range: Range<u32>) -> impl Iterator<Item = Beat> + '_;
+    fn rhythm_at(&self, tick: u32) -> Rhythm;
+    fn key_at(&self, tick: u32) -> Key;
+    fn note_repo(&self) -> &BagStore<u32, Rc<Note>, ModelChangeMetadata>;
+    fn bar_repo(&self) -> &Store<u32, Bar, ModelChangeMetadata>;
+    fn tempo_repo(&self) -> &Store<u32, Tempo, ModelChangeMetadata>;
+    fn soft_repo(&self) -> &Store<u32, CtrlChg, ModelChangeMetadata>;
+    fn dumper_repo(&self) -> &Store<u32, CtrlChg, ModelChangeMetadata>;
+}
+
+type ProjectMutation = Box<dyn FnOnce(&mut ProjectImpl) -> ProjectCmdResult<ProjectCmd>>;
+
+// Every mutation goes through here so that slurs follow the changed notes the same way as when the command is redone.
+fn mutate_project<S>(store: &mut S, f: ProjectMutation) -> ProjectCmdResult<()>
+    where S: UndoStore<ModelType = ProjectImpl, CmdType = ProjectCmd, ErrType = ProjectCmdErr>
+{
+    store.mutate(Box::new(move |proj| {
+        let cmd = f(proj)?;
+        match &cmd {
+            ProjectCmd::ModelChanged { added, removed, .. } | ProjectCmd::ClipChanged { added, removed, .. }
+                | ProjectCmd::SetupScore { added, removed, .. } => proj.retarget_slurs(&removed.notes, &added.notes),
+            _ => {},
+        }
+        proj.setting_events.extend(cmd.setting_event());
+        proj.sent_cmds.set(proj.sent_cmds.get() + 1);
+        Ok(cmd)
+    }))
+}
+
+impl<S> Project for S where S: UndoStore<ModelType = ProjectImpl, CmdType = ProjectCmd, ErrType = ProjectCmdErr> {
+    fn set_rhythm(&mut self, rhythm: Rhythm) {
+        self.add_cmd(ProjectCmd::SetRhythm(self.model().rhythm, rhythm));
+    }
+    
+    fn rhythm(&self) -> Rhythm {
+        self.model().rhythm
+    }
+
+    fn set_key(&mut self, key: Key) {
+        self.add_cmd(ProjectCmd::SetKey(self.model().key, key));
+    }
+
+    fn key(&self) -> Key {
+        self.model().key
+    }
+    
+    fn set_grid(&mut self, grid: Grid) {
+        self.add_cmd(ProjectCmd::SetGrid(self.model().grid, grid));
+    }
+
+    fn grid(&self) -> Grid {
+        self.model().grid
+    }
+
+    // For new scores. Bars and tempos are replaced with the bars of the setup and its tempo in one undo step.
+    fn setup_score(&mut self, setup: ScoreSetup) {
+        let metadata = ModelChangeMetadata::new();
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let bar_len = setup.rhythm.tick_len();
+            let added = Models::empty()
+                .with_bars((1..=setup.bar_count).map(|i| Bar::new(i * bar_len, None, None, RepeatSet::EMPTY)).collect())
+                .with_tempos(vec![Tempo { start_tick: 0, value: setup.tempo, marking: None }]);
+            let removed = Models::empty()
+                .with_bars(proj.bar_repo.iter().map(|(_, b)| *b).collect())
+                .with_tempos(proj.tempo_repo.iter().map(|(_, t)| *t).collect());
+            let (old_rhythm, old_key, old_grid) = (proj.rhythm, proj.key, proj.grid);
+            proj.rhythm = setup.rhythm;
+            proj.key = setup.key;
+            proj.grid = setup.grid;
+            proj.redo_model_change(&added, &removed, metadata);
+            Ok(ProjectCmd::SetupScore { setup, old_rhythm, old_key, old_grid, added, removed, metadata })
+        }));
+    }
+
+    #[inline]
+    fn default_channel(&self) -> Channel {
+        self.model().default_channel()
+    }
+
+    fn set_default_channel(&mut self, channel: Channel) {
+        self.add_cmd(ProjectCmd::SetDefaultChannel(self.model().default_channel, channel));
+    }
+
+    #[inline]
+    fn instruments(&self) -> &InstrumentTable {
+        self.model().instruments()
+    }
+
+    // None removes the instrument of the channel.
+    fn set_instrument(&mut self, channel: Channel, instrument: Option<Instrument>) {
+        let _ = mutate_project(self, Box::new(move |proj| {
+            if proj.instruments.get(channel) == instrument.as_ref() { return ProjectCmdErr::NoOp.raise(); }
+            let old_instruments = proj.instruments.clone();
+            proj.instruments.set(channel, instrument);
+            Ok(ProjectCmd::InstrumentsChanged(old_instruments, proj.instruments.clone()))
+        }));
+    }
+
+    #[inline]
+    fn dynamics_table(&self) -> &DynamicsTable {
+        self.model().dynamics_table()
+    }
+
+    fn set_dynamics_table(&mut self, table: DynamicsTable) {
+        let _ = mutate_project(self, Box::new(move |proj| {
+            if proj.dynamics == table { return ProjectCmdErr::NoOp.raise(); }
+            let old_table = proj.dynamics;
+            proj.dynamics = table;
+            Ok(ProjectCmd::SetDynamics(old_table, table))
+        }));
+    }
+
+    #[inline]
+    fn track_offsets(&self) -> &TrackOffsets {
+        self.model().track_offsets()
+    }
+
+    // None removes the curve of the channel.
+    fn set_track_offset(&mut self, channel: Channel, curve: Option<OffsetCurve>) {
+        self.irreversible_mutate(Box::new(move |proj| {
+            if proj.track_offsets.get(channel) == curve.as_ref() { return; }
+            proj.track_offsets.set(channel, curve);
+            proj.setting_events.push(SettingEvent::TrackOffsets);
+            proj.save_settings();
+        }));
+    }
+
+    #[inline]
+    fn pitch_shifts(&self) -> &PitchShifts {
+        self.model().pitch_shifts()
+    }
+
+    fn set_pitch_shift(&mut self, channel: Channel, semitones: i8) {
+        let _ = mutate_project(self, Box::new(move |proj| {
+            if proj.pitch_shifts.get(channel) == semitones { return ProjectCmdErr::NoOp.raise(); }
+            let old_shifts = proj.pitch_shifts.clone();
+            proj.pitch_shifts.set(channel, semitones);
+            Ok(ProjectCmd::SetPitchShifts(old_shifts, proj.pitch_shifts.clone()))
+        }));
+    }
+
+    #[inline]
+    fn skip_regions(&self) -> &[SkipRegion] {
+        self.model().skip_regions()
+    }
+
+    fn add_skip_region(&mut self, region: SkipRegion) {
+        let _ = mutate_project(self, Box::new(move |proj| {
+            if region.tick_range.is_empty() || proj.skip_regions.contains(&region) { return ProjectCmdErr::NoOp.raise(); }
+            let old_regions = proj.skip_regions.clone();
+            let idx = proj.skip_regions.partition_point(|r| r.tick_range.start <= region.tick_range.start);
+            proj.skip_regions.insert(idx, region);
+            Ok(ProjectCmd::SkipRegionChanged(old_regions, proj.skip_regions.clone()))
+        }));
+    }
+
+    fn remove_skip_region(&mut self, region: &SkipRegion) {
+        let region = region.clone();
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let old_regions = proj.skip_regions.clone();
+            proj.skip_regions.retain(|r| *r != region);
+            if proj.skip_regions.len() == old_regions.len() { return ProjectCmdErr::NoOp.raise(); }
+            Ok(ProjectCmd::SkipRegionChanged(old_regions, proj.skip_regions.clone()))
+        }));
+    }
+
+    #[inline]
+    fn add_policy(&self) -> AddPolicy {
+        self.model().add_policy()
+    }
+
+    fn set_add_policy(&mut self, policy: AddPolicy) {
+        let _ = mutate_project(self, Box::new(move |proj| {
+            if proj.add_policy == policy { return ProjectCmdErr::NoOp.raise(); }
+            let old_policy = proj.add_policy;
+            proj.add_policy = policy;
+            Ok(ProjectCmd::SetAddPolicy(old_policy, policy))
+        }));
+    }
+
+    #[inline]
+    fn notes_with_nondefault_channel(&self) -> Vec<Rc<Note>> {
+        self.model().notes_with_nondefault_channel()
+    }
+    
+    fn add_note(&mut self, note: Note, select: bool) {
+        let mut metadata = ModelChangeMetadata::new();
+        if select { metadata.need_select = Some(true); }
+
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let (added, removed) = proj.resolve_duplicates(vec![note.clone()], proj.add_policy.on_duplicate);
+            let Some(note) = added.into_iter().next().map(Rc::new) else {
+                return ProjectCmdErr::NoOp.raise();
+            };
+            for n in removed.iter() {
+                proj.note_repo.remove(&n.start_tick(), n);
+            }
+            proj.note_repo.add(note.start_tick(), note.clone(), metadata);
+            let replenishid_bars = proj.replenish_bars();
+            Ok(
+                ProjectCmd::ModelChanged {
+                    added: Models::empty().with_notes(&[note.clone()]).with_bars(replenishid_bars),
+                    removed: Models::empty().with_notes(&removed),
+                    metadata,
+                }
+            )
+        }));
+    }
+
+    fn add_tied_note(&mut self, prev: &Note, note: Note, select: bool) {
+        let prev = prev.clone();
+        let mut metadata = ModelChangeMetadata::new();
+        if select { metadata.need_select = Some(true); }
+
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let Some(prev) = proj.note_repo.range(prev.start_tick()..prev.start_tick() + 1).find(|(_, n)| ***n == prev).map(|(_, n)| n.clone()) else {
+                return ProjectCmdErr::NoOp.raise();
+            };
+            let (added, mut removed) = proj.resolve_duplicates(vec![Note { tied: true, ..note.clone() }], proj.add_policy.on_duplicate);
+            let Some(note) = added.into_iter().next().map(Rc::new) else {
+                return ProjectCmdErr::NoOp.raise();
+            };
+            for n in removed.iter() {
+                proj.note_repo.remove(&n.start_tick(), n);
+            }
+            let mut added = vec![note.clone()];
+            if !prev.tie {
+                let tying = Rc::new(Note { tie: true, ..(*prev).clone() });
+                proj.note_repo.remove(&prev.start_tick(), &prev);
+                proj.note_repo.add(tying.start_tick(), tying.clone(), metadata);
+                added.push(tying);
+                removed.push(prev);
+            }
+            proj.note_repo.add(note.start_tick(), note.clone(), metadata);
+            let replenished_bars = proj.replenish_bars();
+            Ok(ProjectCmd::ModelChanged {
+                added: Models::empty().with_notes(&added).with_bars(replenished_bars),
+                removed: Models::empty().with_notes(&removed),
+                metadata,
+            })
+        }));
+    }
+    
+    fn copy_bar_attributes(&mut self, from_bar: usize, to_bars: &[usize], what: EnumSet<BarAttribute>) {
+        let bars = self.bar_repo();
+        if bars.len() <= from_bar { return; }
+        let from = bars[from_bar].1;
+        let targets: BTreeSet<usize> = to_bars.iter().copied().filter(|i| *i < bars.len()).collect();
+        let changes: Vec<(Bar, Bar)> = targets.into_iter()
+            .map(|i| (bars[i].1, bars[i].1.with_attributes_of(&from, what)))
+            .filter(|(to, copied)| to != copied)
+            .collect();
+        if !changes.is_empty() {
+            self.change(ModelChanges::empty().with_bars(changes), ModelChangeMetadata::new());
+        }
+    }
+
+    fn add_bar(&mut self, bar: Bar, select: bool) {
+        let mut metadata = ModelChangeMetadata::new();
+        if select { metadata.need_select = Some(true); }
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let origin = proj.bar_repo.add(bar.start_tick, bar, metadata).map(|o| vec![o]).unwrap_or(vec![]);
+            
+            Ok(
+                ProjectCmd::ModelChanged {
+                    added: Models::empty().with_bars(vec![bar]),
+                    removed: Models::empty().with_bars(origin),
+                    metadata,
+                }
+            )
+        }));
+    }
+    
+    // Returns the achieved duration minus the target in seconds. Tempos are integers, so it is rarely zero.
+    fn fit_duration(&mut self, range: Range<u32>, target_secs: f64) -> Result<f64, FitDurationError> {
+        let (added, removed, error) = self.model().duration_fit(range, target_secs)?;
+        let metadata = ModelChangeMetadata::new();
+        let _ = mutate_project(self, Box::new(move |proj| {
+            if added == removed { return ProjectCmdErr::NoOp.raise(); }
+            proj.redo_model_change(&added, &removed, metadata);
+            Ok(ProjectCmd::ModelChanged { added, removed, metadata })
+        }));
+        Ok(error)
+    }
+
+    fn remove_bar(&mut self, bar: Bar, policy: BarContentPolicy) {
+        let metadata = ModelChangeMetadata::new();
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let Some((added, removed)) = proj.bar_removal(&bar, policy) else {
+                return ProjectCmdErr::NoOp.raise();
+            };
+            proj.redo_model_change(&added, &removed, metadata);
+            Ok(ProjectCmd::ModelChanged { added, removed, metadata })
+        }));
+    }
+
+    fn add_tempo(&mut self, tempo: Tempo, select: bool) {
+        let mut metadata = ModelChangeMetadata::new();
+        if select { metadata.need_select = Some(true); }
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let origin = proj.tempo_repo.add(tempo.start_tick, tempo, metadata).map(|o| vec![o]).unwrap_or(vec![]);
+            let replenishid_bars = proj.replenish_bars();
+            Ok(
+                ProjectCmd::ModelChanged {
+                    added: Models::empty().with_bars(replenishid_bars).with_tempos(vec![tempo]),
+                    removed: Models::empty().with_tempos(origin),
+                    metadata,
+                }
+                
+            )
+        }));
+    }
+    
+    fn add_dumper(&mut self, dumper: CtrlChg, select: bool) {
+        let mut metadata = ModelChangeMetadata::new();
+        if select { metadata.need_select = Some(true); }
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let origin = proj.dumper_repo.add(dumper.start_tick, dumper, metadata).map(|o| vec![o]).unwrap_or(vec![]);
+            let replenishid_bars = proj.replenish_bars();
+            Ok(
+                ProjectCmd::ModelChanged {
+                    added: Models::empty().with_bars(replenishid_bars).with_dumpers(vec![dumper]),
+                    removed: Models::empty().with_dumpers(origin),
+                    metadata,
+                }
+            )
+        }));
+    }
+    
+    fn add_soft(&mut self, soft: CtrlChg, select: bool) {
+        let mut metadata = ModelChangeMetadata::new();
+        if select { metadata.need_select = Some(true); }
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let origin = proj.soft_repo.add(soft.start_tick, soft, metadata).map(|o| vec![o]).unwrap_or(vec![]);
+            let replenishid_bars = proj.replenish_bars();
+            Ok(
+                ProjectCmd::ModelChanged {
+                    added: Models::empty().with_bars(replenishid_bars).with_softs(vec![soft]),
+                    removed: Models::empty().with_softs(origin),
+                    metadata
+                }
+            )
+        }));
+    }
+    
+    fn ramp_ctrl(&mut self, kind: CtrlChgKind, range: RangeInclusive<u32>, from: Velocity, to: Velocity, step_ticks: u32, replace: bool) {
+        let ramp = CtrlChg::ramp(range.clone(), from, to, step_ticks, Channel::default());
+        if ramp.is_empty() { return; }
+        let metadata = ModelChangeMetadata::new();
+
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let repo = match kind {
+                CtrlChgKind::Dumper => &mut proj.dumper_repo,
+                CtrlChgKind::Soft => &mut proj.soft_repo,
+            };
+
+            let mut removed: Vec<CtrlChg> = vec![];
+            if replace {
+                let ticks: Vec<u32> = repo.range(range).1.iter().map(|(tick, _)| *tick).collect();
+                removed.extend(repo.bulk_remove(&ticks, metadata).into_iter().map(|(_, c)| c));
+            }
+            removed.extend(
+                repo.bulk_add(ramp.iter().map(|c| (c.start_tick, *c)).collect(), metadata).into_iter().map(|(_, c)| c)
+            );
+            let replenishid_bars = proj.replenish_bars();
+
+            let (added, removed) = match kind {
+                CtrlChgKind::Dumper => (Models::empty().with_dumpers(ramp), Models::empty().with_dumpers(removed)),
+                CtrlChgKind::Soft => (Models::empty().with_softs(ramp), Models::empty().with_softs(removed)),
+            };
+            Ok(ProjectCmd::ModelChanged { added: added.with_bars(replenishid_bars), removed, metadata })
+        }));
+    }
+
+    fn auto_pedal(&mut self, range: RangeInclusive<u32>, policy: AutoPedalPolicy) {
+        if range.is_empty() { return; }
+        let (end, channel) = (*range.end(), Channel::default());
+        let ticks = self.model().pedal_change_ticks(range.clone(), policy);
+        let mut pedals: Vec<CtrlChg> = Vec::with_capacity(ticks.len() * 2 + 1);
+        for (i, tick) in ticks.iter().enumerate() {
+            let next = ticks.get(i + 1).copied().unwrap_or(end);
+            if i == 0 {
+                pedals.push(CtrlChg::new(*tick, velocity::MAX, channel));
+            } else {
+                pedals.push(CtrlChg::new(*tick, velocity::MIN, channel));
+                if *tick + AUTO_PEDAL_GAP < next {
+                    pedals.push(CtrlChg::new(*tick + AUTO_PEDAL_GAP, velocity::MAX, channel));
+                }
+            }
+        }
+        if ticks[0] < end {
+            pedals.push(CtrlChg::new(end, velocity::MIN, channel));
+        }
+        let metadata = ModelChangeMetadata::new();
+
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let existing: Vec<u32> = proj.dumper_repo.range(range).1.iter().map(|(tick, _)| *tick).collect();
+            let removed: Vec<CtrlChg> = proj.dumper_repo.bulk_remove(&existing, metadata).into_iter().map(|(_, c)| c).collect();
+            proj.dumper_repo.bulk_add(pedals.iter().map(|c| (c.start_tick, *c)).collect(), metadata);
+            let replenishid_bars = proj.replenish_bars();
+            Ok(
+                ProjectCmd::ModelChanged {
+                    added: Models::empty().with_bars(replenishid_bars).with_dumpers(pedals),
+                    removed: Models::empty().with_dumpers(removed),
+                    metadata,
+                }
+            )
+        }));
+    }
+
+    fn tuplize(&mut self, notes: Vec<Rc<Note>>) {
+        let metadata = ModelChangeMetadata::new().with_need_select(true);
+        let _ = mutate_project(self, Box::new(move |proj| {
+            if 1 < notes.len() {
+                let mut to_remove = Vec::with_capacity(notes.len());
+                for n in notes.iter() {
+                    to_remove.push((n.start_tick(), n.clone()));
+                }
+                let tupled = tuple::tuplize(notes.clone());
+                proj.note_repo.bulk_remove(&to_remove, ModelChangeMetadata::new());
+                proj.note_repo.bulk_add(
+                    tupled.iter().map(|n| (n.start_tick(), n.clone())).collect(),
+                    metadata
+                );
+                let replenishid_bars = proj.replenish_bars();
+                
+                Ok(
+                    ProjectCmd::ModelChanged {
+                        added: Models::empty().with_notes(&tupled).with_bars(replenishid_bars),
+                        removed: Models::empty().with_notes(&notes),
+                        metadata,
+                    }
+                )
+            } else {
+                ProjectCmdErr::NoOp.raise()
+            }
+        }));
+        
+    }
+
+    fn split_note(&mut self, note: Rc<Note>, tick: u32) -> Result<(), SplitError> {
+        let (first, second) = note.split_at(tick)?;
+        let metadata = ModelChangeMetadata::new().with_need_select(true);
+        let _ = mutate_project(self, Box::new(move |proj| {
+            if proj.note_repo.remove(&note.start_tick(), &note).is_none() {
+                return ProjectCmdErr::NoOp.raise();
+            }
+            let added = vec![Rc::new(first), Rc::new(second)];
+            proj.note_repo.bulk_add(added.iter().map(|n| (n.start_tick(), n.clone())).collect(), metadata);
+            let replenished_bars = proj.replenish_bars();
+            Ok(ProjectCmd::ModelChanged {
+                added: Models::empty().with_notes(&added).with_bars(replenished_bars),
+                removed: Models::empty().with_notes(&[note]),
+                metadata,
+            })
+        }));
+        Ok(())
+    }
+
+    fn bulk_remove(&mut self, to_remove: Models, metadata: ModelChangeMetadata) {
+        self.add_cmd(ProjectCmd::ModelChanged { added: Models::empty(), removed: to_remove, metadata });
+    }
+
+    fn remove_notes_tie_aware(&mut self, notes: &[Rc<Note>], policy: TieRemovalPolicy) {
+        let notes = notes.to_vec();
+        let metadata = ModelChangeMetadata::new();
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let (added, removed) = proj.tie_aware_removal(&notes, policy);
+            if removed.is_empty() { return ProjectCmdErr::NoOp.raise(); }
+            proj.redo_model_change(&added, &removed, metadata);
+            Ok(ProjectCmd::ModelChanged { added, removed, metadata })
+        }));
+    }
+
+    // Moves of notes no longer in the project are ignored.
+    fn apply_quantize(&mut self, preview: &[QuantizeMove]) {
+        let preview = preview.to_vec();
+        let metadata = ModelChangeMetadata::new();
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let (moved, removed): (Vec<Note>, Vec<Note>) = preview.iter()
+                .filter(|m| proj.note_repo.range(m.note.start_tick()..m.note.start_tick() + 1).any(|(_, n)| *n == m.note))
+                .map(|m| {
+                    let base_start_tick = (m.note.base_start_tick as i64 + m.new_tick as i64 - m.note.start_tick() as i64).max(0) as u32;
+                    (Note { base_start_tick, ..*m.note }, (*m.note).clone())
+                })
+                .unzip();
+            if removed.is_empty() { return ProjectCmdErr::NoOp.raise(); }
+            let added = Models { notes: moved, ..Models::empty() };
+            let removed = Models { notes: removed, ..Models::empty() };
+            proj.redo_model_change(&added, &removed, metadata);
+            Ok(ProjectCmd::ModelChanged { added, removed, metadata })
+        }));
+    }
+
+    // Tempos are added at both ends of the range so that the tempo outside stays the same.
+    fn scale_tempo(&mut self, range: Range<u32>, factor: PercentU16) {
+        let metadata = ModelChangeMetadata::new();
+        let _ = mutate_project(self, Box::new(move |proj| {
+            if range.is_empty() || factor == PercentU16::HUNDRED { return ProjectCmdErr::NoOp.raise(); }
+            let (added, removed) = proj.tempo_scaling(range, |v| v.scaled(factor));
+            proj.redo_model_change(&added, &removed, metadata);
+            Ok(ProjectCmd::ModelChanged { added, removed, metadata })
+        }));
+    }
+
+    fn fix_unison_conflicts(&mut self, range: Range<u32>, fix: UnisonFix) {
+        let metadata = ModelChangeMetadata::new();
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let conflicts = validation::unison_conflicts(proj, range);
+            let (added, removed) = validation::unison_fixes(&conflicts, fix);
+            if removed.is_empty() { return ProjectCmdErr::NoOp.raise(); }
+            proj.redo_model_change(&added, &removed, metadata);
+            Ok(ProjectCmd::ModelChanged { added, removed, metadata })
+        }));
+    }
+
+    fn bulk_add(&mut self, to_add: Models, metadata: ModelChangeMetadata) {
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let on_duplicate = proj.add_policy.on_duplicate;
+            Ok(proj.add_models(to_add, metadata, on_duplicate))
+        }));
+    }
+
+    // Paste models at the tick as one command.
+    fn paste(&mut self, models: Models, tick: u32, options: PasteOptions) -> Vec<BarMergeWarning> {
+        let mut to_add = models.move_to_tick(tick).remap_channels(&options.channel_map);
+        let mut warnings = vec![];
+        if options.bar_merge == BarMerge::Merge {
+            let (bars, w) = self.model().bars_merged(&to_add.bars);
+            to_add.bars = bars;
+            warnings = w;
+        }
+        let metadata = ModelChangeMetadata::new().with_need_select(options.select_result);
+        let _ = mutate_project(self, Box::new(move |proj| {
+            if to_add.is_empty() { return ProjectCmdErr::NoOp.raise(); }
+            Ok(proj.add_models(to_add, metadata, options.on_conflict.duplicate_policy()))
+        }));
+        warnings
+    }
+
+    fn set_muted(&mut self, notes: &[Rc<Note>], muted: bool) {
+        let changes: Vec<(Note, Note)> = notes.iter()
+            .filter(|n| n.muted != muted)
+            .map(|n| ((**n).clone(), n.with_muted(muted)))
+            .collect();
+        if changes.is_empty() { return; }
+        self.change(ModelChanges::empty().with_notes(changes), ModelChangeMetadata::new());
+    }
+
+    #[cfg(feature = "practice")]
+    fn set_play_probability(&mut self, notes: &[Rc<Note>], play_probability: Option<PercentU16>) {
+        let changes: Vec<(Note, Note)> = notes.iter()
+            .filter(|n| n.play_probability != play_probability)
+            .map(|n| ((**n).clone(), n.with_play_probability(play_probability)))
+            .collect();
+        if changes.is_empty() { return; }
+        self.change(ModelChanges::empty().with_notes(changes), ModelChangeMetadata::new());
+    }
+
+    fn set_note_tag(&mut self, notes: &[Rc<Note>], tag: Option<NoteTag>) {
+        let changes: Vec<(Note, Note)> = notes.iter()
+            .filter(|n| n.tag != tag)
+            .map(|n| ((**n).clone(), n.with_tag(tag)))
+            .collect();
+        if changes.is_empty() { return; }
+        self.change(ModelChanges::empty().with_notes(changes), ModelChangeMetadata::new());
+    }
+
+    fn conform_to_scale(&mut self, range: Range<u32>, scale: Scale) -> Vec<ScaleSnap> {
+        let changes = self.model().scale_conformation(range, scale);
+        let report = changes.iter().map(|(from, to)| ScaleSnap {
+            start_tick: from.start_tick(), channel: from.channel, from: from.pitch, to: to.pitch,
+        }).collect();
+        if !changes.is_empty() {
+            self.change(ModelChanges::empty().with_notes(changes), ModelChangeMetadata::new());
+        }
+        report
+    }
+
+    fn normalize_velocities(&mut self, range: Range<u32>, target_min: Velocity, target_max: Velocity, preserve_relative: bool) {
+        let changes = self.model().velocity_normalization(range, target_min, target_max, preserve_relative);
+        if changes.is_empty() { return; }
+        self.change(ModelChanges::empty().with_notes(changes), ModelChangeMetadata::new());
+    }
+
+    fn normalize_trimmers(&mut self, notes: &[Rc<Note>], mode: TrimmerNormalizeMode) {
+        let changes: Vec<(Note, Note)> = notes.iter()
+            .map(|n| ((**n).clone(), match mode {
+                TrimmerNormalizeMode::FoldIntoBase => n.with_trimmers_folded(),
+                TrimmerNormalizeMode::Clear => n.with_trimmers_cleared(),
+            }))
+            .filter(|(from, to)| from != to)
+            .collect();
+        if changes.is_empty() { return; }
+        self.change(ModelChanges::empty().with_notes(changes), ModelChangeMetadata::new());
+    }
+
+    // The selection is shown as the drag preview until commit_drag() or cancel_drag().
+    fn begin_drag(&mut self, selection: Models) {
+        self.irreversible_mutate(Box::new(move |proj| {
+            proj.drag = Some(DragSession::new(selection));
+        }))
+    }
+
+    // Deltas are from the start of the drag. False if not dragging or the selection cannot move by the deltas.
+    fn update_drag(&mut self, delta_tick: i32, delta_pitch: i32) -> bool {
+        self.irreversible_mutate(Box::new(move |proj| {
+            proj.drag.as_mut().is_some_and(|d| d.update(delta_tick, delta_pitch))
+        }))
+    }
+
+    #[inline]
+    fn drag_preview(&self) -> Option<&Models> {
+        self.model().drag_session().map(|d| d.preview())
+    }
+
+    fn commit_drag(&mut self) {
+        let session = self.irreversible_mutate(Box::new(|proj| proj.drag.take()));
+        if let Some(session) = session {
+            if session.is_moved() {
+                self.change(session.to_changes(), ModelChangeMetadata::new().with_need_select(true).with_dragged(true));
+            }
+        }
+    }
+
+    fn cancel_drag(&mut self) {
+        self.irreversible_mutate(Box::new(|proj| {
+            proj.drag = None;
+        }))
+    }
+
+    #[inline]
+    fn preview_models(&self) -> &Models {
+        self.model().preview_models()
+    }
+
+    fn set_preview_models(&mut self, models: Models) {
+        self.irreversible_mutate(Box::new(move |proj| {
+            proj.preview_models = models;
+        }))
+    }
+
+    fn clear_preview_models(&mut self) {
+        self.set_preview_models(Models::empty());
+    }
+
+    // Add the preview to the tune as an undoable change and clear the preview.
+    fn promote_preview(&mut self, select: bool) {
+        let preview = self.irreversible_mutate(Box::new(|proj| std::mem::replace(&mut proj.preview_models, Models::empty())));
+        if !preview.is_empty() {
+            self.bulk_add(preview, ModelChangeMetadata::new().with_need_select(select));
+        }
+    }
+
+    fn change(&mut self, from_to: ModelChanges, metadata: ModelChangeMetadata) {
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let mut added: Models = Models::with_capacity(
+                from_to.notes.len(),
+                from_to.bars.len(),
+                from_to.tempos.len(),
+                from_to.dumpers.len(),
+                from_to.softs.len(),
+            );
+
+            let mut removed: Models = Models::with_capacity(
+                from_to.notes.len(),
+                from_to.bars.len(),
+                from_to.tempos.len(),
+                from_to.dumpers.len(),
+                from_to.softs.len(),
+            );
+
+            let mut note_change: Vec<((u32, Rc<Note>), (u32, Rc<Note>))> = Vec::with_capacity(from_to.notes.len());
+            for (from, to) in from_to.notes.iter() {
+                note_change.push((
+                    (from.start_tick(), Rc::new(from.clone())), (to.start_tick(), Rc::new(to.clone()))
+                ));
+                added.notes.push(to.clone());
+                removed.notes.push(from.clone());
+            }
+            proj.note_repo.change(&note_change, metadata);
+
+            let mut bar_change: Vec<(&u32, (u32, Bar))> = Vec::with_capacity(from_to.bars.len());
+            for (from, to) in from_to.bars.iter() {
+                bar_change.push((
+                    &from.start_tick, (to.start_tick, *to)
+                ));
+                added.bars.push(*to);
+                removed.bars.push(*from);
+            }
+            removed.bars.extend(proj.bar_repo.change(&bar_change, metadata).iter().map(|(_, b)| *b).collect::<Vec<Bar>>());
+
+            let mut tempo_change: Vec<(&u32, (u32, Tempo))> = Vec::with_capacity(from_to.tempos.len());
+            for (from, to) in from_to.tempos.iter() {
+                tempo_change.push((
+                    &from.start_tick, (to.start_tick, *to)
+                ));
+                added.tempos.push(*to);
+                removed.tempos.push(*from);
+            }
+            removed.tempos.extend(proj.tempo_repo.change(&tempo_change,metadata).iter().map(|(_, t)| *t).collect::<Vec<Tempo>>());
+
+            let mut dumper_change: Vec<(&u32, (u32, CtrlChg))> = Vec::with_capacity(from_to.dumpers.len());
+            for (from, to) in from_to.dumpers.iter() {
+                dumper_change.push((
+                    &from.start_tick, (to.start_tick, *to)
+                ));
+                added.dumpers.push(*to);
+                removed.dumpers.push(*from);
+            }
+            removed.dumpers.extend(proj.dumper_repo.change(&dumper_change,metadata).iter().map(|(_, t)| *t).collect::<Vec<CtrlChg>>());
+
+            let mut soft_change: Vec<(&u32, (u32, CtrlChg))> = Vec::with_capacity(from_to.softs.len());
+            for (from, to) in from_to.softs.iter() {
+                soft_change.push((
+                    &from.start_tick, (to.start_tick, *to)
+                ));
+                added.softs.push(*to);
+                removed.softs.push(*from);
+            }
+            removed.softs.extend(proj.soft_repo.change(&soft_change,metadata).iter().map(|(_, t)| *t).collect::<Vec<CtrlChg>>());
+
+            added.bars.extend(proj.replenish_bars());
+            Ok(ProjectCmd::ModelChanged {
+                added, removed, metadata
+            })
+        }));
+    }
+
+    #[inline]
+    fn bar_no(&self, bar: &Bar) -> Option<usize> {
+        self.model().bar_no(bar)
+    }
+
+    #[inline]
+    fn repeat_labels(&self, bar: &Bar) -> Vec<RepeatLabel> {
+        self.model().repeat_labels(bar)
+    }
+
+    #[inline]
+    fn models_in(&self, tick_range: Range<u32>, pitch_range: RangeInclusive<u8>) -> ModelsView<'_> {
+        self.model().models_in(tick_range, pitch_range)
+    }
+
+    #[inline]
+    fn repeat_diagnostics(&self) -> Vec<(u32, RepeatDiagnostic)> {
+        self.model().repeat_diagnostics()
+    }
+
+    #[inline]
+    fn preview_rhythm_change(&self, at_bar: usize, new_rhythm: Rhythm) -> Option<ImpactReport> {
+        self.model().preview_rhythm_change(at_bar, new_rhythm)
+    }
+
+    #[inline]
+    fn content_hash(&self) -> u64 {
+        self.model().content_hash()
+    }
+
+    #[inline]
+    fn describe_range(&self, range: Range<u32>) -> Vec<String> {
+        self.model().describe_range(range)
+    }
+
+    #[inline]
+    fn tempo_at(&self, tick: u32) -> TempoValue {
+        self.model().tempo_at(tick)
+    }
+
+    #[inline]
+    fn dumper_at(&self, tick: u32) -> Velocity {
+        self.model().dumper_at(tick)
+    }
+
+    #[inline]
+    fn soft_at(&self, tick: u32) -> Velocity {
+        self.model().soft_at(tick)
+    }
+
+    #[inline]
+    fn dumper_at_chan(&self, tick: u32, channel: Channel) -> Velocity {
+        self.model().dumper_at_chan(tick, channel)
+    }
+
+    #[inline]
+    fn soft_at_chan(&self, tick: u32, channel: Channel) -> Velocity {
+        self.model().soft_at_chan(tick, channel)
+    }
+
+    #[inline]
+    fn events_at(&self, tick: u32) -> Models {
+        self.model().events_at(tick)
+    }
+
+    #[inline]
+    fn playback_settings(&self) -> &PlaybackSettings {
+        self.model().playback_settings()
+    }
+
+    fn set_tempo_scale(&mut self, scale: PercentU16) {
+        self.irreversible_mutate(Box::new(move |proj| {
+            if proj.playback.tempo_scale == scale { return; }
+            proj.playback.tempo_scale = scale;
+            proj.setting_events.push(SettingEvent::Playback);
+            proj.save_settings();
+        }));
+    }
+
+    fn set_event_order(&mut self, policy: EventOrderPolicy) {
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let old_playback = proj.playback;
+            proj.playback.event_order = policy;
+            if proj.playback == old_playback { return ProjectCmdErr::NoOp.raise(); }
+            Ok(ProjectCmd::SetPlayback(old_playback, proj.playback))
+        }));
+    }
+
+    fn set_note_length(&mut self, mode: NoteLengthMode, legato_overlap: u32) {
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let old_playback = proj.playback;
+            proj.playback.note_length = mode;
+            proj.playback.legato_overlap = legato_overlap;
+            if proj.playback == old_playback { return ProjectCmdErr::NoOp.raise(); }
+            Ok(ProjectCmd::SetPlayback(old_playback, proj.playback))
+        }));
+    }
+
+    fn set_beat_emphasis(&mut self, emphasis: Option<BeatEmphasis>) {
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let old_playback = proj.playback;
+            proj.playback.beat_emphasis = emphasis;
+            if proj.playback == old_playback { return ProjectCmdErr::NoOp.raise(); }
+            Ok(ProjectCmd::SetPlayback(old_playback, proj.playback))
+        }));
+    }
+
+    fn set_slur_legato(&mut self, slur_legato: bool) {
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let old_playback = proj.playback;
+            proj.playback.slur_legato = slur_legato;
+            if proj.playback == old_playback { return ProjectCmdErr::NoOp.raise(); }
+            Ok(ProjectCmd::SetPlayback(old_playback, proj.playback))
+        }));
+    }
+
+    #[inline]
+    fn note_defaults(&self) -> &NoteDefaults {
+        self.model().note_defaults()
+    }
+
+    #[inline]
+    fn display_settings(&self) -> &DisplaySettings {
+        self.model().display_settings()
+    }
+
+    fn set_transposition(&mut self, channel: Channel, transposition: Transposition) {
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let old_display = proj.display.clone();
+            proj.display.set_transposition(channel, transposition);
+            if proj.display == old_display { return ProjectCmdErr::NoOp.raise(); }
+            Ok(ProjectCmd::SetDisplay(old_display, proj.display.clone()))
+        }));
+    }
+
+    fn set_concert_pitch(&mut self, concert_pitch: bool) {
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let old_display = proj.display.clone();
+            proj.display.concert_pitch = concert_pitch;
+            if proj.display == old_display { return ProjectCmdErr::NoOp.raise(); }
+            Ok(ProjectCmd::SetDisplay(old_display, proj.display.clone()))
+        }));
+    }
+
+    #[inline]
+    fn display_pitch(&self, note: &Note) -> Pitch {
+        self.model().display_pitch(note)
+    }
+
+    #[inline]
+    fn sounding_pitch(&self, note: &Note) -> Pitch {
+        self.model().sounding_pitch(note)
+    }
+
+    fn clips(&self) -> &ClipLibrary {
+        self.model().clips()
+    }
+
+    // Instances placed by reference are updated to the new contents.
+    fn save_clip(&mut self, name: &str, models: &Models) {
+        let clip = Clip::new(name, models);
+        let metadata = ModelChangeMetadata::new();
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let old_clips = proj.clips.clone();
+            let mut new_clips = old_clips.clone();
+            let mut to_remove = Models::empty();
+            let mut added = Models::empty();
+            if let Some(old) = new_clips.insert(clip.clone()) {
+                if old == clip { return ProjectCmdErr::NoOp.raise(); }
+                for i in old_clips.instances(&clip.name) {
+                    let (old_models, new_models) = (old.models_at(i.tick), clip.models_at(i.tick));
+                    to_remove.notes.extend(old_models.notes);
+                    to_remove.tempos.extend(old_models.tempos);
+                    to_remove.dumpers.extend(old_models.dumpers);
+                    to_remove.softs.extend(old_models.softs);
+                    added.notes.extend(new_models.notes);
+                    added.tempos.extend(new_models.tempos);
+                    added.dumpers.extend(new_models.dumpers);
+                    added.softs.extend(new_models.softs);
+                }
+            }
+
+            let removed = proj.clip_change_removed(to_remove, &added);
+            proj.redo_model_change(&added, &removed, metadata);
+            proj.clips = new_clips.clone();
+            added.bars.extend(proj.replenish_bars());
+            Ok(ProjectCmd::ClipChanged { old_clips, new_clips, added, removed, metadata })
+        }));
+    }
+
+    fn remove_clip(&mut self, name: &str) {
+        let name = name.to_owned();
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let old_clips = proj.clips.clone();
+            let mut new_clips = old_clips.clone();
+            if new_clips.remove(&name).is_none() { return ProjectCmdErr::NoOp.raise(); }
+            proj.clips = new_clips.clone();
+            Ok(ProjectCmd::ClipChanged {
+                old_clips, new_clips, added: Models::empty(), removed: Models::empty(), metadata: ModelChangeMetadata::new()
+            })
+        }));
+    }
+
+    fn instantiate_clip(&mut self, name: &str, tick: u32, by_ref: bool) {
+        let name = name.to_owned();
+        let metadata = ModelChangeMetadata::new().with_need_select(true);
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let clip = match proj.clips.get(&name) {
+                Some(clip) if !clip.is_empty() => clip,
+                _ => return ProjectCmdErr::NoOp.raise(),
+            };
+            let mut added = clip.models_at(tick);
+            let old_clips = proj.clips.clone();
+            let mut new_clips = old_clips.clone();
+            if by_ref {
+                new_clips.add_instance(&name, ClipInstance { tick });
+            }
+
+            let removed = proj.clip_change_removed(Models::empty(), &added);
+            proj.redo_model_change(&added, &removed, metadata);
+            proj.clips = new_clips.clone();
+            added.bars.extend(proj.replenish_bars());
+            Ok(ProjectCmd::ClipChanged { old_clips, new_clips, added, removed, metadata })
+        }));
+    }
+
+    // Tile the pattern across the range as one command. The last repetition is cut at the end of range.
+    fn repeat_fill(&mut self, pattern: Models, range: Range<u32>) {
+        let clip = Clip::new("", &pattern);
+        if clip.is_empty() || range.is_empty() { return; }
+        self.bulk_add(clip.tile(range), ModelChangeMetadata::new().with_need_select(true));
+    }
+
+    // Paste models copied from a bar in another rhythm, re-barring them to the rhythm at the location.
+    fn paste_fit(&mut self, models: Models, location: Location, options: PasteFitOptions) -> Result<(), LocationError> {
+        let tick = self.location_to_tick(location)?;
+        let fitted = models.fit_to_rhythm(options.source_rhythm, self.rhythm_at(tick), options.scale_durations);
+        if !Clip::new("", &fitted).is_empty() {
+            self.bulk_add(fitted.move_to_tick(tick), ModelChangeMetadata::new().with_need_select(true));
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn glissandos(&self) -> &[Glissando] {
+        self.model().glissandos()
+    }
+
+    // Both notes should exist in the project.
+    fn add_glissando(&mut self, glissando: Glissando) {
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let exists = |n: &Note| proj.note_repo.iter().any(|(_, e)| **e == *n);
+            if !glissando.is_valid() || !exists(&glissando.from_note) || !exists(&glissando.to_note) || proj.glissando_from(&glissando.from_note).is_some() {
+                return ProjectCmdErr::NoOp.raise();
+            }
+            let old_glissandos = proj.glissandos.clone();
+            proj.glissandos.push(glissando);
+            Ok(ProjectCmd::GlissandoChanged(old_glissandos, proj.glissandos.clone()))
+        }));
+    }
+
+    fn remove_glissando(&mut self, glissando: &Glissando) {
+        let glissando = glissando.clone();
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let old_glissandos = proj.glissandos.clone();
+            proj.glissandos.retain(|g| *g != glissando);
+            if proj.glissandos.len() == old_glissandos.len() { return ProjectCmdErr::NoOp.raise(); }
+            Ok(ProjectCmd::GlissandoChanged(old_glissandos, proj.glissandos.clone()))
+        }));
+    }
+
+    #[inline]
+    fn slurs(&self) -> &[Slur] {
+        self.model().slurs()
+    }
+
+    // Both notes should exist in the project. Overlapping slurs on the same channel are not allowed.
+    fn add_slur(&mut self, slur: Slur) {
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let exists = |n: &Note| proj.note_repo.iter().any(|(_, e)| **e == *n);
+            let overlaps = proj.slurs.iter().any(|s| s.covers(&slur.start_note) || slur.covers(&s.start_note));
+            if !slur.is_valid() || !exists(&slur.start_note) || !exists(&slur.end_note) || overlaps {
+                return ProjectCmdErr::NoOp.raise();
+            }
+            let old_slurs = proj.slurs.clone();
+            proj.slurs.push(slur);
+            Ok(ProjectCmd::SlurChanged(old_slurs, proj.slurs.clone()))
+        }));
+    }
+
+    fn remove_slur(&mut self, slur: &Slur) {
+        let slur = slur.clone();
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let old_slurs = proj.slurs.clone();
+            proj.slurs.retain(|s| *s != slur);
+            if proj.slurs.len() == old_slurs.len() { return ProjectCmdErr::NoOp.raise(); }
+            Ok(ProjectCmd::SlurChanged(old_slurs, proj.slurs.clone()))
+        }));
+    }
+
+    #[inline]
+    fn comments(&self) -> &[Comment] {
+        self.model().comments()
+    }
+
+    #[inline]
+    fn comments_in(&self, range: Range<u32>) -> impl Iterator<Item = &Comment> + '_ {
+        self.model().comments_in(range)
+    }
+
+    fn add_comment(&mut self, comment: Comment) {
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let old_comments = proj.comments.clone();
+            proj.comments.push(comment);
+            Ok(ProjectCmd::CommentChanged(old_comments, proj.comments.clone()))
+        }));
+    }
+
+    fn remove_comment(&mut self, comment: &Comment) {
+        let comment = comment.clone();
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let old_comments = proj.comments.clone();
+            let Some(idx) = proj.comments.iter().position(|c| *c == comment) else {
+                return ProjectCmdErr::NoOp.raise();
+            };
+            proj.comments.remove(idx);
+            Ok(ProjectCmd::CommentChanged(old_comments, proj.comments.clone()))
+        }));
+    }
+
+    fn set_comment_resolved(&mut self, comment: &Comment, resolved: bool) {
+        let comment = comment.clone();
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let old_comments = proj.comments.clone();
+            let Some(c) = proj.comments.iter_mut().find(|c| **c == comment && c.resolved != resolved) else {
+                return ProjectCmdErr::NoOp.raise();
+            };
+            c.resolved = resolved;
+            Ok(ProjectCmd::CommentChanged(old_comments, proj.comments.clone()))
+        }));
+    }
+
+    #[inline]
+    fn rehearsal_marks(&self) -> &[RehearsalMark] {
+        self.model().rehearsal_marks()
+    }
+
+    // Replaces the mark of the same bar.
+    fn add_rehearsal_mark(&mut self, mark: RehearsalMark) {
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let old_marks = proj.rehearsal_marks.clone();
+            match proj.rehearsal_marks.binary_search_by_key(&mark.bar_no, |m| m.bar_no) {
+                Ok(idx) => proj.rehearsal_marks[idx] = mark,
+                Err(idx) => proj.rehearsal_marks.insert(idx, mark),
+            }
+            rehearsal::renumber(&mut proj.rehearsal_marks);
+            if proj.rehearsal_marks == old_marks { return ProjectCmdErr::NoOp.raise(); }
+            Ok(ProjectCmd::RehearsalMarkChanged(old_marks, proj.rehearsal_marks.clone()))
+        }));
+    }
+
+    fn remove_rehearsal_mark(&mut self, bar_no: usize) {
+        let _ = mutate_project(self, Box::new(move |proj| {
+            let old_marks = proj.rehearsal_marks.clone();
+            let Ok(idx) = proj.rehearsal_marks.binary_search_by_key(&bar_no, |m| m.bar_no) else {
+                return ProjectCmdErr::NoOp.raise();
+            };
+            proj.rehearsal_marks.remove(idx);
+            rehearsal::renumber(&mut proj.rehearsal_marks);
+            Ok(ProjectCmd::RehearsalMarkChanged(old_marks, proj.rehearsal_marks.clone()))
+        }));
+    }
+
+    fn set_note_defaults(&mut self, defaults: NoteDefaults) {
+        self.irreversible_mutate(Box::new(move |proj| {
+            if proj.note_defaults == defaults { return; }
+            proj.note_defaults = defaults;
+            proj.setting_events.push(SettingEvent::NoteDefaults);
+            proj.save_settings();
+        }));
+    }
+
+    fn clear_model_events(&mut self) {
+        let _ = self.irreversible_mutate(Box::new(|proj| {
+            proj.fold_content_hash();
+            proj.note_repo.clear_events();
+            proj.bar_repo.clear_events();
+            proj.tempo_repo.clear_events();
+            proj.dumper_repo.clear_events();
+            proj.soft_repo.clear_events();
+            proj.setting_events.clear();
+            proj.content_hash.get_mut().events_cleared();
+        }));
+    }
+
+    fn compact(&mut self) {
+        self.irreversible_mutate(Box::new(|proj| proj.shrink_to_fit()));
+    }
+
+    // Tick ranges changed since events were cleared last time. Events are cleared.
+    fn take_dirty_regions(&mut self) -> DirtyRegions {
+        let proj = self.model();
+        let regions = DirtyRegions {
+            notes: dirty_region::from_bag_store_events(proj.note_repo.events(), |n| n.start_tick()..n.start_tick() + n.tick_len().max(1)),
+            bars: dirty_region::from_store_events(proj.bar_repo.events(), |b| b.start_tick..b.start_tick + 1),
+            tempos: dirty_region::from_store_events(proj.tempo_repo.events(), |t| t.start_tick..t.start_tick + 1),
+            dumpers: dirty_region::from_store_events(proj.dumper_repo.events(), |d| d.start_tick..d.start_tick + 1),
+            softs: dirty_region::from_store_events(proj.soft_repo.events(), |s| s.start_tick..s.start_tick + 1),
+        };
+        self.clear_model_events();
+        regions
+    }
+
+    #[inline]
+    fn bar_events(&self) -> &Vec<StoreEvent<u32, Bar, ModelChangeMetadata>> {
+        self.model().bar_repo.events()
+    }
+
+    #[inline]
+    fn tempo_events(&self) -> &Vec<StoreEvent<u32, Tempo, ModelChangeMetadata>> {
+        self.model().tempo_repo.events()
+    }
+
+    #[inline]
+    fn dumper_events(&self) -> &Vec<StoreEvent<u32, CtrlChg, ModelChangeMetadata>> {
+        self.model().dumper_repo.events()
+
+    }
+
+    #[inline]
+    fn soft_events(&self) -> &Vec<StoreEvent<u32, CtrlChg, ModelChangeMetadata>> {
+        self.model().soft_repo.events()
+    }
+
+    #[inline]
+    fn note_events(&self) -> &Vec<BagStoreEvent<u32, Rc<Note>, ModelChangeMetadata>> {
+        self.model().note_repo.events()
+    }
+
+    #[inline]
+    fn setting_events(&self) -> &Vec<SettingEvent> {
+        &self.model().setting_events
+    }
+
+    #[inline]
+    fn location_to_tick(&self, loc: Location) -> Result<u32, LocationError> {
+        self.model().location_to_tick(loc)
+    }
+
+    #[inline]
+    fn tick_to_location(&self, tick: u32) -> Location {
+        self.model().tick_to_location(tick)
+    }
+
+    #[inline]
+    fn notes_by_bar(&self) -> impl Iterator<Item = (usize, Vec<Rc<Note>>)> + '_ {
+        self.model().notes_by_bar()
+    }
+
+    #[inline]
+    fn notes_chordwise(&self) -> impl Iterator<Item = (u32, Vec<Rc<Note>>)> + '_ {
+        self.model().notes_chordwise()
+    }
+
+    #[inline]
+    fn beats(&self, range: Range<u32>) -> impl Iterator<Item = Beat> + '_ {
+        self.model().beats(range)
+    }
+
+    #[inline]
+    fn rhythm_at(&self, tick: u32) -> Rhythm {
+        self.model().rhythm_at(tick)
+    }
+
+    #[inline]
+    fn key_at(&self, tick: u32) -> Key {
+        self.model().key_at(tick)
+    }
+
+    #[inline]
+    fn note_repo(&self) -> &BagStore<u32, Rc<Note>, ModelChangeMetadata> {
+        &self.model().note_repo()
+    }
+
+    #[inline]
+    fn bar_repo(&self) -> &Store<u32, Bar, ModelChangeMetadata> {
+        self.model().bar_repo()
+    }
+
+    #[inline]
+    fn tempo_repo(&self) -> &Store<u32, Tempo, ModelChangeMetadata> {
+        self.model().tempo_repo()
+    }
+
+    #[inline]
+    fn soft_repo(&self) -> &Store<u32, CtrlChg, ModelChangeMetadata> {
+        self.model().soft_repo()
+    }
+
+    #[inline]
+    fn dumper_repo(&self) -> &Store<u32, CtrlChg, ModelChangeMetadata> {
+        self.model().dumper_repo()
+    }
+}   
+
+#[cfg(feature = "sqlite")]
+pub type ProjectStore = SqliteUndoStore<ProjectCmd, ProjectImpl, ProjectCmdErr>;
+
+#[cfg(feature = "sqlite")]
+#[derive(Debug)]
+pub enum HistoryError {
+    AlreadyExists(PathBuf),
+    Io { path: PathBuf, error: std::io::Error },
+    CannotOpen(PathBuf),
+    NoBackup(usize),
+}
+
+#[cfg(feature = "sqlite")]
+impl std::error::Error for HistoryError {}
+
+#[cfg(feature = "sqlite")]
+impl std::fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HistoryError::AlreadyExists(path) => write!(f, "Project already exists at {:?}", path),
+            HistoryError::Io { path, error } => write!(f, "Cannot access {:?}: {}", path, error),
+            HistoryError::CannotOpen(path) => write!(f, "Cannot open project at {:?}", path),
+            HistoryError::NoBackup(index) => write!(f, "No backup #{}", index),
+        }
+    }
+}
+
+// Opens the store of the directory with the settings kept out of undo history. See SettingsFile. The hook of the
+// options, if any, runs before the settings are applied.
+#[cfg(feature = "sqlite")]
+pub fn open_project<P: AsRef<Path>>(
+    dir: P, mut options: undo_store::Options<ProjectImpl>
+) -> Result<ProjectStore, error_stack::Report<HistoryError>> {
+    let dir = dir.as_ref().to_path_buf();
+    let path = SettingsFile::path(&dir);
+    let settings = SettingsFile::read(&path).map_err(history_io_err(&path))?;
+    let on_restored = options.on_snapshot_restored.take();
+    let options = options.with_on_snapshot_restored(Box::new(move |proj| {
+        let mut proj = match on_restored {
+            Some(f) => f(proj),
+            None => proj,
+        };
+        if let Some(settings) = settings { proj.restore_settings(&settings); }
+        proj.settings_path = Some(path);
+        proj
+    }));
+    ProjectStore::open(&dir, options).map_err(|e| e.change_context(HistoryError::CannotOpen(dir)))
+}
+
+// Moves a working session between machines with its undo history. The exported file is the serdo
+// command log (commands and snapshots) of the store. The settings out of undo history stay behind.
+#[cfg(feature = "sqlite")]
+pub trait ProjectHistory: Sized {
+    // The store is closed to flush pending commands. Returns the project directory to open it again.
+    fn export_history<P: AsRef<Path>>(self, path: P) -> Result<PathBuf, error_stack::Report<HistoryError>>;
+    // The dir should not contain a project yet.
+    fn import_history<P: AsRef<Path>, D: AsRef<Path>>(
+        path: P, dir: D, options: undo_store::Options<ProjectImpl>
+    ) -> Result<Self, error_stack::Report<HistoryError>>;
+}
+
+#[cfg(feature = "sqlite")]
+impl ProjectHistory for ProjectStore {
+    fn export_history<P: AsRef<Path>>(self, path: P) -> Result<PathBuf, error_stack::Report<HistoryError>> {
+        // Closing the store is the only way to be sure that all commands are written.
+        let dir = self.dir().clone();
+        drop(self);
+        let from = dir.join(undo_store::SQLITE_FILE_NAME);
+        std::fs::copy(&from, path.as_ref())
+            .map_err(|error| error_stack::Report::new(HistoryError::Io { path: from, error }))?;
+        Ok(dir)
+    }
+
+    fn import_history<P: AsRef<Path>, D: AsRef<Path>>(
+        path: P, dir: D, options: undo_store::Options<ProjectImpl>
+    ) -> Result<Self, error_stack::Report<HistoryError>> {
+        let dir = dir.as_ref().to_path_buf();
+        let to = dir.join(undo_store::SQLITE_FILE_NAME);
+        if to.exists() {
+            return Err(error_stack::Report::new(HistoryError::AlreadyExists(dir)));
+        }
+        std::fs::create_dir_all(&dir)
+            .map_err(|error| error_stack::Report::new(HistoryError::Io { path: dir.clone(), error }))?;
+        std::fs::copy(path.as_ref(), &to)
+            .map_err(|error| error_stack::Report::new(HistoryError::Io { path: path.as_ref().to_path_buf(), error }))?;
+        open_project(&dir, options)
+    }
+}
+
+// Loads the model of a project for viewing. A copy of the sqlite file is opened, so the project takes
+// neither locks nor commands and an editor may keep it open.
+#[cfg(feature = "sqlite")]
+pub trait ProjectViewer {
+    fn open_readonly<P: AsRef<Path>>(dir: P) -> Result<ProjectImpl, error_stack::Report<HistoryError>>;
+}
+
+#[cfg(feature = "sqlite")]
+impl ProjectViewer for ProjectStore {
+    fn open_readonly<P: AsRef<Path>>(dir: P) -> Result<ProjectImpl, error_stack::Report<HistoryError>> {
+        static SEQ: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let dir = dir.as_ref();
+        let from = dir.join(undo_store::SQLITE_FILE_NAME);
+        if !from.exists() {
+            return Err(error_stack::Report::new(HistoryError::CannotOpen(dir.to_path_buf())));
+        }
+        let copy_dir = std::env::temp_dir().join(format!(
+            "klavier-readonly-{}-{}", std::process::id(), SEQ.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&copy_dir).map_err(history_io_err(&copy_dir))?;
+        let loaded = (|| {
+            // The backup API reads the store in one transaction, so a write in progress is never half copied.
+            let cannot_open = |e: rusqlite::Error| error_stack::Report::new(e).change_context(HistoryError::CannotOpen(dir.to_path_buf()));
+            let src = rusqlite::Connection::open_with_flags(&from, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(cannot_open)?;
+            src.backup(rusqlite::MAIN_DB, copy_dir.join(undo_store::SQLITE_FILE_NAME), None).map_err(cannot_open)?;
+            let store = ProjectStore::open(&copy_dir, undo_store::Options::new())
+                .map_err(|e| e.change_context(HistoryError::CannotOpen(dir.to_path_buf())))?;
+            let mut proj = store.model().clone();
+            let path = SettingsFile::path(dir);
+            if let Some(settings) = SettingsFile::read(&path).map_err(history_io_err(&path))? {
+                proj.restore_settings(&settings);
+            }
+            Ok(proj)
+        })();
+        let _ = std::fs::remove_dir_all(&copy_dir);
+        loaded
+    }
+}
+
+#[cfg(feature = "sqlite")]
+pub const BACKUP_DIR_NAME: &str = "backup";
+
+// Rotated copies of the sqlite file, kept in the backup directory of the project.
+#[cfg(feature = "sqlite")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackupPolicy {
+    // Number of backups kept. Zero disables backups.
+    pub count: usize,
+    // Minimum time between backups taken by backup_if_due().
+    pub interval: std::time::Duration,
+}
+
+#[cfg(feature = "sqlite")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backup {
+    pub path: PathBuf,
+    pub modified: std::time::SystemTime,
+}
+
+#[cfg(feature = "sqlite")]
+fn backup_path(dir: &Path, index: usize) -> PathBuf {
+    dir.join(BACKUP_DIR_NAME).join(format!("{}.{}", undo_store::SQLITE_FILE_NAME, index))
+}
+
+#[cfg(feature = "sqlite")]
+fn history_io_err(path: &Path) -> impl FnOnce(std::io::Error) -> error_stack::Report<HistoryError> + '_ {
+    move |error| error_stack::Report::new(HistoryError::Io { path: path.to_path_buf(), error })
+}
+
+// Backups of the project directory, newest first. The store is not opened since it may be broken.
+#[cfg(feature = "sqlite")]
+pub fn list_backups<P: AsRef<Path>>(dir: P) -> Result<Vec<Backup>, error_stack::Report<HistoryError>> {
+    let mut backups = vec![];
+    loop {
+        let path = backup_path(dir.as_ref(), backups.len());
+        let modified = match std::fs::metadata(&path) {
+            Ok(meta) => meta.modified().map_err(history_io_err(&path))?,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(backups),
+            Err(error) => return Err(history_io_err(&path)(error)),
+        };
+        backups.push(Backup { path, modified });
+    }
+}
+
+// Replaces the sqlite file of the project with the backup. The store of the directory should be closed. The
+// settings out of undo history are not backed up and are kept.
+#[cfg(feature = "sqlite")]
+pub fn restore_backup<P: AsRef<Path>>(dir: P, index: usize) -> Result<(), error_stack::Report<HistoryError>> {
+    let from = backup_path(dir.as_ref(), index);
+    if !from.exists() {
+        return Err(error_stack::Report::new(HistoryError::NoBackup(index)));
+    }
+    std::fs::copy(&from, dir.as_ref().join(undo_store::SQLITE_FILE_NAME)).map_err(history_io_err(&from))?;
+    Ok(())
+}
+
+// Shifts the backups by one, dropping the ones past the count, and copies the sqlite file as backup 0.
+#[cfg(feature = "sqlite")]
+fn rotate_backups(dir: &Path, count: usize) -> Result<(), error_stack::Report<HistoryError>> {
+    let from = dir.join(undo_store::SQLITE_FILE_NAME);
+    if count == 0 || !from.exists() { return Ok(()); }
+    let backup_dir = dir.join(BACKUP_DIR_NAME);
+    std::fs::create_dir_all(&backup_dir).map_err(history_io_err(&backup_dir))?;
+
+    let mut index = count - 1;
+    while backup_path(dir, index).exists() {
+        let path = backup_path(dir, index);
+        std::fs::remove_file(&path).map_err(history_io_err(&path))?;
+        index += 1;
+    }
+    for index in (0..count - 1).rev() {
+        let path = backup_path(dir, index);
+        if path.exists() {
+            std::fs::rename(&path, backup_path(dir, index + 1)).map_err(history_io_err(&path))?;
+        }
+    }
+    std::fs::copy(&from, backup_path(dir, 0)).map_err(history_io_err(&from))?;
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+pub trait ProjectBackup: Sized {
+    // Backs up the sqlite file, if any, before opening it.
+    fn open_with_backups<P: AsRef<Path>>(
+        dir: P, options: undo_store::Options<ProjectImpl>, policy: BackupPolicy
+    ) -> Result<Self, error_stack::Report<HistoryError>>;
+    // Backs up the store if the newest backup is older than the interval. Returns true if backed up.
+    fn backup_if_due(&mut self, policy: BackupPolicy) -> Result<bool, error_stack::Report<HistoryError>>;
+    fn list_backups(&self) -> Result<Vec<Backup>, error_stack::Report<HistoryError>>;
+    // The store is closed and opened again with the backup.
+    fn restore_backup(self, index: usize, options: undo_store::Options<ProjectImpl>) -> Result<Self, error_stack::Report<HistoryError>>;
+}
+
+#[cfg(feature = "sqlite")]
+impl ProjectBackup for ProjectStore {
+    fn open_with_backups<P: AsRef<Path>>(
+        dir: P, options: undo_store::Options<ProjectImpl>, policy: BackupPolicy
+    ) -> Result<Self, error_stack::Report<HistoryError>> {
+        let dir = dir.as_ref().to_path_buf();
+        rotate_backups(&dir, policy.count)?;
+        open_project(&dir, options)
+    }
+
+    fn backup_if_due(&mut self, policy: BackupPolicy) -> Result<bool, error_stack::Report<HistoryError>> {
+        let due = match list_backups(self.dir())?.first() {
+            Some(newest) => newest.modified.elapsed().map_or(true, |elapsed| policy.interval <= elapsed),
+            None => true,
+        };
+        if !due || policy.count == 0 { return Ok(false); }
+        // The copy should not miss commands still queued for the sqlite file.
+        self.wait_until_saved();
+        rotate_backups(self.dir(), policy.count)?;
+        Ok(true)
+    }
+
+    #[inline]
+    fn list_backups(&self) -> Result<Vec<Backup>, error_stack::Report<HistoryError>> {
+        list_backups(self.dir())
+    }
+
+    fn restore_backup(self, index: usize, options: undo_store::Options<ProjectImpl>) -> Result<Self, error_stack::Report<HistoryError>> {
+        let dir = self.dir().clone();
+        drop(self);
+        restore_backup(&dir, index)?;
+        open_project(&dir, options)
+    }
+}
+
+pub type MemoryProjectStore = InMemoryUndoStore<ProjectCmd, ProjectImpl, ProjectCmdErr>;
+
+pub const DEFAULT_MEMORY_UNDO_LIMIT: usize = 100;
+
+// InMemoryUndoStore cannot work with zero capacity.
+pub fn memory_project_store(undo_limit: usize) -> MemoryProjectStore {
+    MemoryProjectStore::new(std::cmp::max(1, undo_limit))
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use std::rc::Rc;
+    use klavier_helper::store::Store;
+    use serdo::undo_store::{SqliteUndoStore, UndoStore, self};
+    use crate::{tempo::{Tempo, TempoValue}, project::{tempo_at, ProjectCmd, ProjectCmdErr, ModelChangeMetadata, ProjectStore, ProjectHistory, ProjectBackup, ProjectViewer, BackupPolicy, LocationError, AddPolicy, DuplicatePolicy, open_project}, note::{Note, NoteTag}, solfa::Solfa, octave::Octave, sharp_flat::SharpFlat, pitch::Pitch, duration::{Duration, Numerator, Denominator, Dots}, velocity::{Velocity, Dynamic, DynamicsTable}, trimmer::{Trimmer, RateTrimmer}, bar::{Bar, RepeatSet}, location::Location, rhythm::Rhythm, ctrl_chg::CtrlChg, key::Key, grid::Grid, models::{Models, ModelChanges}, channel::Channel, percent::PercentU16, note_defaults::NoteDefaults, playback::{NoteLengthMode, OffsetCurve}, transposition::Transposition, slur::Slur, small_string::SmallString, skip_region::SkipRegion};
+    use super::{DEFAULT_TEMPO, ProjectImpl};
+
+    #[test]
+    fn tempo() {
+        let mut store: Store<u32, Tempo, ModelChangeMetadata> = Store::new(false);
+        assert_eq!(tempo_at(0, &store), DEFAULT_TEMPO);
+        let metadata = ModelChangeMetadata::new();
+
+        store.add(10, Tempo { start_tick: 10, value: TempoValue::new(100), marking: None }, metadata);
+        assert_eq!(tempo_at(0, &store), DEFAULT_TEMPO);
+        assert_eq!(tempo_at(10, &store), TempoValue::new(100));
+        assert_eq!(tempo_at(11, &store), TempoValue::new(100));
+        
+        store.add(20, Tempo { start_tick: 20, value: TempoValue::new(200), marking: None }, metadata);
+        assert_eq!(tempo_at(0, &store), DEFAULT_TEMPO);
+        assert_eq!(tempo_at(10, &store), TempoValue::new(100));
+        assert_eq!(tempo_at(11, &store), TempoValue::new(100));
+        assert_eq!(tempo_at(19, &store), TempoValue::new(100));
+        assert_eq!(tempo_at(20, &store), TempoValue::new(200));
+        assert_eq!(tempo_at(100, &store), TempoValue::new(200));
+    }
+    
+    #[test]
+    fn undo_note_addition() {
+        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
+        dir.push("project");
+        let mut store: ProjectStore = ProjectStore::open(dir.clone(), undo_store::Options::new()).unwrap();
+
+        let note = Note::new(
+            100,
+            Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null),
+            Duration::new(Numerator::Quarter, Denominator::from_value(2).unwrap(), Dots::ZERO),
+            false,
+            false,
+            Velocity::new(64),
+            Trimmer::ZERO,
+            RateTrimmer::ONE,
+            Trimmer::ZERO,
+            Channel::default(),
+        );
+        
+        store.add_note(note, false);
+        assert_eq!(store.model().note_repo().len(), 1);
+        
+        store.undo();
+        assert_eq!(store.model().note_repo().len(), 0);
+    }
+    
+    #[test]
+    fn undo_bar_addition() {
+        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
+        dir.push("project");
+        let mut store: ProjectStore = ProjectStore::open(dir.clone(), undo_store::Options::new()).unwrap();
+
+        let bar = Bar::new(
+            100,
+            None,
+            None,
+            RepeatSet::EMPTY,
+        );
+        store.add_bar(bar, false);
+        assert_eq!(store.model().bar_repo().len(), 1);
+        store.undo();
+        assert_eq!(store.model().bar_repo().len(), 0);
+    }
+    
+    #[test]
+    fn location_to_tick() {
+        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
+        dir.push("project");
+        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
+
+        assert_eq!(store.model().location_to_tick(Location::new(0, 0)).unwrap(), 0);
+        assert_eq!(store.model().location_to_tick(Location::new(0, 1)).unwrap(), 1);
+        assert_eq!(store.model().location_to_tick(Location::new(0, u32::MAX as usize)).unwrap(), u32::MAX);
+        assert_eq!(store.model().location_to_tick(Location::new(0, u32::MAX as usize + 1)), Err(LocationError::Overflow));
+        assert_eq!(
+            store.model().location_to_tick(Location::new(1, 1)).unwrap(),
+            store.model().rhythm().tick_len() + 1
+        );
+        
+        let bar0 = Bar::new(
+            100, None, None, RepeatSet::EMPTY
+        );
+        store.add_bar(bar0, false);
+        
+        assert_eq!(store.model().location_to_tick(Location::new(0, 0)).unwrap(), 0);
+        assert_eq!(store.model().location_to_tick(Location::new(0, 1)).unwrap(), 1);
+        assert_eq!(store.model().location_to_tick(Location::new(1, 1)).unwrap(), 101);
+        assert_eq!(store.model().location_to_tick(Location::new(1, (u32::MAX as usize) - 100)).unwrap(), u32::MAX);
+        assert_eq!(store.model().location_to_tick(Location::new(1, (u32::MAX as usize) - 99)), Err(LocationError::Overflow));
+
+        // 0   bar0(t=100)
+        //     |
+        assert_eq!(store.model().location_to_tick(Location::new(2, 1)).unwrap(), 100 + store.model().rhythm().tick_len() + 1);
+        
+        let bar1 = Bar::new(
+            1000, None, None, RepeatSet::EMPTY
+        );
+        store.add_bar(bar1, false);
+        
+        assert_eq!(store.model().location_to_tick(Location::new(0, 0)).unwrap(), 0);
+        assert_eq!(store.model().location_to_tick(Location::new(0, 1)).unwrap(), 1);
+        assert_eq!(store.model().location_to_tick(Location::new(1, 1)).unwrap(), 101);
+        assert_eq!(store.model().location_to_tick(Location::new(2, 0)).unwrap(), 1000);
+
+        let bar2 = Bar::new(
+            2000, Some(Rhythm::new(2, 4)), None, RepeatSet::EMPTY
+        );
+        store.add_bar(bar2, false);
+
+        let bar3 = Bar::new(
+            3000, None, None, RepeatSet::EMPTY
+        );
+        store.add_bar(bar3, false);
+
+        // 0   bar0(t=100)  bar1(t=1000) bar2(t=2000 r=2/4) bar3(t = 3000)
+        //     |            |            |                  |
+        assert_eq!(store.model().location_to_tick(Location::new(4, 1)).unwrap(), 3001);
+        assert_eq!(store.model().location_to_tick(Location::new(5, 1)).unwrap(), 3000 + 480 + 1);
+
+    }
+    
+    #[test]
+    fn tick_to_location() {
+        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
+        dir.push("project");
+        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
+
+        assert_eq!(store.model().tick_to_location(0), Location::new(0, 0));
+        assert_eq!(store.model().tick_to_location(100), Location::new(0, 100));
+        assert_eq!(store.model().tick_to_location(u32::MAX), Location::new(0, u32::MAX as usize));
+        
+        let bar = Bar::new(
+            100, None, None, RepeatSet::EMPTY
+        );
+        store.add_bar(bar, false);
+        
+        assert_eq!(store.model().tick_to_location(0), Location::new(0, 0));
+        assert_eq!(store.model().tick_to_location(99), Location::new(0, 99));
+        assert_eq!(store.model().tick_to_location(100), Location::new(1, 0));
+        assert_eq!(store.model().tick_to_location(u32::MAX), Location::new(1, (u32::MAX - 100) as usize));
+        
+        let bar = Bar::new(
+            1000, None, None, RepeatSet::EMPTY,
+        );
+        store.add_bar(bar, false);
+        
+        assert_eq!(store.model().tick_to_location(0), Location::new(0, 0));
+        assert_eq!(store.model().tick_to_location(99), Location::new(0, 99));
+        assert_eq!(store.model().tick_to_location(999), Location::new(1, 899));
+        assert_eq!(store.model().tick_to_location(1000), Location::new(2, 0));
+        assert_eq!(store.model().tick_to_location(u32::MAX), Location::new(2, (u32::MAX - 1000) as usize));
+    }
+    
+    #[test]
+    fn rhythm_at() {
+        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
+        dir.push("project");
+        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
+
+        store.set_rhythm(Rhythm::new(6, 8));
+        assert_eq!(store.model().last_bar(), None);
+        
+        assert_eq!(store.model().rhythm_at(500), Rhythm::new(6, 8));
+        assert_eq!(store.model().rhythm_at(0), Rhythm::new(6, 8));
+        
+        let bar0 = Bar::new(100, None, None, RepeatSet::EMPTY);
+        store.add_bar(bar0, false);
+        assert_eq!(store.model().last_bar().map(|(_, bar)| bar), Some(bar0));
+        
+        assert_eq!(store.model().rhythm_at(0), Rhythm::new(6, 8));
+        assert_eq!(store.model().rhythm_at(99), Rhythm::new(6, 8));
+        assert_eq!(store.model().rhythm_at(100), Rhythm::new(6, 8));
+        assert_eq!(store.model().rhythm_at(101), Rhythm::new(6, 8));
+        
+        let bar1 = Bar::new(
+            200, Some(Rhythm::new(3, 4)), None, RepeatSet::EMPTY
+        );
+        store.add_bar(bar1, false);
+        assert_eq!(store.model().last_bar().map(|(_, bar)| bar), Some(bar1));
+        
+        let bar2 = Bar::new(300, None, None, RepeatSet::EMPTY);
+        store.add_bar(bar2, false);
+        assert_eq!(store.model().last_bar().map(|(_, bar)| bar), Some(bar2));
+        
+        let bar3 = Bar::new(
+            400, Some(Rhythm::new(4, 4)), None, RepeatSet::EMPTY
+        );
+        store.add_bar(bar3, false);
+        assert_eq!(store.model().last_bar().map(|(_, bar)| bar), Some(bar3));
+        
+        assert_eq!(store.model().rhythm_at(0), Rhythm::new(6, 8));
+        assert_eq!(store.model().rhythm_at(99), Rhythm::new(6, 8));
+        assert_eq!(store.model().rhythm_at(100), Rhythm::new(6, 8));
+        assert_eq!(store.model().rhythm_at(101), Rhythm::new(6, 8));
+        assert_eq!(store.model().rhythm_at(199), Rhythm::new(6, 8));
+        assert_eq!(store.model().rhythm_at(200), Rhythm::new(3, 4));
+        assert_eq!(store.model().rhythm_at(201), Rhythm::new(3, 4));
+        assert_eq!(store.model().rhythm_at(299), Rhythm::new(3, 4));
+        assert_eq!(store.model().rhythm_at(300), Rhythm::new(3, 4));
+        assert_eq!(store.model().rhythm_at(301), Rhythm::new(3, 4));
+        assert_eq!(store.model().rhythm_at(399), Rhythm::new(3, 4));
+        assert_eq!(store.model().rhythm_at(400), Rhythm::new(4, 4));
+        assert_eq!(store.model().rhythm_at(401), Rhythm::new(4, 4));
+    }
+    
+    #[test]
+    fn note_max_tick_loc() {
+        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
+        dir.push("project");
+        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
+
+        let pitch = Pitch::new(Solfa::C, Octave::Oct0, SharpFlat::Null);
+        assert_eq!(store.model().note_max_end_tick(), None);
+        
+        let note0 = Note::new( // end tick: 100 + 240 * 1.5 = 460
+            100, pitch,
+            Duration::new(Numerator::Quarter, Denominator::from_value(2).unwrap(), Dots::ONE),
+            false, false, Velocity::new(10), Trimmer::ZERO,
+            RateTrimmer::new(1.0, 1.0, 1.0, 1.0),
+            Trimmer::ZERO,
+            Channel::default(),
+        );
+        
+        let end_tick0 = note0.base_start_tick + note0.tick_len();
+        store.add_note(note0, false);
+        assert_eq!(store.model().note_max_end_tick(), Some(end_tick0));
+        
+        let note1 = Note::new( // end tick: 100 + 960 * 1.5 = 1540
+            100, pitch,
+            Duration::new(Numerator::Whole, Denominator::from_value(2).unwrap(), Dots::ONE),
+            false, false, Velocity::new(10), Trimmer::ZERO,
+            RateTrimmer::new(1.0, 1.0, 1.0, 1.0),
+            Trimmer::ZERO,
+            Channel::default(),
+        );
+        
+        store.add_note(note1.clone(), false);
+        assert_eq!(store.model().note_max_end_tick(), Some(note1.base_start_tick + note1.tick_len()));
+        
+        let _ = store.add_note(
+            Note::new( // end tick: 200 + 120 * 2.5 = 440
+                200, pitch,
+                Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
+                false, false, Velocity::new(10), Trimmer::ZERO,
+                RateTrimmer::new(1.0, 1.0, 1.0, 2.0),
+                Trimmer::ZERO,
+                Channel::default(),
+            ), false
+        );
+        
+        assert_eq!(store.model().note_max_end_tick(), Some(note1.base_start_tick + note1.tick_len()));
+    }
+    
+    #[test]
+    fn replenish_bars() {
+        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
+        dir.push("project");
+        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
+
+        let pitch = Pitch::new(Solfa::C, Octave::Oct0, SharpFlat::Null);
+        assert_eq!(store.model().bar_repo().len(), 0);
+        store.add_note(
+            Note::new( // end tick: 100 + 240 * 1.5 = 460
+                100, pitch,
+                Duration::new(Numerator::Quarter, Denominator::from_value(2).unwrap(), Dots::ONE),
+                false, false, Velocity::new(10), Trimmer::ZERO,
+                RateTrimmer::new(1.0, 1.0, 1.0, 1.0),
+                Trimmer::ZERO,
+                Channel::default(),
+            ), false
+        );
+        assert_eq!(store.model().bar_repo().len(), 1);
+        assert_eq!(store.model().bar_repo().peek_last().unwrap().0, 960);
+        
+        store.add_note(
+            Note::new(
+                0, pitch,
+                Duration::new(Numerator::Whole, Denominator::from_value(2).unwrap(), Dots::ZERO),
+                false, false, Velocity::new(10), Trimmer::ZERO,
+                RateTrimmer::new(1.0, 1.0, 1.0, 1.0),
+                Trimmer::ZERO,
+                Channel::default(),
+            ), false
+        );
+
+        assert_eq!(store.model().bar_repo().len(), 1);
+        assert_eq!(store.model().bar_repo().peek_last().unwrap().0, 960);
+        
+        store.add_note(
+            Note::new(
+                960, pitch,
+                Duration::new(Numerator::Whole, Denominator::from_value(2).unwrap(), Dots::ONE),
+                false, false, Velocity::new(10), Trimmer::ZERO,
+                RateTrimmer::new(1.0, 1.0, 1.0, 1.0),
+                Trimmer::ZERO,
+                Channel::default(),
+            ), false
+        );
+
+        assert_eq!(store.model().bar_repo().len(), 3);
+        assert_eq!(store.model().bar_repo().peek_last().unwrap().0, 960 * 3);
+        
+        store.add_tempo(Tempo::new(960*3, 200), false);
+
+        assert_eq!(store.model().bar_repo().len(), 3);
+        assert_eq!(store.model().bar_repo().peek_last().unwrap().0, 960 * 3);
+        
+        store.add_tempo(Tempo::new(960 * 3 + 1, 200), false);
+
+        assert_eq!(store.model().bar_repo().len(), 4);
+        assert_eq!(store.model().bar_repo().peek_last().unwrap().0, 960 * 4);
+        
+        store.add_dumper(CtrlChg::new(960 * 4, Velocity::new(20), Channel::default()), false);
+
+        assert_eq!(store.model().bar_repo().len(), 4);
+        assert_eq!(store.model().bar_repo().peek_last().unwrap().0, 960 * 4);
+        
+        store.add_dumper(CtrlChg::new(960 * 4 + 1, Velocity::new(20), Channel::default()), false);
+
+        assert_eq!(store.model().bar_repo().len(), 5);
+        assert_eq!(store.model().bar_repo().peek_last().unwrap().0, 960 * 5);
+        
+        store.add_soft(CtrlChg::new(960 * 5, Velocity::new(20), Channel::default()), false);
+
+        assert_eq!(store.model().bar_repo().len(), 5);
+        assert_eq!(store.model().bar_repo().peek_last().unwrap().0, 960 * 5);
+        
+        store.add_dumper(CtrlChg::new(960 * 5 + 1, Velocity::new(20), Channel::default()), false);
+
+        assert_eq!(store.model().bar_repo().len(), 6);
+        assert_eq!(store.model().bar_repo().peek_last().unwrap().0, 960 * 6);
+    }
+    
+    #[test]
+    fn tuplize() {
+        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
+        dir.push("project");
+        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
+
+        let note0 = Note::new(
+            100,
+            Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null),
+            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
+            false, false,
+            Velocity::new(64),
+            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
+            Channel::default(),
+        );
+        store.add_note(note0.clone(), false);
+        
+        let note1 = Note::new(
+            120,
+            Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null),
+            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
+            false, false,
+            Velocity::new(64),
+            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
+            Channel::default(),
+        );
+        store.add_note(note1.clone(), false);
+        
+        let note2 = Note::new(
+            150,
+            Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null),
+            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
+            false, false,
+            Velocity::new(64),
+            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
+            Channel::default(),
+        );
+        store.add_note(note2.clone(), false);
+        assert_eq!(store.model().note_repo().len(), 3);
+        
+        store.tuplize(vec![Rc::new(note0), Rc::new(note1), Rc::new(note2)]);
+        assert_eq!(store.model().note_repo().len(), 3);
+        
+        let mut z = store.model().note_repo().iter();
+        let (tick, note) = z.next().unwrap();
+        assert_eq!(*tick, 100);
+        assert_eq!(note.duration, Duration::new(Numerator::N8th, Denominator::from_value(3).unwrap(), Dots::ZERO));
+        
+        let (tick, note) = z.next().unwrap();
+        assert_eq!(*tick, 100 + 80);
+        assert_eq!(note.duration, Duration::new(Numerator::N8th, Denominator::from_value(3).unwrap(), Dots::ZERO));
+        
+        let (tick, note) = z.next().unwrap();
+        assert_eq!(*tick, 100 + 80 * 2);
+        assert_eq!(note.duration, Duration::new(Numerator::N8th, Denominator::from_value(3).unwrap(), Dots::ZERO));
+        
+        store.undo();
+        assert_eq!(store.model().note_repo().len(), 3);
+        
+        let mut z = store.model().note_repo().iter();
+        let (tick, note) = z.next().unwrap();
+        assert_eq!(*tick, 100);
+        assert_eq!(note.duration, Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO));
+        
+        let (tick, note) = z.next().unwrap();
+        assert_eq!(*tick, 120);
+        assert_eq!(note.duration, Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO));
+        
+        let (tick, note) = z.next().unwrap();
+        assert_eq!(*tick, 150);
+        assert_eq!(note.duration, Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO));
+    }
+    
+    #[test]
+    fn can_serialize_project() {
+        let mut proj = ProjectImpl::default();
+        proj.key = Key::FLAT_2;
+        proj.rhythm = Rhythm::new(3, 4);
+        
+        let note0 = Rc::new(Note::new(
+            100,
+            Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null),
+            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
+            false, false,
+            Velocity::new(64),
+            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
+            Channel::default(),
+        ));
+        proj.note_repo.add(note0.start_tick(), note0.clone(), ModelChangeMetadata::new());
+
+        let ser = bincode::serialize(&proj).unwrap();
+        
+        let des: ProjectImpl = bincode::deserialize(&ser).unwrap();
+        
+        assert_eq!(proj.key, des.key);
+        assert_eq!(proj.rhythm, des.rhythm);
+        assert_eq!(des.note_repo.len(), 1);
+        assert_eq!(*des.note_repo.iter().map(|(_, n)| n).next().unwrap(), note0);
+    }
+    
+    use tempfile::tempdir;
+    use super::Project;
+    
+    #[test]
+    fn can_undo_set_rhythm() {
+        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
+        dir.push("project");
+        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
+        
+        store.set_rhythm(Rhythm::new(12, 8));
+        store.wait_until_saved();
+        assert_eq!(store.model().rhythm(), Rhythm::new(12, 8));
+        
+        store.set_rhythm(Rhythm::new(12, 4));
+        store.wait_until_saved();
+        assert_eq!(store.model().rhythm(), Rhythm::new(12, 4));
+        
+        store.undo();
+        assert_eq!(store.model().rhythm(), Rhythm::new(12, 8));
+        
+        store.undo();
+        assert_eq!(store.model().rhythm(), Rhythm::default());
+        
+        store.redo();
+        assert_eq!(store.model().rhythm(), Rhythm::new(12, 8));
+        
+        store.set_rhythm(Rhythm::new(16, 8));
+        store.wait_until_saved();
+        assert_eq!(store.model().rhythm(), Rhythm::new(16, 8));
+        
+        store.undo();
+        assert_eq!(store.model().rhythm(), Rhythm::new(12, 8));
+    }
+    
+    #[test]
+    fn can_undo_set_key() {
+        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
+        dir.push("project");
+        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
+        
+        store.set_key(Key::FLAT_1);
+        store.set_key(Key::FLAT_2);
+        store.wait_until_saved();
+        assert_eq!(store.model().key, Key::FLAT_2);
+        
+        store.undo();
+        assert_eq!(store.model().key, Key::FLAT_1);
+        
+        store.redo();
+        assert_eq!(store.model().key, Key::FLAT_2);
+    }
+    
+    #[test]
+    fn can_undo_set_grid() {
+        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
+        dir.push("project");
+        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
+        
+        store.set_grid(Grid::from_u32(100).unwrap());
+        store.set_grid(Grid::from_u32(200).unwrap());
+        store.wait_until_saved();        
+        assert_eq!(store.model().grid.as_u32(), 200);
+        
+        store.undo();
+        assert_eq!(store.model().grid.as_u32(), 100);
+        
+        store.redo();
+        assert_eq!(store.model().grid.as_u32(), 200);
+    }
+
+    #[test]
+    fn can_undo_add_note() {
+        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
+        dir.push("project");
+        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
+        
+        let note0 = Note::new(
+            100,
+            Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null),
+            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
+            false, false,
+            Velocity::new(64),
+            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
+            Channel::default(),
+        );
+        
+        store.add_note(note0.clone(), false);
+        store.wait_until_saved();
+        assert_eq!(store.model().note_repo.len(), 1);
+        assert_eq!(store.model().bar_repo.len(), 1); // bar is replenished.
+        
+        store.undo();
+        assert_eq!(store.model().note_repo.len(), 0);
+        assert_eq!(store.model().bar_repo.len(), 0);
+        
+        store.redo();
+        assert_eq!(store.model().note_repo.len(), 1);
+        assert_eq!(store.model().note_repo.get(100u32), &vec![Rc::new(note0.clone())]);
+        assert_eq!(store.model().bar_repo.len(), 1);
+    }
+    
+    #[test]
+    fn can_undo_add_bar() {
+        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
+        dir.push("project");
+        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
+        
+        let bar0 = Bar::new(
+            1000, Some(Rhythm::new(3, 4)), None, RepeatSet::EMPTY
+        );
+        
+        store.add_bar(bar0, false);
+        store.wait_until_saved();
+        assert_eq!(store.model().bar_repo.len(), 1); // with replenished bar
+        
+        store.undo();
+        assert_eq!(store.model().bar_repo.len(), 0);
+        
+        store.redo();
+        assert_eq!(store.model().bar_repo.len(), 1);
+        assert_eq!(store.model().bar_repo[0], (1000u32, bar0))
+    }
+    
+    #[test]
+    fn can_undo_add_tempo() {
+        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
+        dir.push("project");
+        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
+        
+        let tempo0 = Tempo::new(200, 200);
+        
+        store.add_tempo(tempo0, false);
+        store.wait_until_saved();
+        assert_eq!(store.model().tempo_repo.len(), 1);
+        assert_eq!(store.model().bar_repo.len(), 1); // bar is replenished.
+        
+        store.undo();
+        assert_eq!(store.model().tempo_repo.len(), 0);
+        assert_eq!(store.model().bar_repo.len(), 0);
+        
+        store.redo();
+        assert_eq!(store.model().tempo_repo.len(), 1);
+        assert_eq!(store.model().bar_repo.len(), 1);
+        assert_eq!(store.model().tempo_repo[0], (200u32, tempo0));
+    }
+    
+    #[test]
+    fn can_undo_add_dumper() {
+        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
+        dir.push("project");
+        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
+        
+        let dumper = CtrlChg::new(200, Velocity::new(20), Channel::default());
+        store.add_dumper(dumper, false);
+        store.wait_until_saved();
+        assert_eq!(store.model().dumper_repo.len(), 1);
+        assert_eq!(store.model().bar_repo.len(), 1); // bar is replenished.
+        
+        store.undo();
+        assert_eq!(store.model().dumper_repo.len(), 0);
+        assert_eq!(store.model().bar_repo.len(), 0);
+        
+        store.redo();
+        assert_eq!(store.model().dumper_repo.len(), 1);
+        assert_eq!(store.model().bar_repo.len(), 1);
+        assert_eq!(store.model().dumper_repo[0], (200u32, dumper));
+    }
+    
+    #[test]
+    fn can_undo_add_soft() {
+        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
+        dir.push("project");
+        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
+        
+        let soft = CtrlChg::new(200, Velocity::new(20), Channel::default());
+        store.add_soft(soft, false);
+        store.wait_until_saved();
+        assert_eq!(store.model().soft_repo.len(), 1);
+        assert_eq!(store.model().bar_repo.len(), 1); // bar is replenished.
+        
+        store.undo();
+        assert_eq!(store.model().soft_repo.len(), 0);
+        assert_eq!(store.model().bar_repo.len(), 0);
+        
+        store.redo();
+        assert_eq!(store.model().soft_repo.len(), 1);
+        assert_eq!(store.model().bar_repo.len(), 1);
+        assert_eq!(store.model().soft_repo[0], (200u32, soft));
+    }
+    
+    #[test]
+    fn can_undo_tuplize() {
+        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
+        dir.push("project");
+        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
+        
+        // Do nothing for empty.
+        store.tuplize(vec![]);
+        assert_eq!(store.model().soft_repo.len(), 0);
+        assert_eq!(store.model().bar_repo.len(), 0);
+        
+        let note0 = Note::new(
+            100,
+            Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null),
+            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
+            false, false,
+            Velocity::new(64),
+            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
+            Channel::default(),
+        );
+        store.add_note(note0.clone(), false);
+        
+        // Tuplize one note do nothing.
+        store.tuplize(vec![Rc::new(note0.clone())]);
+        store.wait_until_saved();
+        assert_eq!(store.model().note_repo.len(), 1);
+        assert_eq!(store.model().bar_repo.len(), 1); // bar replenished
+        assert_eq!(store.model().note_repo.get(100u32), &vec![Rc::new(note0.clone())]);
+        
+        store.undo(); // this undo adding note.
+        assert_eq!(store.model().note_repo.len(), 0);
+        assert_eq!(store.model().bar_repo.len(), 0);
+        
+        let note1 = Note::new(
+            110,
+            Pitch::new(Solfa::D, Octave::Oct4, SharpFlat::Null),
+            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
+            false, false,
+            Velocity::new(64),
+            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
+            Channel::default(),
+        );
+        
+        let note2 = Note::new(
+            120,
+            Pitch::new(Solfa::E, Octave::Oct4, SharpFlat::Null),
+            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
+            false, false,
+            Velocity::new(64),
+            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
+            Channel::default(),
+        );
+        store.add_note(note0.clone(), false);
+        store.add_note(note1.clone(), false);
+        store.add_note(note2.clone(), false);
+        
+        store.tuplize(vec![Rc::new(note0.clone()), Rc::new(note1.clone()), Rc::new(note2.clone())]);
+        store.wait_until_saved();
+        
+        assert_eq!(store.model().note_repo.len(), 3);
+        assert_eq!(store.model().bar_repo.len(), 1);
+        
+        store.undo();
+        assert_eq!(store.model().note_repo.len(), 3);
+        assert_eq!(store.model().bar_repo.len(), 1);
+        assert_eq!(store.model().note_repo.get(100u32)[0].pitch.solfa(), Solfa::C);
+        assert_eq!(store.model().note_repo.get(110u32)[0].pitch.solfa(), Solfa::D);
+        assert_eq!(store.model().note_repo.get(120u32)[0].pitch.solfa(), Solfa::E);
+        
+        store.redo();
+        assert_eq!(store.model().note_repo.len(), 3);
+        assert_eq!(store.model().bar_repo.len(), 1);
+        
+        assert_eq!(store.model().note_repo.get(100u32)[0].pitch.solfa(), Solfa::C);
+        assert_eq!(store.model().note_repo.get(180u32)[0].pitch.solfa(), Solfa::D);
+        assert_eq!(store.model().note_repo.get(260u32)[0].pitch.solfa(), Solfa::E);
+        
+    }
+
+    #[test]
+    fn can_undo_bulk_remove() {
+        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
+        dir.push("project");
+        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
+        
+        let note0 = Note::new(
+            100,
+            Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null),
+            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
+            false, false,
+            Velocity::new(64),
+            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
+            Channel::default(),
+        );
+        let tempo0 = Tempo::new(200, 200);
+        store.bulk_add(
+            Models {
+                notes: vec![note0.clone()],
+                bars: vec![], tempos: vec![tempo0], dumpers: vec![], softs: vec![]
+            },
+            ModelChangeMetadata::new()
+        );
+        store.wait_until_saved();
+        assert_eq!(store.model().note_repo().len(), 1);
+        assert_eq!(store.model().bar_repo().len(), 1); // bar is replenished.
+        assert_eq!(store.model().tempo_repo().len(), 1);
+
+        store.bulk_remove(
+            Models {
+                notes: vec![note0.clone()],
+                bars: vec![], tempos: vec![tempo0], dumpers: vec![], softs: vec![]
+            },
+            ModelChangeMetadata::new()
+        );
+        store.wait_until_saved();
+
+        assert_eq!(store.model().note_repo().len(), 0);
+        assert_eq!(store.model().bar_repo().len(), 1);
+        assert_eq!(store.model().tempo_repo().len(), 0);
+
+        store.undo();
+
+        assert_eq!(store.model().note_repo().len(), 1);
+        assert_eq!(store.model().bar_repo().len(), 1); // bar is replenished.
+        assert_eq!(store.model().tempo_repo().len(), 1);
+    }
+
+    #[test]
+    fn can_undo_bulk_add() {
+        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
+        dir.push("project");
+        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
+        
+        let note0 = Note::new(
+            100,
+            Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null),
+            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
+            false, false,
+            Velocity::new(64),
+            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
+            Channel::default(),
+        );
+
+        let tempo0 = Tempo::new(200, 200);
+        store.bulk_add(
+            Models {
+                notes: vec![note0.clone()],
+                bars: vec![], tempos: vec![tempo0], dumpers: vec![], softs: vec![]
+            },
+            ModelChangeMetadata::new()
+        );
+        store.wait_until_saved();
+
+        assert_eq!(store.model().note_repo().len(), 1);
+        assert_eq!(store.model().bar_repo().len(), 1); // bar is replenished.
+        assert_eq!(store.model().tempo_repo().len(), 1);
+
+        store.undo();
+
+        assert_eq!(store.model().note_repo().len(), 0);
+        assert_eq!(store.model().bar_repo().len(), 0);
+        assert_eq!(store.model().tempo_repo().len(), 0);
+    }
+
+    #[test]
+    fn can_undo_change() {
+        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
+        dir.push("project");
+        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
+        
+        let note00 = Note::new(
+            100,
+            Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null),
+            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
+            false, false,
+            Velocity::new(64),
+            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
+            Channel::default(),
+        );
+        let note01 = Note::new(
+            101,
+            Pitch::new(Solfa::C, Octave::Oct3, SharpFlat::Null),
+            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
+            false, false,
+            Velocity::new(64),
+            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
+            Channel::default(),
+        );
+        let tempo0 = Tempo::new(200, 200);
+        store.bulk_add(
+            Models {
+                notes: vec![note00.clone(), note01.clone()],
+                bars: vec![], tempos: vec![tempo0], dumpers: vec![], softs: vec![]
+            },
+            ModelChangeMetadata::new()
+        );
+        store.wait_until_saved();
+
+        assert_eq!(store.model().note_repo().len(), 2);
+        assert_eq!(store.model().bar_repo().len(), 1); // bar is replenished.
+        assert_eq!(store.model().tempo_repo().len(), 1);
+
+        let note10 = note00.with_duration_numerator(Numerator::Half);
+        let note11 = note01.with_duration_numerator(Numerator::Half);
+        let change = ModelChanges {
+            notes: vec![(note00.clone(), note10.clone()), (note01.clone(), note11.clone())],
+            bars: vec![], tempos: vec![], dumpers: vec![], softs: vec![]
+        };
+        store.change(change, ModelChangeMetadata::new());
+        store.wait_until_saved();
+
+        assert_eq!(store.model().note_repo().len(), 2);
+        let mut z = store.model().note_repo().iter();
+        assert_eq!(z.next(), Some((&note10.start_tick(), &Rc::new(note10.clone()))));
+        assert_eq!(z.next(), Some((&note11.start_tick(), &Rc::new(note11.clone()))));
+        assert_eq!(z.next(), None);
+        let mut z = store.model().tempo_repo().iter();
+        assert_eq!(z.next(), Some(&(tempo0.start_tick, tempo0)));
+        assert_eq!(z.next(), None);
+
+        store.undo();
+
+        assert_eq!(store.model().note_repo().len(), 2);
+        let mut z = store.model().note_repo().iter();
+        assert_eq!(z.next(), Some((&note00.start_tick(), &Rc::new(note00.clone()))));
+        assert_eq!(z.next(), Some((&note01.start_tick(), &Rc::new(note01.clone()))));
+        assert_eq!(z.next(), None);
+        let mut z = store.model().tempo_repo().iter();
+        assert_eq!(z.next(), Some(&(tempo0.start_tick, tempo0)));
+        assert_eq!(z.next(), None);
+    }
+
+    #[test]
+    fn many_changes() {
+        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
+        dir.push("project");
+        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new().with_undo_limit(10)).unwrap();
+        
+        let note00 = Note::new(
+            100,
+            Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null),
+            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
+            false, false,
+            Velocity::new(64),
+            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
+            Channel::default(),
+        );
+        let note01 = Note::new(
+            101,
+            Pitch::new(Solfa::C, Octave::Oct3, SharpFlat::Null),
+            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
+            false, false,
+            Velocity::new(64),
+            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
+            Channel::default(),
+        );
+        store.add_note(note00, false);
+        store.add_note(note01, false);
+
+        for i in 0..20 {
+            let tempo = Tempo::new(200 + i, 200 + i as u16);
+            store.add_tempo(tempo, false);
+        }
+        assert_eq!(store.model().note_repo().len(), 2);
+        assert_eq!(store.model().tempo_repo().len(), 20);
+        drop(store);
+
+        let store2 = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new().with_undo_limit(10)).unwrap();
+        assert_eq!(store2.model().note_repo().len(), 2);
+        assert_eq!(store2.model().tempo_repo().len(), 20);
+    }
+
+    #[test]
+    fn export_import_history() {
+        let tmp = tempdir().unwrap();
+        let dir = tmp.path().join("project");
+        let mut store: ProjectStore = ProjectStore::open(dir.clone(), undo_store::Options::new()).unwrap();
+        store.add_tempo(Tempo::new(100, 100), false);
+        store.add_tempo(Tempo::new(200, 200), false);
+        store.undo();
+
+        let file = tmp.path().join("history.sqlite");
+        assert_eq!(store.export_history(&file).unwrap(), dir);
+
+        let imported_dir = tmp.path().join("imported");
+        let mut imported = ProjectStore::import_history(&file, &imported_dir, undo_store::Options::new()).unwrap();
+        assert_eq!(imported.model().tempo_repo().len(), 1);
+        assert!(imported.can_redo());
+        imported.redo();
+        assert_eq!(imported.model().tempo_repo().len(), 2);
+        imported.undo();
+        imported.undo();
+        assert_eq!(imported.model().tempo_repo().len(), 0);
+        drop(imported);
+
+        assert!(matches!(
+            ProjectStore::import_history(&file, &imported_dir, undo_store::Options::new()).map_err(|e| e.current_context().to_string()),
+            Err(msg) if msg.starts_with("Project already exists")
+        ));
+    }
+
+    #[test]
+    fn open_readonly() {
+        let tmp = tempdir().unwrap();
+        let dir = tmp.path().join("project");
+        assert!(ProjectStore::open_readonly(&dir).is_err());
+
+        let mut store: ProjectStore = ProjectStore::open(dir.clone(), undo_store::Options::new()).unwrap();
+        store.add_tempo(Tempo::new(100, 100), false);
+        store.wait_until_saved();
+        let viewed = ProjectStore::open_readonly(&dir).unwrap();
+        assert_eq!(viewed.tempo_repo().len(), 1);
+
+        // The editor keeps working on the project.
+        store.add_tempo(Tempo::new(200, 200), false);
+        assert_eq!(store.model().tempo_repo().len(), 2);
+        assert_eq!(viewed.tempo_repo().len(), 1);
+
+        // Viewing while the editor writes sees a whole number of commands.
+        for i in 0..20 {
+            store.add_tempo(Tempo::new(300 + i, 100), false);
+            let len = ProjectStore::open_readonly(&dir).unwrap().tempo_repo().len();
+            assert!((1..=3 + i as usize).contains(&len));
+        }
+        store.wait_until_saved();
+        assert_eq!(ProjectStore::open_readonly(&dir).unwrap().tempo_repo().len(), 22);
+    }
+
+    #[test]
+    fn backup_rotation() {
+        let tmp = tempdir().unwrap();
+        let dir = tmp.path().join("project");
+        let policy = BackupPolicy { count: 2, interval: std::time::Duration::ZERO };
+        let mut store = ProjectStore::open_with_backups(&dir, undo_store::Options::new(), policy).unwrap();
+        assert!(store.list_backups().unwrap().is_empty());
+        store.add_tempo(Tempo::new(100, 100), false);
+        assert!(store.backup_if_due(policy).unwrap());
+        store.add_tempo(Tempo::new(200, 200), false);
+        assert!(store.backup_if_due(policy).unwrap());
+        store.add_tempo(Tempo::new(300, 300), false);
+        assert!(store.backup_if_due(policy).unwrap());
+        assert!(!store.backup_if_due(BackupPolicy { interval: std::time::Duration::from_secs(3600), ..policy }).unwrap());
+        assert_eq!(store.list_backups().unwrap().len(), 2);
+
+        let store = store.restore_backup(1, undo_store::Options::new()).unwrap();
+        assert_eq!(store.model().tempo_repo().len(), 2);
+        assert!(matches!(
+            store.restore_backup(2, undo_store::Options::new()).map_err(|e| e.current_context().to_string()),
+            Err(msg) if msg == "No backup #2"
+        ));
+
+        // Opening keeps the backups to the count of the policy.
+        let store = ProjectStore::open_with_backups(&dir, undo_store::Options::new(), BackupPolicy { count: 1, ..policy }).unwrap();
+        assert_eq!(store.list_backups().unwrap().len(), 1);
+        assert_eq!(store.model().tempo_repo().len(), 2);
+    }
+
+    // Settings are part of the document and are read back from the store.
+    #[test]
+    fn settings_survive_reopen() {
+        let tmp = tempdir().unwrap();
+        let dir = tmp.path().join("project");
+        let mut store = open_project(&dir, undo_store::Options::new()).unwrap();
+        store.set_tempo_scale(PercentU16::from(0.7));
+        store.set_note_defaults(NoteDefaults::default().with_velocity(Velocity::new(100)));
+        store.set_note_length(NoteLengthMode::Gate(PercentU16::from(0.9)), 10);
+        store.set_transposition(Channel::new(1), Transposition::B_FLAT);
+        store.set_slur_legato(true);
+        store.set_dynamics_table(DynamicsTable::new([20, 40, 60, 80, 100, 120].map(Velocity::new)).unwrap());
+        store.set_track_offset(Channel::new(1), Some(OffsetCurve::constant(-10)));
+        store.set_add_policy(AddPolicy { on_duplicate: DuplicatePolicy::Reject });
+        store.set_pitch_shift(Channel::new(1), 12);
+        store.add_skip_region(SkipRegion::new(960..1920));
+        drop(store);
+
+        let mut store = open_project(&dir, undo_store::Options::new()).unwrap();
+        assert_eq!(store.playback_settings().tempo_scale, PercentU16::from(0.7));
+        assert_eq!(store.note_defaults().velocity, Velocity::new(100));
+        assert_eq!(store.playback_settings().note_length, NoteLengthMode::Gate(PercentU16::from(0.9)));
+        assert_eq!(store.playback_settings().legato_overlap, 10);
+        assert_eq!(store.display_settings().transposition(Channel::new(1)), Transposition::B_FLAT);
+        assert!(store.playback_settings().slur_legato);
+        assert_eq!(store.dynamics_table().velocity(Dynamic::PP), Velocity::new(20));
+        assert_eq!(store.track_offsets().get(Channel::new(1)), Some(&OffsetCurve::constant(-10)));
+        assert_eq!(store.add_policy().on_duplicate, DuplicatePolicy::Reject);
+        assert_eq!(store.pitch_shifts().get(Channel::new(1)), 12);
+        assert_eq!(store.skip_regions(), &[SkipRegion::new(960..1920)]);
+
+        // The tempo scale, the note defaults and the track offsets are out of undo history.
+        while store.can_undo() { store.undo(); }
+        assert!(!store.playback_settings().slur_legato);
+        assert_eq!(store.playback_settings().tempo_scale, PercentU16::from(0.7));
+        assert_eq!(store.note_defaults().velocity, Velocity::new(100));
+        assert_eq!(store.track_offsets().get(Channel::new(1)), Some(&OffsetCurve::constant(-10)));
+    }
+
+    #[test]
+    fn note_tag_survives_reopen() {
+        let tmp = tempdir().unwrap();
+        let dir = tmp.path().join("project");
+        let mut store: ProjectStore = ProjectStore::open(dir.clone(), undo_store::Options::new()).unwrap();
+        store.add_note(Note { base_start_tick: 240, ..Default::default() }, false);
+        let tag = NoteTag::new(SmallString::new("motif").unwrap(), Some(0x00ff00));
+        let notes: Vec<Rc<Note>> = store.note_repo().iter().map(|(_, n)| n.clone()).collect();
+        store.set_note_tag(&notes, Some(tag));
+        drop(store);
+
+        let store: ProjectStore = ProjectStore::open(dir.clone(), undo_store::Options::new()).unwrap();
+        assert_eq!(store.note_repo().iter().next().unwrap().1.tag, Some(tag));
     }
-    
+
     #[test]
-    fn undo_note_addition() {
-        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
-        dir.push("project");
+    fn tuplet_survives_reopen() {
+        let tmp = tempdir().unwrap();
+        let dir = tmp.path().join("project");
         let mut store: ProjectStore = ProjectStore::open(dir.clone(), undo_store::Options::new()).unwrap();
+        let quintuplet = Duration::tuplet(Numerator::N16th, Dots::ZERO, 5, 4).unwrap();
+        store.add_note(Note { base_start_tick: 240, duration: quintuplet, ..Default::default() }, false);
+        drop(store);
 
-        let note = Note::new(
-            100,
-            Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null),
+        let store: ProjectStore = ProjectStore::open(dir.clone(), undo_store::Options::new()).unwrap();
+        let duration = store.note_repo().iter().next().unwrap().1.duration;
+        assert_eq!(duration, quintuplet);
+        assert_eq!(duration.normal.value(), 4);
+    }
+
+    #[test]
+    fn slur_follows_notes_after_reopen() {
+        let tmp = tempdir().unwrap();
+        let dir = tmp.path().join("project");
+        let mut store: ProjectStore = ProjectStore::open(dir.clone(), undo_store::Options::new()).unwrap();
+        let note = |tick: u32| Note::new(
+            tick, Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null),
+            Duration::new(Numerator::Quarter, Denominator::from_value(2).unwrap(), Dots::ZERO),
+            false, false, Velocity::new(64), Trimmer::ZERO, RateTrimmer::new(1.0, 1.0, 1.0, 1.0), Trimmer::ZERO, Channel::default()
+        );
+        store.add_note(note(0), false);
+        store.add_note(note(240), false);
+        store.add_slur(Slur::new(note(0), note(240)));
+        let moved = Note { base_start_tick: 480, ..note(240) };
+        let mut changes = ModelChanges::empty();
+        changes.notes.push((note(240), moved.clone()));
+        store.change(changes, ModelChangeMetadata::new());
+        drop(store);
+
+        let store: ProjectStore = ProjectStore::open(dir.clone(), undo_store::Options::new()).unwrap();
+        assert_eq!(store.slurs()[0].end_note, moved);
+    }
+}
+
+#[cfg(test)]
+mod memory_tests {
+    use crate::{project::{Project, AutoPedalPolicy, PasteFitOptions, PasteOptions, PasteConflict, BarMerge, TrimmerNormalizeMode, DEFAULT_MEMORY_UNDO_LIMIT, memory_project_store, MemoryProjectStore, ModelChangeMetadata, ProjectImpl, Beat, BarContentPolicy, FitDurationError, ActivityBucket, ScoreSetup, AddPolicy, DuplicatePolicy, TieRemovalPolicy}, comment::{Comment, CommentAnchor}, score_position::ScorePosition, template::Template, note::{Note, NoteTag}, small_string::SmallString, scale::{Scale, ScaleMode}, solfa::Solfa, octave::Octave, sharp_flat::SharpFlat, pitch::Pitch, duration::{Duration, Numerator, Denominator, Dots}, velocity::{Velocity, Dynamic, DynamicsTable}, trimmer::{Trimmer, RateTrimmer}, tempo::{Tempo, TempoValue}, rhythm::{Rhythm, Accent}, channel::Channel, bar::{Bar, BarAttribute, BarMergeWarning, Repeat, RepeatSet}, repeat::{Chunk, RenderRegionError, RepeatDiagnostic}, repeat_set, ctrl_chg::{CtrlChg, CtrlChgKind}, models::{Models, ModelChanges}, key::Key, transposition::Transposition, location::Location, grid::Grid, percent::PercentU16, slur::Slur, rehearsal::{RehearsalLabel, RehearsalMark}, instrument::Instrument};
+    use serdo::undo_store::UndoStore;
+    use std::rc::Rc;
+
+    fn note(tick: u32) -> Note {
+        note_of(tick, Solfa::C)
+    }
+
+    fn note_of(tick: u32, solfa: Solfa) -> Note {
+        Note::new(
+            tick,
+            Pitch::new(solfa, Octave::Oct4, SharpFlat::Null),
             Duration::new(Numerator::Quarter, Denominator::from_value(2).unwrap(), Dots::ZERO),
             false,
             false,
@@ -975,861 +4862,1178 @@ mod tests {
             RateTrimmer::ONE,
             Trimmer::ZERO,
             Channel::default(),
-        );
-        
-        store.add_note(note, false);
-        assert_eq!(store.model().note_repo().len(), 1);
-        
+        )
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(note(100), false);
+        store.add_tempo(Tempo { start_tick: 0, value: TempoValue::new(100), marking: None }, false);
+        assert_eq!(store.note_repo().len(), 1);
+        assert_eq!(store.tempo_repo().len(), 1);
+
         store.undo();
-        assert_eq!(store.model().note_repo().len(), 0);
+        assert_eq!(store.note_repo().len(), 1);
+        assert_eq!(store.tempo_repo().len(), 0);
+
+        store.undo();
+        assert_eq!(store.note_repo().len(), 0);
+        assert!(!store.can_undo());
+
+        store.redo();
+        store.redo();
+        assert_eq!(store.note_repo().len(), 1);
+        assert_eq!(store.tempo_repo().len(), 1);
+        assert!(!store.can_redo());
     }
-    
+
     #[test]
-    fn undo_bar_addition() {
-        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
-        dir.push("project");
-        let mut store: ProjectStore = ProjectStore::open(dir.clone(), undo_store::Options::new()).unwrap();
+    fn set_rhythm() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        let default_rhythm = store.rhythm();
+        store.set_rhythm(Rhythm::new(3, 4));
+        assert_eq!(store.rhythm(), Rhythm::new(3, 4));
+        store.undo();
+        assert_eq!(store.rhythm(), default_rhythm);
+    }
 
-        let bar = Bar::new(
-            100,
-            None,
-            None,
-            RepeatSet::EMPTY,
-        );
-        store.add_bar(bar, false);
-        assert_eq!(store.model().bar_repo().len(), 1);
+    #[test]
+    fn zero_limit() {
+        let mut store = memory_project_store(0);
+        store.add_note(note(100), false);
+        store.add_note(note(200), false);
         store.undo();
-        assert_eq!(store.model().bar_repo().len(), 0);
+        assert_eq!(store.note_repo().len(), 1);
+        assert!(!store.can_undo());
     }
-    
+
     #[test]
-    fn location_to_tick() {
-        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
-        dir.push("project");
-        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
+    fn ramp_ctrl() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_dumper(CtrlChg::new(50, Velocity::new(1), Channel::default()), false);
+        store.add_dumper(CtrlChg::new(500, Velocity::new(2), Channel::default()), false);
 
-        assert_eq!(store.model().location_to_tick(Location::new(0, 0)).unwrap(), 0);
-        assert_eq!(store.model().location_to_tick(Location::new(0, 1)).unwrap(), 1);
-        assert_eq!(store.model().location_to_tick(Location::new(0, u32::MAX as usize)).unwrap(), u32::MAX);
-        assert_eq!(store.model().location_to_tick(Location::new(0, u32::MAX as usize + 1)), Err(LocationError::Overflow));
-        assert_eq!(
-            store.model().location_to_tick(Location::new(1, 1)).unwrap(),
-            store.model().rhythm().tick_len() + 1
-        );
-        
-        let bar0 = Bar::new(
-            100, None, None, RepeatSet::EMPTY
-        );
-        store.add_bar(bar0, false);
-        
-        assert_eq!(store.model().location_to_tick(Location::new(0, 0)).unwrap(), 0);
-        assert_eq!(store.model().location_to_tick(Location::new(0, 1)).unwrap(), 1);
-        assert_eq!(store.model().location_to_tick(Location::new(1, 1)).unwrap(), 101);
-        assert_eq!(store.model().location_to_tick(Location::new(1, (u32::MAX as usize) - 100)).unwrap(), u32::MAX);
-        assert_eq!(store.model().location_to_tick(Location::new(1, (u32::MAX as usize) - 99)), Err(LocationError::Overflow));
+        store.ramp_ctrl(CtrlChgKind::Dumper, 0..=120, Velocity::new(0), Velocity::new(120), 60, false);
+        let ticks: Vec<(u32, u8)> = store.dumper_repo().iter().map(|(t, c)| (*t, c.velocity.as_u8())).collect();
+        assert_eq!(ticks, vec![(0, 0), (50, 1), (60, 60), (120, 120), (500, 2)]);
 
-        // 0   bar0(t=100)
-        //     |
-        assert_eq!(store.model().location_to_tick(Location::new(2, 1)).unwrap(), 100 + store.model().rhythm().tick_len() + 1);
-        
-        let bar1 = Bar::new(
-            1000, None, None, RepeatSet::EMPTY
-        );
-        store.add_bar(bar1, false);
-        
-        assert_eq!(store.model().location_to_tick(Location::new(0, 0)).unwrap(), 0);
-        assert_eq!(store.model().location_to_tick(Location::new(0, 1)).unwrap(), 1);
-        assert_eq!(store.model().location_to_tick(Location::new(1, 1)).unwrap(), 101);
-        assert_eq!(store.model().location_to_tick(Location::new(2, 0)).unwrap(), 1000);
+        store.undo();
+        store.ramp_ctrl(CtrlChgKind::Dumper, 0..=120, Velocity::new(0), Velocity::new(120), 60, true);
+        let ticks: Vec<(u32, u8)> = store.dumper_repo().iter().map(|(t, c)| (*t, c.velocity.as_u8())).collect();
+        assert_eq!(ticks, vec![(0, 0), (60, 60), (120, 120), (500, 2)]);
+        assert_eq!(store.soft_repo().len(), 0);
 
-        let bar2 = Bar::new(
-            2000, Some(Rhythm::new(2, 4)), None, RepeatSet::EMPTY
-        );
-        store.add_bar(bar2, false);
+        store.undo();
+        let ticks: Vec<(u32, u8)> = store.dumper_repo().iter().map(|(t, c)| (*t, c.velocity.as_u8())).collect();
+        assert_eq!(ticks, vec![(50, 1), (500, 2)]);
+    }
 
-        let bar3 = Bar::new(
-            3000, None, None, RepeatSet::EMPTY
-        );
-        store.add_bar(bar3, false);
+    fn dumpers(store: &impl Project) -> Vec<(u32, u8)> {
+        store.dumper_repo().iter().map(|(t, c)| (*t, c.velocity.as_u8())).collect()
+    }
 
-        // 0   bar0(t=100)  bar1(t=1000) bar2(t=2000 r=2/4) bar3(t = 3000)
-        //     |            |            |                  |
-        assert_eq!(store.model().location_to_tick(Location::new(4, 1)).unwrap(), 3001);
-        assert_eq!(store.model().location_to_tick(Location::new(5, 1)).unwrap(), 3000 + 480 + 1);
+    #[test]
+    fn auto_pedal_per_bar() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_bar(Bar::new(960, None, None, RepeatSet::EMPTY), false);
+        store.add_bar(Bar::new(1920, None, None, RepeatSet::EMPTY), false);
+        store.add_dumper(CtrlChg::new(100, Velocity::new(64), Channel::default()), false);
 
+        store.auto_pedal(0..=1920, AutoPedalPolicy::PerBar);
+        assert_eq!(dumpers(&store), vec![(0, 127), (960, 0), (975, 127), (1920, 0)]);
+
+        store.undo();
+        assert_eq!(dumpers(&store), vec![(100, 64)]);
     }
-    
+
     #[test]
-    fn tick_to_location() {
-        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
-        dir.push("project");
-        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
+    fn auto_pedal_per_harmony_change() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(note_of(0, Solfa::C), false);
+        store.add_note(note_of(0, Solfa::E), false);
+        store.add_note(note_of(240, Solfa::E), false);
+        store.add_note(note_of(480, Solfa::F), false);
+        store.add_note(note_of(480, Solfa::A), false);
 
-        assert_eq!(store.model().tick_to_location(0), Location::new(0, 0));
-        assert_eq!(store.model().tick_to_location(100), Location::new(0, 100));
-        assert_eq!(store.model().tick_to_location(u32::MAX), Location::new(0, u32::MAX as usize));
-        
-        let bar = Bar::new(
-            100, None, None, RepeatSet::EMPTY
-        );
-        store.add_bar(bar, false);
-        
-        assert_eq!(store.model().tick_to_location(0), Location::new(0, 0));
-        assert_eq!(store.model().tick_to_location(99), Location::new(0, 99));
-        assert_eq!(store.model().tick_to_location(100), Location::new(1, 0));
-        assert_eq!(store.model().tick_to_location(u32::MAX), Location::new(1, (u32::MAX - 100) as usize));
-        
-        let bar = Bar::new(
-            1000, None, None, RepeatSet::EMPTY,
-        );
-        store.add_bar(bar, false);
-        
-        assert_eq!(store.model().tick_to_location(0), Location::new(0, 0));
-        assert_eq!(store.model().tick_to_location(99), Location::new(0, 99));
-        assert_eq!(store.model().tick_to_location(999), Location::new(1, 899));
-        assert_eq!(store.model().tick_to_location(1000), Location::new(2, 0));
-        assert_eq!(store.model().tick_to_location(u32::MAX), Location::new(2, (u32::MAX - 1000) as usize));
+        store.auto_pedal(0..=960, AutoPedalPolicy::PerHarmonyChange);
+        assert_eq!(dumpers(&store), vec![(0, 127), (480, 0), (495, 127), (960, 0)]);
     }
-    
+
     #[test]
-    fn rhythm_at() {
-        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
-        dir.push("project");
-        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
+    fn render_range() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        // 0    100   200    300   400
+        //   A  |: B  |  C  :|  D  |
+        store.add_bar(Bar::new(100, None, None, repeat_set!(Repeat::Start)), false);
+        store.add_bar(Bar::new(200, None, None, RepeatSet::EMPTY), false);
+        store.add_bar(Bar::new(300, None, None, repeat_set!(Repeat::End)), false);
+        store.add_bar(Bar::new(400, None, None, RepeatSet::EMPTY), false);
+        let proj = store.model();
 
-        store.set_rhythm(Rhythm::new(6, 8));
-        assert_eq!(store.model().last_bar(), None);
-        
-        assert_eq!(store.model().rhythm_at(500), Rhythm::new(6, 8));
-        assert_eq!(store.model().rhythm_at(0), Rhythm::new(6, 8));
-        
-        let bar0 = Bar::new(100, None, None, RepeatSet::EMPTY);
-        store.add_bar(bar0, false);
-        assert_eq!(store.model().last_bar().map(|(_, bar)| bar), Some(bar0));
-        
-        assert_eq!(store.model().rhythm_at(0), Rhythm::new(6, 8));
-        assert_eq!(store.model().rhythm_at(99), Rhythm::new(6, 8));
-        assert_eq!(store.model().rhythm_at(100), Rhythm::new(6, 8));
-        assert_eq!(store.model().rhythm_at(101), Rhythm::new(6, 8));
-        
-        let bar1 = Bar::new(
-            200, Some(Rhythm::new(3, 4)), None, RepeatSet::EMPTY
-        );
-        store.add_bar(bar1, false);
-        assert_eq!(store.model().last_bar().map(|(_, bar)| bar), Some(bar1));
-        
-        let bar2 = Bar::new(300, None, None, RepeatSet::EMPTY);
-        store.add_bar(bar2, false);
-        assert_eq!(store.model().last_bar().map(|(_, bar)| bar), Some(bar2));
-        
-        let bar3 = Bar::new(
-            400, Some(Rhythm::new(4, 4)), None, RepeatSet::EMPTY
-        );
-        store.add_bar(bar3, false);
-        assert_eq!(store.model().last_bar().map(|(_, bar)| bar), Some(bar3));
-        
-        assert_eq!(store.model().rhythm_at(0), Rhythm::new(6, 8));
-        assert_eq!(store.model().rhythm_at(99), Rhythm::new(6, 8));
-        assert_eq!(store.model().rhythm_at(100), Rhythm::new(6, 8));
-        assert_eq!(store.model().rhythm_at(101), Rhythm::new(6, 8));
-        assert_eq!(store.model().rhythm_at(199), Rhythm::new(6, 8));
-        assert_eq!(store.model().rhythm_at(200), Rhythm::new(3, 4));
-        assert_eq!(store.model().rhythm_at(201), Rhythm::new(3, 4));
-        assert_eq!(store.model().rhythm_at(299), Rhythm::new(3, 4));
-        assert_eq!(store.model().rhythm_at(300), Rhythm::new(3, 4));
-        assert_eq!(store.model().rhythm_at(301), Rhythm::new(3, 4));
-        assert_eq!(store.model().rhythm_at(399), Rhythm::new(3, 4));
-        assert_eq!(store.model().rhythm_at(400), Rhythm::new(4, 4));
-        assert_eq!(store.model().rhythm_at(401), Rhythm::new(4, 4));
+        assert_eq!(proj.render_range(1..4, false).unwrap(), vec![Chunk::new(100, 400)]);
+        assert_eq!(proj.render_range(1..4, true).unwrap(), vec![Chunk::new(100, 300), Chunk::new(100, 400)]);
+        assert_eq!(proj.render_range(0..3, true).unwrap(), vec![Chunk::new(0, 300), Chunk::new(100, 300)]);
+        // Repeat starts before the range. Do not play C twice.
+        assert_eq!(proj.render_range(2..4, true).unwrap(), vec![Chunk::new(200, 400)]);
+        assert_eq!(proj.render_range(3..3, true).unwrap(), vec![]);
     }
-    
+
     #[test]
-    fn note_max_tick_loc() {
-        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
-        dir.push("project");
-        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
+    fn note_defaults() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(note(0), false);
+        let defaults = store.note_defaults().with_velocity(Velocity::new(100));
+        store.set_note_defaults(defaults);
+        assert_eq!(store.note_defaults().velocity, Velocity::new(100));
 
-        let pitch = Pitch::new(Solfa::C, Octave::Oct0, SharpFlat::Null);
-        assert_eq!(store.model().note_max_end_tick(), None);
-        
-        let note0 = Note::new( // end tick: 100 + 240 * 1.5 = 460
-            100, pitch,
-            Duration::new(Numerator::Quarter, Denominator::from_value(2).unwrap(), Dots::ONE),
-            false, false, Velocity::new(10), Trimmer::ZERO,
-            RateTrimmer::new(1.0, 1.0, 1.0, 1.0),
-            Trimmer::ZERO,
-            Channel::default(),
-        );
-        
-        let end_tick0 = note0.base_start_tick + note0.tick_len();
-        store.add_note(note0, false);
-        assert_eq!(store.model().note_max_end_tick(), Some(end_tick0));
-        
-        let note1 = Note::new( // end tick: 100 + 960 * 1.5 = 1540
-            100, pitch,
-            Duration::new(Numerator::Whole, Denominator::from_value(2).unwrap(), Dots::ONE),
-            false, false, Velocity::new(10), Trimmer::ZERO,
-            RateTrimmer::new(1.0, 1.0, 1.0, 1.0),
-            Trimmer::ZERO,
-            Channel::default(),
-        );
-        
-        store.add_note(note1.clone(), false);
-        assert_eq!(store.model().note_max_end_tick(), Some(note1.base_start_tick + note1.tick_len()));
-        
-        let _ = store.add_note(
-            Note::new( // end tick: 200 + 120 * 2.5 = 440
-                200, pitch,
-                Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
-                false, false, Velocity::new(10), Trimmer::ZERO,
-                RateTrimmer::new(1.0, 1.0, 1.0, 2.0),
-                Trimmer::ZERO,
-                Channel::default(),
-            ), false
+        store.undo();
+        assert_eq!(store.note_repo().len(), 0);
+        assert_eq!(store.note_defaults().velocity, Velocity::new(100));
+    }
+
+    fn note_ticks(store: &impl Project) -> Vec<(u32, Solfa)> {
+        let mut ticks: Vec<(u32, Solfa)> = store.note_repo().iter().map(|(t, n)| (*t, n.pitch.solfa())).collect();
+        ticks.sort_by_key(|(t, s)| (*t, s.score_offset()));
+        ticks
+    }
+
+    #[test]
+    fn clip_by_copy() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        let riff = Models::empty().with_notes(&[Rc::new(note_of(480, Solfa::C)), Rc::new(note_of(720, Solfa::E))]);
+        store.save_clip("riff", &riff);
+        store.instantiate_clip("riff", 960, false);
+        assert_eq!(note_ticks(&store), vec![(960, Solfa::C), (1200, Solfa::E)]);
+        assert!(store.clips().instances("riff").is_empty());
+
+        store.save_clip("riff", &Models::empty().with_notes(&[Rc::new(note_of(0, Solfa::G))]));
+        assert_eq!(note_ticks(&store), vec![(960, Solfa::C), (1200, Solfa::E)]);
+
+        store.undo();
+        store.undo();
+        assert_eq!(store.note_repo().len(), 0);
+        store.undo();
+        assert!(store.clips().is_empty());
+    }
+
+    #[test]
+    fn clip_by_ref() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        let riff = Models::empty().with_notes(&[Rc::new(note_of(0, Solfa::C))])
+            .with_dumpers(vec![CtrlChg::new(0, Velocity::new(127), Channel::default())]);
+        store.save_clip("riff", &riff);
+        store.instantiate_clip("riff", 0, true);
+        store.instantiate_clip("riff", 960, true);
+        store.add_note(note_of(240, Solfa::A), false);
+        assert_eq!(note_ticks(&store), vec![(0, Solfa::C), (240, Solfa::A), (960, Solfa::C)]);
+
+        let updated = Models::empty().with_notes(&[Rc::new(note_of(0, Solfa::D)), Rc::new(note_of(240, Solfa::F))]);
+        store.save_clip("riff", &updated);
+        assert_eq!(
+            note_ticks(&store),
+            vec![(0, Solfa::D), (240, Solfa::F), (240, Solfa::A), (960, Solfa::D), (1200, Solfa::F)]
         );
-        
-        assert_eq!(store.model().note_max_end_tick(), Some(note1.base_start_tick + note1.tick_len()));
+        assert_eq!(dumpers(&store), vec![]);
+
+        store.undo();
+        assert_eq!(note_ticks(&store), vec![(0, Solfa::C), (240, Solfa::A), (960, Solfa::C)]);
+        assert_eq!(dumpers(&store), vec![(0, 127), (960, 127)]);
+
+        store.remove_clip("riff");
+        store.save_clip("riff", &updated);
+        assert_eq!(note_ticks(&store), vec![(0, Solfa::C), (240, Solfa::A), (960, Solfa::C)]);
+        store.undo();
+        store.undo();
+        assert_eq!(store.clips().instances("riff").len(), 2);
     }
-    
+
     #[test]
-    fn replenish_bars() {
-        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
-        dir.push("project");
-        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
+    fn display_pitch() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.set_key(Key::FLAT_2);
+        let b = Note { channel: Channel::new(1), ..note_of(0, Solfa::B) };
+        let e = note_of(0, Solfa::E);
+        store.add_note(b.clone(), false);
+        store.set_transposition(Channel::new(1), Transposition::B_FLAT);
 
-        let pitch = Pitch::new(Solfa::C, Octave::Oct0, SharpFlat::Null);
-        assert_eq!(store.model().bar_repo().len(), 0);
-        store.add_note(
-            Note::new( // end tick: 100 + 240 * 1.5 = 460
-                100, pitch,
-                Duration::new(Numerator::Quarter, Denominator::from_value(2).unwrap(), Dots::ONE),
-                false, false, Velocity::new(10), Trimmer::ZERO,
-                RateTrimmer::new(1.0, 1.0, 1.0, 1.0),
-                Trimmer::ZERO,
-                Channel::default(),
-            ), false
-        );
-        assert_eq!(store.model().bar_repo().len(), 1);
-        assert_eq!(store.model().bar_repo().peek_last().unwrap().0, 960);
-        
-        store.add_note(
-            Note::new(
-                0, pitch,
-                Duration::new(Numerator::Whole, Denominator::from_value(2).unwrap(), Dots::ZERO),
-                false, false, Velocity::new(10), Trimmer::ZERO,
-                RateTrimmer::new(1.0, 1.0, 1.0, 1.0),
-                Trimmer::ZERO,
-                Channel::default(),
-            ), false
-        );
+        assert_eq!(store.sounding_pitch(&b), Pitch::new(Solfa::B, Octave::Oct4, SharpFlat::Flat));
+        assert_eq!(store.display_pitch(&b), Pitch::new(Solfa::C, Octave::Oct5, SharpFlat::Natural));
+        assert_eq!(store.display_pitch(&e), e.pitch);
 
-        assert_eq!(store.model().bar_repo().len(), 1);
-        assert_eq!(store.model().bar_repo().peek_last().unwrap().0, 960);
-        
-        store.add_note(
-            Note::new(
-                960, pitch,
-                Duration::new(Numerator::Whole, Denominator::from_value(2).unwrap(), Dots::ONE),
-                false, false, Velocity::new(10), Trimmer::ZERO,
-                RateTrimmer::new(1.0, 1.0, 1.0, 1.0),
-                Trimmer::ZERO,
-                Channel::default(),
-            ), false
-        );
+        store.set_concert_pitch(true);
+        assert_eq!(store.display_pitch(&b), b.pitch);
+        store.undo();
+        assert_eq!(store.display_settings().transposition(Channel::new(1)), Transposition::B_FLAT);
+        assert!(!store.display_settings().concert_pitch);
+        store.undo();
+        assert_eq!(store.display_settings().transposition(Channel::new(1)), Transposition::NONE);
+        assert_eq!(store.note_repo().len(), 1);
+    }
 
-        assert_eq!(store.model().bar_repo().len(), 3);
-        assert_eq!(store.model().bar_repo().peek_last().unwrap().0, 960 * 3);
-        
-        store.add_tempo(Tempo::new(960*3, 200), false);
+    #[test]
+    fn slur_follows_notes() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(note(0), false);
+        store.add_note(note(240), false);
+        store.add_slur(Slur::new(note(0), note(240)));
 
-        assert_eq!(store.model().bar_repo().len(), 3);
-        assert_eq!(store.model().bar_repo().peek_last().unwrap().0, 960 * 3);
-        
-        store.add_tempo(Tempo::new(960 * 3 + 1, 200), false);
+        let louder = Note { base_velocity: Velocity::new(100), ..note(240) };
+        let mut changes = ModelChanges::empty();
+        changes.notes.push((note(240), louder.clone()));
+        store.change(changes, ModelChangeMetadata::new());
+        assert_eq!(store.slurs()[0].end_note, louder);
+        assert!(store.model().slur_over(&louder).is_some());
 
-        assert_eq!(store.model().bar_repo().len(), 4);
-        assert_eq!(store.model().bar_repo().peek_last().unwrap().0, 960 * 4);
-        
-        store.add_dumper(CtrlChg::new(960 * 4, Velocity::new(20), Channel::default()), false);
+        store.undo();
+        assert_eq!(store.slurs()[0].end_note, note(240));
+        store.redo();
+        assert_eq!(store.slurs()[0].end_note, louder);
 
-        assert_eq!(store.model().bar_repo().len(), 4);
-        assert_eq!(store.model().bar_repo().peek_last().unwrap().0, 960 * 4);
-        
-        store.add_dumper(CtrlChg::new(960 * 4 + 1, Velocity::new(20), Channel::default()), false);
+        // A change that would turn the slur around leaves it as is.
+        let mut changes = ModelChanges::empty();
+        changes.notes.push((note(0), note(480)));
+        store.change(changes, ModelChangeMetadata::new());
+        assert_eq!(store.slurs()[0].start_note, note(0));
+    }
 
-        assert_eq!(store.model().bar_repo().len(), 5);
-        assert_eq!(store.model().bar_repo().peek_last().unwrap().0, 960 * 5);
-        
-        store.add_soft(CtrlChg::new(960 * 5, Velocity::new(20), Channel::default()), false);
+    #[test]
+    fn split_note() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(note(0), false);
+        let n = store.note_repo().iter().next().unwrap().1.clone();
+        assert!(store.split_note(n.clone(), 100).is_err());
+        store.split_note(n.clone(), 120).unwrap();
 
-        assert_eq!(store.model().bar_repo().len(), 5);
-        assert_eq!(store.model().bar_repo().peek_last().unwrap().0, 960 * 5);
-        
-        store.add_dumper(CtrlChg::new(960 * 5 + 1, Velocity::new(20), Channel::default()), false);
+        let parts: Vec<(u32, u32, bool, bool)> = store.note_repo().iter().map(|(t, n)| (*t, n.tick_len(), n.tie, n.tied)).collect();
+        assert_eq!(parts, vec![(0, 120, true, false), (120, 120, false, true)]);
 
-        assert_eq!(store.model().bar_repo().len(), 6);
-        assert_eq!(store.model().bar_repo().peek_last().unwrap().0, 960 * 6);
+        store.undo();
+        assert_eq!(store.note_repo().len(), 1);
+        assert_eq!(*store.note_repo().iter().next().unwrap().1, n);
     }
-    
+
     #[test]
-    fn tuplize() {
-        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
-        dir.push("project");
-        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
+    fn repeat_diagnostics() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_bar(Bar::new(960, None, None, repeat_set!(Repeat::Start)), false);
+        assert_eq!(store.repeat_diagnostics(), vec![(960, RepeatDiagnostic::Error(RenderRegionError::NoRepeatEnd { tick: 960 }))]);
+
+        store.add_bar(Bar::new(1920, None, None, repeat_set!(Repeat::End)), false);
+        assert!(store.repeat_diagnostics().is_empty());
 
-        let note0 = Note::new(
-            100,
-            Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null),
-            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
-            false, false,
-            Velocity::new(64),
-            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
-            Channel::default(),
-        );
-        store.add_note(note0.clone(), false);
-        
-        let note1 = Note::new(
-            120,
-            Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null),
-            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
-            false, false,
-            Velocity::new(64),
-            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
-            Channel::default(),
-        );
-        store.add_note(note1.clone(), false);
-        
-        let note2 = Note::new(
-            150,
-            Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null),
-            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
-            false, false,
-            Velocity::new(64),
-            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
-            Channel::default(),
-        );
-        store.add_note(note2.clone(), false);
-        assert_eq!(store.model().note_repo().len(), 3);
-        
-        store.tuplize(vec![Rc::new(note0), Rc::new(note1), Rc::new(note2)]);
-        assert_eq!(store.model().note_repo().len(), 3);
-        
-        let mut z = store.model().note_repo().iter();
-        let (tick, note) = z.next().unwrap();
-        assert_eq!(*tick, 100);
-        assert_eq!(note.duration, Duration::new(Numerator::N8th, Denominator::from_value(3).unwrap(), Dots::ZERO));
-        
-        let (tick, note) = z.next().unwrap();
-        assert_eq!(*tick, 100 + 80);
-        assert_eq!(note.duration, Duration::new(Numerator::N8th, Denominator::from_value(3).unwrap(), Dots::ZERO));
-        
-        let (tick, note) = z.next().unwrap();
-        assert_eq!(*tick, 100 + 80 * 2);
-        assert_eq!(note.duration, Duration::new(Numerator::N8th, Denominator::from_value(3).unwrap(), Dots::ZERO));
-        
         store.undo();
-        assert_eq!(store.model().note_repo().len(), 3);
-        
-        let mut z = store.model().note_repo().iter();
-        let (tick, note) = z.next().unwrap();
-        assert_eq!(*tick, 100);
-        assert_eq!(note.duration, Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO));
-        
-        let (tick, note) = z.next().unwrap();
-        assert_eq!(*tick, 120);
-        assert_eq!(note.duration, Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO));
-        
-        let (tick, note) = z.next().unwrap();
-        assert_eq!(*tick, 150);
-        assert_eq!(note.duration, Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO));
+        assert_eq!(store.repeat_diagnostics().len(), 1);
     }
-    
+
     #[test]
-    fn can_serialize_project() {
-        let mut proj = ProjectImpl::default();
-        proj.key = Key::FLAT_2;
-        proj.rhythm = Rhythm::new(3, 4);
-        
-        let note0 = Rc::new(Note::new(
-            100,
-            Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null),
-            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
-            false, false,
-            Velocity::new(64),
-            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
-            Channel::default(),
-        ));
-        proj.note_repo.add(note0.start_tick(), note0.clone(), ModelChangeMetadata::new());
+    fn velocity_aggregation() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        for (tick, velocity) in [(0, 10), (240, 20), (480, 127), (960, 64)] {
+            store.add_note(Note { base_velocity: Velocity::new(velocity), ..note(tick) }, false);
+        }
+        let histogram = store.model().velocity_histogram(0..960, 32);
+        assert_eq!(histogram, vec![2, 0, 0, 1]);
+        assert_eq!(store.model().velocity_histogram(0..u32::MAX, 0).iter().sum::<usize>(), 4);
 
-        let ser = bincode::serialize(&proj).unwrap();
-        
-        let des: ProjectImpl = bincode::deserialize(&ser).unwrap();
-        
-        assert_eq!(proj.key, des.key);
-        assert_eq!(proj.rhythm, des.rhythm);
-        assert_eq!(des.note_repo.len(), 1);
-        assert_eq!(*des.note_repo.iter().map(|(_, n)| n).next().unwrap(), note0);
+        let bands = store.model().notes_by_velocity_band(0..u32::MAX);
+        let counts: Vec<usize> = bands.iter().map(|b| b.len()).collect();
+        assert_eq!(counts, vec![1, 1, 0, 0, 1, 0, 0, 1]);
     }
-    
-    use tempfile::tempdir;
-    use super::Project;
-    
+
     #[test]
-    fn can_undo_set_rhythm() {
-        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
-        dir.push("project");
-        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
-        
-        store.set_rhythm(Rhythm::new(12, 8));
-        store.wait_until_saved();
-        assert_eq!(store.model().rhythm(), Rhythm::new(12, 8));
-        
-        store.set_rhythm(Rhythm::new(12, 4));
-        store.wait_until_saved();
-        assert_eq!(store.model().rhythm(), Rhythm::new(12, 4));
-        
+    fn models_in() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(note_of(0, Solfa::C), false);
+        store.add_note(note_of(240, Solfa::G), false);
+        store.add_note(note_of(480, Solfa::C), false);
+        store.add_note(note_of(1200, Solfa::C), false);
+        store.add_bar(Bar::new(960, None, None, RepeatSet::EMPTY), false);
+        store.add_dumper(CtrlChg::new(300, Velocity::new(127), Channel::default()), false);
+
+        let view = store.models_in(300..960, 72..=76);
+        let ticks: Vec<u32> = view.notes.iter().map(|n| n.start_tick()).collect();
+        assert_eq!(ticks, vec![480]);
+        assert!(view.bars.is_empty());
+        assert_eq!(view.dumpers.len(), 1);
+
+        let view = store.models_in(300..961, 0..=127);
+        let ticks: Vec<u32> = view.notes.iter().map(|n| n.start_tick()).collect();
+        assert_eq!(ticks, vec![240, 480]);
+        assert_eq!(view.to_models().bars.len(), 1);
+        assert!(store.models_in(2000..3000, 0..=127).is_empty());
+    }
+
+    #[test]
+    fn take_dirty_regions() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(note(0), false);
+        store.add_note(note(240), false);
+        store.add_note(note(960), false);
+        store.add_tempo(Tempo { start_tick: 480, value: TempoValue::new(100), marking: None }, false);
+        store.clear_model_events();
+
+        store.bulk_remove(Models::empty().with_notes(&[Rc::new(note(0)), Rc::new(note(240))]), ModelChangeMetadata::new());
+        let regions = store.take_dirty_regions();
+        assert_eq!(regions.notes, vec![0..480]);
+        assert!(regions.tempos.is_empty());
+
         store.undo();
-        assert_eq!(store.model().rhythm(), Rhythm::new(12, 8));
-        
+        store.add_tempo(Tempo { start_tick: 720, value: TempoValue::new(90), marking: None }, false);
+        let regions = store.take_dirty_regions();
+        assert_eq!(regions.notes, vec![0..480]);
+        assert_eq!(regions.tempos, vec![720..721]);
+        assert_eq!(regions.merged(), vec![0..480, 720..721]);
+        assert!(store.take_dirty_regions().is_empty());
+    }
+
+    #[test]
+    fn copy_bar_attributes() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        let d_major = Key::SHARP_2;
+        store.add_bar(Bar::new(960, Some(Rhythm::new(3, 4)), Some(d_major), repeat_set!(Repeat::Start)), false);
+        store.add_bar(Bar::new(1680, None, None, RepeatSet::EMPTY), false);
+        store.add_bar(Bar::new(2400, None, None, RepeatSet::EMPTY), false);
+
+        store.copy_bar_attributes(0, &[1, 2, 9], BarAttribute::Key | BarAttribute::Repeats);
+        let bars: Vec<Bar> = store.bar_repo().iter().map(|(_, b)| *b).collect();
+        assert_eq!(bars[1], Bar::new(1680, None, Some(d_major), repeat_set!(Repeat::Start)));
+        assert_eq!(bars[2], Bar::new(2400, None, Some(d_major), repeat_set!(Repeat::Start)));
+
         store.undo();
-        assert_eq!(store.model().rhythm(), Rhythm::default());
-        
-        store.redo();
-        assert_eq!(store.model().rhythm(), Rhythm::new(12, 8));
-        
-        store.set_rhythm(Rhythm::new(16, 8));
-        store.wait_until_saved();
-        assert_eq!(store.model().rhythm(), Rhythm::new(16, 8));
-        
+        assert!(store.bar_repo().iter().skip(1).all(|(_, b)| b.key.is_none() && b.repeats == RepeatSet::EMPTY));
+    }
+
+    #[test]
+    fn normalize_velocities() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        for (tick, v) in [(0, 40), (240, 60), (480, 100)] {
+            store.add_note(Note { base_velocity: Velocity::new(v), ..note(tick) }, false);
+        }
+        let velocities = |store: &MemoryProjectStore| -> Vec<u8> { store.note_repo().iter().map(|(_, n)| n.velocity().as_u8()).collect() };
+
+        store.normalize_velocities(0..960, Velocity::new(50), Velocity::new(90), true);
+        assert_eq!(velocities(&store), vec![50, 63, 90]);
+        // Base velocities are kept.
+        assert_eq!(store.note_repo().iter().next().unwrap().1.base_velocity().as_u8(), 40);
+        store.undo();
+        assert_eq!(velocities(&store), vec![40, 60, 100]);
+
+        store.normalize_velocities(0..960, Velocity::new(90), Velocity::new(50), false);
+        assert_eq!(velocities(&store), vec![50, 60, 90]);
+        store.normalize_velocities(240..480, Velocity::new(10), Velocity::new(20), true);
+        assert_eq!(velocities(&store), vec![50, 15, 90]);
+    }
+
+    #[test]
+    fn compact() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        for i in 0..200 {
+            store.add_note(note(i * 240), false);
+        }
+        store.add_tempo(Tempo { start_tick: 480, value: TempoValue::new(100), marking: None }, false);
+        let removed: Vec<Rc<Note>> = store.note_repo().iter().skip(2).map(|(_, n)| n.clone()).collect();
+        store.bulk_remove(Models::empty().with_notes(&removed), ModelChangeMetadata::new());
+        let hash = store.content_hash();
+        let canonical = store.model().to_canonical_string().unwrap();
+
+        store.compact();
+        assert_eq!(store.content_hash(), hash);
+        assert_eq!(store.model().to_canonical_string().unwrap(), canonical);
+        assert!(store.take_dirty_regions().is_empty());
+        assert_eq!(store.note_repo().len(), 2);
+
+        // Undo still works on the compacted repos.
+        store.undo();
+        assert_eq!(store.note_repo().len(), 200);
+    }
+
+    #[test]
+    fn repeat_fill() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        let pattern = Models::empty().with_notes(&[Rc::new(note_of(480, Solfa::C)), Rc::new(note_of(720, Solfa::G))]);
+        store.repeat_fill(pattern, 0..1200);
+        assert_eq!(note_ticks(&store), vec![(0, Solfa::C), (240, Solfa::G), (480, Solfa::C), (720, Solfa::G), (960, Solfa::C)]);
+
+        store.undo();
+        assert!(note_ticks(&store).is_empty());
+    }
+
+    #[test]
+    fn paste_fit() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.set_rhythm(Rhythm::new(3, 4));
+        // A 4/4 bar of quarters followed by the first beat of the next bar.
+        let models = Models::empty().with_notes(&[0, 240, 480, 720, 960].map(|t| Rc::new(note(t))));
+
+        let keep = PasteFitOptions { source_rhythm: Rhythm::new(4, 4), scale_durations: false };
+        store.paste_fit(models.clone(), Location::new(0, 0), keep).unwrap();
+        assert_eq!(note_ticks(&store).iter().map(|(t, _)| *t).collect::<Vec<u32>>(), vec![0, 240, 480, 720]);
+        store.undo();
+
+        let scale = PasteFitOptions { source_rhythm: Rhythm::new(4, 4), scale_durations: true };
+        store.paste_fit(models, Location::new(0, 0), scale).unwrap();
+        assert_eq!(note_ticks(&store).iter().map(|(t, _)| *t).collect::<Vec<u32>>(), vec![0, 180, 360, 540, 720]);
+        let durations: Vec<u32> = store.note_repo().iter().map(|(_, n)| n.duration.tick_length()).collect();
+        assert_eq!(durations, vec![180; 5]);
+    }
+
+    #[test]
+    fn default_channel() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(note(0), false);
+        store.add_note(Note { channel: Channel::new(3), ..note(240) }, false);
+        assert_eq!(store.notes_with_nondefault_channel().len(), 1);
+
+        store.set_default_channel(Channel::new(3));
+        assert_eq!(store.default_channel(), Channel::new(3));
+        assert_eq!(store.note_defaults().channel, Channel::new(3));
+        assert_eq!(store.notes_with_nondefault_channel()[0].start_tick(), 0);
+
         store.undo();
-        assert_eq!(store.model().rhythm(), Rhythm::new(12, 8));
+        assert_eq!(store.default_channel(), Channel::default());
+        assert_eq!(store.note_defaults().channel, Channel::default());
     }
-    
+
     #[test]
-    fn can_undo_set_key() {
-        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
-        dir.push("project");
-        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
-        
-        store.set_key(Key::FLAT_1);
-        store.set_key(Key::FLAT_2);
-        store.wait_until_saved();
-        assert_eq!(store.model().key, Key::FLAT_2);
-        
+    fn normalize_trimmers() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(Note { start_tick_trimmer: Trimmer::new(10, 0, 0, 0), duration_trimmer: RateTrimmer::new(2.0, 1.0, 1.0, 1.0), ..note(0) }, false);
+        store.add_note(note(480), false);
+        let notes: Vec<Rc<Note>> = store.model().note_repo().iter().map(|(_, n)| n.clone()).collect();
+
+        store.normalize_trimmers(&notes, TrimmerNormalizeMode::FoldIntoBase);
+        let folded: Vec<(u32, u32, Trimmer)> = store.model().note_repo().iter().map(|(_, n)| (n.base_start_tick, n.duration.tick_length(), n.start_tick_trimmer)).collect();
+        assert_eq!(folded, vec![(10, 480, Trimmer::ZERO), (480, 240, Trimmer::ZERO)]);
+
         store.undo();
-        assert_eq!(store.model().key, Key::FLAT_1);
-        
-        store.redo();
-        assert_eq!(store.model().key, Key::FLAT_2);
+        let notes: Vec<Rc<Note>> = store.model().note_repo().iter().map(|(_, n)| n.clone()).collect();
+        assert_eq!(notes[0].start_tick(), 10);
+        store.normalize_trimmers(&notes, TrimmerNormalizeMode::Clear);
+        let cleared: Vec<(u32, u32)> = store.model().note_repo().iter().map(|(_, n)| (n.start_tick(), n.tick_len())).collect();
+        assert_eq!(cleared, vec![(0, 240), (480, 240)]);
     }
-    
+
     #[test]
-    fn can_undo_set_grid() {
-        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
-        dir.push("project");
-        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
-        
-        store.set_grid(Grid::from_u32(100).unwrap());
-        store.set_grid(Grid::from_u32(200).unwrap());
-        store.wait_until_saved();        
-        assert_eq!(store.model().grid.as_u32(), 200);
-        
+    fn preview_rhythm_change() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_bar(Bar::new(960, None, None, RepeatSet::EMPTY), false);
+        store.add_bar(Bar::new(1920, None, None, repeat_set!(Repeat::End)), false);
+        store.add_bar(Bar::new(2880, Some(Rhythm::new(2, 4)), None, RepeatSet::EMPTY), false);
+        store.add_bar(Bar::new(3360, None, None, RepeatSet::EMPTY), false);
+        // Half note at 1440 crosses the bar line at 1680 after the change.
+        store.add_note(Note { duration: Duration::new(Numerator::Half, Denominator::from_value(2).unwrap(), Dots::ZERO), ..note(1440) }, false);
+
+        let report = store.preview_rhythm_change(0, Rhythm::new(3, 4)).unwrap();
+        let shifted: Vec<(u32, u32)> = report.shifted_bars.iter().map(|(b, tick)| (b.start_tick, *tick)).collect();
+        assert_eq!(shifted, vec![(1920, 1680), (2880, 2400), (3360, 2880)]);
+        assert_eq!(report.crossing_notes.len(), 1);
+        assert!(report.invalid_repeats.is_empty());
+        assert_eq!(store.model().bar_repo()[1].1.start_tick, 1920);
+
+        assert!(store.preview_rhythm_change(3, Rhythm::new(2, 4)).unwrap().is_empty());
+        assert!(store.preview_rhythm_change(4, Rhythm::new(2, 4)).is_none());
+    }
+
+    #[test]
+    fn drag() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(note(0), false);
+        store.add_note(note(240), false);
+        let selection = Models::empty().with_notes(&[Rc::new(note(240))]);
+        store.clear_model_events();
+
+        store.begin_drag(selection);
+        assert!(store.update_drag(120, 0));
+        assert!(store.update_drag(240, 1));
+        assert!(!store.update_drag(-241, 0));
+        assert_eq!(store.drag_preview().unwrap().notes[0].base_start_tick, 480);
+        assert!(store.note_events().is_empty());
+        assert_eq!(note_ticks(&store), vec![(0, Solfa::C), (240, Solfa::C)]);
+
+        store.commit_drag();
+        assert!(store.drag_preview().is_none());
+        assert_eq!(note_ticks(&store), vec![(0, Solfa::C), (480, Solfa::D)]);
         store.undo();
-        assert_eq!(store.model().grid.as_u32(), 100);
-        
-        store.redo();
-        assert_eq!(store.model().grid.as_u32(), 200);
+        assert_eq!(note_ticks(&store), vec![(0, Solfa::C), (240, Solfa::C)]);
+
+        store.begin_drag(Models::empty().with_notes(&[Rc::new(note(0))]));
+        assert!(store.update_drag(960, 0));
+        store.cancel_drag();
+        store.commit_drag();
+        assert_eq!(note_ticks(&store), vec![(0, Solfa::C), (240, Solfa::C)]);
     }
 
     #[test]
-    fn can_undo_add_note() {
-        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
-        dir.push("project");
-        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
-        
-        let note0 = Note::new(
-            100,
-            Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null),
-            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
-            false, false,
-            Velocity::new(64),
-            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
-            Channel::default(),
-        );
-        
-        store.add_note(note0.clone(), false);
-        store.wait_until_saved();
-        assert_eq!(store.model().note_repo.len(), 1);
-        assert_eq!(store.model().bar_repo.len(), 1); // bar is replenished.
-        
+    fn preview_models() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.set_preview_models(Models { notes: vec![note(240)], ..Models::empty() });
+        assert_eq!(store.preview_models().notes, vec![note(240)]);
+        assert!(note_ticks(&store).is_empty());
+        assert!(!store.can_undo());
+
+        let json = serde_json::to_string(store.model()).unwrap();
+        assert!(!json.contains("preview"));
+
+        store.promote_preview(true);
+        assert!(store.preview_models().is_empty());
+        assert_eq!(note_ticks(&store), vec![(240, Solfa::C)]);
         store.undo();
-        assert_eq!(store.model().note_repo.len(), 0);
-        assert_eq!(store.model().bar_repo.len(), 0);
-        
-        store.redo();
-        assert_eq!(store.model().note_repo.len(), 1);
-        assert_eq!(store.model().note_repo.get(100u32), &vec![Rc::new(note0.clone())]);
-        assert_eq!(store.model().bar_repo.len(), 1);
+        assert!(note_ticks(&store).is_empty());
+
+        store.set_preview_models(Models { notes: vec![note(0)], ..Models::empty() });
+        store.clear_preview_models();
+        store.promote_preview(false);
+        assert!(note_ticks(&store).is_empty());
     }
-    
+
     #[test]
-    fn can_undo_add_bar() {
-        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
-        dir.push("project");
-        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
-        
-        let bar0 = Bar::new(
-            1000, Some(Rhythm::new(3, 4)), None, RepeatSet::EMPTY
-        );
-        
-        store.add_bar(bar0, false);
-        store.wait_until_saved();
-        assert_eq!(store.model().bar_repo.len(), 1); // with replenished bar
-        
+    fn events_at() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        // Tie chain 0 -> 240 whose first part is shortened by the trimmer.
+        store.add_note(Note { tie: true, duration_trimmer: RateTrimmer::new(0.5, 1.0, 1.0, 1.0), ..note(0) }, false);
+        store.add_note(Note { tied: true, ..note(240) }, false);
+        store.add_note(note_of(240, Solfa::E), false);
+        store.add_note(Note { muted: true, ..note_of(240, Solfa::G) }, false);
+        store.add_tempo(Tempo::new(100, 90), false);
+        store.add_dumper(CtrlChg::new(200, Velocity::new(127), Channel::default()), false);
+
+        let slice = store.events_at(180);
+        assert_eq!(slice.notes, vec![Note { tie: true, duration_trimmer: RateTrimmer::new(0.5, 1.0, 1.0, 1.0), ..note(0) }]);
+        assert_eq!(slice.tempos, vec![Tempo::new(180, 90)]);
+        assert_eq!(slice.dumpers[0].velocity, Velocity::new(0));
+
+        let slice = store.events_at(300);
+        let solfas: Vec<Solfa> = slice.notes.iter().map(|n| n.pitch.solfa()).collect();
+        assert_eq!(solfas, vec![Solfa::C, Solfa::E]);
+        assert_eq!(slice.dumpers[0].velocity, Velocity::new(127));
+
+        assert!(store.events_at(480).notes.is_empty());
+    }
+
+    #[test]
+    fn pedal_per_channel() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_dumper(CtrlChg::new(0, Velocity::new(127), Channel::default()), false);
+        store.add_dumper(CtrlChg::new(100, Velocity::new(100), Channel::new(1)), false);
+        store.add_dumper(CtrlChg::new(200, Velocity::new(0), Channel::default()), false);
+
+        assert_eq!(store.dumper_at_chan(150, Channel::default()), Velocity::new(127));
+        assert_eq!(store.dumper_at_chan(250, Channel::new(1)), Velocity::new(100));
+        assert_eq!(store.dumper_at_chan(50, Channel::new(1)), Velocity::new(0));
+        // No dumper on the channel: follows the global value.
+        assert_eq!(store.dumper_at_chan(150, Channel::new(2)), Velocity::new(100));
+        assert_eq!(store.soft_at_chan(150, Channel::new(2)), Velocity::new(0));
+    }
+
+    #[test]
+    fn dynamics_table() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        assert_eq!(store.dynamics_table().velocity(Dynamic::MF), Velocity::from_dynamic(Dynamic::MF));
+        let table = DynamicsTable::new([20, 40, 60, 80, 100, 120].map(Velocity::new)).unwrap();
+        store.set_dynamics_table(table);
         store.undo();
-        assert_eq!(store.model().bar_repo.len(), 0);
-        
+        assert_eq!(store.dynamics_table(), &DynamicsTable::default());
         store.redo();
-        assert_eq!(store.model().bar_repo.len(), 1);
-        assert_eq!(store.model().bar_repo[0], (1000u32, bar0))
+
+        let json = serde_json::to_string(store.model()).unwrap();
+        let restored: ProjectImpl = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.dynamics_table().dynamic(Velocity::new(115)), Dynamic::FF);
+        assert_eq!(restored.dynamics_table(), &table);
     }
-    
+
     #[test]
-    fn can_undo_add_tempo() {
-        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
-        dir.push("project");
-        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
-        
-        let tempo0 = Tempo::new(200, 200);
-        
-        store.add_tempo(tempo0, false);
-        store.wait_until_saved();
-        assert_eq!(store.model().tempo_repo.len(), 1);
-        assert_eq!(store.model().bar_repo.len(), 1); // bar is replenished.
-        
+    fn content_hash() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        let empty = store.content_hash();
+        store.add_note(note(0), false);
+        store.add_note(note(240), false);
+        store.add_tempo(Tempo { start_tick: 0, value: TempoValue::new(100), marking: None }, false);
+        let added = store.content_hash();
+        assert_ne!(added, empty);
+
+        store.clear_model_events();
+        store.change(ModelChanges::empty().with_notes(vec![(note(240), note(480))]), ModelChangeMetadata::new());
+        store.add_bar(Bar::new(960, None, None, RepeatSet::EMPTY), false);
+        assert_ne!(store.content_hash(), added);
+
+        // Same as rebuilding the hash from scratch.
+        let json = serde_json::to_string(store.model()).unwrap();
+        let loaded: ProjectImpl = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.content_hash(), store.content_hash());
+
         store.undo();
-        assert_eq!(store.model().tempo_repo.len(), 0);
-        assert_eq!(store.model().bar_repo.len(), 0);
-        
-        store.redo();
-        assert_eq!(store.model().tempo_repo.len(), 1);
-        assert_eq!(store.model().bar_repo.len(), 1);
-        assert_eq!(store.model().tempo_repo[0], (200u32, tempo0));
+        store.undo();
+        assert_eq!(store.content_hash(), added);
+        store.set_rhythm(Rhythm::new(3, 4));
+        assert_ne!(store.content_hash(), added);
     }
-    
+
     #[test]
-    fn can_undo_add_dumper() {
-        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
-        dir.push("project");
-        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
-        
-        let dumper = CtrlChg::new(200, Velocity::new(20), Channel::default());
-        store.add_dumper(dumper, false);
-        store.wait_until_saved();
-        assert_eq!(store.model().dumper_repo.len(), 1);
-        assert_eq!(store.model().bar_repo.len(), 1); // bar is replenished.
-        
+    fn note_iterators() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_bar(Bar::new(960, None, None, RepeatSet::EMPTY), false);
+        store.add_bar(Bar::new(1200, Some(Rhythm::new(3, 8)), None, RepeatSet::EMPTY), false);
+        store.add_note(note_of(0, Solfa::C), false);
+        store.add_note(note_of(0, Solfa::E), false);
+        store.add_note(note_of(480, Solfa::G), false);
+        store.add_note(note_of(1200, Solfa::C), false);
+
+        let by_bar: Vec<(usize, usize)> = store.notes_by_bar().map(|(bar_no, notes)| (bar_no, notes.len())).collect();
+        assert_eq!(by_bar, vec![(0, 3), (2, 1)]);
+        let chords: Vec<(u32, usize)> = store.notes_chordwise().map(|(tick, notes)| (tick, notes.len())).collect();
+        assert_eq!(chords, vec![(0, 2), (480, 1), (1200, 1)]);
+
+        let beat = |tick, bar_no, beat_no, accent| Beat { tick, bar_no, beat_no, accent };
+        let beats: Vec<Beat> = store.beats(500..1500).collect();
+        assert_eq!(beats, vec![
+            beat(720, 0, 3, Accent::Weak),
+            // Bar of a single beat since the next bar line is at 1200.
+            beat(960, 1, 0, Accent::Strong),
+            beat(1200, 2, 0, Accent::Strong),
+            beat(1320, 2, 1, Accent::Weak),
+            beat(1440, 2, 2, Accent::Weak),
+        ]);
+    }
+
+    #[test]
+    fn remove_bar() {
+        let bar = Bar::new(960, Some(Rhythm::new(3, 4)), None, repeat_set!(Repeat::Start));
+        let setup = || {
+            let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+            store.add_bar(bar, false);
+            store.add_bar(Bar::new(1680, None, None, repeat_set!(Repeat::End)), false);
+            store.add_bar(Bar::new(2400, None, None, RepeatSet::EMPTY), false);
+            store.add_note(note(480), false);
+            store.add_note(note(1000), false);
+            store.add_note(note(1800), false);
+            store.add_tempo(Tempo { start_tick: 1200, value: TempoValue::new(100), marking: None }, false);
+            store
+        };
+        let bars = |store: &MemoryProjectStore| -> Vec<Bar> { store.bar_repo().iter().map(|(_, b)| *b).collect() };
+        let ticks = |store: &MemoryProjectStore| -> Vec<u32> { note_ticks(store).iter().map(|(t, _)| *t).collect() };
+        let inherited = |tick| Bar::new(tick, Some(Rhythm::new(3, 4)), None, repeat_set!(Repeat::Start, Repeat::End));
+
+        let mut store = setup();
+        let before = bars(&store);
+        store.remove_bar(bar, BarContentPolicy::ShiftUp);
+        assert_eq!(bars(&store), vec![inherited(960), Bar::new(1680, None, None, RepeatSet::EMPTY)]);
+        assert_eq!(ticks(&store), vec![480, 1080]);
+        assert_eq!(store.tempo_repo().len(), 0);
         store.undo();
-        assert_eq!(store.model().dumper_repo.len(), 0);
-        assert_eq!(store.model().bar_repo.len(), 0);
-        
-        store.redo();
-        assert_eq!(store.model().dumper_repo.len(), 1);
-        assert_eq!(store.model().bar_repo.len(), 1);
-        assert_eq!(store.model().dumper_repo[0], (200u32, dumper));
+        assert_eq!(bars(&store), before);
+        assert_eq!(ticks(&store), vec![480, 1000, 1800]);
+
+        let mut store = setup();
+        store.remove_bar(bar, BarContentPolicy::LeaveGap);
+        assert_eq!(bars(&store), vec![inherited(1680), Bar::new(2400, None, None, RepeatSet::EMPTY)]);
+        assert_eq!(ticks(&store), vec![480, 1000, 1800]);
+        assert_eq!(store.tempo_repo().len(), 1);
+
+        let mut store = setup();
+        store.remove_bar(bar, BarContentPolicy::DeleteContents);
+        assert_eq!(bars(&store), vec![inherited(1680), Bar::new(2400, None, None, RepeatSet::EMPTY)]);
+        assert_eq!(ticks(&store), vec![480, 1800]);
+        assert_eq!(store.tempo_repo().len(), 0);
+
+        // Not in the project.
+        store.remove_bar(bar, BarContentPolicy::ShiftUp);
+        assert_eq!(ticks(&store), vec![480, 1800]);
     }
-    
+
     #[test]
-    fn can_undo_add_soft() {
-        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
-        dir.push("project");
-        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
-        
-        let soft = CtrlChg::new(200, Velocity::new(20), Channel::default());
-        store.add_soft(soft, false);
-        store.wait_until_saved();
-        assert_eq!(store.model().soft_repo.len(), 1);
-        assert_eq!(store.model().bar_repo.len(), 1); // bar is replenished.
-        
+    fn fit_duration() {
+        let tempos = |store: &MemoryProjectStore| -> Vec<(u32, u16)> { store.tempo_repo().iter().map(|(t, tempo)| (*t, tempo.value.value())).collect() };
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        assert_eq!(store.model().seconds_of(0..960), 2.0);
+        assert_eq!(store.fit_duration(0..960, 4.0), Ok(0.0));
+        assert_eq!(tempos(&store), vec![(0, 60), (960, 120)]);
         store.undo();
-        assert_eq!(store.model().soft_repo.len(), 0);
-        assert_eq!(store.model().bar_repo.len(), 0);
-        
+        assert!(store.tempo_repo().is_empty());
+
+        let error = store.fit_duration(0..960, 2.9).unwrap();
+        assert!(error < 0.0 && -0.01 < error);
+        assert_eq!(tempos(&store), vec![(0, 83), (960, 120)]);
+
+        // A ramp keeps its shape.
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_tempo(Tempo::new(0, 100), false);
+        store.add_tempo(Tempo::new(480, 200), false);
+        assert!((store.model().seconds_of(0..960) - 1.8).abs() < 1e-9);
+        assert!(store.fit_duration(0..960, 3.6).unwrap().abs() < 1e-9);
+        assert_eq!(tempos(&store), vec![(0, 50), (480, 100), (960, 200)]);
+
+        assert_eq!(store.fit_duration(0..0, 1.0), Err(FitDurationError::EmptyRange));
+        assert_eq!(store.fit_duration(0..960, 0.0), Err(FitDurationError::InvalidTarget(0.0)));
+    }
+
+    #[test]
+    fn describe_range() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_bar(Bar::new(960, Some(Rhythm::new(3, 4)), Some(Key::SHARP_1), RepeatSet::EMPTY), false);
+        store.add_tempo(Tempo::new(960, 100), false);
+        store.add_note(Note { base_velocity: Velocity::new(96), ..note_of(1320, Solfa::F) }, false);
+        store.add_note(Note { duration: Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO), ..note_of(1440, Solfa::C) }, false);
+
+        assert_eq!(store.describe_range(960..2000), vec![
+            "Bar 2: time signature 3/4",
+            "Bar 2: key 1 sharp",
+            "Bar 2, beat 1: tempo 100",
+            "Bar 2, beat 2.5: F sharp 5 quarter note, forte",
+            "Bar 2, beat 3: C 5 eighth note, mezzo piano",
+        ]);
+        assert!(store.describe_range(0..960).is_empty());
+    }
+
+    #[test]
+    fn set_note_tag() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(note_of(0, Solfa::C), false);
+        store.add_note(note_of(240, Solfa::D), false);
+        let notes: Vec<Rc<Note>> = store.model().note_repo().iter().map(|(_, n)| n.clone()).collect();
+        let tag = NoteTag::new(SmallString::new("motif").unwrap(), Some(0xff0000));
+
+        store.set_note_tag(&notes, Some(tag));
+        assert!(store.model().note_repo().iter().all(|(_, n)| n.tag == Some(tag)));
+        let json = serde_json::to_string(store.model().note_repo().iter().next().unwrap().1).unwrap();
+        assert!(json.contains(r#""tag":{"label":"motif","color":16711680}"#));
+
+        store.undo();
+        assert!(store.model().note_repo().iter().all(|(_, n)| n.tag.is_none()));
         store.redo();
-        assert_eq!(store.model().soft_repo.len(), 1);
-        assert_eq!(store.model().bar_repo.len(), 1);
-        assert_eq!(store.model().soft_repo[0], (200u32, soft));
+        assert!(store.model().note_repo().iter().all(|(_, n)| n.tag == Some(tag)));
     }
-    
+
     #[test]
-    fn can_undo_tuplize() {
-        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
-        dir.push("project");
-        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
-        
-        // Do nothing for empty.
-        store.tuplize(vec![]);
-        assert_eq!(store.model().soft_repo.len(), 0);
-        assert_eq!(store.model().bar_repo.len(), 0);
-        
-        let note0 = Note::new(
-            100,
-            Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null),
-            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
-            false, false,
-            Velocity::new(64),
-            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
-            Channel::default(),
-        );
-        store.add_note(note0.clone(), false);
-        
-        // Tuplize one note do nothing.
-        store.tuplize(vec![Rc::new(note0.clone())]);
-        store.wait_until_saved();
-        assert_eq!(store.model().note_repo.len(), 1);
-        assert_eq!(store.model().bar_repo.len(), 1); // bar replenished
-        assert_eq!(store.model().note_repo.get(100u32), &vec![Rc::new(note0.clone())]);
-        
-        store.undo(); // this undo adding note.
-        assert_eq!(store.model().note_repo.len(), 0);
-        assert_eq!(store.model().bar_repo.len(), 0);
-        
-        let note1 = Note::new(
-            110,
-            Pitch::new(Solfa::D, Octave::Oct4, SharpFlat::Null),
-            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
-            false, false,
-            Velocity::new(64),
-            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
-            Channel::default(),
-        );
-        
-        let note2 = Note::new(
-            120,
-            Pitch::new(Solfa::E, Octave::Oct4, SharpFlat::Null),
-            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
-            false, false,
-            Velocity::new(64),
-            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
-            Channel::default(),
-        );
-        store.add_note(note0.clone(), false);
-        store.add_note(note1.clone(), false);
-        store.add_note(note2.clone(), false);
-        
-        store.tuplize(vec![Rc::new(note0.clone()), Rc::new(note1.clone()), Rc::new(note2.clone())]);
-        store.wait_until_saved();
-        
-        assert_eq!(store.model().note_repo.len(), 3);
-        assert_eq!(store.model().bar_repo.len(), 1);
-        
+    fn conform_to_scale() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(Note { pitch: Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Sharp), ..note(0) }, false);
+        store.add_note(note_of(240, Solfa::F), false);
+        store.add_note(Note { pitch: Pitch::new(Solfa::B, Octave::Oct4, SharpFlat::Flat), ..note(480) }, false);
+        store.add_note(Note { pitch: Pitch::new(Solfa::B, Octave::Oct4, SharpFlat::Flat), ..note(960) }, false);
+        let g_major = Scale::new(Pitch::new(Solfa::G, Octave::Oct4, SharpFlat::Null), ScaleMode::Major);
+
+        let report = store.conform_to_scale(0..960, g_major);
+        assert_eq!(report.iter().map(|s| (s.start_tick, s.to)).collect::<Vec<_>>(), vec![
+            (0, Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null)),
+            (240, Pitch::new(Solfa::F, Octave::Oct4, SharpFlat::Sharp)),
+            (480, Pitch::new(Solfa::B, Octave::Oct4, SharpFlat::Null)),
+        ]);
+        assert_eq!(store.model().note_repo().iter().last().unwrap().1.pitch, Pitch::new(Solfa::B, Octave::Oct4, SharpFlat::Flat));
+        assert!(store.conform_to_scale(0..960, g_major).is_empty());
+
+        store.undo();
+        assert_eq!(store.model().note_repo().iter().next().unwrap().1.pitch, Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Sharp));
+    }
+
+    #[test]
+    fn add_policy() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(note_of(0, Solfa::C), false);
+        store.add_note(Note { base_velocity: Velocity::new(10), ..note_of(0, Solfa::C) }, false);
+        assert_eq!(store.model().note_repo().len(), 2);
+
         store.undo();
-        assert_eq!(store.model().note_repo.len(), 3);
-        assert_eq!(store.model().bar_repo.len(), 1);
-        assert_eq!(store.model().note_repo.get(100u32)[0].pitch.solfa(), Solfa::C);
-        assert_eq!(store.model().note_repo.get(110u32)[0].pitch.solfa(), Solfa::D);
-        assert_eq!(store.model().note_repo.get(120u32)[0].pitch.solfa(), Solfa::E);
-        
+        store.set_add_policy(AddPolicy { on_duplicate: DuplicatePolicy::Replace });
+        store.add_note(Note { base_velocity: Velocity::new(10), ..note_of(0, Solfa::C) }, false);
+        assert_eq!(store.model().note_repo().iter().map(|(_, n)| n.base_velocity).collect::<Vec<_>>(), vec![Velocity::new(10)]);
+        store.undo();
+        assert_eq!(store.model().note_repo().iter().map(|(_, n)| n.base_velocity).collect::<Vec<_>>(), vec![note_of(0, Solfa::C).base_velocity]);
         store.redo();
-        assert_eq!(store.model().note_repo.len(), 3);
-        assert_eq!(store.model().bar_repo.len(), 1);
-        
-        assert_eq!(store.model().note_repo.get(100u32)[0].pitch.solfa(), Solfa::C);
-        assert_eq!(store.model().note_repo.get(180u32)[0].pitch.solfa(), Solfa::D);
-        assert_eq!(store.model().note_repo.get(260u32)[0].pitch.solfa(), Solfa::E);
-        
+        assert_eq!(store.model().note_repo().iter().map(|(_, n)| n.base_velocity).collect::<Vec<_>>(), vec![Velocity::new(10)]);
+
+        // Pasted notes replace the existing one. The other channel is not a duplicate.
+        let pasted = vec![
+            Note { base_velocity: Velocity::new(20), ..note_of(0, Solfa::C) },
+            Note { channel: Channel::new(1), ..note_of(0, Solfa::C) },
+            note_of(240, Solfa::D),
+        ];
+        store.bulk_add(Models { notes: pasted, ..Models::empty() }, ModelChangeMetadata::new());
+        assert_eq!(store.model().note_repo().len(), 3);
+        assert!(store.model().note_repo().iter().any(|(_, n)| n.base_velocity == Velocity::new(20)));
+
+        store.set_add_policy(AddPolicy { on_duplicate: DuplicatePolicy::Reject });
+        store.add_note(note_of(240, Solfa::D), false);
+        store.bulk_add(Models { notes: vec![note_of(240, Solfa::D), note_of(480, Solfa::E), note_of(480, Solfa::E)], ..Models::empty() }, ModelChangeMetadata::new());
+        assert_eq!(note_ticks(&store), vec![(0, Solfa::C), (0, Solfa::C), (240, Solfa::D), (480, Solfa::E)]);
+        assert_eq!(store.add_policy().on_duplicate, DuplicatePolicy::Reject);
+
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.set_add_policy(AddPolicy { on_duplicate: DuplicatePolicy::Reject });
+        store.undo();
+        assert_eq!(store.add_policy(), AddPolicy::default());
     }
 
     #[test]
-    fn can_undo_bulk_remove() {
-        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
-        dir.push("project");
-        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
-        
-        let note0 = Note::new(
-            100,
-            Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null),
-            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
-            false, false,
-            Velocity::new(64),
-            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
-            Channel::default(),
-        );
-        let tempo0 = Tempo::new(200, 200);
-        store.bulk_add(
-            Models {
-                notes: vec![note0.clone()],
-                bars: vec![], tempos: vec![tempo0], dumpers: vec![], softs: vec![]
-            },
-            ModelChangeMetadata::new()
-        );
-        store.wait_until_saved();
-        assert_eq!(store.model().note_repo().len(), 1);
-        assert_eq!(store.model().bar_repo().len(), 1); // bar is replenished.
-        assert_eq!(store.model().tempo_repo().len(), 1);
+    fn remove_notes_tie_aware() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(Note { tie: true, ..note(0) }, false);
+        store.add_note(Note { tie: true, tied: true, ..note(240) }, false);
+        store.add_note(Note { tied: true, ..note(480) }, false);
+        store.add_note(note(720), false);
+        let flags = |store: &MemoryProjectStore| store.model().note_repo().iter().map(|(t, n)| (*t, n.tie, n.tied)).collect::<Vec<_>>();
+        let middle = store.model().note_repo().iter().find(|(t, _)| **t == 240).unwrap().1.clone();
+        assert_eq!(store.model().next_tie_partner(&middle).unwrap().start_tick(), 480);
+        assert_eq!(store.model().prev_tie_partner(&middle).unwrap().start_tick(), 0);
 
-        store.bulk_remove(
-            Models {
-                notes: vec![note0.clone()],
-                bars: vec![], tempos: vec![tempo0], dumpers: vec![], softs: vec![]
-            },
-            ModelChangeMetadata::new()
-        );
-        store.wait_until_saved();
+        store.remove_notes_tie_aware(std::slice::from_ref(&middle), TieRemovalPolicy::Unlink);
+        assert_eq!(flags(&store), vec![(0, false, false), (480, false, false), (720, false, false)]);
+        store.undo();
+        assert_eq!(flags(&store), vec![(0, true, false), (240, true, true), (480, false, true), (720, false, false)]);
 
-        assert_eq!(store.model().note_repo().len(), 0);
-        assert_eq!(store.model().bar_repo().len(), 1);
-        assert_eq!(store.model().tempo_repo().len(), 0);
+        store.remove_notes_tie_aware(&[middle], TieRemovalPolicy::RemoveChain);
+        assert_eq!(flags(&store), vec![(720, false, false)]);
+        store.undo();
+        store.redo();
+        assert_eq!(flags(&store), vec![(720, false, false)]);
 
+        // Partners too far away are not part of the chain.
+        let limit = 240 + Duration::MAX_TICK_LENGTH as u32;
+        store.add_note(Note { tie: true, ..note(10_000) }, false);
+        store.add_note(Note { tied: true, ..note(10_000 + limit) }, false);
+        store.add_note(Note { tied: true, ..note(10_000 + limit + 1) }, false);
+        let notes: Vec<Rc<Note>> = store.model().note_repo().iter().filter(|(t, _)| 10_000 <= **t).map(|(_, n)| n.clone()).collect();
+        assert_eq!(store.model().next_tie_partner(&notes[0]).unwrap().start_tick(), 10_000 + limit);
+        assert_eq!(store.model().prev_tie_partner(&notes[1]).unwrap().start_tick(), 10_000);
+        assert!(store.model().prev_tie_partner(&notes[2]).is_none());
+    }
+
+    #[test]
+    fn comments() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        let comment = Comment::new(CommentAnchor::Range(960..1920), "teacher", "Rushing here.", 1_700_000_000);
+        store.add_comment(comment.clone());
+        store.add_comment(Comment::new(CommentAnchor::Position(ScorePosition::new(240, 2)), "me", "Ok?", 1_700_000_100));
+        assert_eq!(store.comments().len(), 2);
+        assert_eq!(store.comments_in(1000..1100).collect::<Vec<_>>(), vec![&comment]);
+
+        store.set_comment_resolved(&comment, true);
+        assert!(store.comments()[0].resolved);
         store.undo();
+        assert!(!store.comments()[0].resolved);
 
-        assert_eq!(store.model().note_repo().len(), 1);
-        assert_eq!(store.model().bar_repo().len(), 1); // bar is replenished.
-        assert_eq!(store.model().tempo_repo().len(), 1);
+        store.remove_comment(&comment);
+        assert_eq!(store.comments().len(), 1);
+        store.undo();
+        assert_eq!(store.comments()[0], comment);
+
+        let json = serde_json::to_string(store.model()).unwrap();
+        let restored: ProjectImpl = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.comments(), store.comments());
     }
 
     #[test]
-    fn can_undo_bulk_add() {
-        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
-        dir.push("project");
-        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
-        
-        let note0 = Note::new(
-            100,
-            Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null),
-            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
-            false, false,
-            Velocity::new(64),
-            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
-            Channel::default(),
-        );
+    fn template() {
+        let proj = ProjectImpl::from_template(Template::solo_piano());
+        assert_eq!(proj.bar_repo().len(), 32);
+        assert_eq!(proj.tempo_repo()[0].1.value.as_u16(), 120);
 
-        let tempo0 = Tempo::new(200, 200);
-        store.bulk_add(
-            Models {
-                notes: vec![note0.clone()],
-                bars: vec![], tempos: vec![tempo0], dumpers: vec![], softs: vec![]
-            },
-            ModelChangeMetadata::new()
-        );
-        store.wait_until_saved();
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.set_rhythm(Rhythm::new(3, 4));
+        store.add_bar(Bar::new(720, None, None, Default::default()), false);
+        store.add_note(note(0), false);
+        let template = store.model().to_template("Waltz");
+        assert_eq!(template.name, "Waltz");
+        let proj = ProjectImpl::from_template(template);
+        assert_eq!(proj.rhythm(), Rhythm::new(3, 4));
+        assert_eq!(proj.bar_repo().len(), 1);
+        assert!(proj.note_repo().is_empty());
+    }
 
-        assert_eq!(store.model().note_repo().len(), 1);
-        assert_eq!(store.model().bar_repo().len(), 1); // bar is replenished.
-        assert_eq!(store.model().tempo_repo().len(), 1);
+    #[test]
+    fn quantize() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.bulk_add(Models { notes: vec![note(10), note(230), note(480)], ..Models::empty() }, ModelChangeMetadata::new());
+        let notes: Vec<Rc<Note>> = store.model().note_repo().iter().map(|(_, n)| n.clone()).collect();
+        let grid = Grid::from_u32(240).unwrap();
+
+        let half = store.model().quantize_preview(&notes, grid, PercentU16::from_value(500));
+        assert_eq!(half.iter().map(|m| (m.note.start_tick(), m.new_tick)).collect::<Vec<_>>(), vec![(10, 5), (230, 235)]);
+        assert_eq!(note_ticks(&store), vec![(10, Solfa::C), (230, Solfa::C), (480, Solfa::C)]);
 
+        let full = store.model().quantize_preview(&notes, grid, PercentU16::MAX);
+        store.apply_quantize(&full);
+        assert_eq!(note_ticks(&store), vec![(0, Solfa::C), (240, Solfa::C), (480, Solfa::C)]);
         store.undo();
+        assert_eq!(note_ticks(&store), vec![(10, Solfa::C), (230, Solfa::C), (480, Solfa::C)]);
 
-        assert_eq!(store.model().note_repo().len(), 0);
-        assert_eq!(store.model().bar_repo().len(), 0);
-        assert_eq!(store.model().tempo_repo().len(), 0);
+        // The grid counts from the bar line after a pickup bar, as the entry cursor does.
+        store.add_bar(Bar::new(100, None, None, Default::default()), false);
+        let moves = store.model().quantize_preview(&notes, grid, PercentU16::MAX);
+        assert_eq!(moves.iter().map(|m| (m.note.start_tick(), m.new_tick)).collect::<Vec<_>>(), vec![(10, 0), (230, 340), (480, 580)]);
     }
 
     #[test]
-    fn can_undo_change() {
-        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
-        dir.push("project");
-        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new()).unwrap();
-        
-        let note00 = Note::new(
-            100,
-            Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null),
-            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
-            false, false,
-            Velocity::new(64),
-            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
-            Channel::default(),
-        );
-        let note01 = Note::new(
-            101,
-            Pitch::new(Solfa::C, Octave::Oct3, SharpFlat::Null),
-            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
-            false, false,
-            Velocity::new(64),
-            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
-            Channel::default(),
-        );
-        let tempo0 = Tempo::new(200, 200);
-        store.bulk_add(
-            Models {
-                notes: vec![note00.clone(), note01.clone()],
-                bars: vec![], tempos: vec![tempo0], dumpers: vec![], softs: vec![]
-            },
-            ModelChangeMetadata::new()
-        );
-        store.wait_until_saved();
+    fn setup_score() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_bar(Bar::new(960, None, None, Default::default()), false);
+        store.add_tempo(Tempo::new(480, 100), false);
+        store.setup_score(ScoreSetup {
+            rhythm: Rhythm::new(3, 4), key: Key::SHARP_1, tempo: TempoValue::new(90), grid: Grid::from_u32(120).unwrap(), bar_count: 8,
+        });
+        assert_eq!(store.rhythm(), Rhythm::new(3, 4));
+        assert_eq!(store.key(), Key::SHARP_1);
+        assert_eq!(store.grid(), Grid::from_u32(120).unwrap());
+        assert_eq!(store.bar_repo().iter().map(|(t, _)| *t).collect::<Vec<_>>(), (1..=8).map(|i| i * 720).collect::<Vec<_>>());
+        assert_eq!(store.tempo_repo().iter().map(|(t, tempo)| (*t, tempo.value.as_u16())).collect::<Vec<_>>(), vec![(0, 90)]);
 
-        assert_eq!(store.model().note_repo().len(), 2);
-        assert_eq!(store.model().bar_repo().len(), 1); // bar is replenished.
-        assert_eq!(store.model().tempo_repo().len(), 1);
+        store.undo();
+        assert_eq!(store.rhythm(), Rhythm::default());
+        assert_eq!(store.bar_repo().len(), 1);
+        assert_eq!(store.tempo_repo()[0].0, 480);
+        store.redo();
+        assert_eq!(store.bar_repo().len(), 8);
+    }
 
-        let note10 = note00.with_duration_numerator(Numerator::Half);
-        let note11 = note01.with_duration_numerator(Numerator::Half);
-        let change = ModelChanges {
-            notes: vec![(note00.clone(), note10.clone()), (note01.clone(), note11.clone())],
-            bars: vec![], tempos: vec![], dumpers: vec![], softs: vec![]
+    #[test]
+    fn repair() {
+        let mut proj = ProjectImpl::default();
+        assert!(proj.repair().is_clean());
+
+        let metadata = ModelChangeMetadata::new();
+        proj.bar_repo.add(960, Bar::new(960, None, None, Default::default()), metadata);
+        proj.bar_repo.add(1000, Bar::new(960, None, None, Default::default()), metadata);
+        proj.bar_repo.add(2000, Bar::new(1920, None, None, Default::default()), metadata);
+        proj.note_repo.add(100, Rc::new(note(0)), metadata);
+        proj.note_repo.add(u32::MAX - 10, Rc::new(note(u32::MAX - 10)), metadata);
+        proj.slurs.push(Slur::new(note(0), note(480)));
+
+        let report = proj.repair();
+        assert_eq!(report.misplaced, 2);
+        assert_eq!(report.duplicates_removed, 1);
+        assert_eq!(report.notes_out_of_range, 1);
+        assert_eq!(report.dangling_slurs, 1);
+        assert_eq!(proj.bar_repo().iter().map(|(t, _)| *t).collect::<Vec<_>>(), vec![960, 1920]);
+        assert_eq!(proj.note_repo().iter().map(|(t, _)| *t).collect::<Vec<_>>(), vec![0]);
+        assert!(proj.repair().is_clean());
+    }
+
+    #[test]
+    fn rehearsal_marks() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_rehearsal_mark(RehearsalMark::auto(8));
+        store.add_rehearsal_mark(RehearsalMark::auto(16));
+        store.add_rehearsal_mark(RehearsalMark::auto(1));
+        assert_eq!(store.model().rehearsal_labels(), vec![(1, "A".to_owned()), (8, "B".to_owned()), (16, "C".to_owned())]);
+        assert_eq!(store.rehearsal_marks()[2].label, RehearsalLabel::Auto(2));
+
+        store.remove_rehearsal_mark(8);
+        assert_eq!(store.model().rehearsal_labels(), vec![(1, "A".to_owned()), (16, "B".to_owned())]);
+        assert_eq!(store.rehearsal_marks()[1].label, RehearsalLabel::Auto(1));
+        store.undo();
+        assert_eq!(store.rehearsal_marks()[2].label, RehearsalLabel::Auto(2));
+        store.redo();
+        store.add_rehearsal_mark(RehearsalMark::custom(1, "Intro"));
+        assert_eq!(store.model().rehearsal_labels(), vec![(1, "Intro".to_owned()), (16, "A".to_owned())]);
+        store.undo();
+        store.undo();
+        assert_eq!(store.rehearsal_marks().len(), 3);
+
+        let json = serde_json::to_string(store.model()).unwrap();
+        let restored: ProjectImpl = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.rehearsal_marks(), store.rehearsal_marks());
+    }
+
+    #[test]
+    fn scale_tempo() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_tempo(Tempo::new(0, 100), false);
+        store.add_tempo(Tempo::new(960, 120), false);
+        store.scale_tempo(480..1920, PercentU16::from_value(1100));
+        let tempos: Vec<(u32, u16)> = store.model().tempo_repo().iter().map(|(t, tempo)| (*t, tempo.value.as_u16())).collect();
+        assert_eq!(tempos, vec![(0, 100), (480, 110), (960, 132), (1920, 120)]);
+
+        store.undo();
+        assert_eq!(store.model().tempo_repo().len(), 2);
+        store.scale_tempo(480..480, PercentU16::from_value(1100));
+        assert_eq!(store.model().tempo_repo().len(), 2);
+    }
+
+    #[test]
+    fn pedal_segments() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_dumper(CtrlChg::new(100, Velocity::new(127), Channel::default()), false);
+        store.add_dumper(CtrlChg::new(500, Velocity::new(0), Channel::default()), false);
+        store.add_soft(CtrlChg::new(200, Velocity::new(80), Channel::default()), false);
+        let segments = store.model().pedal_segments(CtrlChgKind::Dumper, 300..1000);
+        assert_eq!(segments.iter().map(|s| (s.down_tick, s.up_tick, s.depth.as_u8())).collect::<Vec<_>>(), vec![(300, 500, 127)]);
+        let segments = store.model().pedal_segments(CtrlChgKind::Soft, 0..1000);
+        assert_eq!(segments.iter().map(|s| (s.down_tick, s.up_tick, s.depth.as_u8())).collect::<Vec<_>>(), vec![(200, 1000, 80)]);
+    }
+
+    #[test]
+    fn activity_map() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        assert!(store.model().activity_map(ActivityBucket::Beat).is_empty());
+        store.add_bar(Bar::new(960, None, None, RepeatSet::EMPTY), false);
+        store.add_bar(Bar::new(1920, None, None, RepeatSet::EMPTY), false);
+        store.add_note(note(0), false);
+        store.add_note(note_of(0, Solfa::E), false);
+        store.add_note(note(240), false);
+        store.add_note(note(1920), false);
+        assert_eq!(store.model().activity_map(ActivityBucket::Bar), vec![3, 0, 1]);
+        assert_eq!(store.model().activity_map(ActivityBucket::Beat), vec![2, 1, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn instruments() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        let clarinet = Instrument::new("Clarinet", 71).with_transposition(Transposition::B_FLAT);
+        store.set_instrument(Channel::new(1), Some(clarinet.clone()));
+        assert_eq!(store.instruments().channel_name(Channel::new(1)), "Clarinet");
+        let c = Note { channel: Channel::new(1), ..note(0) };
+        assert_eq!(store.model().display_pitch(&c), Pitch::new(Solfa::D, Octave::Oct4, SharpFlat::Null));
+
+        store.set_instrument(Channel::new(1), None);
+        assert!(store.instruments().is_empty());
+        store.undo();
+        assert_eq!(store.instruments().get(Channel::new(1)), Some(&clarinet));
+
+        let json = serde_json::to_string(store.model()).unwrap();
+        let restored: ProjectImpl = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.instruments(), store.instruments());
+        assert_eq!(ProjectImpl::from_template(Template::two_piano()).instruments().channel_name(Channel::new(1)), "Piano 2");
+    }
+
+    #[test]
+    fn paste_with_options() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note(Note { channel: Channel::new(2), ..note(960) }, false);
+        let copied = Models {
+            notes: vec![note(0), note_of(240, Solfa::E)],
+            dumpers: vec![CtrlChg::new(0, Velocity::new(127), Channel::new(0))],
+            ..Models::empty()
         };
-        store.change(change, ModelChangeMetadata::new());
-        store.wait_until_saved();
+        let channel_map = [(Channel::new(0), Channel::new(2))].into_iter().collect();
 
-        assert_eq!(store.model().note_repo().len(), 2);
-        let mut z = store.model().note_repo().iter();
-        assert_eq!(z.next(), Some((&note10.start_tick(), &Rc::new(note10.clone()))));
-        assert_eq!(z.next(), Some((&note11.start_tick(), &Rc::new(note11.clone()))));
-        assert_eq!(z.next(), None);
-        let mut z = store.model().tempo_repo().iter();
-        assert_eq!(z.next(), Some(&(tempo0.start_tick, tempo0)));
-        assert_eq!(z.next(), None);
+        let skip = PasteOptions { channel_map, on_conflict: PasteConflict::Skip, select_result: false, bar_merge: BarMerge::Replace };
+        store.paste(copied.clone(), 960, skip.clone());
+        assert_eq!(note_ticks(&store), vec![(960, Solfa::C), (1200, Solfa::E)]);
+        assert!(store.model().note_repo().iter().all(|(_, n)| n.channel == Channel::new(2)));
+        assert_eq!(store.model().dumper_repo()[0].1.channel, Channel::new(2));
+        store.undo();
 
+        store.paste(copied.clone(), 960, PasteOptions { on_conflict: PasteConflict::Merge, ..skip.clone() });
+        assert_eq!(store.model().note_repo().len(), 3);
         store.undo();
 
+        store.paste(copied, 960, PasteOptions { on_conflict: PasteConflict::Replace, ..skip });
         assert_eq!(store.model().note_repo().len(), 2);
-        let mut z = store.model().note_repo().iter();
-        assert_eq!(z.next(), Some((&note00.start_tick(), &Rc::new(note00.clone()))));
-        assert_eq!(z.next(), Some((&note01.start_tick(), &Rc::new(note01.clone()))));
-        assert_eq!(z.next(), None);
-        let mut z = store.model().tempo_repo().iter();
-        assert_eq!(z.next(), Some(&(tempo0.start_tick, tempo0)));
-        assert_eq!(z.next(), None);
     }
 
     #[test]
-    fn many_changes() {
-        let mut dir = tempdir().unwrap().as_ref().to_path_buf();
-        dir.push("project");
-        let mut store = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new().with_undo_limit(10)).unwrap();
-        
-        let note00 = Note::new(
-            100,
-            Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null),
-            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
-            false, false,
-            Velocity::new(64),
-            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
-            Channel::default(),
-        );
-        let note01 = Note::new(
-            101,
-            Pitch::new(Solfa::C, Octave::Oct3, SharpFlat::Null),
-            Duration::new(Numerator::N8th, Denominator::from_value(2).unwrap(), Dots::ZERO),
-            false, false,
-            Velocity::new(64),
-            Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO,
-            Channel::default(),
-        );
-        store.add_note(note00, false);
-        store.add_note(note01, false);
+    fn paste_merging_bars() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        let key = Some(Key::FLAT_2);
+        store.add_bar(Bar::new(960, Some(Rhythm::new(3, 4)), key, repeat_set!(Repeat::Start)), false);
+        let copied = Models {
+            bars: vec![
+                Bar::new(0, None, Some(Key::SHARP_1), repeat_set!(Repeat::End)),
+                Bar::new(720, None, None, repeat_set!(Repeat::Coda)),
+            ],
+            ..Models::empty()
+        };
+        let merge = PasteOptions { bar_merge: BarMerge::Merge, ..PasteOptions::default() };
 
-        for i in 0..20 {
-            let tempo = Tempo::new(200 + i, 200 + i as u16);
-            store.add_tempo(tempo, false);
+        let warnings = store.paste(copied.clone(), 960, merge);
+        assert_eq!(warnings, vec![BarMergeWarning::KeyReplaced { tick: 960, existing: Key::FLAT_2, pasted: Key::SHARP_1 }]);
+        let bar = store.model().bar_repo()[0].1;
+        assert_eq!(bar.rhythm, Some(Rhythm::new(3, 4)));
+        assert_eq!(bar.key, Some(Key::SHARP_1));
+        assert!(bar.repeats.contains(Repeat::Start) && bar.repeats.contains(Repeat::End));
+        assert_eq!(store.model().bar_repo()[1].1.repeats, repeat_set!(Repeat::Coda));
+        store.undo();
+
+        store.paste(copied, 960, PasteOptions::default());
+        assert_eq!(store.model().bar_repo()[0].1, Bar::new(960, None, Some(Key::SHARP_1), repeat_set!(Repeat::End)));
+
+        let var = Bar::new(960, None, None, repeat_set!(Repeat::Var1));
+        let (bar, warnings) = Bar::new(960, None, None, repeat_set!(Repeat::Start)).merged_with(&var);
+        assert_eq!(bar.repeats, repeat_set!(Repeat::Start));
+        assert_eq!(warnings, vec![BarMergeWarning::RepeatDropped { tick: 960, repeat: Repeat::Var1 }]);
+    }
+
+    #[test]
+    fn canonical_string() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_tempo(Tempo::new(0, 100), false);
+        store.add_note(note_of(0, Solfa::E), false);
+        store.add_note(note(0), false);
+        store.add_note(note(240).with_muted(true), false);
+        let dump = store.model().to_canonical_string().unwrap();
+        let lines: Vec<&str> = dump.lines().filter(|l| l.starts_with("note ") || l.starts_with("tempo ")).collect();
+        assert_eq!(lines, vec!["tempo 0 100", "note 0 ch0 C5 4 64", "note 0 ch0 E5 4 64", "note 240 ch0 C5 4 64 muted"]);
+
+        let mut other = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        other.add_note(note(240).with_muted(true), false);
+        other.add_note(note(0), false);
+        other.add_note(note_of(0, Solfa::E), false);
+        other.add_tempo(Tempo::new(0, 100), false);
+        assert_eq!(other.model().to_canonical_string().unwrap(), dump);
+    }
+
+    #[test]
+    fn canonical_string_ignores_insertion_order() {
+        let loud = Note { velocity_trimmer: Trimmer::new(10, 0, 0, 0), ..note(0) };
+        let comment0 = Comment::new(CommentAnchor::Range(0..240), "teacher", "Slower.", 0);
+        let comment1 = Comment::new(CommentAnchor::Range(0..240), "teacher", "Louder.", 0);
+        let slur0 = Slur::new(note(0), note(240));
+        let slur1 = Slur::new(note(480), note(720));
+
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        for n in [note(0), loud.clone(), note(240), note(480), note(720)] {
+            store.add_note(n, false);
         }
-        assert_eq!(store.model().note_repo().len(), 2);
-        assert_eq!(store.model().tempo_repo().len(), 20);
-        drop(store);
+        store.add_comment(comment0.clone());
+        store.add_comment(comment1.clone());
+        store.add_slur(slur0.clone());
+        store.add_slur(slur1.clone());
 
-        let store2 = SqliteUndoStore::<ProjectCmd, ProjectImpl, ProjectCmdErr>::open(dir.clone(), undo_store::Options::new().with_undo_limit(10)).unwrap();
-        assert_eq!(store2.model().note_repo().len(), 2);
-        assert_eq!(store2.model().tempo_repo().len(), 20);
+        let mut other = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        for n in [note(720), note(480), note(240), loud, note(0)] {
+            other.add_note(n, false);
+        }
+        other.add_comment(comment1);
+        other.add_comment(comment0);
+        other.add_slur(slur1);
+        other.add_slur(slur0);
+
+        assert_eq!(other.model().to_canonical_string().unwrap(), store.model().to_canonical_string().unwrap());
+        assert_eq!(store.model().slurs().len(), 2);
+    }
+
+    #[test]
+    fn extract_parts() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        let ch1 = Channel::new(1);
+        store.add_bar(Bar::new(960, Some(Rhythm::new(3, 4)), None, repeat_set!(Repeat::End)), false);
+        store.add_tempo(Tempo::new(0, 90), false);
+        store.add_note(note(0), false);
+        store.add_note(note(240), false);
+        store.add_note(Note { channel: ch1, ..note_of(0, Solfa::E) }, false);
+        store.add_dumper(CtrlChg::new(0, Velocity::new(127), Channel::default()), false);
+        store.add_dumper(CtrlChg::new(240, Velocity::new(0), ch1), false);
+        store.add_soft(CtrlChg::new(0, Velocity::new(64), Channel::default()), false);
+        store.add_slur(Slur::new(note(0), note(240)));
+
+        let parts: Vec<ProjectImpl> = store.model().extract_parts().into_iter().map(ProjectImpl::from).collect();
+        assert_eq!(parts.len(), 2);
+        for part in &parts {
+            assert_eq!(part.bar_repo().iter().map(|(_, b)| *b).collect::<Vec<_>>(), vec![Bar::new(960, Some(Rhythm::new(3, 4)), None, repeat_set!(Repeat::End))]);
+            assert_eq!(part.tempo_repo().len(), 1);
+            assert_eq!(part.note_defaults().channel, part.default_channel());
+        }
+
+        assert_eq!(parts[0].note_repo().len(), 2);
+        assert_eq!(parts[0].dumper_repo().iter().map(|(t, _)| *t).collect::<Vec<_>>(), vec![0]);
+        assert_eq!(parts[0].soft_repo().len(), 1);
+        assert_eq!(parts[0].slurs().len(), 1);
+        assert_eq!(parts[1].note_repo().iter().map(|(_, n)| n.pitch.solfa()).collect::<Vec<_>>(), vec![Solfa::E]);
+        assert_eq!(parts[1].dumper_repo().iter().map(|(t, _)| *t).collect::<Vec<_>>(), vec![240]);
+        assert!(parts[1].soft_repo().is_empty());
+        assert!(parts[1].slurs().is_empty());
+        assert_eq!(parts[1].default_channel(), ch1);
+
+        assert!(ProjectImpl::default().extract_parts().is_empty());
     }
 }
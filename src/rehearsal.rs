@@ -0,0 +1,75 @@
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RehearsalLabel {
+    // Position among the auto marks, kept up to date by renumber().
+    Auto(usize),
+    Custom(String),
+}
+
+// Rehearsal mark at the start of a bar. Bar 0 is the one before the first bar line.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RehearsalMark {
+    pub bar_no: usize,
+    pub label: RehearsalLabel,
+}
+
+impl RehearsalMark {
+    pub fn auto(bar_no: usize) -> Self {
+        Self { bar_no, label: RehearsalLabel::Auto(0) }
+    }
+
+    pub fn custom(bar_no: usize, label: &str) -> Self {
+        Self { bar_no, label: RehearsalLabel::Custom(label.to_owned()) }
+    }
+}
+
+// A to Z, then AA, AB and so on.
+pub fn auto_letter(index: usize) -> String {
+    let mut letters = vec![];
+    let mut i = index + 1;
+    while 0 < i {
+        i -= 1;
+        letters.push((b'A' + (i % 26) as u8) as char);
+        i /= 26;
+    }
+    letters.iter().rev().collect()
+}
+
+// Numbers the auto marks in order of the bar. The marks should be sorted by bar. Custom marks do not use up a letter.
+pub fn renumber(marks: &mut [RehearsalMark]) {
+    let mut auto_count = 0;
+    for m in marks.iter_mut() {
+        if let RehearsalLabel::Auto(index) = &mut m.label {
+            *index = auto_count;
+            auto_count += 1;
+        }
+    }
+}
+
+// Text of the marks, which should be sorted by bar and numbered by renumber().
+pub fn labels(marks: &[RehearsalMark]) -> Vec<(usize, String)> {
+    marks.iter().map(|m| match &m.label {
+        RehearsalLabel::Auto(index) => (m.bar_no, auto_letter(*index)),
+        RehearsalLabel::Custom(text) => (m.bar_no, text.clone()),
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{auto_letter, labels, renumber, RehearsalLabel, RehearsalMark};
+
+    #[test]
+    fn letters() {
+        assert_eq!(auto_letter(0), "A");
+        assert_eq!(auto_letter(25), "Z");
+        assert_eq!(auto_letter(26), "AA");
+        assert_eq!(auto_letter(27), "AB");
+        assert_eq!(auto_letter(26 * 27), "AAA");
+
+        let mut marks = [RehearsalMark::auto(4), RehearsalMark::custom(8, "Intro"), RehearsalMark::auto(12)];
+        renumber(&mut marks);
+        assert_eq!(marks[2].label, RehearsalLabel::Auto(1));
+        assert_eq!(labels(&marks), vec![(4, "A".to_owned()), (8, "Intro".to_owned()), (12, "B".to_owned())]);
+    }
+}
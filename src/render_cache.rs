@@ -0,0 +1,197 @@
+use std::ops::Range;
+
+use error_stack::Report;
+
+use crate::{dirty_region::{coalesce, DirtyRegions}, glissando::Glissando, key::Key, playback::{chunk_end_accum, render_span, restoration_events, sort_key, PitchShifts, PlayEvent, PlaybackSettings, TrackOffsets}, project::ProjectImpl, repeat::{AccumTick, Chunk, RenderRegionError}, rhythm::Rhythm, skip_region::SkipRegion, slur::Slur};
+
+// Project state that any event may depend on. A change rebuilds the whole cache.
+#[derive(Clone, PartialEq)]
+struct Globals {
+    rhythm: Rhythm,
+    key: Key,
+    playback: PlaybackSettings,
+    track_offsets: TrackOffsets,
+    pitch_shifts: PitchShifts,
+    skip_regions: Vec<SkipRegion>,
+    glissandos: Vec<Glissando>,
+    slurs: Vec<Slur>,
+}
+
+impl Globals {
+    fn of(proj: &ProjectImpl) -> Self {
+        Self {
+            rhythm: proj.rhythm(),
+            key: proj.key(),
+            playback: *proj.playback_settings(),
+            track_offsets: proj.track_offsets().clone(),
+            pitch_shifts: proj.pitch_shifts().clone(),
+            skip_regions: proj.skip_regions().to_vec(),
+            glissandos: proj.glissandos().to_vec(),
+            slurs: proj.slurs().to_vec(),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Tagged {
+    chunk: usize,
+    // Tick of the element rendering the event. None for the state restored at the start of the chunk.
+    source: Option<u32>,
+    // Order among the events rendered for the same chunk and source.
+    seq: usize,
+    event: PlayEvent,
+}
+
+// Phase of render_span() producing the event.
+fn phase(e: &PlayEvent) -> u8 {
+    match e {
+        PlayEvent::Tempo { .. } => 0,
+        PlayEvent::Dumper { .. } => 1,
+        PlayEvent::Soft { .. } => 2,
+        PlayEvent::NoteOff { .. } | PlayEvent::NoteOn { .. } => 3,
+    }
+}
+
+// Rendered events of the project kept across edits. Only the spans touched by an edit are rendered again, in
+// every pass playing them. Changes of bars, the rhythm, the key or playback settings render everything again.
+// Events are in the same order as playback::render().
+pub struct RenderCache {
+    globals: Globals,
+    // With the accumulated tick at the start of each chunk.
+    chunks: Vec<(AccumTick, Chunk)>,
+    events: Vec<Tagged>,
+}
+
+impl RenderCache {
+    pub fn new(proj: &ProjectImpl) -> Result<Self, Report<RenderRegionError>> {
+        let mut cache = Self {
+            globals: Globals::of(proj),
+            chunks: Chunk::by_accum_tick(&proj.play_chunks()?)?.to_vec(),
+            events: vec![],
+        };
+        cache.check_open_chunk(proj)?;
+        cache.restore_states(proj);
+        for (i, (accum, chunk)) in cache.chunks.clone().into_iter().enumerate() {
+            cache.render(proj, i, chunk, chunk.start_tick()..chunk.end_tick(), accum);
+        }
+        cache.sort(proj);
+        Ok(cache)
+    }
+
+    pub fn events(&self) -> impl Iterator<Item = &PlayEvent> + '_ {
+        self.events.iter().map(|t| &t.event)
+    }
+
+    pub fn to_events(&self) -> Vec<PlayEvent> {
+        self.events().copied().collect()
+    }
+
+    // Dirty regions should be those taken from the project since the last update.
+    pub fn update(&mut self, proj: &ProjectImpl, dirty: &DirtyRegions) -> Result<(), Report<RenderRegionError>> {
+        if !dirty.bars.is_empty() || self.globals != Globals::of(proj) {
+            *self = Self::new(proj)?;
+            return Ok(());
+        }
+        if dirty.is_empty() { return Ok(()); }
+        self.check_open_chunk(proj)?;
+
+        for range in self.widened(proj, dirty.merged()) {
+            for (i, (accum, chunk)) in self.chunks.clone().into_iter().enumerate() {
+                let span = range.start.max(chunk.start_tick())..range.end.min(chunk.end_tick());
+                if span.is_empty() { continue; }
+                self.events.retain(|t| t.chunk != i || !t.source.is_some_and(|s| span.contains(&s)));
+                self.render(proj, i, chunk, span, accum);
+            }
+        }
+        // States restored at jumps depend on the tempos and pedals before them.
+        if !dirty.tempos.is_empty() || !dirty.dumpers.is_empty() || !dirty.softs.is_empty() {
+            self.events.retain(|t| t.source.is_some());
+            self.restore_states(proj);
+        }
+        self.sort(proj);
+        Ok(())
+    }
+
+    // Events added to the open ended last chunk may go past the range of AccumTick. Other chunks are checked
+    // by Chunk::by_accum_tick().
+    fn check_open_chunk(&self, proj: &ProjectImpl) -> Result<(), Report<RenderRegionError>> {
+        match self.chunks.last() {
+            Some((accum, chunk)) => chunk_end_accum(proj, *chunk, *accum).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    // Notes under a slur or a glissando are rendered with their neighbors, so these are rendered again together.
+    fn widened(&self, proj: &ProjectImpl, ranges: Vec<Range<u32>>) -> Vec<Range<u32>> {
+        let spans: Vec<Range<u32>> = proj.slurs().iter().map(|s| s.start_note.start_tick()..s.end_note.start_tick() + 1)
+            .chain(proj.glissandos().iter().map(|g| g.from_note.start_tick()..g.to_note.start_tick() + 1))
+            .collect();
+        coalesce(ranges.into_iter().map(|r| spans.iter()
+            .filter(|s| s.start < r.end && r.start < s.end)
+            .fold(r.clone(), |acc, s| acc.start.min(s.start)..acc.end.max(s.end))
+        ).collect())
+    }
+
+    fn render(&mut self, proj: &ProjectImpl, chunk_idx: usize, chunk: Chunk, span: Range<u32>, accum: AccumTick) {
+        let mut rendered = vec![];
+        render_span(proj, chunk, span, accum, &mut rendered);
+        self.events.extend(rendered.into_iter().enumerate().map(|(seq, (source, event))| Tagged { chunk: chunk_idx, source: Some(source), seq, event }));
+    }
+
+    fn restore_states(&mut self, proj: &ProjectImpl) {
+        for (i, (accum, chunk)) in self.chunks.iter().enumerate() {
+            let jumped = if i == 0 { 0 < chunk.start_tick() } else { self.chunks[i - 1].1.end_tick() != chunk.start_tick() };
+            if jumped {
+                self.events.extend(restoration_events(proj, *chunk, *accum).into_iter().enumerate()
+                    .map(|(seq, event)| Tagged { chunk: i, source: None, seq, event }));
+            }
+        }
+    }
+
+    fn sort(&mut self, proj: &ProjectImpl) {
+        let policy = proj.playback_settings().event_order;
+        self.events.sort_by_key(|t| (sort_key(&t.event, &policy), t.chunk, t.source.is_some(), phase(&t.event), t.source, t.seq));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serdo::undo_store::UndoStore;
+
+    use crate::{bar::{Bar, Repeat, RepeatSet}, channel::Channel, ctrl_chg::CtrlChg, note::Note, pitch::Pitch, playback::render, project::{memory_project_store, MemoryProjectStore, Project, DEFAULT_MEMORY_UNDO_LIMIT}, repeat_set, solfa::Solfa, octave::Octave, sharp_flat::SharpFlat, tempo::Tempo, velocity::Velocity};
+    use super::RenderCache;
+
+    fn note(tick: u32, solfa: Solfa) -> Note {
+        Note { base_start_tick: tick, pitch: Pitch::new(solfa, Octave::Oct4, SharpFlat::Null), ..Default::default() }
+    }
+
+    #[test]
+    fn incremental_update_matches_full_render() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_bar(Bar::new(960, None, None, repeat_set!(Repeat::End)), false);
+        store.add_bar(Bar::new(1920, None, None, Default::default()), false);
+        store.add_note(note(0, Solfa::C), false);
+        store.add_note(note(480, Solfa::E), false);
+        store.add_note(note(960, Solfa::G), false);
+        store.take_dirty_regions();
+        let mut cache = RenderCache::new(store.model()).unwrap();
+        assert_eq!(cache.to_events(), render(store.model()).unwrap());
+
+        let mut check = |store: &mut MemoryProjectStore| {
+            let dirty = store.take_dirty_regions();
+            cache.update(store.model(), &dirty).unwrap();
+            assert_eq!(cache.to_events(), render(store.model()).unwrap());
+        };
+
+        store.add_note(note(240, Solfa::D), false);
+        check(&mut store);
+        store.add_dumper(CtrlChg::new(240, Velocity::new(127), Channel::default()), false);
+        check(&mut store);
+        store.add_tempo(Tempo::new(720, 90), false);
+        check(&mut store);
+        store.undo();
+        check(&mut store);
+        store.add_bar(Bar::new(2880, None, None, Default::default()), false);
+        check(&mut store);
+    }
+}
@@ -1,4 +1,4 @@
-use std::{ops::Range, fmt::Display};
+use std::{collections::BTreeSet, ops::Range, fmt::Display};
 use error_stack::{Context, report};
 use gcollections::ops::{Intersection, Union, Bounded};
 use interval::{IntervalSet, interval_set::ToIntervalSet};
@@ -7,7 +7,10 @@ use klavier_helper::store::Store;
 use crate::{bar::{Bar, VarIndex, Repeat}, rhythm::Rhythm, have_start_tick::HaveBaseStartTick, global_repeat::{GlobalRepeat, RenderRegionWarning, GlobalRepeatBuilder}};
 
 // Accumulated tick after repeats are rendered.
+#[cfg(not(feature = "tick64"))]
 pub type AccumTick = u32;
+#[cfg(feature = "tick64")]
+pub type AccumTick = u64;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Chunk {
@@ -59,24 +62,97 @@ impl Chunk {
     self.end_tick - self.start_tick()
   }
 
-  pub fn by_accum_tick(chunks: &[Chunk]) -> Store<AccumTick, Chunk, ()> {
-    let mut offset: u32 = 0;
+  // Open ended chunk (the last one of a tune) ends at u32::MAX.
+  pub fn is_open_ended(self) -> bool {
+    self.end_tick == u32::MAX
+  }
+
+  pub fn intersect(self, range: Range<u32>) -> Option<Chunk> {
+    let start_tick = self.start_tick.max(range.start);
+    let end_tick = self.end_tick.min(range.end);
+    if start_tick < end_tick { Some(Chunk::new(start_tick, end_tick)) } else { None }
+  }
+
+  // Returns the part before the tick and the part at or after the tick.
+  pub fn split_at(self, tick: u32) -> (Option<Chunk>, Option<Chunk>) {
+    (self.intersect(self.start_tick..tick), self.intersect(tick..self.end_tick))
+  }
+
+  // Fails if a chunk ends past the range of AccumTick. The tick64 feature widens it.
+  pub fn by_accum_tick(chunks: &[Chunk]) -> std::result::Result<Store<AccumTick, Chunk, ()>, error_stack::Report<RenderRegionError>> {
+    let mut offset: AccumTick = 0;
     let mut buf: Store<AccumTick, Chunk, ()> = Store::new(false);
 
     for c in chunks {
         buf.add(offset, c.clone(), ());
         if c.end_tick() != u32::MAX {
-            offset += c.len();
+            offset = offset.checked_add(AccumTick::from(c.len()))
+              .ok_or(RenderRegionError::AccumTickOverflow { tick: c.start_tick() })?;
         }
     }
 
-    buf
+    Ok(buf)
+  }
+}
+
+pub trait Chunks {
+  // None if the chunks are open ended or too long.
+  fn total_len(&self) -> Option<AccumTick>;
+}
+
+impl Chunks for [Chunk] {
+  fn total_len(&self) -> Option<AccumTick> {
+    self.iter().try_fold(0 as AccumTick, |sum, c| if c.is_open_ended() { None } else { sum.checked_add(AccumTick::from(c.len())) })
   }
 }
 
 pub trait Region: std::fmt::Debug {
   fn to_chunks(&self) -> Vec<Chunk>;
   fn to_iter1_interval_set(&self) -> IntervalSet<u32>;
+
+  fn global_repeat(&self) -> Option<&GlobalRepeat> {
+    None
+  }
+
+  // Chunks before and after the D.C./D.S. jump. The latter is empty without global repeat.
+  fn to_chunk_passes(&self) -> (Vec<Chunk>, Vec<Chunk>) {
+    (self.to_chunks(), vec![])
+  }
+
+  // Playback order by section such as "A A B B C | D.C. al Fine: A B".
+  // Sections are named in tick order, split at every chunk boundary.
+  fn playback_plan(&self) -> String {
+    let (pass0, pass1) = self.to_chunk_passes();
+    let boundaries: BTreeSet<u32> = pass0.iter().chain(pass1.iter())
+      .flat_map(|c| [c.start_tick, c.end_tick])
+      .collect();
+    let boundaries: Vec<u32> = boundaries.into_iter().collect();
+    let describe_pass = |chunks: &[Chunk]| -> String {
+      chunks.iter().flat_map(|c| {
+        let from = boundaries.partition_point(|b| *b < c.start_tick);
+        let to = boundaries.partition_point(|b| *b < c.end_tick);
+        (from..to).map(section_name)
+      }).collect::<Vec<String>>().join(" ")
+    };
+
+    let mut plan = describe_pass(&pass0);
+    if let Some(gr) = self.global_repeat() {
+      plan.push_str(&format!(" | {}: {}", gr.describe(), describe_pass(&pass1)));
+    }
+    plan
+  }
+}
+
+// A, B, ..., Z, AA, AB, ...
+fn section_name(idx: usize) -> String {
+  let mut name = vec![];
+  let mut i = idx + 1;
+  while 0 < i {
+    i -= 1;
+    name.push((b'A' + (i % 26) as u8) as char);
+    i /= 26;
+  }
+  name.iter().rev().collect()
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -285,17 +361,28 @@ pub struct CompoundRegion {
 
 impl Region for CompoundRegion {
   fn to_chunks(&self) -> Vec<Chunk> {
+    let (mut chunks, iter1) = self.to_chunk_passes();
+    chunks.extend(iter1);
+    chunks
+  }
+
+  fn global_repeat(&self) -> Option<&GlobalRepeat> {
+    self.global_repeat.as_ref()
+  }
+
+  fn to_chunk_passes(&self) -> (Vec<Chunk>, Vec<Chunk>) {
     match self.global_repeat.as_ref() {
         Some(gr) => {
-          let mut chunks = vec![];
+          let mut iter0 = vec![];
           for r in self.regions.iter() {
-            chunks.extend(r.render_chunks(&RenderPhase::DcDsIter0 { dc_ds_tick: gr.ds_dc().tick() }));
+            iter0.extend(r.render_chunks(&RenderPhase::DcDsIter0 { dc_ds_tick: gr.ds_dc().tick() }));
           }
+          let mut iter1 = vec![];
           for r in self.regions.iter() {
-            chunks.extend(r.render_chunks(&RenderPhase::DcDsIter1(gr.clone())));
+            iter1.extend(r.render_chunks(&RenderPhase::DcDsIter1(gr.clone())));
           }
 
-          chunks
+          (iter0, iter1)
         }
         None => {
           let mut chunks = vec![];
@@ -303,7 +390,7 @@ impl Region for CompoundRegion {
             chunks.extend(r.render_chunks(&RenderPhase::NonDcDs));
           }
 
-          chunks
+          (chunks, vec![])
         }
     }
   }
@@ -353,10 +440,58 @@ pub enum RenderRegionError {
   DcDsWhileVariation { tick: u32 },
   SegnoWhildVariation { tick: u32 },
   CodaAfterFine { coda_from: u32, coda_to: u32, fine: u32 },
+  // Playing up to the chunk starting at the tick goes past the range of AccumTick.
+  AccumTickOverflow { tick: u32 },
 }
 
 impl Context for RenderRegionError {}
 
+impl RenderRegionError {
+  // Tick of the bar to blame. The later one if more than one bar is involved.
+  pub fn tick(&self) -> u32 {
+    match self {
+      RenderRegionError::DuplicatedRepeatStart { tick } => *tick,
+      RenderRegionError::DuplicatedSegno { tick } => tick[1],
+      RenderRegionError::DuplicatedDsDc { tick } => tick[1],
+      RenderRegionError::DuplicatedFine { tick } => tick[1],
+      RenderRegionError::OrphanRepeatEnd { tick } => *tick,
+      RenderRegionError::FineWithoutDsDc { tick } => *tick,
+      RenderRegionError::SegnoWithoutDs { tick } => *tick,
+      RenderRegionError::CodaWithoutDsDc { tick } => tick[1],
+      RenderRegionError::NoRepeatEnd { tick } => *tick,
+      RenderRegionError::InvalidRegionIndex { tick, .. } => *tick,
+      RenderRegionError::RepeatInVariation { tick } => *tick,
+      RenderRegionError::VariationNotClosed { tick } => *tick,
+      RenderRegionError::RepeatOrVariationOnDc { tick } => *tick,
+      RenderRegionError::RepeatOrVariationOnDs { tick } => *tick,
+      RenderRegionError::FineNotAfterSegno { fine_tick, .. } => *fine_tick,
+      RenderRegionError::NoSegnoForDs { ds_tick } => *ds_tick,
+      RenderRegionError::MoreThanTwoCodas { tick } => tick[2],
+      RenderRegionError::OnlyOneCoda { tick } => *tick,
+      RenderRegionError::DcDsWhileRepeat { tick } => *tick,
+      RenderRegionError::DcDsWhileVariation { tick } => *tick,
+      RenderRegionError::SegnoWhildVariation { tick } => *tick,
+      RenderRegionError::CodaAfterFine { coda_to, .. } => *coda_to,
+      RenderRegionError::AccumTickOverflow { tick } => *tick,
+    }
+  }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RepeatDiagnostic {
+  Error(RenderRegionError),
+  Warning(RenderRegionWarning),
+}
+
+impl RepeatDiagnostic {
+  pub fn tick(&self) -> u32 {
+    match self {
+      RepeatDiagnostic::Error(e) => e.tick(),
+      RepeatDiagnostic::Warning(w) => w.tick(),
+    }
+  }
+}
+
 impl Display for RenderRegionError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self)
@@ -499,11 +634,22 @@ pub fn render_region<'a>(tune_rhythm: Rhythm, bars: impl Iterator<Item = &'a Bar
   }
 }
 
+// Problems of repeat structure by tick of the bar. Rendering stops at the first error, so at most one error is reported.
+pub fn repeat_diagnostics<'a>(tune_rhythm: Rhythm, bars: impl Iterator<Item = &'a Bar>) -> Vec<(u32, RepeatDiagnostic)> {
+  match render_region(tune_rhythm, bars) {
+    Ok((_, warnings)) => warnings.into_iter().map(|w| (w.tick(), RepeatDiagnostic::Warning(w))).collect(),
+    Err(report) => {
+      let e = report.current_context().clone();
+      vec![(e.tick(), RepeatDiagnostic::Error(e))]
+    }
+  }
+}
+
 #[cfg(test)]
 mod tests {
-  use crate::{bar::{Bar, Repeat}, play_iter::PlayIter, play_start_tick::{PlayStartTick, ToAccumTickError}, repeat::{render_region, Chunk, GlobalRepeatBuilder, RenderRegionError, SimpleRegion}, rhythm::Rhythm};
+  use crate::{bar::{Bar, Repeat}, play_iter::PlayIter, play_start_tick::{PlayStartTick, ToAccumTickError}, repeat::{render_region, repeat_diagnostics, Chunk, GlobalRepeatBuilder, RenderRegionError, RepeatDiagnostic, SimpleRegion}, rhythm::Rhythm, global_repeat::RenderRegionWarning};
   use crate::repeat_set;
-  use super::{AccumTick, RenderPhase, SequenceRegion};
+  use super::{AccumTick, Chunks, RenderPhase, SequenceRegion};
   use crate::bar::RepeatSet;
   use error_stack::Result;
 
@@ -519,7 +665,7 @@ mod tests {
     assert_eq!(chunks.len(), 1);
     assert_eq!(chunks[0], Chunk::new(0, u32::MAX));
 
-    let by_accum_tick = Chunk::by_accum_tick(&chunks);
+    let by_accum_tick = Chunk::by_accum_tick(&chunks).unwrap();
     assert_eq!(to_accum_tick(0, 1, &by_accum_tick).unwrap(), 0);
     assert_eq!(to_accum_tick(100, 1, &by_accum_tick).unwrap(), 100);
     assert_eq!(to_accum_tick(100, 2, &by_accum_tick), Err(ToAccumTickError::CannotFind { specified_iter: PlayIter::new(2), max_iter: 1 }));
@@ -536,7 +682,7 @@ mod tests {
     assert_eq!(chunks.len(), 1);
     assert_eq!(chunks[0], Chunk::new(0, u32::MAX));
 
-    let by_accum_tick = Chunk::by_accum_tick(&chunks);
+    let by_accum_tick = Chunk::by_accum_tick(&chunks).unwrap();
     assert_eq!(to_accum_tick(0, 1, &by_accum_tick).unwrap(), 0);
     assert_eq!(to_accum_tick(100, 1, &by_accum_tick).unwrap(), 100);
     assert_eq!(to_accum_tick(100, 2, &by_accum_tick), Err(ToAccumTickError::CannotFind { specified_iter: PlayIter::new(2), max_iter: 1 }));
@@ -564,7 +710,7 @@ mod tests {
     assert_eq!(chunks[0], Chunk::new(0, 100));
     assert_eq!(chunks[1], Chunk::new(0, u32::MAX));
 
-    let by_accum_tick = Chunk::by_accum_tick(&chunks);
+    let by_accum_tick = Chunk::by_accum_tick(&chunks).unwrap();
     assert_eq!(to_accum_tick(0, 1, &by_accum_tick).unwrap(), 0);
     assert_eq!(to_accum_tick(50, 1, &by_accum_tick).unwrap(), 50);
     assert_eq!(to_accum_tick(0, 2, &by_accum_tick).unwrap(), 100);
@@ -615,7 +761,7 @@ mod tests {
     assert_eq!(chunks[0], Chunk::new(0, 200));
     assert_eq!(chunks[1], Chunk::new(100, u32::MAX));
 
-    let by_accum_tick = Chunk::by_accum_tick(&chunks);
+    let by_accum_tick = Chunk::by_accum_tick(&chunks).unwrap();
     assert_eq!(to_accum_tick(0, 1, &by_accum_tick).unwrap(), 0);
     assert_eq!(to_accum_tick(0, 2, &by_accum_tick), Err(ToAccumTickError::CannotFind { specified_iter: PlayIter::new(2), max_iter: 1 }));
     assert_eq!(to_accum_tick(50, 2, &by_accum_tick), Err(ToAccumTickError::CannotFind { specified_iter: PlayIter::new(2), max_iter: 1 }));
@@ -654,7 +800,7 @@ mod tests {
     assert_eq!(chunks[0], Chunk::new(0, 200));
     assert_eq!(chunks[1], Chunk::new(50, u32::MAX));
 
-    let by_accum_tick = Chunk::by_accum_tick(&chunks);
+    let by_accum_tick = Chunk::by_accum_tick(&chunks).unwrap();
     assert_eq!(to_accum_tick(0, 1, &by_accum_tick).unwrap(), 0);
     assert_eq!(to_accum_tick(50, 1, &by_accum_tick).unwrap(), 50);
     assert_eq!(to_accum_tick(50, 2, &by_accum_tick).unwrap(), 200);
@@ -684,6 +830,7 @@ mod tests {
     assert_eq!(chunks[2], Chunk::new(0, 50));
     assert_eq!(chunks[3], Chunk::new(100, 150));
     assert_eq!(chunks[4], Chunk::new(150, u32::MAX));
+    assert_eq!(region.playback_plan(), "A B A C D");
 
     let chunks = Chunk::optimize(&chunks);
     assert_eq!(chunks.len(), 3);
@@ -691,7 +838,7 @@ mod tests {
     assert_eq!(chunks[1], Chunk::new(0, 50));
     assert_eq!(chunks[2], Chunk::new(100, u32::MAX));
 
-    let by_accum_tick = Chunk::by_accum_tick(&chunks);
+    let by_accum_tick = Chunk::by_accum_tick(&chunks).unwrap();
     assert_eq!(to_accum_tick(0, 1, &by_accum_tick).unwrap(), 0);
     assert_eq!(to_accum_tick(0, 2, &by_accum_tick).unwrap(), 100);
     assert_eq!(to_accum_tick(20, 2, &by_accum_tick).unwrap(), 120);
@@ -729,7 +876,7 @@ mod tests {
     assert_eq!(chunks[1], Chunk::new(0, 100));
     assert_eq!(chunks[2], Chunk::new(350, u32::MAX));
 
-    let by_accum_tick = Chunk::by_accum_tick(&chunks);
+    let by_accum_tick = Chunk::by_accum_tick(&chunks).unwrap();
     assert_eq!(to_accum_tick(0, 1, &by_accum_tick).unwrap(), 0);
     assert_eq!(to_accum_tick(200, 1, &by_accum_tick).unwrap(), 200);
     assert_eq!(to_accum_tick(0, 2, &by_accum_tick).unwrap(), 350);
@@ -764,7 +911,7 @@ mod tests {
     assert_eq!(chunks[0], Chunk::new(0, 1440));
     assert_eq!(chunks[1], Chunk::new(0, 480));
 
-    let by_accum_tick = Chunk::by_accum_tick(&chunks);
+    let by_accum_tick = Chunk::by_accum_tick(&chunks).unwrap();
     assert_eq!(to_accum_tick(0, 1, &by_accum_tick).unwrap(), 0);
     assert_eq!(to_accum_tick(0, 2, &by_accum_tick).unwrap(), 1440);
     assert_eq!(to_accum_tick(0, 3, &by_accum_tick), Err(ToAccumTickError::CannotFind { specified_iter: PlayIter::new(3), max_iter: 2 }));
@@ -806,7 +953,7 @@ mod tests {
     assert_eq!(chunks[3], Chunk::new(0, 480));
     assert_eq!(chunks[4], Chunk::new(730, 1030));
 
-    let by_accum_tick = Chunk::by_accum_tick(&chunks);
+    let by_accum_tick = Chunk::by_accum_tick(&chunks).unwrap();
     assert_eq!(to_accum_tick(0, 1, &by_accum_tick).unwrap(), 0);
     assert_eq!(to_accum_tick(480, 1, &by_accum_tick).unwrap(), 480);
     assert_eq!(to_accum_tick(400, 2, &by_accum_tick).unwrap(), 730 + 400);
@@ -1118,6 +1265,8 @@ mod tests {
     assert_eq!(*z.next().unwrap(), Chunk::new(270, 370));
     assert_eq!(*z.next().unwrap(), Chunk::new(0, 170));
     assert_eq!(z.next(), None);
+
+    assert_eq!(region.playback_plan(), "A B B C C D D | D.C. al Fine: A B");
   }
 
   // 0 120 170  270  370    470
@@ -1264,6 +1413,8 @@ mod tests {
     assert_eq!(*z.next().unwrap(), Chunk::new(200, 370));
     assert_eq!(*z.next().unwrap(), Chunk::new(570, u32::MAX));
     assert_eq!(z.next(), None);
+
+    assert_eq!(region.playback_plan(), "A B A B C D E | D.S. al Coda: B C E F");
   }    
 
   // 0   120   200         270  370     470  570     670   770
@@ -1415,4 +1566,63 @@ mod tests {
     let chunks = region.to_chunks();
     assert_eq!(chunks.len(), 1);
   }
+
+  #[test]
+  fn section_name() {
+    assert_eq!(super::section_name(0), "A");
+    assert_eq!(super::section_name(25), "Z");
+    assert_eq!(super::section_name(26), "AA");
+    assert_eq!(super::section_name(27), "AB");
+  }
+
+  #[test]
+  fn chunk_intersect() {
+    let c = Chunk::new(100, 200);
+    assert_eq!(c.intersect(0..150), Some(Chunk::new(100, 150)));
+    assert_eq!(c.intersect(150..u32::MAX), Some(Chunk::new(150, 200)));
+    assert_eq!(c.intersect(120..130), Some(Chunk::new(120, 130)));
+    assert_eq!(c.intersect(200..300), None);
+    assert_eq!(c.intersect(0..100), None);
+  }
+
+  #[test]
+  fn chunk_split_at() {
+    let c = Chunk::new(100, 200);
+    assert_eq!(c.split_at(150), (Some(Chunk::new(100, 150)), Some(Chunk::new(150, 200))));
+    assert_eq!(c.split_at(100), (None, Some(c)));
+    assert_eq!(c.split_at(200), (Some(c), None));
+    assert_eq!(c.split_at(0), (None, Some(c)));
+  }
+
+  #[test]
+  fn chunks_total_len() {
+    let chunks = [Chunk::new(0, 100), Chunk::new(0, 100), Chunk::new(100, 250)];
+    assert_eq!(chunks.total_len(), Some(350));
+    assert_eq!([Chunk::new(0, 100), Chunk::new(100, u32::MAX)].total_len(), None);
+    assert_eq!(([] as [Chunk; 0]).total_len(), Some(0));
+  }
+
+  #[test]
+  fn diagnostics() {
+    let bars = [
+      Bar::new(960, None, None, repeat_set!(Repeat::Start)),
+      Bar::new(1920, None, None, repeat_set!(Repeat::Start)),
+    ];
+    assert_eq!(
+      repeat_diagnostics(Rhythm::new(4, 4), bars.iter()),
+      vec![(1920, RepeatDiagnostic::Error(RenderRegionError::DuplicatedRepeatStart { tick: 1920 }))]
+    );
+
+    let bars = [
+      Bar::new(960, None, None, repeat_set!(Repeat::Coda)),
+      Bar::new(1920, None, None, repeat_set!(Repeat::Dc)),
+    ];
+    assert_eq!(
+      repeat_diagnostics(Rhythm::new(4, 4), bars.iter()),
+      vec![(960, RepeatDiagnostic::Warning(RenderRegionWarning::OrphanCodaFound { coda_tick: 960 }))]
+    );
+
+    let bars = [Bar::new(960, None, None, repeat_set!(Repeat::End))];
+    assert!(repeat_diagnostics(Rhythm::new(4, 4), bars.iter()).is_empty());
+  }
 }
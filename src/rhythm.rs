@@ -1,12 +1,15 @@
-use std::{fmt::Display, str::FromStr};
+use std::{fmt::Display, ops::Range, str::FromStr};
 
 use super::duration::Duration;
 
 pub const MIN_NUMERATOR: u8 = 1;
 pub const MAX_NUMERATOR: u8 = 99;
+pub const MAX_BEAT_GROUPS: usize = 16;
 
 #[derive(serde::Deserialize, serde::Serialize)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "schema", schemars(rename = "RhythmNumerator"))]
 pub struct Numerator(u8);
 
 impl Display for Numerator {
@@ -48,6 +51,8 @@ impl FromStr for Numerator {
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[derive(serde::Deserialize, serde::Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "schema", schemars(rename = "RhythmDenominator"))]
 pub enum Denominator {
     D2, D4, D8, D16, D32, D64,
 }
@@ -90,18 +95,146 @@ impl Denominator {
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Accent {
+    Strong,
+    Medium,
+    Weak,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BeatGroupingError {
+    Empty,
+    TooManyGroups(usize),
+    ZeroGroup,
+    CannotParse(String),
+    // Groups after the length are not zero.
+    TrailingGroups,
+}
+
+impl Display for BeatGroupingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BeatGroupingError::Empty => write!(f, "No beat groups"),
+            BeatGroupingError::TooManyGroups(len) => write!(f, "Too many beat groups ({})", len),
+            BeatGroupingError::ZeroGroup => write!(f, "Beat group of zero beats"),
+            BeatGroupingError::CannotParse(s) => write!(f, "Cannot parse beat grouping '{}'", s),
+            BeatGroupingError::TrailingGroups => write!(f, "Beat groups after the length"),
+        }
+    }
+}
+
+// Beats per group such as 2+2+3 for 7/8. Kept in a fixed array so that Rhythm stays Copy.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "RawBeatGrouping")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct BeatGrouping {
+    len: u8,
+    groups: [u8; MAX_BEAT_GROUPS],
+}
+
+// Same shape as BeatGrouping. Validated by BeatGrouping::new() when deserialized.
+#[derive(serde::Deserialize)]
+struct RawBeatGrouping {
+    len: u8,
+    groups: [u8; MAX_BEAT_GROUPS],
+}
+
+impl TryFrom<RawBeatGrouping> for BeatGrouping {
+    type Error = BeatGroupingError;
+
+    fn try_from(raw: RawBeatGrouping) -> Result<Self, Self::Error> {
+        let len = raw.len as usize;
+        if MAX_BEAT_GROUPS < len {
+            return Err(BeatGroupingError::TooManyGroups(len));
+        }
+        if raw.groups[len..].iter().any(|g| *g != 0) {
+            return Err(BeatGroupingError::TrailingGroups);
+        }
+        Self::new(&raw.groups[..len])
+    }
+}
+
+impl BeatGrouping {
+    pub fn new(groups: &[u8]) -> Result<Self, BeatGroupingError> {
+        if groups.is_empty() {
+            return Err(BeatGroupingError::Empty);
+        }
+        if MAX_BEAT_GROUPS < groups.len() {
+            return Err(BeatGroupingError::TooManyGroups(groups.len()));
+        }
+        if groups.contains(&0) {
+            return Err(BeatGroupingError::ZeroGroup);
+        }
+        let mut array = [0; MAX_BEAT_GROUPS];
+        array[..groups.len()].copy_from_slice(groups);
+        Ok(Self { len: groups.len() as u8, groups: array })
+    }
+
+    pub fn groups(&self) -> &[u8] {
+        &self.groups[..self.len as usize]
+    }
+
+    pub fn total_beats(&self) -> u32 {
+        self.groups().iter().map(|g| *g as u32).sum()
+    }
+}
+
+impl Display for BeatGrouping {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let groups: Vec<String> = self.groups().iter().map(|g| g.to_string()).collect();
+        write!(f, "{}", groups.join("+"))
+    }
+}
+
+impl FromStr for BeatGrouping {
+    type Err = BeatGroupingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let groups = s.split('+').map(|g| u8::from_str(g.trim()))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| BeatGroupingError::CannotParse(s.to_owned()))?;
+        Self::new(&groups)
+    }
+}
+
 #[derive(serde::Deserialize, serde::Serialize)]
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum RhythmError {
     NumeratorError(u8),
-    DenominatorError(u8)
+    DenominatorError(u8),
+    // Total beats of the grouping does not match the numerator.
+    GroupingError(u32),
 }
 
-#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(serde::Deserialize)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Rhythm {
     pub numerator: Numerator,
     pub denominator: Denominator,
+    // None means the default grouping (see beat_groups()).
+    #[serde(default)]
+    pub grouping: Option<BeatGrouping>,
+}
+
+// The default grouping is left out of JSON so that it reads the same as before the grouping was added.
+// Binary formats always have the field.
+impl serde::Serialize for Rhythm {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let skip_grouping = serializer.is_human_readable() && self.grouping.is_none();
+        let mut state = serializer.serialize_struct("Rhythm", if skip_grouping { 2 } else { 3 })?;
+        state.serialize_field("numerator", &self.numerator)?;
+        state.serialize_field("denominator", &self.denominator)?;
+        if skip_grouping {
+            state.skip_field("grouping")?;
+        } else {
+            state.serialize_field("grouping", &self.grouping)?;
+        }
+        state.end()
+    }
 }
 
 impl Rhythm {
@@ -134,12 +267,90 @@ impl Rhythm {
             Ok(d) => d,
         };
 
-        Ok(Self {numerator, denominator})
+        Ok(Self {numerator, denominator, grouping: None})
+    }
+
+    pub fn with_grouping(self, grouping: BeatGrouping) -> Result<Rhythm, RhythmError> {
+        if grouping.total_beats() != self.numerator.0 as u32 {
+            return Err(RhythmError::GroupingError(grouping.total_beats()));
+        }
+        Ok(Self { grouping: Some(grouping), ..self })
+    }
+
+    pub fn without_grouping(self) -> Rhythm {
+        Self { grouping: None, ..self }
     }
 
     pub fn tick_len(self) -> u32 {
         ((self.numerator.0 as i32) * Duration::TICK_RESOLUTION * 4 / (self.denominator.value() as i32)) as u32
     }
+
+    pub fn beat_tick_len(self) -> u32 {
+        (Duration::TICK_RESOLUTION * 4 / (self.denominator.value() as i32)) as u32
+    }
+
+    // 6/8, 9/8, 6/4 and so on. Beats are grouped by three.
+    pub fn is_compound(self) -> bool {
+        3 < self.numerator.0 && self.numerator.0.is_multiple_of(3)
+    }
+
+    // Beats per group. Unless the grouping is specified, beats are grouped by three for compound meters,
+    // otherwise by two (the last group takes three for odd meters such as 5/4 = 2 + 3).
+    pub fn beat_groups(self) -> Vec<u8> {
+        if let Some(grouping) = self.grouping {
+            return grouping.groups().to_vec();
+        }
+        let n = self.numerator.0;
+        if n <= 3 {
+            vec![n]
+        } else if self.is_compound() {
+            vec![3; (n / 3) as usize]
+        } else {
+            let mut groups = vec![2; (n / 2) as usize];
+            if n % 2 == 1 {
+                *groups.last_mut().unwrap() = 3;
+            }
+            groups
+        }
+    }
+
+    // Tick range of each beat group in a bar. Used to decide beaming and click emphasis.
+    pub fn group_ranges(self) -> Vec<Range<u32>> {
+        let beat_len = self.beat_tick_len();
+        let mut start = 0;
+        self.beat_groups().into_iter().map(|g| {
+            let end = start + g as u32 * beat_len;
+            let r = start..end;
+            start = end;
+            r
+        }).collect()
+    }
+
+    // Emphasis of each beat in a bar. The first beat of a bar is strong, the first beat of other groups
+    // is medium.
+    pub fn accent_pattern(self) -> Vec<Accent> {
+        let n = self.numerator.0 as usize;
+        let mut group_starts = vec![];
+        let mut start = 0;
+        for g in self.beat_groups() {
+            group_starts.push(start);
+            start += g as usize;
+        }
+
+        (0..n).map(|i|
+            if i == 0 { Accent::Strong }
+            else if group_starts.contains(&i) { Accent::Medium }
+            else { Accent::Weak }
+        ).collect()
+    }
+
+    pub fn accent_at(self, offset_in_bar: u32) -> Accent {
+        let beat_len = self.beat_tick_len();
+        if !offset_in_bar.is_multiple_of(beat_len) {
+            return Accent::Weak;
+        }
+        self.accent_pattern().get((offset_in_bar / beat_len) as usize).copied().unwrap_or(Accent::Weak)
+    }
 }
 
 impl Default for Rhythm {
@@ -153,6 +364,7 @@ mod tests {
     use serde_json::Value;
     use serde_json::json;
 
+    use super::Accent;
     use super::Rhythm;
     use super::RhythmError;
 
@@ -191,6 +403,22 @@ mod tests {
         assert_eq!(Rhythm::value_of(6, 8).ok().unwrap().tick_len(), 120 * 6);
     }
 
+    #[test]
+    fn accent_pattern() {
+        use Accent::{Strong as S, Medium as M, Weak as W};
+        assert_eq!(Rhythm::new(1, 4).accent_pattern(), vec![S]);
+        assert_eq!(Rhythm::new(3, 4).accent_pattern(), vec![S, W, W]);
+        assert_eq!(Rhythm::new(4, 4).accent_pattern(), vec![S, W, M, W]);
+        assert_eq!(Rhythm::new(5, 4).accent_pattern(), vec![S, W, M, W, W]);
+        assert_eq!(Rhythm::new(7, 8).accent_pattern(), vec![S, W, M, W, M, W, W]);
+        assert_eq!(Rhythm::new(6, 8).accent_pattern(), vec![S, W, W, M, W, W]);
+        assert_eq!(Rhythm::new(12, 8).accent_pattern(), vec![S, W, W, M, W, W, M, W, W, M, W, W]);
+
+        assert_eq!(Rhythm::new(6, 8).accent_at(360), M);
+        assert_eq!(Rhythm::new(7, 8).with_grouping("3+2+2".parse().unwrap()).unwrap().accent_pattern(), vec![S, W, W, M, W, M, W]);
+        assert_eq!(Rhythm::new(6, 8).accent_at(370), W);
+    }
+
     #[test]
     fn can_serialize_to_json() {
         let json_str = serde_json::to_string(&Rhythm::new(3, 4)).unwrap();
@@ -214,4 +442,41 @@ mod tests {
         "#).unwrap();
         assert_eq!(rhythm, Rhythm::new(3, 4));
     }
+
+    #[test]
+    fn beat_grouping() {
+        use super::{BeatGrouping, BeatGroupingError};
+        let grouping: BeatGrouping = "2+2+3".parse().unwrap();
+        assert_eq!(grouping.groups(), &[2, 2, 3]);
+        assert_eq!(grouping.to_string(), "2+2+3");
+        assert_eq!(BeatGrouping::new(&[]), Err(BeatGroupingError::Empty));
+        assert_eq!(BeatGrouping::new(&[2, 0]), Err(BeatGroupingError::ZeroGroup));
+        assert!("2+x".parse::<BeatGrouping>().is_err());
+
+        assert_eq!(Rhythm::new(7, 8).with_grouping(BeatGrouping::new(&[3, 3]).unwrap()), Err(RhythmError::GroupingError(6)));
+        let rhythm = Rhythm::new(7, 8).with_grouping(grouping).unwrap();
+        assert_eq!(rhythm.beat_groups(), vec![2, 2, 3]);
+        assert_eq!(rhythm.group_ranges(), vec![0..240, 240..480, 480..840]);
+        assert_eq!(rhythm.without_grouping(), Rhythm::new(7, 8));
+        assert_eq!(Rhythm::new(5, 4).beat_groups(), vec![2, 3]);
+        assert_eq!(Rhythm::new(9, 8).beat_groups(), vec![3, 3, 3]);
+
+        let json_str = serde_json::to_string(&rhythm).unwrap();
+        assert_eq!(serde_json::from_str::<Rhythm>(&json_str).unwrap(), rhythm);
+        for rhythm in [rhythm, Rhythm::new(7, 8)] {
+            let bin = bincode::serialize(&rhythm).unwrap();
+            assert_eq!(bincode::deserialize::<Rhythm>(&bin).unwrap(), rhythm);
+        }
+
+        let groups = |len: u8, groups: &[u8]| {
+            let mut array = [0u8; 16];
+            array[..groups.len()].copy_from_slice(groups);
+            serde_json::from_value::<BeatGrouping>(serde_json::json!({ "len": len, "groups": array }))
+        };
+        assert_eq!(groups(3, &[2, 2, 3]).unwrap(), grouping);
+        assert!(groups(0, &[]).is_err());
+        assert!(groups(2, &[2, 0]).is_err());
+        assert!(groups(2, &[2, 2, 3]).is_err());
+        assert!(groups(17, &[2; 16]).is_err());
+    }
 }
@@ -0,0 +1,181 @@
+use std::ops::RangeInclusive;
+
+use crate::{grid::{Grid, SnapRounding}, models::Models, note::Note, velocity::Velocity};
+
+// Cleanup shared by importers. Each step is skipped when its option is off (None/false).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SanitizeOptions {
+    pub dedupe: bool,
+    pub velocity_range: Option<RangeInclusive<u8>>,
+    // Start ticks within the epsilon of the grid are snapped. The grid counts from the bar lines of the models.
+    // See Grid::snap_in_bar().
+    pub snap: Option<(Grid, u32)>,
+    // Notes whose duration is longer than this are split into tied notes.
+    pub max_tick_len: Option<u32>,
+}
+
+impl Default for SanitizeOptions {
+    fn default() -> Self {
+        Self {
+            dedupe: true,
+            velocity_range: None,
+            snap: None,
+            max_tick_len: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SanitizeReport {
+    pub duplicates_removed: usize,
+    pub velocities_clamped: usize,
+    pub ticks_snapped: usize,
+    pub notes_split: usize,
+}
+
+impl SanitizeReport {
+    pub fn is_clean(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+// Bar lines are the start ticks of the bars, sorted.
+fn snap_tick(tick: u32, grid: Grid, epsilon: u32, bar_lines: &[u32]) -> u32 {
+    let i = bar_lines.partition_point(|t| *t <= tick);
+    let start = if i == 0 { 0 } else { bar_lines[i - 1] };
+    let end = bar_lines.get(i).copied().unwrap_or(u32::MAX);
+    let snapped = grid.snap_in_bar(tick, start..end, SnapRounding::Nearest);
+    if snapped.abs_diff(tick) <= epsilon { snapped } else { tick }
+}
+
+// Split the note into tied notes no longer than max_tick_len. Parts that cannot be notated are left as is.
+fn split_overlong(note: Note, max_tick_len: u32, report: &mut SanitizeReport) -> Vec<Note> {
+    let mut parts = vec![];
+    let mut rest = note;
+    while max_tick_len < rest.duration.tick_length() {
+        match rest.split_at(rest.base_start_tick + max_tick_len) {
+            Ok((first, second)) => {
+                parts.push(first);
+                rest = second;
+                report.notes_split += 1;
+            }
+            Err(_) => break,
+        }
+    }
+    parts.push(rest);
+    parts
+}
+
+// Bars are kept as is.
+pub fn sanitize(models: Models, options: &SanitizeOptions) -> (Models, SanitizeReport) {
+    let mut report = SanitizeReport::default();
+    let mut notes = models.notes;
+    let mut tempos = models.tempos;
+    let mut dumpers = models.dumpers;
+    let mut softs = models.softs;
+
+    if let Some((grid, epsilon)) = options.snap {
+        let mut bar_lines: Vec<u32> = models.bars.iter().map(|b| b.start_tick).collect();
+        bar_lines.sort_unstable();
+        for n in notes.iter_mut() {
+            let tick = snap_tick(n.base_start_tick, grid, epsilon, &bar_lines);
+            if tick != n.base_start_tick {
+                n.base_start_tick = tick;
+                report.ticks_snapped += 1;
+            }
+        }
+        let ctrl_ticks = tempos.iter_mut().map(|t| &mut t.start_tick)
+            .chain(dumpers.iter_mut().map(|d| &mut d.start_tick))
+            .chain(softs.iter_mut().map(|s| &mut s.start_tick));
+        for start_tick in ctrl_ticks {
+            let tick = snap_tick(*start_tick, grid, epsilon, &bar_lines);
+            if tick != *start_tick {
+                *start_tick = tick;
+                report.ticks_snapped += 1;
+            }
+        }
+    }
+
+    if let Some(range) = &options.velocity_range {
+        for n in notes.iter_mut() {
+            let v = n.base_velocity.as_u8();
+            let clamped = v.clamp(*range.start(), *range.end());
+            if clamped != v {
+                n.base_velocity = Velocity::new(clamped);
+                report.velocities_clamped += 1;
+            }
+        }
+    }
+
+    if options.dedupe {
+        let mut unique: Vec<Note> = Vec::with_capacity(notes.len());
+        for n in notes {
+            if unique.contains(&n) {
+                report.duplicates_removed += 1;
+            } else {
+                unique.push(n);
+            }
+        }
+        notes = unique;
+    }
+
+    if let Some(max_tick_len) = options.max_tick_len {
+        notes = notes.into_iter().flat_map(|n| split_overlong(n, max_tick_len, &mut report)).collect();
+    }
+
+    (Models { notes, bars: models.bars, tempos, dumpers, softs }, report)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{bar::Bar, models::Models, note::Note, grid::Grid, velocity::Velocity, tempo::{Tempo, TempoValue}, duration::{Duration, Numerator, Denominator, Dots}};
+    use super::{sanitize, SanitizeOptions, SanitizeReport};
+
+    #[test]
+    fn default_only_dedupes() {
+        let note = Note { base_start_tick: 3, base_velocity: Velocity::new(127), ..Default::default() };
+        let models = Models { notes: vec![note.clone(), note.clone()], ..Models::empty() };
+        let (sanitized, report) = sanitize(models, &SanitizeOptions::default());
+        assert_eq!(sanitized.notes, vec![note]);
+        assert_eq!(report, SanitizeReport { duplicates_removed: 1, ..Default::default() });
+    }
+
+    #[test]
+    fn all_steps() {
+        let whole = Duration::new(Numerator::Whole, Denominator::from_value(2).unwrap(), Dots::ZERO);
+        let models = Models {
+            notes: vec![
+                Note { base_start_tick: 238, base_velocity: Velocity::new(127), ..Default::default() },
+                Note { base_start_tick: 240, base_velocity: Velocity::new(127), ..Default::default() },
+                Note { base_start_tick: 1000, duration: whole, base_velocity: Velocity::new(64), ..Default::default() },
+            ],
+            tempos: vec![Tempo { start_tick: 485, value: TempoValue::new(100), marking: None }],
+            ..Models::empty()
+        };
+        let options = SanitizeOptions {
+            dedupe: true,
+            velocity_range: Some(1..=100),
+            snap: Some((Grid::from_u32(240).unwrap(), 5)),
+            max_tick_len: Some(480),
+        };
+        let (sanitized, report) = sanitize(models, &options);
+        assert_eq!(report, SanitizeReport { duplicates_removed: 1, velocities_clamped: 2, ticks_snapped: 2, notes_split: 1 });
+        let notes: Vec<(u32, u32, bool, bool)> = sanitized.notes.iter().map(|n| (n.base_start_tick, n.duration.tick_length(), n.tie, n.tied)).collect();
+        assert_eq!(notes, vec![(240, 240, false, false), (1000, 480, true, false), (1480, 480, false, true)]);
+        assert_eq!(sanitized.tempos[0].start_tick, 480);
+        assert!(!report.is_clean());
+    }
+
+    #[test]
+    fn snap_from_bar_line() {
+        let models = Models {
+            notes: vec![Note { base_start_tick: 338, ..Default::default() }, Note { base_start_tick: 95, ..Default::default() }],
+            bars: vec![Bar::new(100, None, None, Default::default())],
+            ..Models::empty()
+        };
+        let options = SanitizeOptions { snap: Some((Grid::from_u32(240).unwrap(), 5)), ..Default::default() };
+        let (sanitized, report) = sanitize(models, &options);
+        assert_eq!(sanitized.notes.iter().map(|n| n.base_start_tick).collect::<Vec<_>>(), vec![340, 100]);
+        assert_eq!(report.ticks_snapped, 2);
+    }
+}
@@ -0,0 +1,123 @@
+use std::time::SystemTime;
+
+use error_stack::Report;
+use serdo::{sqlite_undo_store_error::SqliteUndoStoreError, undo_store::{UndoStore, SQLITE_FILE_NAME}};
+
+use crate::project::ProjectStore;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SaveStatus {
+    // Commands not yet written to the sqlite file. Serdo only tells whether it has caught up, so this counts the
+    // commands sent since the store was last seen saved. Zero right after open.
+    pub pending_cmds: usize,
+    // Last write to the sqlite file. None if the file is not there.
+    pub last_saved_at: Option<SystemTime>,
+}
+
+pub trait SaveProgress {
+    // Unlike wait_until_saved(), this never blocks.
+    fn save_status(&mut self) -> Result<SaveStatus, Report<SqliteUndoStoreError>>;
+}
+
+impl SaveProgress for ProjectStore {
+    fn save_status(&mut self) -> Result<SaveStatus, Report<SqliteUndoStoreError>> {
+        // A freshly opened store has written nothing yet but has nothing to write either.
+        let saved = self.saved()?;
+        let sent_cmds = self.model().sent_cmds();
+        if saved { sent_cmds.set(0); }
+        let last_saved_at = std::fs::metadata(self.dir().join(SQLITE_FILE_NAME)).and_then(|m| m.modified()).ok();
+        Ok(SaveStatus { pending_cmds: sent_cmds.get(), last_saved_at })
+    }
+}
+
+pub type SavedCallback = Box<dyn FnOnce(&SaveStatus)>;
+
+// Callbacks run once the store has written every command queued so far. Poll it from the event loop of the UI.
+#[derive(Default)]
+pub struct SaveWatch {
+    callbacks: Vec<SavedCallback>,
+}
+
+impl SaveWatch {
+    pub fn on_saved<F: FnOnce(&SaveStatus) + 'static>(&mut self, f: F) {
+        self.callbacks.push(Box::new(f));
+    }
+
+    pub fn is_waiting(&self) -> bool {
+        !self.callbacks.is_empty()
+    }
+
+    // The status is returned so that the UI can show a spinner while pending.
+    pub fn poll<S: SaveProgress>(&mut self, store: &mut S) -> Result<SaveStatus, Report<SqliteUndoStoreError>> {
+        let status = store.save_status()?;
+        if status.pending_cmds == 0 {
+            for f in self.callbacks.drain(..) {
+                f(&status);
+            }
+        }
+        Ok(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc, time::Duration};
+
+    use serdo::undo_store::{self, UndoStore};
+    use tempfile::tempdir;
+
+    use crate::{project::{Project, ProjectStore}, tempo::Tempo};
+    use super::{SaveProgress, SaveWatch};
+
+    #[test]
+    fn callback_runs_when_saved() {
+        let tmp = tempdir().unwrap();
+        let mut store = ProjectStore::open(tmp.path(), undo_store::Options::new()).unwrap();
+        store.add_tempo(Tempo::new(100, 100), false);
+
+        let called = Rc::new(Cell::new(false));
+        let mut watch = SaveWatch::default();
+        let c = called.clone();
+        watch.on_saved(move |status| {
+            assert!(status.last_saved_at.is_some());
+            c.set(true);
+        });
+
+        for _ in 0..100 {
+            if watch.poll(&mut store).unwrap().pending_cmds == 0 { break; }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        assert!(called.get());
+        assert!(!watch.is_waiting());
+        assert_eq!(store.save_status().unwrap().pending_cmds, 0);
+        assert_eq!(store.model().tempo_repo().len(), 1);
+    }
+
+    #[test]
+    fn counts_pending_cmds() {
+        let tmp = tempdir().unwrap();
+        let mut store = ProjectStore::open(tmp.path(), undo_store::Options::new()).unwrap();
+        assert_eq!(store.save_status().unwrap().pending_cmds, 0);
+
+        store.add_tempo(Tempo::new(100, 100), false);
+        store.add_tempo(Tempo::new(200, 100), false);
+        assert!(store.save_status().unwrap().pending_cmds <= 2);
+        // Serdo never reports itself saved after an undo until the next command.
+        store.undo();
+        assert_eq!(store.save_status().unwrap().pending_cmds, 0);
+        store.add_tempo(Tempo::new(300, 100), false);
+        store.wait_until_saved();
+        assert_eq!(store.save_status().unwrap().pending_cmds, 0);
+        drop(store);
+
+        // Nothing has been sent since the reopen.
+        let mut store = ProjectStore::open(tmp.path(), undo_store::Options::new()).unwrap();
+        assert_eq!(store.save_status().unwrap().pending_cmds, 0);
+        let mut watch = SaveWatch::default();
+        let called = Rc::new(Cell::new(false));
+        let c = called.clone();
+        watch.on_saved(move |_| c.set(true));
+        watch.poll(&mut store).unwrap();
+        assert!(called.get());
+    }
+}
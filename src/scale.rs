@@ -0,0 +1,177 @@
+use std::fmt;
+
+use crate::{channel::Channel, key::Key, pitch::Pitch, pitch_class::{self, PitchClassSet}, sharp_flat::SharpFlat};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScaleMode {
+    Major,
+    NaturalMinor,
+    HarmonicMinor,
+    MelodicMinor,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Locrian,
+    MajorPentatonic,
+    MinorPentatonic,
+}
+
+impl ScaleMode {
+    // Semitones from the root.
+    pub const fn intervals(self) -> &'static [u8] {
+        match self {
+            ScaleMode::Major => &[0, 2, 4, 5, 7, 9, 11],
+            ScaleMode::NaturalMinor => &[0, 2, 3, 5, 7, 8, 10],
+            ScaleMode::HarmonicMinor => &[0, 2, 3, 5, 7, 8, 11],
+            ScaleMode::MelodicMinor => &[0, 2, 3, 5, 7, 9, 11],
+            ScaleMode::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+            ScaleMode::Phrygian => &[0, 1, 3, 5, 7, 8, 10],
+            ScaleMode::Lydian => &[0, 2, 4, 6, 7, 9, 11],
+            ScaleMode::Mixolydian => &[0, 2, 4, 5, 7, 9, 10],
+            ScaleMode::Locrian => &[0, 1, 3, 5, 6, 8, 10],
+            ScaleMode::MajorPentatonic => &[0, 2, 4, 7, 9],
+            ScaleMode::MinorPentatonic => &[0, 3, 5, 7, 10],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScaleError {
+    Empty,
+    InvalidPitchClass(u8),
+}
+
+impl fmt::Display for ScaleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScaleError::Empty => write!(f, "Scale has no pitch class"),
+            ScaleError::InvalidPitchClass(pc) => write!(f, "Invalid pitch class {}", pc),
+        }
+    }
+}
+
+// Set of pitch classes. Pitch class 0 is C.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Scale {
+    classes: PitchClassSet,
+}
+
+impl Scale {
+    pub fn new(root: Pitch, mode: ScaleMode) -> Self {
+        Self {
+            classes: PitchClassSet::from_classes(mode.intervals()).transposed(pitch_class::of(root) as i32),
+        }
+    }
+
+    pub fn custom(pitch_classes: &[u8]) -> Result<Self, ScaleError> {
+        if let Some(pc) = pitch_classes.iter().find(|pc| 12 <= **pc) {
+            return Err(ScaleError::InvalidPitchClass(*pc));
+        }
+        if pitch_classes.is_empty() {
+            return Err(ScaleError::Empty);
+        }
+        Ok(Self { classes: PitchClassSet::from_classes(pitch_classes) })
+    }
+
+    pub fn pitch_classes(&self) -> Vec<u8> {
+        self.classes.iter().collect()
+    }
+
+    pub fn contains(&self, value: u8) -> bool {
+        self.classes.contains(pitch_class::of_value(value))
+    }
+
+    // Closest pitch value in the scale. Ties go down.
+    pub fn nearest(&self, value: u8) -> u8 {
+        (0..=6i32).flat_map(|d| [-d, d])
+            .map(|d| value as i32 + d)
+            .find(|v| (0..=127).contains(v) && self.contains(*v as u8))
+            .unwrap_or(value as i32) as u8
+    }
+
+    // Spelling of the pitch value near the original that sounds right under the key. Writing no
+    // accidental and keeping the note name are preferred.
+    pub fn spell(original: Pitch, value: u8, key: Key) -> Option<Pitch> {
+        const ACCIDENTALS: [SharpFlat; 6] = [
+            SharpFlat::Null, SharpFlat::Natural, SharpFlat::Sharp, SharpFlat::Flat, SharpFlat::DoubleSharp, SharpFlat::DoubleFlat,
+        ];
+        let bases: Vec<Pitch> = [Some(original), original.down().ok(), original.up().ok()].into_iter().flatten().collect();
+        ACCIDENTALS.iter().flat_map(|sf| bases.iter().map(move |base| (*sf, *base)))
+            .filter_map(|(sf, base)| Pitch::value_of(base.solfa(), base.octave(), sf).ok())
+            .find(|p| p.apply_key(key).unwrap_or(*p).value() == value)
+    }
+
+    // Pitch moved to the closest scale tone, or None if it already sounds in the scale or cannot be
+    // spelled. Of two equally close tones, the one keeping the note name wins, then the lower one.
+    pub fn conform(&self, pitch: Pitch, key: Key) -> Option<Pitch> {
+        let value = pitch.apply_key(key).unwrap_or(pitch).value();
+        if self.contains(value) { return None; }
+        (1..=6i32).find_map(|d| {
+            let candidates: Vec<Pitch> = [value as i32 - d, value as i32 + d].into_iter()
+                .filter(|v| (0..=127).contains(v) && self.contains(*v as u8))
+                .filter_map(|v| Self::spell(pitch, v as u8, key))
+                .collect();
+            candidates.iter().find(|p| p.solfa() == pitch.solfa()).or(candidates.first()).copied()
+        })
+    }
+}
+
+// Note moved into a scale. Pitches are as written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScaleSnap {
+    pub start_tick: u32,
+    pub channel: Channel,
+    pub from: Pitch,
+    pub to: Pitch,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{key::Key, octave::Octave, pitch::Pitch, sharp_flat::SharpFlat, solfa::Solfa};
+    use super::{Scale, ScaleError, ScaleMode};
+
+    #[test]
+    fn scales() {
+        let d = Pitch::new(Solfa::D, Octave::Oct4, SharpFlat::Null);
+        assert_eq!(Scale::new(d, ScaleMode::Major).pitch_classes(), vec![1, 2, 4, 6, 7, 9, 11]);
+        assert_eq!(Scale::new(d, ScaleMode::Dorian).pitch_classes(), vec![0, 2, 4, 5, 7, 9, 11]);
+        let a = Pitch::new(Solfa::A, Octave::Oct4, SharpFlat::Null);
+        assert_eq!(Scale::new(a, ScaleMode::MinorPentatonic).pitch_classes(), vec![0, 2, 4, 7, 9]);
+        assert_eq!(Scale::custom(&[0, 4, 7]).unwrap().pitch_classes(), vec![0, 4, 7]);
+        assert_eq!(Scale::custom(&[]), Err(ScaleError::Empty));
+        assert_eq!(Scale::custom(&[12]), Err(ScaleError::InvalidPitchClass(12)));
+    }
+
+    #[test]
+    fn nearest() {
+        let c_major = Scale::new(Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null), ScaleMode::Major);
+        assert_eq!(c_major.nearest(72), 72);
+        // C sharp is between C and D.
+        assert_eq!(c_major.nearest(73), 72);
+        let triad = Scale::custom(&[0, 4, 7]).unwrap();
+        assert_eq!(triad.nearest(74), 72);
+        assert_eq!(triad.nearest(75), 76);
+        assert_eq!(triad.nearest(81), 79);
+    }
+
+    #[test]
+    fn spell() {
+        let f = Pitch::new(Solfa::F, Octave::Oct4, SharpFlat::Null);
+        assert_eq!(Scale::spell(f, f.value() + 1, Key::NONE), Some(Pitch::new(Solfa::F, Octave::Oct4, SharpFlat::Sharp)));
+        assert_eq!(Scale::spell(f, f.value() + 1, Key::SHARP_1), Some(f));
+        assert_eq!(Scale::spell(f, f.value(), Key::SHARP_1), Some(Pitch::new(Solfa::F, Octave::Oct4, SharpFlat::Natural)));
+        assert_eq!(Scale::spell(f, f.value() - 1, Key::NONE), Some(Pitch::new(Solfa::E, Octave::Oct4, SharpFlat::Null)));
+    }
+
+    #[test]
+    fn conform() {
+        let g_major = Scale::new(Pitch::new(Solfa::G, Octave::Oct4, SharpFlat::Null), ScaleMode::Major);
+        let f = Pitch::new(Solfa::F, Octave::Oct4, SharpFlat::Null);
+        // E and F sharp are equally close.
+        assert_eq!(g_major.conform(f, Key::NONE), Some(Pitch::new(Solfa::F, Octave::Oct4, SharpFlat::Sharp)));
+        assert_eq!(g_major.conform(f, Key::SHARP_1), None);
+        let c_sharp = Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Sharp);
+        assert_eq!(g_major.conform(c_sharp, Key::NONE), Some(Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null)));
+    }
+}
@@ -0,0 +1,69 @@
+use schemars::{schema::RootSchema, schema_for};
+
+use crate::{bar::Bar, exported_project::ExportedProject, models::Models, note::Note, tempo::Tempo, ctrl_chg::CtrlChg};
+
+pub fn exported_project_schema() -> RootSchema {
+    schema_for!(ExportedProject)
+}
+
+pub fn models_schema() -> RootSchema {
+    schema_for!(Models)
+}
+
+pub fn note_schema() -> RootSchema {
+    schema_for!(Note)
+}
+
+pub fn bar_schema() -> RootSchema {
+    schema_for!(Bar)
+}
+
+pub fn tempo_schema() -> RootSchema {
+    schema_for!(Tempo)
+}
+
+pub fn ctrl_chg_schema() -> RootSchema {
+    schema_for!(CtrlChg)
+}
+
+pub fn to_json(schema: &RootSchema) -> String {
+    serde_json::to_string_pretty(schema).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::{exported_project_schema, models_schema, note_schema, to_json};
+
+    #[test]
+    fn models_has_all_repos() {
+        let json: Value = serde_json::from_str(&to_json(&models_schema())).unwrap();
+        let props = json["properties"].as_object().unwrap();
+        assert!(props.contains_key("notes"));
+        assert!(props.contains_key("bars"));
+        assert!(props.contains_key("tempos"));
+        assert!(props.contains_key("dumpers"));
+        assert!(props.contains_key("softs"));
+    }
+
+    #[test]
+    fn pitch_does_not_expose_derived_fields() {
+        let json: Value = serde_json::from_str(&to_json(&note_schema())).unwrap();
+        let pitch = json["definitions"]["Pitch"]["properties"].as_object().unwrap();
+        assert_eq!(pitch.len(), 3);
+        assert!(pitch.contains_key("solfa"));
+        assert!(pitch.contains_key("octave"));
+        assert!(pitch.contains_key("sharp_flat"));
+    }
+
+    #[test]
+    fn numerators_do_not_collide() {
+        let json: Value = serde_json::from_str(&to_json(&exported_project_schema())).unwrap();
+        let defs = json["definitions"].as_object().unwrap();
+        assert!(defs.contains_key("Numerator"));
+        assert!(defs.contains_key("RhythmNumerator"));
+        assert!(defs.contains_key("Denominator"));
+        assert!(defs.contains_key("RhythmDenominator"));
+    }
+}
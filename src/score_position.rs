@@ -0,0 +1,93 @@
+use std::fmt;
+
+use crate::{location::Location, play_iter::{PlayIter, MAX_ITER}, play_start_tick::{PlayStartTick, ToAccumTickError}, project::{LocationError, ProjectImpl}, repeat::{AccumTick, Chunk}, tick::Tick};
+
+// Place in the performance such as "second time through bar 9". The iteration counts passes over the tick
+// from 1. Chunks are those returned by Chunk::by_accum_tick().
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ScorePosition {
+    pub tick: u32,
+    pub iteration: u8,
+}
+
+impl ScorePosition {
+    // The iteration is clamped to 1..=MAX_ITER.
+    pub fn new(tick: u32, iteration: u8) -> Self {
+        Self { tick, iteration: iteration.clamp(1, MAX_ITER) }
+    }
+
+    pub fn first(tick: u32) -> Self {
+        Self::new(tick, 1)
+    }
+
+    pub fn to_accum_tick(self, chunks: &[(AccumTick, Chunk)]) -> Result<AccumTick, ToAccumTickError> {
+        PlayStartTick::from(self).to_accum_tick(chunks)
+    }
+
+    // None if the accumulated tick is past the end of the performance.
+    pub fn from_accum_tick(accum_tick: AccumTick, chunks: &[(AccumTick, Chunk)]) -> Option<Self> {
+        let idx = chunks.iter().rposition(|(start, chunk)| *start <= accum_tick && (chunk.is_open_ended() || accum_tick - start < AccumTick::from(chunk.len())))?;
+        let (start, chunk) = chunks[idx];
+        // Within the chunk, so the offset fits a score tick.
+        let tick = chunk.start_tick() + Tick::from_accum(accum_tick - start).ok()?.as_u32();
+        let earlier = chunks[..idx].iter().filter(|(_, c)| c.contains(tick)).count();
+        Some(Self::new(tick, (earlier + 1).min(u8::MAX as usize) as u8))
+    }
+
+    pub fn location(self, proj: &ProjectImpl) -> Location {
+        proj.tick_to_location(self.tick)
+    }
+
+    pub fn from_location(loc: Location, iteration: u8, proj: &ProjectImpl) -> Result<Self, LocationError> {
+        Ok(Self::new(proj.location_to_tick(loc)?, iteration))
+    }
+}
+
+impl From<PlayStartTick> for ScorePosition {
+    fn from(start: PlayStartTick) -> Self {
+        Self::new(start.tick, start.iter.iter())
+    }
+}
+
+impl From<ScorePosition> for PlayStartTick {
+    fn from(pos: ScorePosition) -> Self {
+        PlayStartTick { tick: pos.tick, iter: PlayIter::new(pos.iteration) }
+    }
+}
+
+impl fmt::Display for ScorePosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}#{}", self.tick, self.iteration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serdo::undo_store::UndoStore;
+
+    use crate::{bar::{Bar, Repeat, RepeatSet}, location::Location, project::{memory_project_store, Project, DEFAULT_MEMORY_UNDO_LIMIT}, repeat::{render_region, Chunk}, repeat_set};
+    use super::ScorePosition;
+
+    #[test]
+    fn conversions() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_bar(Bar::new(960, None, None, repeat_set!(Repeat::End)), false);
+        store.add_bar(Bar::new(1920, None, None, Default::default()), false);
+        let (region, _) = render_region(store.model().rhythm(), store.model().bar_repo().iter().map(|(_, b)| b)).unwrap();
+        let chunks = Chunk::by_accum_tick(&region.to_chunks()).unwrap();
+
+        let second = ScorePosition::new(240, 2);
+        assert_eq!(second.to_accum_tick(&chunks).unwrap(), 1200);
+        assert_eq!(ScorePosition::from_accum_tick(1200, &chunks), Some(second));
+        assert_eq!(ScorePosition::from_accum_tick(240, &chunks), Some(ScorePosition::first(240)));
+        assert_eq!(ScorePosition::from_accum_tick(2000, &chunks), Some(ScorePosition::first(1040)));
+        assert!(ScorePosition::new(1040, 2).to_accum_tick(&chunks).is_err());
+
+        assert_eq!(second.location(store.model()), Location::new(0, 240));
+        assert_eq!(ScorePosition::from_location(Location::new(1, 0), 1, store.model()).unwrap(), ScorePosition::first(960));
+        assert_eq!(ScorePosition::new(0, 0).iteration, 1);
+        assert_eq!(second.to_string(), "240#2");
+    }
+}
@@ -0,0 +1,55 @@
+use std::path::{Path, PathBuf};
+
+use crate::{note_defaults::NoteDefaults, percent::PercentU16, playback::TrackOffsets, project::ProjectImpl};
+
+pub const SETTINGS_FILE_NAME: &str = "settings.json";
+
+// Settings stored with the document but out of undo history, such as the tempo scale of practice playback, the
+// entry palette and the offset curves of tracks. serdo persists nothing but commands, which are
+// always undoable, so these are written to a file next to the sqlite file whenever they change and are applied
+// to the model when the project is opened by open_project().
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct SettingsFile {
+    pub tempo_scale: PercentU16,
+    pub note_defaults: NoteDefaults,
+    pub track_offsets: TrackOffsets,
+}
+
+impl Default for SettingsFile {
+    fn default() -> Self {
+        Self { tempo_scale: PercentU16::HUNDRED, note_defaults: NoteDefaults::default(), track_offsets: TrackOffsets::default() }
+    }
+}
+
+impl SettingsFile {
+    pub fn path(dir: &Path) -> PathBuf {
+        dir.join(SETTINGS_FILE_NAME)
+    }
+
+    pub fn of(proj: &ProjectImpl) -> Self {
+        Self {
+            tempo_scale: proj.playback_settings().tempo_scale,
+            note_defaults: *proj.note_defaults(),
+            track_offsets: proj.track_offsets().clone(),
+        }
+    }
+
+    // None if the project has never saved its settings.
+    #[cfg(feature = "sqlite")]
+    pub fn read(path: &Path) -> std::io::Result<Option<Self>> {
+        match std::fs::read(path) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    // Written to a temporary file first so that a crash never leaves a truncated file.
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec(self)?)?;
+        std::fs::rename(&tmp, path)
+    }
+}
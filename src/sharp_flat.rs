@@ -1,5 +1,6 @@
 #[derive(serde::Deserialize, serde::Serialize)]
 #[derive(Debug, PartialEq, Copy, Clone, Eq, Hash)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum SharpFlat {
     Sharp,
     DoubleSharp,
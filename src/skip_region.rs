@@ -0,0 +1,50 @@
+use std::ops::Range;
+
+use crate::repeat::Chunk;
+
+// Ticks on the score left out of playback, to audition a cut without deleting anything.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SkipRegion {
+    pub tick_range: Range<u32>,
+}
+
+impl SkipRegion {
+    pub fn new(tick_range: Range<u32>) -> Self {
+        Self { tick_range }
+    }
+}
+
+// Chunks with the skipped ticks cut out. Every pass over a repeated passage is cut, and chunks left empty are
+// dropped. Regions may overlap.
+pub fn cut(chunks: &[Chunk], skips: &[SkipRegion]) -> Vec<Chunk> {
+    let mut result = Vec::with_capacity(chunks.len());
+    for chunk in chunks {
+        let mut parts = vec![*chunk];
+        for skip in skips {
+            parts = parts.into_iter().flat_map(|c| {
+                [c.intersect(c.start_tick()..skip.tick_range.start), c.intersect(skip.tick_range.end..c.end_tick())]
+            }).flatten().collect();
+        }
+        result.extend(parts);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::repeat::Chunk;
+    use super::{cut, SkipRegion};
+
+    #[test]
+    fn cuts() {
+        let chunks = [Chunk::new(0, 3840), Chunk::new(1920, 3840), Chunk::new(3840, u32::MAX)];
+        let skips = [SkipRegion::new(960..1920), SkipRegion::new(2880..4800), SkipRegion::new(3000..3100)];
+        assert_eq!(
+            cut(&chunks, &skips),
+            vec![Chunk::new(0, 960), Chunk::new(1920, 2880), Chunk::new(1920, 2880), Chunk::new(4800, u32::MAX)]
+        );
+        assert_eq!(cut(&chunks, &[]), chunks.to_vec());
+        assert!(cut(&[Chunk::new(0, 960)], &[SkipRegion::new(0..960)]).is_empty());
+    }
+}
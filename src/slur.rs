@@ -0,0 +1,58 @@
+use crate::note::Note;
+
+// Phrasing slur over notes. Unlike ties, notes under a slur are struck separately.
+// Notes are matched by value as they have no identity. A slur follows its notes when they are changed,
+// but no longer matches once the start or end note is removed.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Slur {
+    pub start_note: Note,
+    pub end_note: Note,
+}
+
+impl Slur {
+    pub fn new(start_note: Note, end_note: Note) -> Self {
+        Self { start_note, end_note }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.start_note.start_tick() < self.end_note.start_tick()
+    }
+
+    // True if the note starts under the slur on the same channel. The end note is included.
+    pub fn covers(&self, note: &Note) -> bool {
+        note.channel == self.start_note.channel
+            && self.start_note.start_tick() <= note.start_tick()
+            && note.start_tick() <= self.end_note.start_tick()
+    }
+
+    // Start (on the start note) and stop (on the end note) notation elements of MusicXML.
+    pub fn to_musicxml(&self, number: u8) -> (String, String) {
+        (
+            format!(r#"<slur number="{}" type="start"/>"#, number),
+            format!(r#"<slur number="{}" type="stop"/>"#, number),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{note::Note, channel::Channel};
+    use super::Slur;
+
+    #[test]
+    fn covers() {
+        let note = |tick: u32| Note { base_start_tick: tick, ..Default::default() };
+        let slur = Slur::new(note(240), note(720));
+        assert!(slur.is_valid());
+        assert!(!Slur::new(note(720), note(240)).is_valid());
+
+        assert!(!slur.covers(&note(0)));
+        assert!(slur.covers(&note(240)));
+        assert!(slur.covers(&note(720)));
+        assert!(!slur.covers(&note(960)));
+        assert!(!slur.covers(&Note { channel: Channel::new(1), ..note(480) }));
+
+        assert_eq!(slur.to_musicxml(2).0, r#"<slur number="2" type="start"/>"#);
+    }
+}
@@ -0,0 +1,105 @@
+use std::fmt;
+
+pub const SMALL_STRING_CAPACITY: usize = 23;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmallStringError {
+    // Length in bytes.
+    TooLong(usize),
+}
+
+impl fmt::Display for SmallStringError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SmallStringError::TooLong(len) => write!(f, "String of {} bytes exceeds {} bytes", len, SMALL_STRING_CAPACITY),
+        }
+    }
+}
+
+// UTF-8 string stored inline so that models holding it stay Copy. Serialized as a plain string.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SmallString {
+    len: u8,
+    bytes: [u8; SMALL_STRING_CAPACITY],
+}
+
+impl SmallString {
+    pub const EMPTY: SmallString = SmallString { len: 0, bytes: [0; SMALL_STRING_CAPACITY] };
+
+    pub fn new(s: &str) -> Result<Self, SmallStringError> {
+        if SMALL_STRING_CAPACITY < s.len() {
+            return Err(SmallStringError::TooLong(s.len()));
+        }
+        let mut bytes = [0; SMALL_STRING_CAPACITY];
+        bytes[..s.len()].copy_from_slice(s.as_bytes());
+        Ok(Self { len: s.len() as u8, bytes })
+    }
+
+    pub fn as_str(&self) -> &str {
+        match std::str::from_utf8(&self.bytes[..self.len as usize]) {
+            Ok(s) => s,
+            Err(_) => panic!("Logic error."),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Default for SmallString {
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+impl fmt::Debug for SmallString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl fmt::Display for SmallString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl serde::Serialize for SmallString {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for SmallString {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Self::new(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SmallString, SmallStringError};
+
+    #[test]
+    fn small_string() {
+        let s = SmallString::new("Theme A").unwrap();
+        assert_eq!(s.as_str(), "Theme A");
+        assert_eq!(s.len(), 7);
+        assert!(SmallString::default().is_empty());
+        assert_eq!(SmallString::new("0123456789abcdefghijklmn"), Err(SmallStringError::TooLong(24)));
+
+        let json = serde_json::to_string(&s).unwrap();
+        assert_eq!(json, r#""Theme A""#);
+        assert_eq!(serde_json::from_str::<SmallString>(&json).unwrap(), s);
+        assert!(serde_json::from_str::<SmallString>(r#""0123456789abcdefghijklmn""#).is_err());
+
+        let bin = bincode::serialize(&s).unwrap();
+        assert_eq!(bincode::deserialize::<SmallString>(&bin).unwrap(), s);
+    }
+}
@@ -2,6 +2,7 @@ use std::{fmt, ops::{AddAssign, SubAssign}};
 
 #[derive(serde::Deserialize, serde::Serialize)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum Solfa {
     C, D, E, F, G, A, B,
 }
@@ -53,6 +54,38 @@ impl Solfa {
         }
     }
 
+    pub fn iter() -> impl Iterator<Item = Solfa> {
+        Self::ALL.iter().copied()
+    }
+
+    // Number of diatonic steps from self up to other in the same octave. Negative if other is lower.
+    pub const fn steps_to(self, other: Solfa) -> i32 {
+        other.score_offset() - self.score_offset()
+    }
+
+    // Number of semitones from self up to other in the same octave. Negative if other is lower.
+    pub const fn semitones_to(self, other: Solfa) -> i32 {
+        other.pitch_offset() - self.pitch_offset()
+    }
+
+    // Move diatonic steps wrapping around the octave. Returns the solfa and the number of octaves carried.
+    pub const fn wrapping_add(self, steps: i32) -> (Solfa, i32) {
+        let so = self.score_offset() + steps;
+        (Self::ALL[so.rem_euclid(7) as usize], so.div_euclid(7))
+    }
+
+    pub const fn solfege(self) -> &'static str {
+        match self {
+            Self::C => "Do",
+            Self::D => "Re",
+            Self::E => "Mi",
+            Self::F => "Fa",
+            Self::G => "Sol",
+            Self::A => "La",
+            Self::B => "Si",
+        }
+    }
+
     pub fn from_score_offset(offset: i32) -> Solfa {
         if offset < Self::C.score_offset() {
             Self::C
@@ -159,4 +192,25 @@ mod tests {
         let mut solfa = Solfa::C;
         solfa -= 1;
     }
+
+    #[test]
+    fn iter() {
+        assert_eq!(Solfa::iter().collect::<Vec<_>>(), Solfa::ALL.to_vec());
+    }
+
+    #[test]
+    fn distance() {
+        assert_eq!(Solfa::C.steps_to(Solfa::G), 4);
+        assert_eq!(Solfa::G.steps_to(Solfa::C), -4);
+        assert_eq!(Solfa::C.semitones_to(Solfa::G), 7);
+        assert_eq!(Solfa::B.semitones_to(Solfa::E), -7);
+    }
+
+    #[test]
+    fn wrapping_add() {
+        assert_eq!(Solfa::C.wrapping_add(2), (Solfa::E, 0));
+        assert_eq!(Solfa::A.wrapping_add(3), (Solfa::D, 1));
+        assert_eq!(Solfa::D.wrapping_add(-2), (Solfa::B, -1));
+        assert_eq!(Solfa::C.wrapping_add(-14), (Solfa::C, -2));
+    }
 }
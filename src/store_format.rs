@@ -0,0 +1,380 @@
+use std::fmt;
+
+use serde::{de::{self, SeqAccess, Visitor}, ser::SerializeTuple, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{bar::{Bar, RepeatSet}, ctrl_chg::CtrlChg, duration::{self, Dots, Duration}, grid::Grid, key::Key, models::Models, note::Note, pitch::Pitch, project::{ExportedProject, ModelChangeMetadata, ProjectCmd, ProjectImpl}, rhythm::{self, Rhythm}, tempo::{Tempo, TempoValue}, trimmer::{RateTrimmer, Trimmer}, velocity::Velocity, channel::Channel};
+
+// Layout of the project and its commands in the sqlite store. serdo writes both with bincode, which has neither
+// field names nor defaults, so any field added to a persisted type makes existing stores unreadable. Records are
+// written as the tag, the version and the current layout. Records without the tag were written before the tag
+// existed and are read with the legacy types below.
+//
+// The tag cannot start a legacy record. Legacy projects start with a rhythm numerator (1 to 99) and legacy
+// commands with a variant index (0 to 3).
+const TAG: u8 = 0xff;
+
+// Bump when the layout of a persisted type changes and read the older version in visit_seq().
+pub const STORE_VERSION: u16 = 1;
+
+// Enough for the longest legacy record: the variant index and three fields.
+const MAX_ELEMENTS: usize = 8;
+
+enum Header {
+    Versioned(u16),
+    Legacy(u8),
+}
+
+fn read_header<'de, A: SeqAccess<'de>>(seq: &mut A) -> Result<Header, A::Error> {
+    let first: u8 = next(seq)?;
+    if first == TAG {
+        Ok(Header::Versioned(next(seq)?))
+    } else {
+        Ok(Header::Legacy(first))
+    }
+}
+
+fn next<'de, T: Deserialize<'de>, A: SeqAccess<'de>>(seq: &mut A) -> Result<T, A::Error> {
+    seq.next_element()?.ok_or_else(|| de::Error::custom("truncated store record"))
+}
+
+fn unsupported<E: de::Error>(version: u16) -> E {
+    E::custom(format!("store version {} is newer than {}", version, STORE_VERSION))
+}
+
+fn serialize_versioned<T: Serialize + ?Sized, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut tuple = serializer.serialize_tuple(3)?;
+    tuple.serialize_element(&TAG)?;
+    tuple.serialize_element(&STORE_VERSION)?;
+    tuple.serialize_element(value)?;
+    tuple.end()
+}
+
+// Human readable formats such as JSON keep the plain ExportedProject.
+impl Serialize for ProjectImpl {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let exported: ExportedProject = self.clone().into();
+        if serializer.is_human_readable() {
+            exported.serialize(serializer)
+        } else {
+            serialize_versioned(&exported, serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ProjectImpl {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            ExportedProject::deserialize(deserializer).map(ProjectImpl::from)
+        } else {
+            deserializer.deserialize_tuple(MAX_ELEMENTS, ProjectVisitor)
+        }
+    }
+}
+
+struct ProjectVisitor;
+
+impl<'de> Visitor<'de> for ProjectVisitor {
+    type Value = ProjectImpl;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a stored project")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ProjectImpl, A::Error> {
+        match read_header(&mut seq)? {
+            Header::Versioned(STORE_VERSION) => next::<ExportedProject, _>(&mut seq).map(ProjectImpl::from),
+            Header::Versioned(version) => Err(unsupported(version)),
+            Header::Legacy(numerator) => {
+                let numerator = rhythm::Numerator::from_value(numerator)
+                    .map_err(|_| de::Error::invalid_value(de::Unexpected::Unsigned(numerator as u64), &"a rhythm numerator"))?;
+                let rhythm = Rhythm { numerator, denominator: next(&mut seq)?, grouping: None };
+                let key: Key = next(&mut seq)?;
+                let grid: Grid = next(&mut seq)?;
+                let models: LegacyModels = next(&mut seq)?;
+                Ok(ExportedProject::with_models(rhythm, key, grid, models.into()).into())
+            }
+        }
+    }
+}
+
+struct CurrentCmd<'a>(&'a ProjectCmd);
+
+impl Serialize for CurrentCmd<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ProjectCmd::serialize(self.0, serializer)
+    }
+}
+
+struct OwnedCmd(ProjectCmd);
+
+impl<'de> Deserialize<'de> for OwnedCmd {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        ProjectCmd::deserialize(deserializer).map(OwnedCmd)
+    }
+}
+
+impl Serialize for ProjectCmd {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            ProjectCmd::serialize(self, serializer)
+        } else {
+            serialize_versioned(&CurrentCmd(self), serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ProjectCmd {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            ProjectCmd::deserialize(deserializer)
+        } else {
+            deserializer.deserialize_tuple(MAX_ELEMENTS, CmdVisitor)
+        }
+    }
+}
+
+struct CmdVisitor;
+
+impl<'de> Visitor<'de> for CmdVisitor {
+    type Value = ProjectCmd;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a stored command")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ProjectCmd, A::Error> {
+        match read_header(&mut seq)? {
+            Header::Versioned(STORE_VERSION) => next::<OwnedCmd, _>(&mut seq).map(|c| c.0),
+            Header::Versioned(version) => Err(unsupported(version)),
+            Header::Legacy(first) => {
+                // The variant index is a little endian u32.
+                let rest: [u8; 3] = next(&mut seq)?;
+                match u32::from_le_bytes([first, rest[0], rest[1], rest[2]]) {
+                    0 => Ok(ProjectCmd::SetRhythm(next::<LegacyRhythm, _>(&mut seq)?.into(), next::<LegacyRhythm, _>(&mut seq)?.into())),
+                    1 => Ok(ProjectCmd::SetKey(next(&mut seq)?, next(&mut seq)?)),
+                    2 => Ok(ProjectCmd::SetGrid(next(&mut seq)?, next(&mut seq)?)),
+                    3 => Ok(ProjectCmd::ModelChanged {
+                        added: next::<LegacyModels, _>(&mut seq)?.into(),
+                        removed: next::<LegacyModels, _>(&mut seq)?.into(),
+                        metadata: next::<ModelChangeMetadata, _>(&mut seq)?,
+                    }),
+                    index => Err(de::Error::invalid_value(de::Unexpected::Unsigned(index as u64), &"a legacy command")),
+                }
+            }
+        }
+    }
+}
+
+// Takes the place of Note::play_probability in builds without the practice feature, so that a store or a
+// clipboard written by one build is read by the other. Always written as None. A probability written by a
+// practice build is dropped.
+#[cfg(not(feature = "practice"))]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct NoPlayProbability;
+
+#[cfg(not(feature = "practice"))]
+impl Serialize for NoPlayProbability {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_none()
+    }
+}
+
+#[cfg(not(feature = "practice"))]
+impl<'de> Deserialize<'de> for NoPlayProbability {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Option::<crate::percent::PercentU16>::deserialize(deserializer).map(|_| NoPlayProbability)
+    }
+}
+
+// Types below mirror the layout before STORE_VERSION 1. Types whose layout has not changed since are used as is.
+
+#[derive(Deserialize)]
+struct LegacyRhythm {
+    numerator: rhythm::Numerator,
+    denominator: rhythm::Denominator,
+}
+
+impl From<LegacyRhythm> for Rhythm {
+    fn from(legacy: LegacyRhythm) -> Self {
+        Rhythm { numerator: legacy.numerator, denominator: legacy.denominator, grouping: None }
+    }
+}
+
+#[derive(Deserialize)]
+struct LegacyDuration {
+    numerator: duration::Numerator,
+    denominator: duration::Denominator,
+    dots: Dots,
+}
+
+impl From<LegacyDuration> for Duration {
+    fn from(legacy: LegacyDuration) -> Self {
+        Duration::new(legacy.numerator, legacy.denominator, legacy.dots)
+    }
+}
+
+#[derive(Deserialize)]
+struct LegacyNote {
+    base_start_tick: u32,
+    pitch: Pitch,
+    duration: LegacyDuration,
+    tie: bool,
+    tied: bool,
+    base_velocity: Velocity,
+    start_tick_trimmer: Trimmer,
+    duration_trimmer: RateTrimmer,
+    velocity_trimmer: Trimmer,
+    channel: Channel,
+}
+
+impl From<LegacyNote> for Note {
+    fn from(legacy: LegacyNote) -> Self {
+        Note {
+            base_start_tick: legacy.base_start_tick,
+            pitch: legacy.pitch,
+            duration: legacy.duration.into(),
+            tie: legacy.tie,
+            tied: legacy.tied,
+            base_velocity: legacy.base_velocity,
+            start_tick_trimmer: legacy.start_tick_trimmer,
+            duration_trimmer: legacy.duration_trimmer,
+            velocity_trimmer: legacy.velocity_trimmer,
+            channel: legacy.channel,
+            ..Note::default()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct LegacyBar {
+    start_tick: u32,
+    rhythm: Option<LegacyRhythm>,
+    key: Option<Key>,
+    repeats: RepeatSet,
+}
+
+impl From<LegacyBar> for Bar {
+    fn from(legacy: LegacyBar) -> Self {
+        Bar::new(legacy.start_tick, legacy.rhythm.map(Rhythm::from), legacy.key, legacy.repeats)
+    }
+}
+
+#[derive(Deserialize)]
+struct LegacyTempo {
+    start_tick: u32,
+    value: TempoValue,
+}
+
+impl From<LegacyTempo> for Tempo {
+    fn from(legacy: LegacyTempo) -> Self {
+        Tempo { start_tick: legacy.start_tick, value: legacy.value, marking: None }
+    }
+}
+
+#[derive(Deserialize)]
+struct LegacyModels {
+    notes: Vec<LegacyNote>,
+    bars: Vec<LegacyBar>,
+    tempos: Vec<LegacyTempo>,
+    dumpers: Vec<CtrlChg>,
+    softs: Vec<CtrlChg>,
+}
+
+impl From<LegacyModels> for Models {
+    fn from(legacy: LegacyModels) -> Self {
+        Models {
+            notes: legacy.notes.into_iter().map(Note::from).collect(),
+            bars: legacy.bars.into_iter().map(Bar::from).collect(),
+            tempos: legacy.tempos.into_iter().map(Tempo::from).collect(),
+            dumpers: legacy.dumpers,
+            softs: legacy.softs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serdo::cmd::Cmd;
+    use crate::{bar::{Bar, Repeat, RepeatSet}, channel::Channel, ctrl_chg::CtrlChg, duration::{Denominator, Dots, Duration, Numerator}, key::Key, models::Models, note::Note, octave::Octave, pitch::Pitch, project::{ModelChangeMetadata, ProjectCmd, ProjectImpl}, repeat_set, rhythm::Rhythm, sharp_flat::SharpFlat, solfa::Solfa, tempo::Tempo, velocity::Velocity};
+
+    #[cfg(not(feature = "practice"))]
+    #[test]
+    fn play_probability_of_practice_build() {
+        use crate::{percent::PercentU16, store_format::NoPlayProbability};
+        let practice = bincode::serialize(&Some(PercentU16::from_value(500))).unwrap();
+        assert_eq!(bincode::deserialize::<NoPlayProbability>(&practice).unwrap(), NoPlayProbability);
+        assert_eq!(bincode::serialize(&NoPlayProbability).unwrap(), bincode::serialize(&None::<PercentU16>).unwrap());
+    }
+
+    #[test]
+    fn versioned() {
+        let note = Note { muted: true, ..Default::default() };
+        let cmd = ProjectCmd::ModelChanged {
+            added: Models { notes: vec![note.clone()], ..Models::empty() }, removed: Models::empty(), metadata: ModelChangeMetadata::new()
+        };
+        let bin = bincode::serialize(&cmd).unwrap();
+        assert_eq!(&bin[..3], &[0xff, 1, 0]);
+        assert_eq!(bincode::deserialize::<ProjectCmd>(&bin).unwrap(), cmd);
+
+        let mut proj = ProjectImpl::default();
+        cmd.redo(&mut proj);
+        let restored: ProjectImpl = bincode::deserialize(&bincode::serialize(&proj).unwrap()).unwrap();
+        assert_eq!(restored.note_repo().iter().map(|(_, n)| (**n).clone()).collect::<Vec<_>>(), vec![note]);
+
+        // JSON is not wrapped.
+        assert!(serde_json::to_string(&proj).unwrap().starts_with("{\"rhythm\""));
+        assert!(bincode::deserialize::<ProjectCmd>(&[0xff, 2, 0]).unwrap_err().to_string().contains("newer"));
+    }
+
+    // testdata/store_v0.sqlite was written by the build before store versions. It has a snapshot, commands
+    // replayed on it and an undone command.
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn legacy_store() {
+        use serdo::undo_store::{self, UndoStore};
+        use tempfile::tempdir;
+        use crate::{models::ModelChanges, project::{Project, ProjectStore}, trimmer::{RateTrimmer, Trimmer}};
+
+        let dir = tempdir().unwrap().as_ref().join("project");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(undo_store::SQLITE_FILE_NAME), include_bytes!("../testdata/store_v0.sqlite")).unwrap();
+
+        let note0 = Note::new(
+            0, Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Sharp),
+            Duration::new(Numerator::N8th, Denominator::from_value(3).unwrap(), Dots::ONE),
+            true, false, Velocity::new(100), Trimmer::new(1, 2, 3, 4), RateTrimmer::new(1.0, 0.9, 1.0, 1.0), Trimmer::new(-5, 0, 0, 0), Channel::new(2)
+        );
+        let note1 = Note::new(
+            240, Pitch::new(Solfa::E, Octave::Oct5, SharpFlat::Null), Duration::default(),
+            false, false, Velocity::new(64), Trimmer::ZERO, RateTrimmer::ONE, Trimmer::ZERO, Channel::new(0)
+        );
+        let notes = |store: &ProjectStore| store.model().note_repo().iter().map(|(_, n)| (**n).clone()).collect::<Vec<_>>();
+
+        let mut store = ProjectStore::open(&dir, undo_store::Options::new()).unwrap();
+        assert_eq!(store.model().rhythm(), Rhythm::new(3, 4));
+        assert_eq!(store.model().key(), Key::FLAT_2);
+        assert_eq!(store.model().grid().as_u32(), 120);
+        assert!(store.model().bar_repo().iter().any(|(_, b)| *b == Bar::new(720, Some(Rhythm::new(2, 4)), Some(Key::SHARP_1), repeat_set!(Repeat::Start))));
+        assert_eq!(store.model().tempo_repo().iter().map(|(_, t)| *t).collect::<Vec<_>>(), vec![Tempo::new(0, 90)]);
+        assert_eq!(store.model().dumper_repo()[0].1, CtrlChg::new(240, Velocity::new(127), Channel::new(0)));
+        assert_eq!(store.model().soft_repo()[0].1, CtrlChg::new(480, Velocity::new(64), Channel::new(1)));
+        assert_eq!(notes(&store), vec![note0.clone(), Note { base_start_tick: 480, ..note1.clone() }]);
+        assert!(notes(&store).iter().all(|n| n.tag.is_none()));
+        assert!(notes(&store).iter().all(|n| n.duration.normal.value() == 2));
+
+        // Legacy commands.
+        store.redo();
+        assert_eq!(store.model().tempo_repo().len(), 2);
+        store.undo();
+        store.undo();
+        assert_eq!(notes(&store), vec![note0.clone(), note1.clone()]);
+
+        // A current command on top of legacy ones.
+        let muted = Note { muted: true, ..note1.clone() };
+        store.change(ModelChanges::empty().with_notes(vec![(note1.clone(), muted.clone())]), ModelChangeMetadata::new());
+        drop(store);
+
+        let store = ProjectStore::open(&dir, undo_store::Options::new()).unwrap();
+        assert_eq!(notes(&store), vec![note0, muted]);
+    }
+}
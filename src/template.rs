@@ -0,0 +1,137 @@
+use std::{fmt, fs::File, io::{BufReader, BufWriter}, path::Path};
+
+use error_stack::{Report, ResultExt};
+
+use crate::{bar::{Bar, Repeat, RepeatSet}, channel::Channel, grid::Grid, instrument::{Instrument, InstrumentTable}, key::Key, note_defaults::NoteDefaults, playback::{PitchShifts, PlaybackSettings, TrackOffsets}, project::{AddPolicy, DEFAULT_TEMPO}, repeat_set, rhythm::Rhythm, tempo::Tempo, transposition::DisplaySettings, velocity::DynamicsTable};
+
+#[derive(Debug)]
+pub enum TemplateError {
+    Io,
+    Json,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::Io => write!(f, "I/O error"),
+            TemplateError::Json => write!(f, "JSON error"),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+// Starting point of a new project. Bars, tempos and settings without any notes.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    pub name: String,
+    pub rhythm: Rhythm,
+    pub key: Key,
+    pub grid: Grid,
+    pub bars: Vec<Bar>,
+    pub tempos: Vec<Tempo>,
+    #[serde(default)]
+    pub playback: PlaybackSettings,
+    #[serde(default)]
+    pub note_defaults: NoteDefaults,
+    #[serde(default)]
+    pub display: DisplaySettings,
+    #[serde(default)]
+    pub default_channel: Channel,
+    #[serde(default)]
+    pub dynamics: DynamicsTable,
+    #[serde(default)]
+    pub track_offsets: TrackOffsets,
+    #[serde(default)]
+    pub pitch_shifts: PitchShifts,
+    #[serde(default)]
+    pub add_policy: AddPolicy,
+    #[serde(default)]
+    pub instruments: InstrumentTable,
+}
+
+impl Template {
+    fn with_bars(name: &str, rhythm: Rhythm, bar_count: u32) -> Self {
+        Self {
+            name: name.to_owned(),
+            rhythm,
+            key: Key::NONE,
+            grid: Grid::default(),
+            bars: (1..=bar_count).map(|i| Bar::new(i * rhythm.tick_len(), None, None, RepeatSet::EMPTY)).collect(),
+            tempos: vec![Tempo::new(0, DEFAULT_TEMPO.as_u16())],
+            playback: PlaybackSettings::default(),
+            note_defaults: NoteDefaults::default(),
+            display: DisplaySettings::default(),
+            default_channel: Channel::default(),
+            dynamics: DynamicsTable::default(),
+            track_offsets: TrackOffsets::default(),
+            pitch_shifts: PitchShifts::default(),
+            add_policy: AddPolicy::default(),
+            instruments: InstrumentTable::default(),
+        }
+    }
+
+    // 32 bars of 4/4 at 120 BPM.
+    pub fn solo_piano() -> Self {
+        Self::with_bars("Solo piano", Rhythm::new(4, 4), 32)
+    }
+
+    // 32 bars of 4/4 at 120 BPM. The chorus is repeated.
+    pub fn lead_sheet() -> Self {
+        let mut template = Self::with_bars("Lead sheet", Rhythm::new(4, 4), 32);
+        if let Some(last) = template.bars.last_mut() {
+            last.repeats = repeat_set!(Repeat::End);
+        }
+        template
+    }
+
+    // Same bars as the solo piano. The first piano is entered on channel 0 and the second one on channel 1.
+    pub fn two_piano() -> Self {
+        let mut instruments = InstrumentTable::default();
+        instruments.set(Channel::new(0), Some(Instrument::new("Piano 1", 0)));
+        instruments.set(Channel::new(1), Some(Instrument::new("Piano 2", 0)));
+        Self { name: "Two pianos".to_owned(), instruments, ..Self::solo_piano() }
+    }
+
+    pub fn builtins() -> Vec<Self> {
+        vec![Self::solo_piano(), Self::lead_sheet(), Self::two_piano()]
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Report<TemplateError>> {
+        let file = File::create(path).change_context(TemplateError::Io)?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self).change_context(TemplateError::Json)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Report<TemplateError>> {
+        let file = File::open(path).change_context(TemplateError::Io)?;
+        serde_json::from_reader(BufReader::new(file)).change_context(TemplateError::Json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{bar::Repeat, rhythm::Rhythm};
+    use super::Template;
+
+    #[test]
+    fn builtins() {
+        let solo = Template::solo_piano();
+        assert_eq!(solo.bars.len(), 32);
+        assert_eq!(solo.bars.last().unwrap().start_tick, 32 * 960);
+        assert_eq!(solo.rhythm, Rhythm::new(4, 4));
+        assert_eq!(solo.tempos[0].value.as_u16(), 120);
+        assert!(Template::lead_sheet().bars.last().unwrap().repeats.contains(Repeat::End));
+        assert_eq!(Template::builtins().len(), 3);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lead_sheet.json");
+        let template = Template::lead_sheet();
+        template.save(&path).unwrap();
+        assert_eq!(Template::load(&path).unwrap(), template);
+        assert!(Template::load(dir.path().join("none.json")).is_err());
+    }
+}
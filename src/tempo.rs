@@ -1,4 +1,6 @@
-use super::{note::TickError, have_start_tick::{HaveBaseStartTick, HaveStartTick}};
+use crate::{can_apply::CanApply, percent::PercentU16};
+
+use super::{note::TickError, tick::Tick, have_start_tick::{HaveBaseStartTick, HaveStartTick}};
 
 pub const MIN_TEMPO_VALUE: u16 = 1;
 pub const MAX_TEMPO_VALUE: u16 = 999;
@@ -9,6 +11,7 @@ pub const MAX_TEMPO: TempoValue = TempoValue(MAX_TEMPO_VALUE);
 #[derive(Clone, Copy, PartialEq, Eq, Debug, PartialOrd, Ord)]
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(from="SerializedTempoValue")]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct TempoValue(u16);
 
 impl Default for TempoValue {
@@ -68,23 +71,111 @@ impl TempoValue {
         }
     }
 
+    pub fn scaled(self, scale: PercentU16) -> TempoValue {
+        let value = scale.apply(self.0 as u32).clamp(MIN_TEMPO_VALUE as u32, MAX_TEMPO_VALUE as u32);
+        TempoValue(value as u16)
+    }
+
     pub fn safe_new(value: u16) -> TempoValue {
         Self::new(if MAX_TEMPO_VALUE < value { MAX_TEMPO_VALUE } else { value })
     }
+
+    pub fn to_marking(self) -> Marking {
+        *Marking::ALL.iter().rev().find(|m| m.lower_bound() <= self.0).unwrap_or(&Marking::Grave)
+    }
+
+    pub fn from_marking(marking: Marking) -> TempoValue {
+        TempoValue(marking.typical_value())
+    }
 }
 
+// Traditional tempo terms. Each covers the tempo values from its lower bound up to the next one.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
 #[derive(serde::Deserialize, serde::Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Marking {
+    Grave,
+    Largo,
+    Adagio,
+    Andante,
+    Moderato,
+    Allegro,
+    Vivace,
+    Presto,
+    Prestissimo,
+}
+
+impl Marking {
+    pub const ALL: [Marking; 9] = [
+        Marking::Grave, Marking::Largo, Marking::Adagio, Marking::Andante, Marking::Moderato,
+        Marking::Allegro, Marking::Vivace, Marking::Presto, Marking::Prestissimo,
+    ];
+
+    // (lower bound, typical value)
+    const fn table(self) -> (u16, u16) {
+        match self {
+            Marking::Grave => (MIN_TEMPO_VALUE, 40),
+            Marking::Largo => (45, 52),
+            Marking::Adagio => (60, 66),
+            Marking::Andante => (73, 92),
+            Marking::Moderato => (108, 114),
+            Marking::Allegro => (120, 132),
+            Marking::Vivace => (156, 166),
+            Marking::Presto => (176, 184),
+            Marking::Prestissimo => (200, 208),
+        }
+    }
+
+    pub const fn lower_bound(self) -> u16 {
+        self.table().0
+    }
+
+    pub const fn typical_value(self) -> u16 {
+        self.table().1
+    }
+}
+
+impl std::fmt::Display for Marking {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[derive(serde::Deserialize)]
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Tempo {
     pub start_tick: u32,
     pub value: TempoValue,
+    // Text shown on the score and exported as a marker.
+    #[serde(default)]
+    pub marking: Option<Marking>,
+}
+
+// Same as skip_serializing_if = "Option::is_none" on the marking, except that binary formats such as the store
+// always have every field as they cannot tell a skipped one.
+impl serde::Serialize for Tempo {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let skip_marking = serializer.is_human_readable() && self.marking.is_none();
+        let mut state = serializer.serialize_struct("Tempo", if skip_marking { 2 } else { 3 })?;
+        state.serialize_field("start_tick", &self.start_tick)?;
+        state.serialize_field("value", &self.value)?;
+        if skip_marking {
+            state.skip_field("marking")?;
+        } else {
+            state.serialize_field("marking", &self.marking)?;
+        }
+        state.end()
+    }
 }
 
 impl Default for Tempo {
     fn default() -> Self {
         Self {
             start_tick: 0,
-            value: Default::default()
+            value: Default::default(),
+            marking: None,
         }
     }
 }
@@ -94,29 +185,25 @@ impl Tempo {
         Self {
             start_tick,
             value: TempoValue::new(value),
+            marking: None,
         }
     }
 
+    pub fn with_marking(self, marking: Option<Marking>) -> Self {
+        Self { marking, ..self }
+    }
+
     pub fn drag(&self, tick_delta: i32, tempo_delta: i32) -> Self {
         Self {
-             start_tick: (self.start_tick as i64 + tick_delta as i64) as u32,
+             start_tick: Tick::new(self.start_tick).saturating_add_signed(tick_delta as i64).as_u32(),
              value: TempoValue::safe_new((self.value.as_u16() as i32 + tempo_delta) as u16),
              ..*self
         }
     }
 
     pub fn with_tick_added(&self, tick_delta: i32) -> Result<Self, TickError> {
-        let tick = self.start_tick as i64 + tick_delta as i64;
-        if tick < 0 {
-            Err(TickError::Minus)
-        } else {
-            Ok(
-                Self {
-                    start_tick: tick as u32,
-                    ..*self
-                }
-            )
-        }
+        let tick = Tick::new(self.start_tick).checked_add_signed(tick_delta as i64)?;
+        Ok(Self { start_tick: tick.as_u32(), ..*self })
     }
 
     pub fn up(&self) -> Result<Self, TempoError> {
@@ -153,7 +240,8 @@ impl HaveStartTick for Tempo {
 
 #[cfg(test)]
 mod tests {
-    use crate::tempo::{Tempo, TempoValue};
+    use crate::tempo::{Tempo, TempoValue, Marking};
+    use crate::percent::PercentU16;
     use serde_json::Value;
     use serde_json::json;
 
@@ -166,7 +254,8 @@ mod tests {
             }"#).unwrap();
         assert_eq!(tempo, Tempo {
             start_tick: 123,
-            value: TempoValue(234)
+            value: TempoValue(234),
+            marking: None,
         });
     }
 
@@ -174,7 +263,8 @@ mod tests {
     fn can_serialize_tempo() {
         let json_str = serde_json::to_string(&Tempo {
             start_tick: 123,
-            value: TempoValue(234)
+            value: TempoValue(234),
+            marking: None,
         }).unwrap();
         let json: Value = serde_json::from_str(&json_str).unwrap();
         assert_eq!(
@@ -185,4 +275,34 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn marking() {
+        assert_eq!(TempoValue::new(120).to_marking(), Marking::Allegro);
+        assert_eq!(TempoValue::new(119).to_marking(), Marking::Moderato);
+        assert_eq!(TempoValue::new(1).to_marking(), Marking::Grave);
+        assert_eq!(TempoValue::new(999).to_marking(), Marking::Prestissimo);
+        for m in Marking::ALL {
+            assert_eq!(TempoValue::from_marking(m).to_marking(), m);
+        }
+        assert_eq!(Marking::Andante.to_string(), "Andante");
+
+        let tempo = Tempo::new(0, 132).with_marking(Some(Marking::Allegro));
+        let json = serde_json::to_string(&tempo).unwrap();
+        assert_eq!(serde_json::from_str::<Tempo>(&json).unwrap(), tempo);
+
+        // The binary layout keeps the field either way.
+        for tempo in [tempo, Tempo::new(0, 132)] {
+            let bin = bincode::serialize(&tempo).unwrap();
+            assert_eq!(bincode::deserialize::<Tempo>(&bin).unwrap(), tempo);
+        }
+    }
+
+    #[test]
+    fn scaled() {
+        assert_eq!(TempoValue::new(100).scaled(PercentU16::from(0.7)), TempoValue::new(70));
+        assert_eq!(TempoValue::new(100).scaled(PercentU16::HUNDRED), TempoValue::new(100));
+        assert_eq!(TempoValue::new(600).scaled(PercentU16::MAX), TempoValue::new(999));
+        assert_eq!(TempoValue::new(100).scaled(PercentU16::ZERO), TempoValue::new(1));
+    }
 }
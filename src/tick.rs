@@ -0,0 +1,103 @@
+use crate::{note::TickError, repeat::AccumTick};
+
+// Tick on the score. Arithmetic is checked so that a tick past u32 is an error instead of a wrap (or a panic in
+// debug builds). Accumulated playback ticks grow with repeats and use AccumTick, which the tick64 feature widens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Tick(u32);
+
+impl Tick {
+    pub const ZERO: Tick = Tick(0);
+    pub const MAX: Tick = Tick(u32::MAX);
+
+    #[inline]
+    pub const fn new(tick: u32) -> Self {
+        Self(tick)
+    }
+
+    #[inline]
+    pub const fn as_u32(self) -> u32 {
+        self.0
+    }
+
+    #[inline]
+    pub const fn to_accum(self) -> AccumTick {
+        self.0 as AccumTick
+    }
+
+    #[cfg(not(feature = "tick64"))]
+    pub const fn from_accum(accum: AccumTick) -> Result<Self, TickError> {
+        Ok(Self(accum))
+    }
+
+    #[cfg(feature = "tick64")]
+    pub fn from_accum(accum: AccumTick) -> Result<Self, TickError> {
+        Self::from_u64(accum)
+    }
+
+    pub fn from_u64(value: u64) -> Result<Self, TickError> {
+        u32::try_from(value).map(Self).map_err(|_| TickError::Overflow)
+    }
+
+    pub fn from_usize(value: usize) -> Result<Self, TickError> {
+        u32::try_from(value).map(Self).map_err(|_| TickError::Overflow)
+    }
+
+    // Start of the bar after the count of bars of the length.
+    pub fn of_bars(count: usize, bar_len: u32) -> Result<Self, TickError> {
+        (count as u64).checked_mul(bar_len as u64).ok_or(TickError::Overflow).and_then(Self::from_u64)
+    }
+
+    pub fn checked_add(self, delta: u64) -> Result<Self, TickError> {
+        (self.0 as u64).checked_add(delta).ok_or(TickError::Overflow).and_then(Self::from_u64)
+    }
+
+    pub fn checked_sub(self, delta: u64) -> Result<Self, TickError> {
+        (self.0 as u64).checked_sub(delta).ok_or(TickError::Minus).and_then(Self::from_u64)
+    }
+
+    pub fn checked_add_signed(self, delta: i64) -> Result<Self, TickError> {
+        match (self.0 as i64).checked_add(delta) {
+            Some(tick) if tick < 0 => Err(TickError::Minus),
+            Some(tick) => Self::from_u64(tick as u64),
+            None => Err(TickError::Overflow),
+        }
+    }
+
+    // Stops at zero and at the maximum.
+    pub fn saturating_add_signed(self, delta: i64) -> Self {
+        Self((self.0 as i64).saturating_add(delta).clamp(0, u32::MAX as i64) as u32)
+    }
+}
+
+impl From<u32> for Tick {
+    fn from(tick: u32) -> Self {
+        Self(tick)
+    }
+}
+
+impl From<Tick> for u32 {
+    fn from(tick: Tick) -> Self {
+        tick.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::note::TickError;
+    use super::Tick;
+
+    #[test]
+    fn checked() {
+        assert_eq!(Tick::new(100).checked_add(20), Ok(Tick::new(120)));
+        assert_eq!(Tick::MAX.checked_add(1), Err(TickError::Overflow));
+        assert_eq!(Tick::new(10).checked_sub(11), Err(TickError::Minus));
+        assert_eq!(Tick::new(10).checked_add_signed(-10), Ok(Tick::ZERO));
+        assert_eq!(Tick::new(10).checked_add_signed(-11), Err(TickError::Minus));
+        assert_eq!(Tick::new(10).checked_add_signed(i64::MAX), Err(TickError::Overflow));
+        assert_eq!(Tick::new(10).saturating_add_signed(-11), Tick::ZERO);
+        assert_eq!(Tick::MAX.saturating_add_signed(1), Tick::MAX);
+        assert_eq!(Tick::of_bars(3, 960), Ok(Tick::new(2880)));
+        assert_eq!(Tick::of_bars(usize::MAX, 960), Err(TickError::Overflow));
+        assert_eq!(Tick::from_usize(u32::MAX as usize + 1), Err(TickError::Overflow));
+    }
+}
@@ -0,0 +1,189 @@
+use std::{fmt, ops::Range, rc::Rc};
+
+use crate::{duration::Numerator, models::ModelChanges, note::Note, pitch::{Pitch, PitchError}, sharp_flat::SharpFlat};
+
+// Classic motif transformations. Each function returns ModelChanges so that the result can be applied
+// through Project::change() and undone as one step.
+
+#[derive(Debug)]
+pub enum TransformError {
+    Pitch(PitchError),
+    NoteOutOfRange(u32),
+    InvalidFactor(u8),
+    DurationOutOfRange(Numerator),
+}
+
+impl fmt::Display for TransformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransformError::Pitch(e) => write!(f, "{}", e),
+            TransformError::NoteOutOfRange(tick) => write!(f, "Note at {} is out of range", tick),
+            TransformError::InvalidFactor(factor) => write!(f, "Factor should be a power of two({})", factor),
+            TransformError::DurationOutOfRange(numerator) => write!(f, "Cannot scale {:?} note", numerator),
+        }
+    }
+}
+
+impl From<PitchError> for TransformError {
+    fn from(e: PitchError) -> Self {
+        TransformError::Pitch(e)
+    }
+}
+
+fn mirror(sharp_flat: SharpFlat) -> SharpFlat {
+    match sharp_flat {
+        SharpFlat::Sharp => SharpFlat::Flat,
+        SharpFlat::DoubleSharp => SharpFlat::DoubleFlat,
+        SharpFlat::Flat => SharpFlat::Sharp,
+        SharpFlat::DoubleFlat => SharpFlat::DoubleSharp,
+        other => other,
+    }
+}
+
+fn to_changes(changes: Vec<(Note, Note)>) -> ModelChanges {
+    ModelChanges::empty().with_notes(changes.into_iter().filter(|(from, to)| from != to).collect())
+}
+
+// Mirror notes around the axis on the staff. Accidentals are mirrored as well (sharp <-> flat).
+pub fn invert(notes: &[Rc<Note>], axis: Pitch) -> Result<ModelChanges, TransformError> {
+    let mut changes = Vec::with_capacity(notes.len());
+    for n in notes.iter() {
+        let delta = 2 * (axis.score_offset() as i32 - n.pitch.score_offset() as i32);
+        let moved = n.pitch.with_score_offset_delta(delta)?;
+        let pitch = Pitch::value_of(moved.solfa(), moved.octave(), mirror(n.pitch.sharp_flat()))?;
+        changes.push(((**n).clone(), Note { pitch, ..(**n).clone() }));
+    }
+    Ok(to_changes(changes))
+}
+
+// Reverse notes in time within the range. Ties are swapped so that tied pairs stay connected.
+pub fn retrograde(notes: &[Rc<Note>], range: Range<u32>) -> Result<ModelChanges, TransformError> {
+    let mut changes = Vec::with_capacity(notes.len());
+    for n in notes.iter() {
+        let end = n.base_start_tick + n.duration.tick_length();
+        if n.base_start_tick < range.start || range.end < end {
+            return Err(TransformError::NoteOutOfRange(n.base_start_tick));
+        }
+        let to = Note {
+            base_start_tick: range.start + (range.end - end),
+            tie: n.tied,
+            tied: n.tie,
+            ..(**n).clone()
+        };
+        changes.push(((**n).clone(), to));
+    }
+    Ok(to_changes(changes))
+}
+
+fn scale(notes: &[Rc<Note>], factor: u8, augment: bool) -> Result<ModelChanges, TransformError> {
+    if !factor.is_power_of_two() {
+        return Err(TransformError::InvalidFactor(factor));
+    }
+    let shift = factor.trailing_zeros();
+    let origin = match notes.iter().map(|n| n.base_start_tick).min() {
+        Some(origin) => origin,
+        None => return Ok(ModelChanges::empty()),
+    };
+
+    let mut changes = Vec::with_capacity(notes.len());
+    for n in notes.iter() {
+        let numerator = n.duration.numerator;
+        let ord = if augment { (numerator.ord() as u32).checked_sub(shift) } else { Some(numerator.ord() as u32 + shift) };
+        let numerator = ord.and_then(|o| Numerator::from_ord(o as u8)).ok_or(TransformError::DurationOutOfRange(numerator))?;
+        let offset = n.base_start_tick - origin;
+        let offset = if augment { offset << shift } else { offset >> shift };
+        let to = Note {
+            base_start_tick: origin + offset,
+            ..n.with_duration_numerator(numerator)
+        };
+        changes.push(((**n).clone(), to));
+    }
+    Ok(to_changes(changes))
+}
+
+// Multiply durations and distances from the first note by the factor (2, 4, ...).
+pub fn augment(notes: &[Rc<Note>], factor: u8) -> Result<ModelChanges, TransformError> {
+    scale(notes, factor, true)
+}
+
+// Divide durations and distances from the first note by the factor (2, 4, ...).
+pub fn diminish(notes: &[Rc<Note>], factor: u8) -> Result<ModelChanges, TransformError> {
+    scale(notes, factor, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use serdo::undo_store::UndoStore;
+
+    use crate::{note::Note, pitch::Pitch, solfa::Solfa, octave::Octave, sharp_flat::SharpFlat, duration::{Duration, Numerator, Denominator, Dots}, project::{Project, ModelChangeMetadata, memory_project_store, DEFAULT_MEMORY_UNDO_LIMIT}};
+    use super::{invert, retrograde, augment, diminish, TransformError};
+
+    fn note(tick: u32, solfa: Solfa, sharp_flat: SharpFlat) -> Rc<Note> {
+        Rc::new(Note {
+            base_start_tick: tick,
+            pitch: Pitch::new(solfa, Octave::Oct4, sharp_flat),
+            ..Default::default()
+        })
+    }
+
+    fn pitches(changes: &crate::models::ModelChanges) -> Vec<Pitch> {
+        changes.notes.iter().map(|(_, to)| to.pitch).collect()
+    }
+
+    #[test]
+    fn can_invert() {
+        let notes = vec![note(0, Solfa::E, SharpFlat::Null), note(240, Solfa::F, SharpFlat::Sharp), note(480, Solfa::D, SharpFlat::Null)];
+        let changes = invert(&notes, Pitch::new(Solfa::D, Octave::Oct4, SharpFlat::Null)).unwrap();
+        assert_eq!(
+            pitches(&changes),
+            vec![Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null), Pitch::new(Solfa::B, Octave::Oct3, SharpFlat::Flat)]
+        );
+    }
+
+    #[test]
+    fn can_retrograde() {
+        let notes = vec![
+            Rc::new(Note { tie: true, ..(*note(0, Solfa::C, SharpFlat::Null)).clone() }),
+            Rc::new(Note { tied: true, ..(*note(240, Solfa::C, SharpFlat::Null)).clone() }),
+            Rc::new(Note { duration: Duration::new(Numerator::Half, Denominator::from_value(2).unwrap(), Dots::ZERO), ..(*note(480, Solfa::E, SharpFlat::Null)).clone() }),
+        ];
+        let changes = retrograde(&notes, 0..960).unwrap();
+        let moved: Vec<(u32, bool, bool)> = changes.notes.iter().map(|(_, to)| (to.base_start_tick, to.tie, to.tied)).collect();
+        assert_eq!(moved, vec![(720, false, true), (480, true, false), (0, false, false)]);
+
+        assert!(matches!(retrograde(&notes, 0..720), Err(TransformError::NoteOutOfRange(480))));
+    }
+
+    #[test]
+    fn can_augment_and_diminish() {
+        let notes = vec![note(240, Solfa::C, SharpFlat::Null), note(480, Solfa::D, SharpFlat::Null)];
+        let changes = augment(&notes, 2).unwrap();
+        let moved: Vec<(u32, Numerator)> = changes.notes.iter().map(|(_, to)| (to.base_start_tick, to.duration.numerator)).collect();
+        assert_eq!(moved, vec![(240, Numerator::Half), (720, Numerator::Half)]);
+
+        let changes = diminish(&notes, 4).unwrap();
+        let moved: Vec<(u32, Numerator)> = changes.notes.iter().map(|(_, to)| (to.base_start_tick, to.duration.numerator)).collect();
+        assert_eq!(moved, vec![(240, Numerator::N16th), (300, Numerator::N16th)]);
+
+        assert!(matches!(augment(&notes, 3), Err(TransformError::InvalidFactor(3))));
+        assert!(matches!(augment(&notes, 8), Err(TransformError::DurationOutOfRange(Numerator::Quarter))));
+    }
+
+    #[test]
+    fn undoable() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_note((*note(0, Solfa::C, SharpFlat::Null)).clone(), false);
+        store.add_note((*note(240, Solfa::E, SharpFlat::Null)).clone(), false);
+        let notes: Vec<Rc<Note>> = store.note_repo().iter().map(|(_, n)| n.clone()).collect();
+
+        store.change(retrograde(&notes, 0..480).unwrap(), ModelChangeMetadata::new());
+        let ticks: Vec<(u32, Solfa)> = store.note_repo().iter().map(|(t, n)| (*t, n.pitch.solfa())).collect();
+        assert_eq!(ticks, vec![(0, Solfa::E), (240, Solfa::C)]);
+
+        store.undo();
+        let ticks: Vec<(u32, Solfa)> = store.note_repo().iter().map(|(t, n)| (*t, n.pitch.solfa())).collect();
+        assert_eq!(ticks, vec![(0, Solfa::C), (240, Solfa::E)]);
+    }
+}
@@ -0,0 +1,377 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use error_stack::Report;
+
+use crate::{channel::Channel, duration::Duration, play_start_tick::ToAccumTickError, playback::{render, PlayEvent}, project::{ProjectImpl, DEFAULT_TEMPO}, repeat::{AccumTick, Chunk, RenderRegionError}, score_position::ScorePosition, tempo::TempoValue, velocity::{self, Velocity}};
+
+// Time in microseconds driven by the caller instead of a real clock, so that playback can be tested without
+// waiting. Time not yet making a whole tick is carried to the next render_until().
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VirtualClock {
+    now: u64,
+    carry: u64,
+}
+
+impl VirtualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportState {
+    Stopped,
+    Playing,
+    Paused,
+}
+
+// Play/pause/stop/seek over rendered events. Every state change returns the events to send so that the
+// output matches the position: sounding notes are turned off and the tempo and pedals are re-primed.
+// Ticks of the returned events are the current position.
+#[derive(Debug, Clone)]
+pub struct Transport {
+    events: Vec<PlayEvent>,
+    initial_tempo: TempoValue,
+    state: TransportState,
+    position: AccumTick,
+    // Index of the first event not sent yet.
+    next: usize,
+    sounding: BTreeSet<(Channel, u8)>,
+    // Output state as far as the transport has sent. None until sent once.
+    tempo: Option<TempoValue>,
+    dumpers: BTreeMap<Channel, Velocity>,
+    softs: BTreeMap<Channel, Velocity>,
+}
+
+impl Transport {
+    // Events should be sorted by tick. The initial tempo is used before the first tempo event.
+    pub fn new(events: Vec<PlayEvent>, initial_tempo: TempoValue) -> Self {
+        Self {
+            events,
+            initial_tempo,
+            state: TransportState::Stopped,
+            position: 0,
+            next: 0,
+            sounding: BTreeSet::new(),
+            tempo: None,
+            dumpers: BTreeMap::new(),
+            softs: BTreeMap::new(),
+        }
+    }
+
+    pub fn from_project(proj: &ProjectImpl) -> Result<Self, Report<RenderRegionError>> {
+        Ok(Self::new(render(proj)?, DEFAULT_TEMPO.scaled(proj.playback_settings().tempo_scale)))
+    }
+
+    pub fn state(&self) -> TransportState {
+        self.state
+    }
+
+    pub fn position(&self) -> AccumTick {
+        self.position
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.events.len() <= self.next
+    }
+
+    pub fn play(&mut self) -> Vec<PlayEvent> {
+        if self.state == TransportState::Playing { return vec![]; }
+        self.state = TransportState::Playing;
+        self.prime()
+    }
+
+    pub fn pause(&mut self) -> Vec<PlayEvent> {
+        if self.state != TransportState::Playing { return vec![]; }
+        self.state = TransportState::Paused;
+        self.silence()
+    }
+
+    // Back to the top.
+    pub fn stop(&mut self) -> Vec<PlayEvent> {
+        let events = if self.state == TransportState::Playing { self.silence() } else { vec![] };
+        self.state = TransportState::Stopped;
+        self.position = 0;
+        self.next = 0;
+        events
+    }
+
+    // Notes at the new position are not struck. Playback starts with the next note on.
+    pub fn seek(&mut self, position: AccumTick) -> Vec<PlayEvent> {
+        let mut events = if self.state == TransportState::Playing { self.silence() } else { vec![] };
+        self.position = position;
+        self.next = self.events.partition_point(|e| e.tick() < position);
+        if self.state == TransportState::Playing {
+            events.extend(self.prime());
+        }
+        events
+    }
+
+    // Chunks are those the events were rendered from, keyed by accumulated tick.
+    pub fn seek_to(&mut self, position: ScorePosition, chunks: &[(AccumTick, Chunk)]) -> Result<Vec<PlayEvent>, ToAccumTickError> {
+        Ok(self.seek(position.to_accum_tick(chunks)?))
+    }
+
+    pub fn score_position(&self, chunks: &[(AccumTick, Chunk)]) -> Option<ScorePosition> {
+        ScorePosition::from_accum_tick(self.position, chunks)
+    }
+
+    // Events before the tick. Nothing unless playing.
+    pub fn advance(&mut self, to: AccumTick) -> Vec<PlayEvent> {
+        if self.state != TransportState::Playing || to <= self.position { return vec![]; }
+        let end = self.next + self.events[self.next..].partition_point(|e| e.tick() < to);
+        let events: Vec<PlayEvent> = self.events[self.next..end].to_vec();
+        events.iter().for_each(|e| self.track(e));
+        self.next = end;
+        self.position = to;
+        events
+    }
+
+    // Moves the clock to the time and advances as far as the tempo goes in the time elapsed. The clock keeps
+    // running while not playing, but the position stays.
+    #[cfg_attr(feature = "tick64", allow(clippy::unnecessary_cast))]
+    pub fn render_until(&mut self, clock: &mut VirtualClock, micros: u64) -> Vec<PlayEvent> {
+        if micros <= clock.now { return vec![]; }
+        let elapsed = micros - clock.now;
+        clock.now = micros;
+        if self.state != TransportState::Playing {
+            clock.carry = 0;
+            return vec![];
+        }
+
+        // Ticks = micros * bpm / UNIT.
+        const UNIT: u64 = 60_000_000 / Duration::TICK_RESOLUTION as u64;
+        let mut remaining = elapsed + clock.carry;
+        let mut tick = self.position;
+        let mut tempo = self.events.iter().take_while(|e| e.tick() <= tick)
+            .fold(self.initial_tempo, |t, e| if let PlayEvent::Tempo { value, .. } = e { *value } else { t });
+        for e in self.events.iter().skip_while(|e| e.tick() <= self.position) {
+            let PlayEvent::Tempo { tick: at, value, .. } = e else { continue; };
+            let needed = ((*at - tick) as u64 * UNIT).div_ceil(tempo.value() as u64);
+            if remaining < needed { break; }
+            remaining -= needed;
+            tick = *at;
+            tempo = *value;
+        }
+        let scaled = remaining * tempo.value() as u64;
+        clock.carry = scaled % UNIT / tempo.value() as u64;
+        let ticks = scaled / UNIT;
+        self.advance(tick + ticks as AccumTick)
+    }
+
+    fn track(&mut self, e: &PlayEvent) {
+        match *e {
+            PlayEvent::Tempo { value, .. } => self.tempo = Some(value),
+            PlayEvent::Dumper { channel, velocity, .. } => { self.dumpers.insert(channel, velocity); }
+            PlayEvent::Soft { channel, velocity, .. } => { self.softs.insert(channel, velocity); }
+            PlayEvent::NoteOn { channel, pitch, .. } => { self.sounding.insert((channel, pitch)); }
+            PlayEvent::NoteOff { channel, pitch, .. } => { self.sounding.remove(&(channel, pitch)); }
+        }
+    }
+
+    // Turn off sounding notes and release pedals.
+    fn silence(&mut self) -> Vec<PlayEvent> {
+        let tick = self.position;
+        let mut events: Vec<PlayEvent> = self.sounding.iter()
+            .map(|(channel, pitch)| PlayEvent::NoteOff { tick, channel: *channel, pitch: *pitch })
+            .collect();
+        events.extend(self.dumpers.iter().filter(|(_, v)| **v != velocity::MIN)
+            .map(|(channel, _)| PlayEvent::Dumper { tick, channel: *channel, velocity: velocity::MIN }));
+        events.extend(self.softs.iter().filter(|(_, v)| **v != velocity::MIN)
+            .map(|(channel, _)| PlayEvent::Soft { tick, channel: *channel, velocity: velocity::MIN }));
+        events.iter().for_each(|e| self.track(e));
+        events
+    }
+
+    // Tempo and pedals in effect at the position that differ from the output. Those changed by events at the
+    // position are left to advance().
+    fn prime(&mut self) -> Vec<PlayEvent> {
+        let tick = self.position;
+        let mut tempo = (self.initial_tempo, false);
+        let mut dumpers: BTreeMap<Channel, (Velocity, bool)> = BTreeMap::new();
+        let mut softs: BTreeMap<Channel, (Velocity, bool)> = BTreeMap::new();
+        for e in self.events.iter().take_while(|e| e.tick() <= tick) {
+            let at_position = e.tick() == tick;
+            match *e {
+                PlayEvent::Tempo { value, .. } => tempo = (value, at_position),
+                PlayEvent::Dumper { channel, velocity, .. } => { dumpers.insert(channel, (velocity, at_position)); }
+                PlayEvent::Soft { channel, velocity, .. } => { softs.insert(channel, (velocity, at_position)); }
+                _ => {}
+            }
+        }
+        // Channels pedaled somewhere are released if nothing is in effect yet.
+        for e in self.events.iter() {
+            match *e {
+                PlayEvent::Dumper { channel, .. } => { dumpers.entry(channel).or_insert((velocity::MIN, false)); }
+                PlayEvent::Soft { channel, .. } => { softs.entry(channel).or_insert((velocity::MIN, false)); }
+                _ => {}
+            }
+        }
+
+        let mut events = vec![];
+        if !tempo.1 && self.tempo != Some(tempo.0) {
+            events.push(PlayEvent::Tempo { tick, value: tempo.0, marking: None });
+        }
+        for (channel, (velocity, at_position)) in dumpers {
+            if !at_position && self.dumpers.get(&channel) != Some(&velocity) {
+                events.push(PlayEvent::Dumper { tick, channel, velocity });
+            }
+        }
+        for (channel, (velocity, at_position)) in softs {
+            if !at_position && self.softs.get(&channel) != Some(&velocity) {
+                events.push(PlayEvent::Soft { tick, channel, velocity });
+            }
+        }
+        events.iter().for_each(|e| self.track(e));
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serdo::undo_store::UndoStore;
+
+    use crate::{bar::{Bar, Repeat, RepeatSet}, channel::Channel, note::Note, octave::Octave, pitch::Pitch, playback::PlayEvent, project::{memory_project_store, Project, DEFAULT_MEMORY_UNDO_LIMIT}, repeat_set, sharp_flat::SharpFlat, solfa::Solfa, tempo::{Tempo, TempoValue}, velocity::{self, Velocity}};
+    use crate::repeat::AccumTick;
+    use super::{Transport, TransportState, VirtualClock};
+
+    fn events() -> Vec<PlayEvent> {
+        let channel = Channel::default();
+        vec![
+            PlayEvent::Tempo { tick: 0, value: TempoValue::new(100), marking: None },
+            PlayEvent::Dumper { tick: 0, channel, velocity: Velocity::new(127) },
+            PlayEvent::NoteOn { tick: 0, channel, pitch: 60, velocity: Velocity::new(64) },
+            PlayEvent::Tempo { tick: 240, value: TempoValue::new(90), marking: None },
+            PlayEvent::NoteOff { tick: 240, channel, pitch: 60 },
+            PlayEvent::NoteOn { tick: 240, channel, pitch: 62, velocity: Velocity::new(64) },
+            PlayEvent::NoteOff { tick: 480, channel, pitch: 62 },
+        ]
+    }
+
+    #[test]
+    fn play_pause_resume() {
+        let channel = Channel::default();
+        let mut transport = Transport::new(events(), TempoValue::new(120));
+        assert_eq!(transport.advance(100), vec![]);
+        // Everything at tick 0 comes from the events themselves.
+        assert_eq!(transport.play(), vec![]);
+        assert_eq!(transport.advance(100).len(), 3);
+
+        assert_eq!(transport.pause(), vec![
+            PlayEvent::NoteOff { tick: 100, channel, pitch: 60 },
+            PlayEvent::Dumper { tick: 100, channel, velocity: velocity::MIN },
+        ]);
+        assert_eq!(transport.state(), TransportState::Paused);
+        assert_eq!(transport.advance(200), vec![]);
+
+        assert_eq!(transport.play(), vec![PlayEvent::Dumper { tick: 100, channel, velocity: Velocity::new(127) }]);
+        assert_eq!(transport.advance(481).len(), 4);
+        assert!(transport.is_at_end());
+
+        assert_eq!(transport.stop(), vec![PlayEvent::Dumper { tick: 481, channel, velocity: velocity::MIN }]);
+        assert_eq!(transport.position(), 0);
+    }
+
+    #[test]
+    fn seek() {
+        let channel = Channel::default();
+        let mut transport = Transport::new(events(), TempoValue::new(120));
+        assert_eq!(transport.seek(300), vec![]);
+        assert_eq!(transport.play(), vec![
+            PlayEvent::Tempo { tick: 300, value: TempoValue::new(90), marking: None },
+            PlayEvent::Dumper { tick: 300, channel, velocity: Velocity::new(127) },
+        ]);
+        assert_eq!(transport.advance(481), vec![PlayEvent::NoteOff { tick: 480, channel, pitch: 62 }]);
+
+        // The tempo and the pedal at tick 0 are sent by advance().
+        assert_eq!(transport.seek(0), vec![PlayEvent::Dumper { tick: 481, channel, velocity: velocity::MIN }]);
+        assert_eq!(transport.advance(1).len(), 3);
+    }
+
+    #[test]
+    fn virtual_clock() {
+        let channel = Channel::default();
+        let mut transport = Transport::new(events(), TempoValue::new(120));
+        let mut clock = VirtualClock::new();
+        // Not playing. The clock runs but the position stays.
+        assert_eq!(transport.render_until(&mut clock, 100_000), vec![]);
+        assert_eq!(transport.position(), 0);
+
+        transport.play();
+        // 100 BPM from tick 0, so a tick takes 2500us. Events at the position wait for the next tick.
+        assert_eq!(transport.render_until(&mut clock, 101_000), vec![]);
+        assert_eq!(transport.render_until(&mut clock, 103_000).len(), 3);
+        assert_eq!(transport.position(), 1);
+        // 239 ticks reach the tempo change at 240 exactly.
+        assert_eq!(transport.render_until(&mut clock, 700_000), vec![]);
+        assert_eq!(transport.position(), 240);
+        // 90 BPM after it.
+        assert_eq!(transport.render_until(&mut clock, 703_000).len(), 3);
+        assert_eq!(transport.position(), 241);
+        assert_eq!(transport.render_until(&mut clock, 703_000 + 240 * 250_000 / 90), vec![PlayEvent::NoteOff { tick: 480, channel, pitch: 62 }]);
+        assert_eq!(transport.position(), 481);
+        assert!(transport.is_at_end());
+    }
+
+    // Quarter note at the start of each bar of 4/4 at 120 BPM, so a bar takes 2 seconds.
+    fn play_through(bars: Vec<Bar>, solfas: &[Solfa]) -> Vec<(AccumTick, Solfa)> {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        store.add_tempo(Tempo::new(0, 120), false);
+        for (i, solfa) in solfas.iter().enumerate() {
+            let pitch = Pitch::new(*solfa, Octave::Oct4, SharpFlat::Null);
+            store.add_note(Note { base_start_tick: i as u32 * 960, pitch, ..Default::default() }, false);
+        }
+        for bar in bars {
+            store.add_bar(bar, false);
+        }
+        let mut transport = Transport::from_project(store.model()).unwrap();
+        let mut clock = VirtualClock::new();
+        transport.play();
+        let mut played = vec![];
+        // 300ms steps, not aligned with the bars.
+        for step in 1..100 {
+            for e in transport.render_until(&mut clock, step * 300_000) {
+                if let PlayEvent::NoteOn { tick, pitch, .. } = e {
+                    let solfa = solfas.iter().find(|s| Pitch::new(**s, Octave::Oct4, SharpFlat::Null).value() == pitch).unwrap();
+                    played.push((tick, *solfa));
+                }
+            }
+        }
+        assert!(transport.is_at_end());
+        played
+    }
+
+    #[test]
+    fn dc_al_fine() {
+        let bars = vec![
+            Bar::new(960, None, None, repeat_set!(Repeat::Fine)),
+            Bar::new(1920, None, None, RepeatSet::EMPTY),
+            Bar::new(2880, None, None, repeat_set!(Repeat::Dc)),
+        ];
+        assert_eq!(
+            play_through(bars, &[Solfa::C, Solfa::D, Solfa::E]),
+            vec![(0, Solfa::C), (960, Solfa::D), (1920, Solfa::E), (2880, Solfa::C)]
+        );
+    }
+
+    #[test]
+    fn ds_al_coda() {
+        //   A |Segno B |Coda C |D.S. D |Coda E
+        //   A | B | C | B | E
+        let bars = vec![
+            Bar::new(960, None, None, repeat_set!(Repeat::Segno)),
+            Bar::new(1920, None, None, repeat_set!(Repeat::Coda)),
+            Bar::new(2880, None, None, repeat_set!(Repeat::Ds)),
+            Bar::new(3840, None, None, repeat_set!(Repeat::Coda)),
+            Bar::new(4800, None, None, RepeatSet::EMPTY),
+        ];
+        assert_eq!(
+            play_through(bars, &[Solfa::A, Solfa::B, Solfa::C, Solfa::D, Solfa::E]),
+            vec![(0, Solfa::A), (960, Solfa::B), (1920, Solfa::C), (2880, Solfa::B), (3840, Solfa::E)]
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::BTreeMap;
+
+use crate::{channel::Channel, pitch::{Pitch, PitchError}};
+
+// Interval from concert pitch to written pitch of a transposing instrument.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Transposition {
+    pub steps: i8,
+    pub semitones: i8,
+}
+
+impl Transposition {
+    pub const NONE: Transposition = Transposition { steps: 0, semitones: 0 };
+    // Clarinet, trumpet, tenor sax.
+    pub const B_FLAT: Transposition = Transposition { steps: 1, semitones: 2 };
+    pub const A: Transposition = Transposition { steps: 2, semitones: 3 };
+    // Horn.
+    pub const F: Transposition = Transposition { steps: 4, semitones: 7 };
+    // Alto sax.
+    pub const E_FLAT: Transposition = Transposition { steps: 5, semitones: 9 };
+
+    pub fn is_none(self) -> bool {
+        self == Self::NONE
+    }
+
+    pub fn to_written(self, concert: Pitch) -> Result<Pitch, PitchError> {
+        concert.transposed(self.steps as i32, self.semitones as i32)
+    }
+
+    pub fn to_concert(self, written: Pitch) -> Result<Pitch, PitchError> {
+        written.transposed(-(self.steps as i32), -(self.semitones as i32))
+    }
+}
+
+// Notes are stored in concert pitch. These settings only change how they are displayed.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DisplaySettings {
+    // Show concert pitch even for transposing channels.
+    pub concert_pitch: bool,
+    transpositions: BTreeMap<Channel, Transposition>,
+}
+
+impl DisplaySettings {
+    pub fn transposition(&self, channel: Channel) -> Transposition {
+        self.transpositions.get(&channel).copied().unwrap_or_default()
+    }
+
+    pub fn set_transposition(&mut self, channel: Channel, transposition: Transposition) {
+        if transposition.is_none() {
+            self.transpositions.remove(&channel);
+        } else {
+            self.transpositions.insert(channel, transposition);
+        }
+    }
+
+    pub fn transpositions(&self) -> impl Iterator<Item = (&Channel, &Transposition)> {
+        self.transpositions.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{channel::Channel, pitch::Pitch, solfa::Solfa, octave::Octave, sharp_flat::SharpFlat};
+    use super::{DisplaySettings, Transposition};
+
+    #[test]
+    fn written_and_concert() {
+        let concert = Pitch::new(Solfa::B, Octave::Oct3, SharpFlat::Flat);
+        let written = Transposition::B_FLAT.to_written(concert).unwrap();
+        assert_eq!(written, Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Natural));
+        assert_eq!(Transposition::B_FLAT.to_concert(written).unwrap().value(), concert.value());
+
+        let concert = Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null);
+        assert_eq!(Transposition::E_FLAT.to_written(concert).unwrap(), Pitch::new(Solfa::A, Octave::Oct4, SharpFlat::Null));
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let mut settings = DisplaySettings::default();
+        settings.set_transposition(Channel::new(2), Transposition::F);
+        settings.set_transposition(Channel::new(3), Transposition::NONE);
+        assert_eq!(settings.transpositions().count(), 1);
+
+        let json = serde_json::to_string(&settings).unwrap();
+        let restored: DisplaySettings = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.transposition(Channel::new(2)), Transposition::F);
+        assert_eq!(restored.transposition(Channel::new(0)), Transposition::NONE);
+    }
+}
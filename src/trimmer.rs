@@ -1,13 +1,38 @@
 use std::hash::{Hash, Hasher};
-use crate::percent::PercentU16;
+use crate::percent::{PercentU16, PercentU32};
 
 use super::can_apply::CanApply;
 
 pub const COUNT: usize = 4;
 
+// Meaning of each trimmer slot so that subsystems adjust their own lane without clobbering user edits.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum TrimmerLane {
+    User,
+    Groove,
+    Articulation,
+    Import,
+}
+
+impl TrimmerLane {
+    pub const ALL: [TrimmerLane; COUNT] = [TrimmerLane::User, TrimmerLane::Groove, TrimmerLane::Articulation, TrimmerLane::Import];
+
+    pub const fn index(self) -> usize {
+        match self {
+            TrimmerLane::User => 0,
+            TrimmerLane::Groove => 1,
+            TrimmerLane::Articulation => 2,
+            TrimmerLane::Import => 3,
+        }
+    }
+}
+
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(from = "TrimmerSerializedForm")]
 #[derive(Debug, Eq, Clone, Copy)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Trimmer {
     values: [i16; COUNT],
     #[serde(skip)]    
@@ -100,6 +125,18 @@ impl Trimmer {
             self.values[2] as i32 + self.values[3] as i32;
         return self;
     }
+
+    pub fn get_lane(&self, lane: TrimmerLane) -> i16 {
+        self.values[lane.index()]
+    }
+
+    pub fn set_lane(self, lane: TrimmerLane, value: i16) -> Self {
+        self.updated(|values| values[lane.index()] = value)
+    }
+
+    pub fn clear_lane(self, lane: TrimmerLane) -> Self {
+        self.set_lane(lane, 0)
+    }
 }
 
 impl PartialEq for Trimmer {
@@ -121,6 +158,7 @@ impl Hash for Trimmer {
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(from = "RateTrimmerSerializedForm")]
 #[derive(Debug, Eq, Clone, Copy)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct RateTrimmer {
     values: [PercentU16; 4],
     #[serde(skip)]
@@ -192,6 +230,25 @@ impl RateTrimmer {
     pub fn sum(self: &Self) -> PercentU16 {
         self.sum
     }
+
+    // Product of all slots without the 200% cap of sum().
+    pub fn product(&self) -> PercentU32 {
+        PercentU32::product(self.values.iter().map(|v| PercentU32::from(*v)))
+    }
+
+    pub fn get_lane(&self, lane: TrimmerLane) -> PercentU16 {
+        self.values[lane.index()]
+    }
+
+    pub fn set_lane(self, lane: TrimmerLane, rate: PercentU16) -> Self {
+        let mut values = self.values;
+        values[lane.index()] = rate;
+        Self::from_array(values)
+    }
+
+    pub fn clear_lane(self, lane: TrimmerLane) -> Self {
+        self.set_lane(lane, PercentU16::HUNDRED)
+    }
 }
 
 impl PartialEq for RateTrimmer {
@@ -293,6 +350,29 @@ mod tests {
 
         let v = RateTrimmer::from_array([PercentU16::HUNDRED, PercentU16::HUNDRED, PercentU16::HUNDRED, PercentU16::HUNDRED]);
         assert_eq!(v.sum(), PercentU16::HUNDRED);
+
+        let v = RateTrimmer::new(1.5, 2.0, 2.0, 0.5);
+        assert_eq!(v.sum(), PercentU16::MAX);
+        assert_eq!(v.product().to_f32(), 3.0);
+        assert_eq!(v.product().apply(100), 300);
+    }
+
+    use crate::trimmer::TrimmerLane;
+
+    #[test]
+    fn lanes() {
+        let v = Trimmer::ZERO.set_lane(TrimmerLane::User, 10).set_lane(TrimmerLane::Groove, -3);
+        assert_eq!(v.get_lane(TrimmerLane::User), 10);
+        assert_eq!(v.get_lane(TrimmerLane::Groove), -3);
+        assert_eq!(v.sum(), 7);
+        let v = v.clear_lane(TrimmerLane::Groove);
+        assert_eq!(v, Trimmer::new(10, 0, 0, 0));
+
+        let r = RateTrimmer::ONE.set_lane(TrimmerLane::Articulation, PercentU16::from(0.5)).set_lane(TrimmerLane::User, PercentU16::from(1.5));
+        assert_eq!(r.get_lane(TrimmerLane::Articulation), PercentU16::from(0.5));
+        assert_eq!(r.sum().to_f32(), 0.75);
+        assert_eq!(r.clear_lane(TrimmerLane::Articulation).sum().to_f32(), 1.5);
+        assert_eq!(r.clear_lane(TrimmerLane::Articulation).clear_lane(TrimmerLane::User), RateTrimmer::ONE);
     }
 
     #[test]
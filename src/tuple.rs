@@ -11,9 +11,8 @@ pub fn tuplize(mut notes: Vec<Rc<Note>>) -> Vec<Rc<Note>> {
     if let Some(min_unit) = min_unit {
         let start_tick = notes[0].base_start_tick();
         let (total_tick, total_unit) = total_tick_unit(&sorted);
-        let denominator = total_unit / min_unit;
-        let total_tick = total_tick / denominator * 2;
-        let denominator = Denominator::from_value(denominator as u8).unwrap();
+        let Ok(denominator) = Denominator::from_count((total_unit / min_unit) as usize) else { return vec![]; };
+        let total_tick = total_tick / denominator.value() as u32 * 2;
         let mut u = 0;
         let mut ret = Vec::with_capacity(notes.len());
 
@@ -0,0 +1,176 @@
+use std::{cmp::Reverse, collections::{BTreeMap, HashSet}, ops::Range, rc::Rc};
+
+use crate::{channel::Channel, duration::Duration, models::Models, note::Note, percent::PercentU16, project::ProjectImpl, trimmer::TrimmerLane};
+
+// Two notes of the same pitch sounding together on a channel. MIDI cannot tell their note-offs apart,
+// so the pitch stops at whichever note-off comes first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnisonConflict {
+    // Starts first. Of notes starting together, the longer one.
+    pub first: Rc<Note>,
+    pub second: Rc<Note>,
+}
+
+impl UnisonConflict {
+    pub fn overlap(&self) -> Range<u32> {
+        let end = |n: &Note| n.start_tick() + n.tick_len();
+        self.second.start_tick()..end(&self.first).min(end(&self.second))
+    }
+}
+
+// Conflicts whose overlap is in the range, ordered by the start of the overlap. Muted notes do not sound
+// and never conflict.
+pub fn unison_conflicts(proj: &ProjectImpl, range: Range<u32>) -> Vec<UnisonConflict> {
+    let lookback = Duration::MAX_TICK_LENGTH as u32 * 2;
+    let mut voices: BTreeMap<(Channel, u8), Vec<&Rc<Note>>> = BTreeMap::new();
+    for (_, note) in proj.note_repo().range(range.start.saturating_sub(lookback)..range.end) {
+        if note.muted || note.tick_len() == 0 { continue; }
+        voices.entry((note.channel, proj.sounding_pitch(note).value())).or_default().push(note);
+    }
+
+    let end = |n: &Note| n.start_tick() + n.tick_len();
+    let mut conflicts = vec![];
+    for notes in voices.values_mut() {
+        notes.sort_by_key(|n| (n.start_tick(), Reverse(n.tick_len())));
+        // The note ending last so far.
+        let mut sounding: Option<&Rc<Note>> = None;
+        for note in notes.iter() {
+            if let Some(s) = sounding.filter(|s| note.start_tick() < end(s)) {
+                let conflict = UnisonConflict { first: s.clone(), second: (*note).clone() };
+                let overlap = conflict.overlap();
+                if overlap.start < range.end && range.start < overlap.end {
+                    conflicts.push(conflict);
+                }
+            }
+            if sounding.is_none_or(|s| end(s) < end(note)) {
+                sounding = Some(note);
+            }
+        }
+    }
+    conflicts.sort_by_key(|c| (c.second.start_tick(), c.second.channel, c.second.pitch.value()));
+    conflicts
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnisonFix {
+    // The first note is cut to end a tick before the second one starts. Notes starting together are merged.
+    Offset,
+    // The second note is dropped. The first one is extended to the end of the second one if it ends later.
+    Merge,
+}
+
+// Note with the user lane of the duration trimmer lowered so that it sounds no longer than the tick length.
+fn shortened(note: &Note, tick_len: u32) -> Option<Note> {
+    let user = note.duration_trimmer.get_lane(TrimmerLane::User).value() as u64;
+    let mut value = (user * tick_len as u64).div_ceil(note.tick_len().max(1) as u64) as u16;
+    loop {
+        let n = Note { duration_trimmer: note.duration_trimmer.set_lane(TrimmerLane::User, PercentU16::from_value(value)), ..*note };
+        if n.tick_len() <= tick_len { return Some(n); }
+        value = value.checked_sub(1)?;
+    }
+}
+
+// Note with the user lane of the duration trimmer raised so that it sounds at least for the tick length, or as long
+// as the lane allows.
+fn lengthened(note: &Note, tick_len: u32) -> Note {
+    let user = note.duration_trimmer.get_lane(TrimmerLane::User).value() as u64;
+    let mut value = ((user * tick_len as u64) / note.tick_len().max(1) as u64).min(PercentU16::MAX.value() as u64) as u16;
+    loop {
+        let n = Note { duration_trimmer: note.duration_trimmer.set_lane(TrimmerLane::User, PercentU16::from_value(value)), ..*note };
+        if tick_len <= n.tick_len() || PercentU16::MAX.value() <= value { return n; }
+        value += 1;
+    }
+}
+
+// Notes to add and remove to fix the conflicts. A note is changed only once, so running the detection again
+// may find conflicts left.
+pub fn unison_fixes(conflicts: &[UnisonConflict], fix: UnisonFix) -> (Models, Models) {
+    let mut touched: HashSet<*const Note> = HashSet::new();
+    let mut added = vec![];
+    let mut removed = vec![];
+    for c in conflicts {
+        if touched.contains(&Rc::as_ptr(&c.first)) || touched.contains(&Rc::as_ptr(&c.second)) { continue; }
+        let gap = c.second.start_tick() - c.first.start_tick();
+        let cut = if fix == UnisonFix::Offset && 1 < gap { shortened(&c.first, gap - 1) } else { None };
+        match cut {
+            Some(note) => {
+                added.push(note);
+                removed.push((*c.first).clone());
+                touched.insert(Rc::as_ptr(&c.first));
+            }
+            None => {
+                let end = |n: &Note| n.start_tick() + n.tick_len();
+                if end(&c.first) < end(&c.second) {
+                    added.push(lengthened(&c.first, end(&c.second) - c.first.start_tick()));
+                    removed.push((*c.first).clone());
+                    touched.insert(Rc::as_ptr(&c.first));
+                }
+                removed.push((*c.second).clone());
+                touched.insert(Rc::as_ptr(&c.second));
+            }
+        }
+    }
+    (Models { notes: added, ..Models::empty() }, Models { notes: removed, ..Models::empty() })
+}
+
+#[cfg(test)]
+mod tests {
+    use serdo::undo_store::UndoStore;
+
+    use crate::{channel::Channel, duration::{Denominator, Dots, Duration, Numerator}, note::Note, octave::Octave, pitch::Pitch, project::{memory_project_store, MemoryProjectStore, Project, DEFAULT_MEMORY_UNDO_LIMIT}, sharp_flat::SharpFlat, solfa::Solfa};
+    use super::{unison_conflicts, UnisonFix};
+
+    fn add(store: &mut MemoryProjectStore, tick: u32, numerator: Numerator, channel: u8) {
+        let duration = Duration::new(numerator, Denominator::default(), Dots::ZERO);
+        let pitch = Pitch::new(Solfa::C, Octave::Oct4, SharpFlat::Null);
+        store.add_note(Note { base_start_tick: tick, pitch, duration, channel: Channel::new(channel), ..Default::default() }, false);
+    }
+
+    #[test]
+    fn detect_and_fix() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        add(&mut store, 0, Numerator::Whole, 0);
+        add(&mut store, 240, Numerator::Quarter, 0);
+        add(&mut store, 240, Numerator::Quarter, 1);
+        add(&mut store, 960, Numerator::Quarter, 0);
+
+        let conflicts = unison_conflicts(store.model(), 0..1920);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].first.start_tick(), 0);
+        assert_eq!(conflicts[0].overlap(), 240..480);
+        assert!(unison_conflicts(store.model(), 480..1920).is_empty());
+
+        store.fix_unison_conflicts(0..1920, UnisonFix::Offset);
+        assert!(unison_conflicts(store.model(), 0..1920).is_empty());
+        let first = store.model().note_repo().iter().next().unwrap().1.clone();
+        assert_eq!(first.tick_len(), 239);
+        store.undo();
+
+        store.fix_unison_conflicts(0..1920, UnisonFix::Merge);
+        assert!(unison_conflicts(store.model(), 0..1920).is_empty());
+        assert_eq!(store.model().note_repo().len(), 3);
+    }
+
+    #[test]
+    fn merge_extends_to_later_end() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        add(&mut store, 0, Numerator::Half, 0);
+        add(&mut store, 240, Numerator::Half, 0);
+        store.fix_unison_conflicts(0..1920, UnisonFix::Merge);
+        let notes: Vec<_> = store.model().note_repo().iter().map(|(t, n)| (*t, n.tick_len())).collect();
+        assert_eq!(notes, vec![(0, 720)]);
+        store.undo();
+        assert_eq!(store.model().note_repo().len(), 2);
+    }
+
+    #[test]
+    fn same_start() {
+        let mut store = memory_project_store(DEFAULT_MEMORY_UNDO_LIMIT);
+        add(&mut store, 0, Numerator::Quarter, 0);
+        add(&mut store, 0, Numerator::Half, 0);
+        let conflicts = unison_conflicts(store.model(), 0..960);
+        assert_eq!(conflicts[0].first.tick_len(), 480);
+        store.fix_unison_conflicts(0..960, UnisonFix::Offset);
+        assert_eq!(store.model().note_repo().iter().map(|(_, n)| n.tick_len()).collect::<Vec<_>>(), vec![480]);
+    }
+}
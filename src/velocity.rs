@@ -1,6 +1,7 @@
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(from="Serialized")]
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Velocity(u8);
 
 impl Default for Velocity {
@@ -41,3 +42,137 @@ impl std::fmt::Display for Velocity {
         write!(f, "{}", self.0)
     }
 }
+
+impl Velocity {
+    pub fn saturating_add(self, delta: u8) -> Self {
+        Self::new(self.0.saturating_add(delta))
+    }
+
+    pub fn saturating_sub(self, delta: u8) -> Self {
+        Self(self.0.saturating_sub(delta))
+    }
+
+    // Signed offset clamped to 0 - 127.
+    pub fn offset(self, delta: i32) -> Self {
+        Self((self.0 as i32 + delta).clamp(MIN_VALUE as i32, MAX_VALUE as i32) as u8)
+    }
+
+    // Linear interpolation at pos of 0..=len, rounded half away from zero.
+    pub fn interpolate(from: Velocity, to: Velocity, pos: u32, len: u32) -> Velocity {
+        if len == 0 { return to; }
+        let (pos, len) = (pos.min(len) as i64, len as i64);
+        let delta = (to.0 as i64 - from.0 as i64) * pos;
+        let offset = (delta * 2 + delta.signum() * len) / (len * 2);
+        Velocity::new((from.0 as i64 + offset) as u8)
+    }
+
+    pub fn from_dynamic(dynamic: Dynamic) -> Self {
+        DynamicsTable::default().velocity(dynamic)
+    }
+
+    pub fn to_dynamic(self) -> Dynamic {
+        DynamicsTable::default().dynamic(self)
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Dynamic {
+    PP,
+    P,
+    MP,
+    MF,
+    F,
+    FF,
+}
+
+impl Dynamic {
+    pub const ALL: [Dynamic; 6] = [Dynamic::PP, Dynamic::P, Dynamic::MP, Dynamic::MF, Dynamic::F, Dynamic::FF];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl std::fmt::Display for Dynamic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            Dynamic::PP => "pp",
+            Dynamic::P => "p",
+            Dynamic::MP => "mp",
+            Dynamic::MF => "mf",
+            Dynamic::F => "f",
+            Dynamic::FF => "ff",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+// Velocity of each dynamic, from pp to ff. Shared by dynamics ramps, humanize and text dynamics.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DynamicsTable {
+    velocities: [Velocity; 6],
+}
+
+impl Default for DynamicsTable {
+    fn default() -> Self {
+        Self {
+            velocities: [Velocity(33), Velocity(49), Velocity(64), Velocity(80), Velocity(96), Velocity(112)],
+        }
+    }
+}
+
+impl DynamicsTable {
+    // None unless velocities are strictly increasing from pp to ff.
+    pub fn new(velocities: [Velocity; 6]) -> Option<Self> {
+        if velocities.windows(2).all(|w| w[0].0 < w[1].0) { Some(Self { velocities }) } else { None }
+    }
+
+    pub fn velocities(&self) -> &[Velocity; 6] {
+        &self.velocities
+    }
+
+    pub fn velocity(&self, dynamic: Dynamic) -> Velocity {
+        self.velocities[dynamic.index()]
+    }
+
+    // The nearest dynamic. Ties go to the softer one.
+    pub fn dynamic(&self, velocity: Velocity) -> Dynamic {
+        *Dynamic::ALL.iter()
+            .min_by_key(|d| self.velocity(**d).0.abs_diff(velocity.0))
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Velocity, Dynamic, DynamicsTable};
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(Velocity::new(120).saturating_add(10), Velocity::new(127));
+        assert_eq!(Velocity::new(5).saturating_sub(10), Velocity::new(0));
+        assert_eq!(Velocity::new(64).offset(-100), Velocity::new(0));
+        assert_eq!(Velocity::new(64).offset(10), Velocity::new(74));
+        assert_eq!(Velocity::interpolate(Velocity::new(0), Velocity::new(127), 1, 2), Velocity::new(64));
+        assert_eq!(Velocity::interpolate(Velocity::new(100), Velocity::new(0), 3, 2), Velocity::new(0));
+        assert_eq!(Velocity::interpolate(Velocity::new(100), Velocity::new(0), 0, 0), Velocity::new(0));
+    }
+
+    #[test]
+    fn dynamics() {
+        assert_eq!(Velocity::from_dynamic(Dynamic::MF), Velocity::new(80));
+        assert_eq!(Velocity::new(90).to_dynamic(), Dynamic::F);
+        assert_eq!(Velocity::new(0).to_dynamic(), Dynamic::PP);
+        assert_eq!(Velocity::new(127).to_dynamic(), Dynamic::FF);
+        assert_eq!(Dynamic::MP.to_string(), "mp");
+
+        let table = DynamicsTable::new([10, 20, 30, 40, 50, 60].map(Velocity::new)).unwrap();
+        assert_eq!(table.velocity(Dynamic::PP), Velocity::new(10));
+        assert_eq!(table.dynamic(Velocity::new(100)), Dynamic::FF);
+        assert_eq!(DynamicsTable::new([10, 20, 20, 40, 50, 60].map(Velocity::new)), None);
+    }
+}
@@ -0,0 +1,107 @@
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::models::Models;
+
+#[wasm_bindgen]
+#[derive(Clone, PartialEq, Debug)]
+pub struct JsModels {
+    models: Models,
+}
+
+#[wasm_bindgen]
+impl JsModels {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self { models: Models::empty() }
+    }
+
+    #[wasm_bindgen(js_name = fromJson)]
+    pub fn from_json(json: &str) -> Result<JsModels, String> {
+        serde_json::from_str::<Models>(json).map(|models| Self { models }).map_err(|e| e.to_string())
+    }
+
+    #[wasm_bindgen(js_name = toJson)]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.models).unwrap()
+    }
+
+    #[wasm_bindgen(js_name = fromClipboardText)]
+    pub fn from_clipboard_text(text: String) -> Result<JsModels, String> {
+        Models::from_clipboard_text(text).map(|models| Self { models }).map_err(|e| format!("{:?}", e))
+    }
+
+    #[wasm_bindgen(js_name = toClipboardText)]
+    pub fn to_clipboard_text(&self) -> String {
+        self.models.to_clipboard_text()
+    }
+
+    #[wasm_bindgen(js_name = moveToTick)]
+    pub fn move_to_tick(self, tick: u32) -> JsModels {
+        Self { models: self.models.move_to_tick(tick) }
+    }
+
+    #[wasm_bindgen(getter, js_name = noteCount)]
+    pub fn note_count(&self) -> usize {
+        self.models.notes.len()
+    }
+
+    #[wasm_bindgen(getter, js_name = barCount)]
+    pub fn bar_count(&self) -> usize {
+        self.models.bars.len()
+    }
+
+    #[wasm_bindgen(getter, js_name = tempoCount)]
+    pub fn tempo_count(&self) -> usize {
+        self.models.tempos.len()
+    }
+
+    #[wasm_bindgen(getter, js_name = dumperCount)]
+    pub fn dumper_count(&self) -> usize {
+        self.models.dumpers.len()
+    }
+
+    #[wasm_bindgen(getter, js_name = softCount)]
+    pub fn soft_count(&self) -> usize {
+        self.models.softs.len()
+    }
+}
+
+impl Default for JsModels {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<Models> for JsModels {
+    fn from(models: Models) -> Self {
+        Self { models }
+    }
+}
+
+impl From<JsModels> for Models {
+    fn from(js: JsModels) -> Self {
+        js.models
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{models::Models, bar::{Bar, RepeatSet}};
+    use super::JsModels;
+
+    #[test]
+    fn json_round_trip() {
+        let models = Models::empty().with_bars(vec![Bar::new(100, None, None, RepeatSet::EMPTY)]);
+        let js: JsModels = models.clone().into();
+        let restored = JsModels::from_json(&js.to_json()).unwrap();
+        assert_eq!(restored.bar_count(), 1);
+        assert_eq!(restored.note_count(), 0);
+        assert_eq!(Models::from(restored), models);
+    }
+
+    #[test]
+    fn invalid_json() {
+        assert!(JsModels::from_json("{").is_err());
+        assert!(JsModels::from_clipboard_text("".to_owned()).is_err());
+    }
+}